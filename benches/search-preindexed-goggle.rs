@@ -21,6 +21,7 @@ macro_rules! bench {
                         goggle_program: Some($goggle.to_string()),
                         skip_pages: None,
                         site_rankings: None,
+                        cursor: None,
                     })
                     .unwrap()
             })
@@ -30,7 +31,7 @@ macro_rules! bench {
 
 pub fn criterion_benchmark(c: &mut Criterion) {
     let index = Index::open(INDEX_PATH).unwrap();
-    let searcher = LocalSearcher::new(index, None, None);
+    let searcher = LocalSearcher::new(index, None, None, None);
     let goggle = include_str!("../testcases/goggles/hacker_news.goggle");
 
     // for _ in 0..10 {
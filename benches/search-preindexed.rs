@@ -20,6 +20,7 @@ macro_rules! bench {
                         goggle_program: None,
                         skip_pages: None,
                         site_rankings: None,
+                        cursor: None,
                     })
                     .unwrap()
             })
@@ -29,7 +30,7 @@ macro_rules! bench {
 
 pub fn criterion_benchmark(c: &mut Criterion) {
     let index = Index::open(INDEX_PATH).unwrap();
-    let searcher = LocalSearcher::new(index, None, None);
+    let searcher = LocalSearcher::new(index, None, None, None);
 
     // for _ in 0..100 {
     bench!("the", searcher, c);
@@ -16,43 +16,58 @@
 
 use fst::{automaton::Str, Automaton, IntoStreamer};
 
+use crate::tokenizer;
 use crate::Result;
 use std::path::Path;
 
 pub struct Autosuggest {
-    queries: fst::Set<Vec<u8>>,
+    /// Maps the normalised and folded form of each query to its index in `originals`,
+    /// so `cafe` suggests `café` and fullwidth input suggests its ascii forms.
+    queries: fst::Map<Vec<u8>>,
+    originals: Vec<String>,
+}
+
+fn key(query: &str) -> String {
+    tokenizer::fold(&tokenizer::normalize(query))
 }
 
 impl Autosuggest {
     pub fn load_csv<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let mut queries: Vec<String> = Vec::new();
+        let mut queries: Vec<(String, String)> = Vec::new();
 
         let mut rdr = csv::Reader::from_path(path)?;
         for result in rdr.records() {
             let record = result?;
             if let Some(query) = record.get(0) {
-                queries.push(query.to_string());
+                queries.push((key(query), query.to_string()));
             }
         }
 
         queries.sort();
+        queries.dedup_by(|(a, _), (b, _)| a == b);
 
-        let queries = fst::Set::from_iter(queries)?;
+        let (keys, originals): (Vec<String>, Vec<String>) = queries.into_iter().unzip();
+        let queries = fst::Map::from_iter(
+            keys.into_iter()
+                .enumerate()
+                .map(|(idx, key)| (key, idx as u64)),
+        )?;
 
-        Ok(Self { queries })
+        Ok(Self { queries, originals })
     }
 
     pub fn suggestions(&self, query: &str) -> Result<Vec<String>> {
-        let query = query.to_ascii_lowercase();
+        let query = key(query);
         let q = Str::new(query.as_str()).starts_with();
 
         Ok(self
             .queries
             .search(q)
             .into_stream()
-            .into_strs()?
+            .into_values()
             .into_iter()
             .take(10)
+            .map(|idx| self.originals[idx as usize].clone())
             .collect())
     }
 }
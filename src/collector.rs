@@ -14,7 +14,11 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use min_max_heap::MinMaxHeap;
 use serde::{Deserialize, Serialize};
@@ -27,7 +31,9 @@ use crate::{
     fastfield_cache,
     inverted_index::{DocAddress, WebsitePointer},
     prehashed::{combine_u64s, Prehashed},
+    ranking::proximity::{Proximity, SegmentProximity, NUM_PROXIMITY_CANDIDATES},
     schema::FastField,
+    simhash::SimHashIndex,
 };
 
 // lower scale -> higher penalty
@@ -35,6 +41,65 @@ const SITE_SCALE: f64 = 14.0;
 const TITLE_SCALE: f64 = 6.0;
 const URL_SCALE: f64 = 0.1;
 
+/// Which of the documents with the same score as a [`SearchAfter`] position come after it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Ties {
+    None,
+    All,
+    /// Documents with a greater address.
+    After(DocAddress),
+}
+
+/// A position in the ranking, where only the documents after it are collected.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SearchAfter {
+    pub score: f64,
+    pub ties: Ties,
+}
+
+impl SearchAfter {
+    pub fn is_after(&self, score: f64, address: DocAddress) -> bool {
+        if score != self.score {
+            return score < self.score;
+        }
+
+        match self.ties {
+            Ties::None => false,
+            Ties::All => true,
+            Ties::After(position) => Tiebreak::from(address) > Tiebreak::from(position),
+        }
+    }
+}
+
+/// Documents with the same score are ranked by their shard and then by their address in the shard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Tiebreak {
+    /// `None` when all the documents are from the same searcher.
+    pub shard: Option<u32>,
+    pub segment: SegmentOrdinal,
+    pub doc_id: DocId,
+}
+
+impl From<DocAddress> for Tiebreak {
+    fn from(address: DocAddress) -> Self {
+        Self {
+            shard: None,
+            segment: address.segment,
+            doc_id: address.doc_id,
+        }
+    }
+}
+
+/// The order of the ranking, where the documents with a higher score come first.
+/// Pages, cursors and the merge of the results from the shards all use it,
+/// so the ranking is the same whichever order the documents are collected in.
+pub fn rank_order<T: Doc>(a: &T, b: &T) -> Ordering {
+    b.score()
+        .partial_cmp(a.score())
+        .unwrap_or(Ordering::Equal)
+        .then_with(|| a.tiebreak().cmp(&b.tiebreak()))
+}
+
 #[derive(Clone)]
 pub struct MaxDocsConsidered {
     pub total_docs: usize,
@@ -46,20 +111,95 @@ pub struct Hashes {
     site: Prehashed,
     title: Prehashed,
     url: Prehashed,
+    simhash: u64,
 }
 
 pub trait Doc: Clone {
     fn score(&self) -> &f64;
-    fn id(&self) -> &DocId;
+    fn tiebreak(&self) -> Tiebreak;
     fn hashes(&self) -> Hashes;
 }
 
+/// A document that lower ranked documents from the same site can be folded under.
+pub trait SiteDoc: Doc {
+    /// Number of documents from the same site folded under this one.
+    fn more_from_site(&mut self) -> &mut usize;
+}
+
+/// Keep at most `max_per_site` documents from each site on the page.
+/// The documents beyond the cap are removed and counted on the highest ranked document
+/// from their site.
+pub fn cap_per_site<T: SiteDoc>(docs: Vec<T>, max_per_site: usize) -> Vec<T> {
+    assert!(max_per_site > 0);
+
+    let mut res: Vec<T> = Vec::with_capacity(docs.len());
+    // index of the top document from the site on the page and number of documents from the site
+    let mut sites_on_page: HashMap<Prehashed, (usize, usize)> = HashMap::new();
+
+    for mut doc in docs {
+        match sites_on_page.get_mut(&doc.hashes().site) {
+            Some((top, count)) if *count >= max_per_site => {
+                let folded = 1 + *doc.more_from_site();
+                *res[*top].more_from_site() += folded;
+            }
+            Some((_, count)) => {
+                *count += 1;
+                res.push(doc);
+            }
+            None => {
+                sites_on_page.insert(doc.hashes().site, (res.len(), 1));
+                res.push(doc);
+            }
+        }
+    }
+
+    res
+}
+
+/// Order the documents of a page for display. Similar documents are ranked lower
+/// and documents beyond `max_per_site` from the same site are folded under the top one.
+/// The documents never leave the page, so the next page can start right after the
+/// lowest ranked of them.
+pub fn build_page<T: SiteDoc>(
+    docs: Vec<T>,
+    de_rank_similar: bool,
+    max_per_site: Option<usize>,
+) -> Vec<T> {
+    if docs.is_empty() {
+        return docs;
+    }
+
+    let mut collector = BucketCollector::new(docs.len());
+
+    for doc in docs {
+        collector.insert(doc);
+    }
+
+    let docs = collector.into_sorted_vec(de_rank_similar);
+
+    match max_per_site {
+        Some(max_per_site) => cap_per_site(docs, max_per_site),
+        None => docs,
+    }
+}
+
+/// The documents shown on a page.
+pub struct Page {
+    pub websites: Vec<WebsitePointer>,
+    /// The lowest ranked document the page was built from, where the next page starts.
+    /// It is not shown if it was folded under a similar document or one from the same site.
+    pub end: Option<WebsitePointer>,
+}
+
 pub struct TopDocs {
     top_n: usize,
     offset: usize,
     max_docs: Option<MaxDocsConsidered>,
     fastfield_cache: Arc<fastfield_cache::FastFieldCache>,
     de_rank_similar: bool,
+    proximity: Option<Proximity>,
+    max_results_per_site: Option<usize>,
+    search_after: Option<SearchAfter>,
 }
 
 impl TopDocs {
@@ -70,6 +210,9 @@ impl TopDocs {
             max_docs: None,
             de_rank_similar: false,
             fastfield_cache,
+            proximity: None,
+            max_results_per_site: None,
+            search_after: None,
         }
     }
 
@@ -90,10 +233,36 @@ impl TopDocs {
         self
     }
 
+    /// Rerank the top candidates of each segment by the proximity of the query terms.
+    pub fn and_proximity(mut self, proximity: Proximity) -> Self {
+        self.proximity = Some(proximity);
+
+        self
+    }
+
+    /// Fold the results beyond `max_results_per_site` from the same site on a page
+    /// under the top result from the site.
+    pub fn and_max_results_per_site(mut self, max_results_per_site: usize) -> Self {
+        self.max_results_per_site = Some(max_results_per_site);
+
+        self
+    }
+
+    /// Only collect the documents ranked after `search_after`.
+    pub fn and_search_after(mut self, search_after: SearchAfter) -> Self {
+        self.search_after = Some(search_after);
+
+        self
+    }
+
+    fn num_candidates(&self) -> usize {
+        self.top_n + self.offset
+    }
+
     pub fn tweak_score<TScoreSegmentTweaker, TScoreTweaker>(
         self,
         score_tweaker: TScoreTweaker,
-    ) -> impl Collector<Fruit = Vec<WebsitePointer>>
+    ) -> impl Collector<Fruit = Page>
     where
         TScoreSegmentTweaker: ScoreSegmentTweaker<f64> + 'static,
         TScoreTweaker: ScoreTweaker<f64, Child = TScoreSegmentTweaker> + Send + Sync,
@@ -103,7 +272,7 @@ impl TopDocs {
 }
 
 impl Collector for TopDocs {
-    type Fruit = Vec<WebsitePointer>;
+    type Fruit = Page;
 
     type Child = TopSegmentCollector;
 
@@ -117,12 +286,32 @@ impl Collector for TopDocs {
             .as_ref()
             .map(|max_docs| max_docs.total_docs / max_docs.segments);
 
+        let num_candidates = self.num_candidates();
+
+        let proximity = match &self.proximity {
+            Some(proximity) => Some(ProximityCandidates {
+                proximity: proximity.for_segment(segment)?,
+                candidates: BucketCollector::new(NUM_PROXIMITY_CANDIDATES),
+            }),
+            None => None,
+        };
+
+        // the documents re-scored by the proximity signal can move up past any of the others
+        let bucket_size = if proximity.is_some() {
+            num_candidates + NUM_PROXIMITY_CANDIDATES
+        } else {
+            num_candidates
+        };
+
         Ok(TopSegmentCollector {
             fastfield_segment_cache: self.fastfield_cache.get_segment(&segment.segment_id()),
             max_docs,
             num_docs_taken: 0,
+            num_candidates,
             segment_ord: segment_local_id,
-            bucket_collector: BucketCollector::new(self.top_n + self.offset),
+            bucket_collector: BucketCollector::new(bucket_size),
+            proximity,
+            search_after: self.search_after,
         })
     }
 
@@ -134,18 +323,15 @@ impl Collector for TopDocs {
         &self,
         segment_fruits: Vec<<Self::Child as tantivy::collector::SegmentCollector>::Fruit>,
     ) -> tantivy::Result<Self::Fruit> {
-        let mut collector = BucketCollector::new(self.top_n + self.offset);
+        let mut docs: Vec<_> = segment_fruits.into_iter().flatten().collect();
+        docs.sort_by(rank_order);
 
-        for docs in segment_fruits {
-            for doc in docs {
-                collector.insert(doc);
-            }
-        }
-
-        Ok(collector
-            .into_sorted_vec(self.de_rank_similar)
+        // the page is the documents at its position in the ranking, which are only
+        // reordered within the page
+        let pointers: Vec<_> = docs
             .into_iter()
             .skip(self.offset)
+            .take(self.top_n)
             .map(|doc| WebsitePointer {
                 score: doc.score,
                 hashes: doc.hashes,
@@ -153,17 +339,33 @@ impl Collector for TopDocs {
                     segment: doc.segment,
                     doc_id: doc.id,
                 },
+                more_from_site: 0,
             })
-            .collect())
+            .collect();
+
+        Ok(Page {
+            end: pointers.last().cloned(),
+            websites: build_page(pointers, self.de_rank_similar, self.max_results_per_site),
+        })
     }
 }
 
+/// The top documents of a segment by their score without the proximity signal,
+/// which are the only ones the signal is added to.
+struct ProximityCandidates {
+    proximity: SegmentProximity,
+    candidates: BucketCollector<SegmentDoc>,
+}
+
 pub struct TopSegmentCollector {
     fastfield_segment_cache: Arc<fastfield_cache::SegmentCache>,
     max_docs: Option<usize>,
     num_docs_taken: usize,
+    num_candidates: usize,
     segment_ord: SegmentOrdinal,
     bucket_collector: BucketCollector<SegmentDoc>,
+    proximity: Option<ProximityCandidates>,
+    search_after: Option<SearchAfter>,
 }
 
 impl TopSegmentCollector {
@@ -179,12 +381,38 @@ impl TopSegmentCollector {
         let hash = [hash[0], hash[1]];
         combine_u64s(hash).into()
     }
+
+    fn address(&self, doc: DocId) -> DocAddress {
+        DocAddress {
+            segment: self.segment_ord,
+            doc_id: doc,
+        }
+    }
+
+    fn segment_doc(&self, doc: DocId, score: f64) -> SegmentDoc {
+        SegmentDoc {
+            hashes: Hashes {
+                site: self.get_hash(&doc, &FastField::SiteHash),
+                title: self.get_hash(&doc, &FastField::TitleHash),
+                url: self.get_hash(&doc, &FastField::UrlHash),
+                simhash: self
+                    .fastfield_segment_cache
+                    .get_doc_cache(&FastField::SimHash)
+                    .get_u64(&doc)
+                    .unwrap(),
+            },
+            id: doc,
+            segment: self.segment_ord,
+            score,
+        }
+    }
 }
 
 impl SegmentCollector for TopSegmentCollector {
     type Fruit = Vec<SegmentDoc>;
 
     fn collect(&mut self, doc: DocId, score: Score) {
+        // documents before the cursor are counted as well, so every page considers the same documents
         if let Some(max_docs) = &self.max_docs {
             if self.num_docs_taken >= *max_docs {
                 return;
@@ -193,20 +421,71 @@ impl SegmentCollector for TopSegmentCollector {
             self.num_docs_taken += 1;
         }
 
-        self.bucket_collector.insert(SegmentDoc {
-            hashes: Hashes {
-                site: self.get_hash(&doc, &FastField::SiteHash),
-                title: self.get_hash(&doc, &FastField::TitleHash),
-                url: self.get_hash(&doc, &FastField::UrlHash),
-            },
-            id: doc,
-            segment: self.segment_ord,
-            score: score as f64,
-        });
+        let score = score as f64;
+        let is_after = match &self.search_after {
+            Some(search_after) => search_after.is_after(score, self.address(doc)),
+            None => true,
+        };
+
+        if !is_after && self.proximity.is_none() {
+            return;
+        }
+
+        let segment_doc = self.segment_doc(doc, score);
+
+        // the candidates for the proximity signal are chosen before the cursor is applied,
+        // so the pages after a cursor are slices of the same ranking as the first page
+        if let Some(proximity) = &mut self.proximity {
+            proximity.candidates.insert(segment_doc.clone());
+        }
+
+        if is_after {
+            self.bucket_collector.insert(segment_doc);
+        }
     }
 
+    /// The proximity signal is only added to the top `NUM_PROXIMITY_CANDIDATES` documents
+    /// of the segment by their score without it. A document below them is never re-scored,
+    /// however close its terms are.
     fn harvest(self) -> Self::Fruit {
-        self.bucket_collector.into_sorted_vec(false)
+        let mut docs = self.bucket_collector.into_sorted_vec(false);
+
+        if let Some(ProximityCandidates {
+            mut proximity,
+            candidates,
+        }) = self.proximity
+        {
+            let mut candidates = candidates.into_sorted_vec(false);
+
+            // the postings can only be advanced, so the candidates are scored in order of their ids
+            candidates.sort_by_key(|doc| doc.id);
+
+            for doc in &mut candidates {
+                doc.score += proximity.score(doc.id);
+            }
+
+            let candidate_ids: HashSet<_> = candidates.iter().map(|doc| doc.id).collect();
+            docs.retain(|doc| !candidate_ids.contains(&doc.id));
+
+            let segment = self.segment_ord;
+            let search_after = self.search_after;
+
+            docs.extend(candidates.into_iter().filter(|doc| match &search_after {
+                Some(search_after) => search_after.is_after(
+                    doc.score,
+                    DocAddress {
+                        segment,
+                        doc_id: doc.id,
+                    },
+                ),
+                None => true,
+            }));
+
+            docs.sort_by(rank_order);
+            docs.truncate(self.num_candidates);
+        }
+
+        docs
     }
 }
 
@@ -216,20 +495,25 @@ struct ScoredDoc<T: Doc> {
 }
 
 impl<T: Doc> PartialOrd for ScoredDoc<T> {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.adjusted_score.partial_cmp(&other.adjusted_score)
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
 impl<T: Doc> PartialEq for ScoredDoc<T> {
     fn eq(&self, other: &Self) -> bool {
-        self.adjusted_score == other.adjusted_score
+        self.cmp(other) == Ordering::Equal
     }
 }
 
 impl<T: Doc> Ord for ScoredDoc<T> {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.partial_cmp(other).unwrap_or(std::cmp::Ordering::Equal)
+    /// The best ranked document is the greatest, and documents with the same score
+    /// keep the order of [`rank_order`].
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.adjusted_score
+            .partial_cmp(&other.adjusted_score)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| other.doc.tiebreak().cmp(&self.doc.tiebreak()))
     }
 }
 
@@ -247,6 +531,7 @@ impl<T: Doc> From<T> for ScoredDoc<T> {
 #[derive(Default)]
 struct BucketCount {
     buckets: HashMap<Prehashed, usize>,
+    simhashes: SimHashIndex<()>,
 }
 
 impl BucketCount {
@@ -274,6 +559,13 @@ impl BucketCount {
         *self.buckets.entry(hashes.site).or_default() += 1;
         *self.buckets.entry(hashes.url).or_default() += 1;
         *self.buckets.entry(hashes.title).or_default() += 1;
+        self.simhashes.insert(hashes.simhash, ());
+    }
+
+    fn is_near_duplicate<T: Doc>(&self, doc: &ScoredDoc<T>) -> bool {
+        self.simhashes
+            .count_near_duplicates(doc.doc.hashes().simhash)
+            > 0
     }
 }
 
@@ -328,6 +620,13 @@ impl<T: Doc> BucketCollector<T> {
 
         while let Some(best_doc) = self.documents.pop_max() {
             if de_rank_similar {
+                // near-duplicates of a document we already show are collapsed
+                // entirely instead of just being ranked lower
+                if self.count.is_near_duplicate(&best_doc) {
+                    self.update_best_doc();
+                    continue;
+                }
+
                 self.count.update_counts(&best_doc);
                 self.update_best_doc();
             }
@@ -355,8 +654,12 @@ impl Doc for SegmentDoc {
         &self.score
     }
 
-    fn id(&self) -> &DocId {
-        &self.id
+    fn tiebreak(&self) -> Tiebreak {
+        Tiebreak {
+            shard: None,
+            segment: self.segment,
+            doc_id: self.id,
+        }
     }
 
     fn hashes(&self) -> Hashes {
@@ -364,6 +667,26 @@ impl Doc for SegmentDoc {
     }
 }
 
+impl Doc for WebsitePointer {
+    fn score(&self) -> &f64 {
+        &self.score
+    }
+
+    fn tiebreak(&self) -> Tiebreak {
+        self.address.into()
+    }
+
+    fn hashes(&self) -> Hashes {
+        self.hashes
+    }
+}
+
+impl SiteDoc for WebsitePointer {
+    fn more_from_site(&mut self) -> &mut usize {
+        &mut self.more_from_site
+    }
+}
+
 pub(crate) struct TweakedScoreTopCollector<TScoreTweaker> {
     score_tweaker: TScoreTweaker,
     collector: TopDocs,
@@ -385,7 +708,7 @@ impl<TScoreTweaker> Collector for TweakedScoreTopCollector<TScoreTweaker>
 where
     TScoreTweaker: ScoreTweaker<f64> + Send + Sync,
 {
-    type Fruit = Vec<WebsitePointer>;
+    type Fruit = Page;
 
     type Child = TopTweakedScoreSegmentCollector<TScoreTweaker::Child>;
 
@@ -476,6 +799,7 @@ mod tests {
                         site: 1.into(),
                         title: 1.into(),
                         url: 1.into(),
+                        simhash: 0,
                     },
                     123,
                     1.0,
@@ -485,6 +809,7 @@ mod tests {
                         site: 2.into(),
                         title: 2.into(),
                         url: 2.into(),
+                        simhash: 0,
                     },
                     124,
                     2.0,
@@ -494,6 +819,7 @@ mod tests {
                         site: 3.into(),
                         title: 3.into(),
                         url: 3.into(),
+                        simhash: 0,
                     },
                     125,
                     3.0,
@@ -503,6 +829,7 @@ mod tests {
                         site: 4.into(),
                         title: 4.into(),
                         url: 4.into(),
+                        simhash: 0,
                     },
                     126,
                     4.0,
@@ -512,6 +839,7 @@ mod tests {
                         site: 5.into(),
                         title: 5.into(),
                         url: 5.into(),
+                        simhash: 0,
                     },
                     127,
                     5.0,
@@ -531,6 +859,7 @@ mod tests {
                         site: 3.into(),
                         title: 3.into(),
                         url: 3.into(),
+                        simhash: 0,
                     },
                     125,
                     3.0,
@@ -540,6 +869,7 @@ mod tests {
                         site: 4.into(),
                         title: 4.into(),
                         url: 4.into(),
+                        simhash: 0,
                     },
                     126,
                     4.0,
@@ -549,6 +879,7 @@ mod tests {
                         site: 5.into(),
                         title: 5.into(),
                         url: 5.into(),
+                        simhash: 0,
                     },
                     127,
                     5.0,
@@ -568,6 +899,7 @@ mod tests {
                         site: 1.into(),
                         title: 1.into(),
                         url: 1.into(),
+                        simhash: 0,
                     },
                     125,
                     3.0,
@@ -577,6 +909,7 @@ mod tests {
                         site: 2.into(),
                         title: 2.into(),
                         url: 2.into(),
+                        simhash: 0,
                     },
                     126,
                     3.1,
@@ -586,6 +919,7 @@ mod tests {
                         site: 2.into(),
                         title: 2.into(),
                         url: 2.into(),
+                        simhash: 0,
                     },
                     127,
                     5.0,
@@ -602,6 +936,7 @@ mod tests {
                         site: 1.into(),
                         title: 1.into(),
                         url: 1.into(),
+                        simhash: 0,
                     },
                     125,
                     3.0,
@@ -611,6 +946,7 @@ mod tests {
                         site: 2.into(),
                         title: 2.into(),
                         url: 2.into(),
+                        simhash: 0,
                     },
                     126,
                     3.1,
@@ -620,6 +956,7 @@ mod tests {
                         site: 2.into(),
                         title: 2.into(),
                         url: 2.into(),
+                        simhash: 0,
                     },
                     127,
                     5.0,
@@ -628,4 +965,96 @@ mod tests {
             &[(5.0, 127), (3.0, 125)],
         );
     }
+
+    #[test]
+    fn near_duplicates_collapsed() {
+        test(
+            10,
+            &[
+                (
+                    Hashes {
+                        site: 1.into(),
+                        title: 1.into(),
+                        url: 1.into(),
+                        simhash: 0b1011_0110,
+                    },
+                    125,
+                    5.0,
+                ),
+                (
+                    Hashes {
+                        site: 2.into(),
+                        title: 2.into(),
+                        url: 2.into(),
+                        simhash: 0b1011_0111,
+                    },
+                    126,
+                    4.0,
+                ),
+                (
+                    Hashes {
+                        site: 3.into(),
+                        title: 3.into(),
+                        url: 3.into(),
+                        simhash: u64::MAX,
+                    },
+                    127,
+                    3.0,
+                ),
+            ],
+            &[(5.0, 125), (3.0, 127)],
+        );
+    }
+
+    #[test]
+    fn ties_ranked_by_address() {
+        let hashes = |i: u64| Hashes {
+            site: i.into(),
+            title: i.into(),
+            url: i.into(),
+            simhash: 0,
+        };
+
+        let docs = [
+            (hashes(1), 3, 1.0),
+            (hashes(2), 1, 1.0),
+            (hashes(3), 2, 1.0),
+        ];
+
+        test(10, &docs, &[(1.0, 1), (1.0, 2), (1.0, 3)]);
+        test(2, &docs, &[(1.0, 1), (1.0, 2)]);
+    }
+
+    #[test]
+    fn site_cap() {
+        let pointer = |site: u64, doc_id: DocId| WebsitePointer {
+            score: 1.0,
+            hashes: Hashes {
+                site: site.into(),
+                title: (doc_id as u64).into(),
+                url: (doc_id as u64).into(),
+                simhash: 0,
+            },
+            address: DocAddress { segment: 0, doc_id },
+            more_from_site: 0,
+        };
+
+        let docs = vec![
+            pointer(1, 0),
+            pointer(1, 1),
+            pointer(1, 2),
+            pointer(2, 3),
+            pointer(1, 4),
+            pointer(3, 5),
+            pointer(1, 6),
+            pointer(1, 7),
+        ];
+
+        let res: Vec<(DocId, usize)> = cap_per_site(docs, 2)
+            .into_iter()
+            .map(|pointer| (pointer.address.doc_id, pointer.more_from_site))
+            .collect();
+
+        assert_eq!(res, vec![(0, 4), (1, 0), (3, 0), (5, 0)]);
+    }
 }
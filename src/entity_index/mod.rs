@@ -269,17 +269,27 @@ impl EntityIndex {
         let title = self.schema.get_field("title").unwrap();
         let entity_abstract = self.schema.get_field("abstract").unwrap();
 
-        let mut term_queries = Vec::new();
+        let mut terms: Vec<(usize, String)> = Vec::new();
         let mut stream = Normal::default().token_stream(query);
         while let Some(token) = stream.next() {
-            if self.stopwords.contains(&token.text) {
+            // a character in Chinese, Japanese, Korean or Thai text is followed by
+            // the bigram it starts at the same position, and the bigram is kept
+            match terms.last_mut() {
+                Some((position, text)) if *position == token.position => *text = token.text.clone(),
+                _ => terms.push((token.position, token.text.clone())),
+            }
+        }
+
+        let mut term_queries = Vec::new();
+        for (_, text) in terms {
+            if self.stopwords.contains(&text) {
                 continue;
             }
 
             term_queries.push((
                 Occur::Must,
                 TermQuery::new(
-                    Term::from_field_text(title, &token.text),
+                    Term::from_field_text(title, &text),
                     IndexRecordOption::WithFreqsAndPositions,
                 )
                 .box_clone(),
@@ -288,7 +298,7 @@ impl EntityIndex {
             term_queries.push((
                 Occur::Should,
                 TermQuery::new(
-                    Term::from_field_text(entity_abstract, &token.text),
+                    Term::from_field_text(entity_abstract, &text),
                     IndexRecordOption::WithFreqsAndPositions,
                 )
                 .box_clone(),
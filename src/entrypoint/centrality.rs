@@ -14,30 +14,186 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::{collections::HashMap, fs::File, path::Path};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufRead},
+    net::SocketAddr,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
 
 use crate::{
+    hyperloglog,
+    mapreduce::{Manager, Map, Reduce, Worker},
     ranking::centrality_store::CentralityStore,
-    webgraph::{Webgraph, WebgraphBuilder},
+    webgraph::{GraphKind, Node, Webgraph, WebgraphBuilder},
+    CentralityMasterConfig, Result,
 };
 
+#[derive(Debug, Clone, Copy)]
+pub enum CentralityAlgorithm {
+    /// Exact harmonic centrality. Runs a BFS from every node in the graph.
+    Exact,
+    /// Approximate harmonic centrality using HyperLogLog counters with `2^precision` registers.
+    HyperBall { precision: u8 },
+}
+
+impl CentralityAlgorithm {
+    /// Parse the HyperBall precision, which must be supported by the HyperLogLog counters.
+    pub fn parse_precision(precision: &str) -> std::result::Result<u8, String> {
+        let precision: u8 = precision.parse().map_err(|err| err.to_string())?;
+
+        if (hyperloglog::MIN_PRECISION..=hyperloglog::MAX_PRECISION).contains(&precision) {
+            Ok(precision)
+        } else {
+            Err(format!(
+                "precision must be between {} and {}",
+                hyperloglog::MIN_PRECISION,
+                hyperloglog::MAX_PRECISION
+            ))
+        }
+    }
+}
+
+/// Calculate the harmonic centrality for the nodes with ids in `start..end`.
+#[derive(Debug, Serialize, Deserialize)]
+struct Job {
+    kind: GraphKind,
+    start: u64,
+    end: u64,
+    output_path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CentralityChunk {
+    kind: GraphKind,
+    output_path: String,
+    centralities: Vec<(String, f64)>,
+}
+
+struct CentralityWorker {
+    graph: Webgraph,
+}
+
+impl CentralityWorker {
+    fn new<P: AsRef<Path>>(webgraph_path: P) -> Self {
+        Self {
+            graph: WebgraphBuilder::new(webgraph_path)
+                .with_host_graph()
+                .with_full_graph()
+                .read_only(true)
+                .open(),
+        }
+    }
+}
+
+impl Worker for CentralityWorker {}
+
+impl Map<CentralityWorker, CentralityChunk> for Job {
+    fn map(&self, worker: &CentralityWorker) -> CentralityChunk {
+        info!(
+            "calculating {:?} centrality for {}..{}",
+            self.kind, self.start, self.end
+        );
+
+        let centrality = match self.kind {
+            GraphKind::Host => worker
+                .graph
+                .partial_host_harmonic_centrality(self.start..self.end),
+            GraphKind::Full => worker
+                .graph
+                .partial_harmonic_centrality(self.start..self.end),
+        };
+
+        CentralityChunk {
+            kind: self.kind,
+            output_path: self.output_path.clone(),
+            centralities: centrality
+                .into_iter()
+                .map(|(node, centrality)| (node.name, centrality))
+                .collect(),
+        }
+    }
+}
+
+/// The centrality stores the results from the workers are merged into.
+/// Only the chunks are kept in memory, so the full result never needs to fit in RAM.
+struct CentralityStores {
+    host: CentralityStore,
+    full: CentralityStore,
+}
+
+impl CentralityStores {
+    fn open<P: AsRef<Path>>(output_path: P) -> Self {
+        Self {
+            host: CentralityStore::new(output_path.as_ref().join("host")),
+            full: CentralityStore::new(output_path.as_ref().join("full")),
+        }
+    }
+}
+
+impl From<CentralityChunk> for CentralityStores {
+    fn from(chunk: CentralityChunk) -> Self {
+        let stores = CentralityStores::open(&chunk.output_path);
+        stores.reduce(chunk)
+    }
+}
+
+impl Reduce<CentralityChunk> for CentralityStores {
+    fn reduce(mut self, chunk: CentralityChunk) -> Self {
+        let store = match chunk.kind {
+            GraphKind::Host => &mut self.host,
+            GraphKind::Full => &mut self.full,
+        };
+
+        store.append(chunk.centralities.into_iter());
+
+        self
+    }
+}
+
+impl Reduce<CentralityStores> for CentralityStores {
+    fn reduce(mut self, other: CentralityStores) -> Self {
+        self.host.append(other.host.iter());
+        self.full.append(other.full.iter());
+
+        self
+    }
+}
+
 pub struct Centrality {}
 
 impl Centrality {
-    fn host_centrality(graph: &Webgraph) -> HashMap<String, f64> {
-        graph
-            .host_harmonic_centrality()
+    fn into_names(centrality: HashMap<Node, f64>) -> HashMap<String, f64> {
+        centrality
             .into_iter()
             .map(|(node, centrality)| (node.name, centrality))
             .collect()
     }
 
-    fn full_centrality(graph: &Webgraph) -> HashMap<String, f64> {
-        graph
-            .harmonic_centrality()
-            .into_iter()
-            .map(|(node, centrality)| (node.name, centrality))
-            .collect()
+    fn host_centrality(graph: &Webgraph, algorithm: CentralityAlgorithm) -> HashMap<String, f64> {
+        let centrality = match algorithm {
+            CentralityAlgorithm::Exact => graph.host_harmonic_centrality(),
+            CentralityAlgorithm::HyperBall { precision } => {
+                graph.host_approximate_harmonic_centrality(precision)
+            }
+        };
+
+        Self::into_names(centrality)
+    }
+
+    fn full_centrality(graph: &Webgraph, algorithm: CentralityAlgorithm) -> HashMap<String, f64> {
+        let centrality = match algorithm {
+            CentralityAlgorithm::Exact => graph.harmonic_centrality(),
+            CentralityAlgorithm::HyperBall { precision } => {
+                graph.approximate_harmonic_centrality(precision)
+            }
+        };
+
+        Self::into_names(centrality)
     }
 
     fn save<P: AsRef<Path>>(centrality: HashMap<String, f64>, output_path: P) {
@@ -64,23 +220,160 @@ impl Centrality {
         wtr.flush().unwrap();
     }
 
-    fn host<P: AsRef<Path>>(graph: &Webgraph, output_path: P) {
-        let centrality = Self::host_centrality(graph);
+    fn host<P: AsRef<Path>>(graph: &Webgraph, output_path: P, algorithm: CentralityAlgorithm) {
+        let centrality = Self::host_centrality(graph, algorithm);
         Self::save(centrality, output_path);
     }
 
-    fn full<P: AsRef<Path>>(graph: &Webgraph, output_path: P) {
-        let centrality = Self::full_centrality(graph);
+    fn full<P: AsRef<Path>>(graph: &Webgraph, output_path: P, algorithm: CentralityAlgorithm) {
+        let centrality = Self::full_centrality(graph, algorithm);
         Self::save(centrality, output_path);
     }
 
-    pub fn run<P: AsRef<Path>>(webgraph_path: P, output_path: P) {
+    fn pagerank<P: AsRef<Path>>(graph: &Webgraph, output_path: P) {
+        info!("calculating host pagerank");
+        Self::save(
+            Self::into_names(graph.host_pagerank()),
+            output_path.as_ref().join("host_pagerank"),
+        );
+
+        info!("calculating full pagerank");
+        Self::save(
+            Self::into_names(graph.pagerank()),
+            output_path.as_ref().join("full_pagerank"),
+        );
+    }
+
+    /// Read seed hosts for trustrank (one host per line).
+    fn read_seeds<P: AsRef<Path>>(path: P) -> Vec<Node> {
+        let file = File::open(path).expect("Failed to open seed file");
+
+        io::BufReader::new(file)
+            .lines()
+            .map(|line| line.expect("Failed to read seed file"))
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty())
+            .map(Node::from)
+            .collect()
+    }
+
+    pub fn run<P: AsRef<Path>>(
+        webgraph_path: P,
+        output_path: P,
+        algorithm: CentralityAlgorithm,
+        trusted_hosts_path: Option<P>,
+        spam_hosts_path: Option<P>,
+    ) {
         let graph = WebgraphBuilder::new(webgraph_path)
             .with_host_graph()
             .with_full_graph()
             .open();
 
-        Self::host(&graph, output_path.as_ref().join("host"));
-        Self::full(&graph, output_path.as_ref().join("full"));
+        Self::host(&graph, output_path.as_ref().join("host"), algorithm);
+        Self::full(&graph, output_path.as_ref().join("full"), algorithm);
+
+        Self::pagerank(&graph, output_path.as_ref());
+
+        if let Some(path) = trusted_hosts_path {
+            info!("calculating host trustrank");
+            let trusted = Self::read_seeds(path);
+            Self::save(
+                Self::into_names(graph.host_trustrank(&trusted)),
+                output_path.as_ref().join("host_trustrank"),
+            );
+        }
+
+        if let Some(path) = spam_hosts_path {
+            info!("calculating host anti-trustrank");
+            let spam = Self::read_seeds(path);
+            Self::save(
+                Self::into_names(graph.host_antitrustrank(&spam)),
+                output_path.as_ref().join("host_antitrustrank"),
+            );
+        }
+    }
+
+    /// Split the exact harmonic centrality of the host and full graphs into jobs for the workers.
+    ///
+    /// Only exact harmonic centrality is distributed. Every worker opens the entire webgraph
+    /// and the jobs only split the BFS runs between them, so the total work is still a BFS
+    /// from every node and the graph must fit on each worker. HyperBall, PageRank and
+    /// TrustRank are computed by [`Centrality::run`] in a single process.
+    pub fn run_master(config: &CentralityMasterConfig) -> Result<()> {
+        let (num_host_nodes, num_full_nodes) = {
+            let graph = WebgraphBuilder::new(&config.webgraph_path)
+                .with_host_graph()
+                .with_full_graph()
+                .read_only(true)
+                .open();
+
+            (graph.host_num_nodes(), graph.num_nodes())
+        };
+
+        info!(
+            "Running master for exact harmonic centrality. {} host node ids, {} full node ids",
+            num_host_nodes, num_full_nodes
+        );
+
+        let batch_size = config.batch_size.unwrap_or(10_000) as u64;
+        let output_path = config.output_path.clone();
+
+        let jobs = [
+            (GraphKind::Host, num_host_nodes),
+            (GraphKind::Full, num_full_nodes),
+        ]
+        .into_iter()
+        .flat_map(move |(kind, num_nodes)| {
+            let output_path = output_path.clone();
+
+            (0..num_nodes)
+                .step_by(batch_size as usize)
+                .map(move |start| Job {
+                    kind,
+                    start,
+                    end: (start + batch_size).min(num_nodes),
+                    output_path: output_path.clone(),
+                })
+        })
+        .collect::<Vec<_>>();
+
+        let workers: Vec<SocketAddr> = config
+            .workers
+            .iter()
+            .map(|worker| worker.parse().unwrap())
+            .collect();
+
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let manager = Manager::new(&workers);
+                let _stores: Option<CentralityStores> = manager
+                    .run::<CentralityWorker, Job, CentralityChunk, CentralityStores>(
+                        jobs.into_iter(),
+                    )
+                    .await;
+            });
+
+        Ok(())
+    }
+
+    pub fn run_worker(worker_addr: String, webgraph_path: String) -> Result<()> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                CentralityWorker::new(webgraph_path)
+                    .run::<Job, CentralityChunk>(
+                        worker_addr
+                            .parse::<SocketAddr>()
+                            .expect("Could not parse worker address"),
+                    )
+                    .await
+                    .unwrap();
+            });
+        Ok(())
     }
 }
@@ -14,23 +14,27 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 use futures::StreamExt;
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use itertools::Itertools;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use tokio::pin;
 use tracing::{debug, info, trace};
 
 use crate::entrypoint::async_download_all_warc_files;
+use crate::feed::Feed;
 use crate::index::{FrozenIndex, Index};
 use crate::mapreduce::{Manager, Map, Reduce, Worker};
 use crate::ranking::centrality_store::CentralityStore;
 use crate::ranking::SignalAggregator;
-use crate::warc::WarcFile;
+use crate::warc::{WarcFile, WarcRecord};
 use crate::webgraph::{Node, Webgraph, WebgraphBuilder};
-use crate::webpage::{Html, Link, Webpage};
+use crate::webpage::{
+    default_tracking_parameters, Canonicalizer, Document, Extractor, FileType, Html, Link,
+    RobotsMeta, Url, Webpage,
+};
 use crate::{
     HttpConfig, IndexingLocalConfig, IndexingMasterConfig, LocalConfig, Result, WarcSource,
 };
@@ -50,22 +54,31 @@ struct Job {
     warc_paths: Vec<String>,
     base_path: String,
     host_centrality_threshold: Option<f64>,
+    content_extractor: Extractor,
+    tracking_parameters: Vec<String>,
 }
 
 struct IndexingWorker {
     host_centrality_store: CentralityStore,
     page_centrality_store: CentralityStore,
+    host_pagerank_store: CentralityStore,
+    page_pagerank_store: CentralityStore,
+    host_trustrank_store: CentralityStore,
+    host_antitrustrank_store: CentralityStore,
     webgraph: Option<Webgraph>,
 }
 
 impl IndexingWorker {
     fn new(centrality_store_path: String, webgraph_path: Option<String>) -> Self {
-        let host_centrality_path = Path::new(&centrality_store_path).join("host");
-        let page_centrality_path = Path::new(&centrality_store_path).join("full");
+        let path = Path::new(&centrality_store_path);
 
         Self {
-            host_centrality_store: CentralityStore::new(host_centrality_path),
-            page_centrality_store: CentralityStore::new(page_centrality_path),
+            host_centrality_store: CentralityStore::new(path.join("host")),
+            page_centrality_store: CentralityStore::new(path.join("full")),
+            host_pagerank_store: CentralityStore::new(path.join("host_pagerank")),
+            page_pagerank_store: CentralityStore::new(path.join("full_pagerank")),
+            host_trustrank_store: CentralityStore::new(path.join("host_trustrank")),
+            host_antitrustrank_store: CentralityStore::new(path.join("host_antitrustrank")),
             webgraph: webgraph_path.map(|path| {
                 WebgraphBuilder::new(path)
                     .with_full_graph()
@@ -77,7 +90,204 @@ impl IndexingWorker {
     }
 }
 
-async fn async_process_job(job: &Job, worker: &IndexingWorker) -> Index {
+/// Pages in a job that declare another page on the same domain as their canonical version.
+/// Canonical urls on other domains are ignored, so a page can not be hidden by another site.
+#[derive(Default)]
+struct Canonicals {
+    /// Urls of the pages in the job that are their own canonical version.
+    canonical_urls: HashSet<String>,
+    /// Urls of the duplicates of each canonical url, in the order they appear in the job.
+    duplicates: HashMap<String, Vec<Url>>,
+}
+
+impl Canonicals {
+    fn canonical(html: &Html) -> Option<Url> {
+        html.canonical_url().filter(|canonical| {
+            canonical.full() != html.url().full() && canonical.domain() == html.url().domain()
+        })
+    }
+
+    fn insert(&mut self, html: &Html) {
+        match Canonicals::canonical(html) {
+            Some(canonical) => self
+                .duplicates
+                .entry(canonical.full())
+                .or_default()
+                .push(html.url().clone()),
+            None => {
+                self.canonical_urls.insert(html.url().full());
+            }
+        }
+    }
+
+    fn contains(&self, url: &Url) -> bool {
+        self.canonical_urls.contains(&url.full())
+    }
+
+    /// Whether a page in the job is indexed under `url`.
+    fn is_indexed(&self, url: &Url) -> bool {
+        self.contains(url) || self.duplicates.contains_key(&url.full())
+    }
+
+    fn duplicates(&self, url: &Url) -> &[Url] {
+        self.duplicates
+            .get(&url.full())
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+}
+
+fn is_noindex(html: &Html, record: &WarcRecord) -> bool {
+    // a user agent in a header only applies to the directives in that header
+    record
+        .response
+        .headers
+        .get("x-robots-tag")
+        .into_iter()
+        .flatten()
+        .map(|directives| RobotsMeta::parse(directives))
+        .fold(html.robots(), RobotsMeta::merge)
+        .noindex
+}
+
+fn is_feed(record: &WarcRecord) -> bool {
+    Feed::is_feed(
+        record.response.payload_type.as_deref(),
+        &record.response.body,
+    )
+}
+
+fn warc_path(job: &Job, file: &str) -> PathBuf {
+    let name = file.split('/').last().unwrap();
+    Path::new(&job.base_path).join("warc_files").join(name)
+}
+
+/// Find the duplicates in the job before anything is indexed, so the canonical page
+/// gets the backlinks of all its duplicates no matter where they are in the job.
+fn find_canonicals(job: &Job, warc_files: &[String], canonicalizer: &Canonicalizer) -> Canonicals {
+    let mut canonicals = Canonicals::default();
+
+    for file in warc_files {
+        if let Ok(file) = WarcFile::open(warc_path(job, file)) {
+            for record in file.records().flatten() {
+                if is_feed(&record)
+                    || FileType::detect(
+                        record.response.payload_type.as_deref(),
+                        &record.request.url.clone().into(),
+                    ) != FileType::Html
+                {
+                    continue;
+                }
+
+                let html = Html::parse_without_text(
+                    &record.response.body,
+                    &record.request.url,
+                    canonicalizer,
+                );
+
+                if !is_noindex(&html, &record) {
+                    canonicals.insert(&html);
+                }
+            }
+        }
+    }
+
+    canonicals
+}
+
+/// Insert the page into the index. The backlinks of `duplicates` are merged into
+/// the backlinks of the page.
+fn insert_page(
+    worker: &IndexingWorker,
+    job: &Job,
+    index: &mut Index,
+    signal_aggregator: &SignalAggregator,
+    mut html: Html,
+    duplicates: &[Url],
+    fetch_time_ms: u64,
+) {
+    let host_centrality = worker
+        .host_centrality_store
+        .get(html.url().host_without_specific_subdomains())
+        .unwrap_or_default();
+
+    if let Some(host_centrality_threshold) = job.host_centrality_threshold {
+        if host_centrality < host_centrality_threshold {
+            return;
+        }
+    }
+
+    html.set_extractor(job.content_extractor);
+    html.parse_text();
+
+    let backlinks: Vec<Link> = worker
+        .webgraph
+        .as_ref()
+        .map(|webgraph| {
+            std::iter::once(html.url())
+                .chain(duplicates)
+                .flat_map(|url| webgraph.ingoing_edges(Node::from(url)))
+                .unique_by(|edge| (edge.from.clone(), edge.label.clone()))
+                .map(|edge| Link {
+                    source: edge.from.name.into(),
+                    destination: edge.to.name.into(),
+                    text: edge.label,
+                    rel: edge.properties.rel,
+                    context: edge.properties.context,
+                })
+                .collect()
+        })
+        .unwrap_or_else(Vec::new);
+
+    let page_centrality = worker
+        .page_centrality_store
+        .get(html.url().raw())
+        .unwrap_or_default();
+
+    let host = html.url().host_without_specific_subdomains();
+
+    let host_pagerank = worker.host_pagerank_store.get(host).unwrap_or_default();
+    let page_pagerank = worker
+        .page_pagerank_store
+        .get(html.url().raw())
+        .unwrap_or_default();
+    let host_trustrank = worker.host_trustrank_store.get(host).unwrap_or_default();
+    let host_antitrustrank = worker
+        .host_antitrustrank_store
+        .get(host)
+        .unwrap_or_default();
+
+    trace!("inserting webpage: {:?}", html.url());
+
+    trace!("title = {:?}", html.title());
+    trace!("text = {:?}", html.clean_text());
+
+    let mut webpage = Webpage {
+        html,
+        backlinks,
+        page_centrality,
+        host_centrality,
+        host_pagerank,
+        page_pagerank,
+        host_trustrank,
+        host_antitrustrank,
+        fetch_time_ms,
+        primary_image: None,
+        pre_computed_score: 0.0,
+    };
+
+    webpage.pre_computed_score = signal_aggregator.precompute_score(&webpage, &index.region_count);
+
+    if let Err(err) = index.insert(webpage) {
+        debug!("{:?}", err);
+    }
+}
+
+async fn async_process_job(
+    job: &Job,
+    worker: &IndexingWorker,
+    canonicalizer: &Canonicalizer,
+) -> Index {
     let name = job.warc_paths.first().unwrap().split('/').last().unwrap();
 
     info!("processing {}", name);
@@ -89,84 +299,126 @@ async fn async_process_job(job: &Job, worker: &IndexingWorker) -> Index {
         JobConfig::Local(config) => WarcSource::Local(config),
     };
 
-    let warc_files = async_download_all_warc_files(&job.warc_paths, &source, &job.base_path).await;
-    pin!(warc_files);
+    let warc_files: Vec<String> =
+        async_download_all_warc_files(&job.warc_paths, &source, &job.base_path)
+            .await
+            .collect()
+            .await;
 
+    let canonicals = find_canonicals(job, &warc_files, canonicalizer);
     let signal_aggregator = SignalAggregator::default();
 
-    while let Some(file) = warc_files.next().await {
-        let name = file.split('/').last().unwrap();
-        let path = Path::new(&job.base_path).join("warc_files").join(name);
-
-        if let Ok(file) = WarcFile::open(path) {
-            for record in
-                file.records()
-                    .flatten()
-                    .filter(|record| match &record.response.payload_type {
-                        Some(payload_type) => !matches!(payload_type.as_str(), "application/pdf"),
-                        None => true,
-                    })
-            {
-                let mut html = Html::parse_without_text(&record.response.body, &record.request.url);
-
-                let host_centrality = worker
-                    .host_centrality_store
-                    .get(html.url().host_without_specific_subdomains())
-                    .unwrap_or_default();
-
-                if let Some(host_centrality_threshold) = job.host_centrality_threshold {
-                    if host_centrality < host_centrality_threshold {
-                        continue;
-                    }
-                }
+    let mut indexed_urls = HashSet::new();
 
-                html.parse_text();
-
-                let backlinks: Vec<Link> = worker
-                    .webgraph
-                    .as_ref()
-                    .map(|webgraph| {
-                        webgraph
-                            .ingoing_edges(Node::from(html.url()))
-                            .into_iter()
-                            .map(|edge| Link {
-                                source: edge.from.name.into(),
-                                destination: edge.to.name.into(),
-                                text: edge.label,
-                            })
-                            .collect()
-                    })
-                    .unwrap_or_else(Vec::new);
-
-                let page_centrality = worker
-                    .page_centrality_store
-                    .get(html.url().raw())
-                    .unwrap_or_default();
+    for file in warc_files {
+        if let Ok(warc) = WarcFile::open(warc_path(job, &file)) {
+            let mut feed_entries = Vec::new();
 
+            for record in warc.records().flatten() {
                 let fetch_time_ms = record.metadata.fetch_time_ms as u64;
 
-                trace!("inserting webpage: {:?}", html.url());
+                if is_feed(&record) {
+                    match Feed::parse(&record.response.body, &record.request.url.clone().into()) {
+                        Ok(feed) => feed_entries
+                            .extend(feed.entries.into_iter().map(|entry| (entry, fetch_time_ms))),
+                        Err(err) => debug!("{:?}", err),
+                    }
 
-                trace!("title = {:?}", html.title());
-                trace!("text = {:?}", html.clean_text());
+                    continue;
+                }
 
-                let mut webpage = Webpage {
-                    html,
-                    backlinks,
-                    page_centrality,
-                    host_centrality,
-                    fetch_time_ms,
-                    primary_image: None,
-                    pre_computed_score: 0.0,
+                let pages = match FileType::detect(
+                    record.response.payload_type.as_deref(),
+                    &record.request.url.clone().into(),
+                ) {
+                    FileType::Html => vec![Html::parse_without_text(
+                        &record.response.body,
+                        &record.request.url,
+                        canonicalizer,
+                    )],
+                    filetype => match Document::parse(
+                        filetype,
+                        record.response.raw_body.as_deref().unwrap_or_default(),
+                        &record.response.body,
+                    ) {
+                        Ok(document) => {
+                            vec![document.into_html(&record.request.url, canonicalizer)]
+                        }
+                        Err(err) => {
+                            debug!("{:?}", err);
+                            Vec::new()
+                        }
+                    },
                 };
 
-                webpage.pre_computed_score =
-                    signal_aggregator.precompute_score(&webpage, &index.region_count);
+                for mut html in pages {
+                    if is_noindex(&html, &record) {
+                        trace!("skipping noindex page: {:?}", html.url());
+                        continue;
+                    }
+
+                    // duplicates are indexed as one document under their canonical url
+                    let duplicates = match Canonicals::canonical(&html) {
+                        None => canonicals.duplicates(html.url()).to_vec(),
+                        Some(canonical) => {
+                            if canonicals.contains(&canonical) {
+                                trace!("folding duplicate into its canonical: {:?}", html.url());
+                                continue;
+                            }
+
+                            // the canonical page is not in the job, so its first duplicate
+                            // is indexed in its place
+                            let duplicates = canonicals.duplicates(&canonical);
+                            if duplicates.first().map(Url::full) != Some(html.url().full()) {
+                                trace!("folding duplicate into its canonical: {:?}", html.url());
+                                continue;
+                            }
+
+                            let duplicates = duplicates.to_vec();
+                            html.set_url(canonical);
+                            duplicates
+                        }
+                    };
+
+                    indexed_urls.insert(html.url().full());
+
+                    insert_page(
+                        worker,
+                        job,
+                        &mut index,
+                        &signal_aggregator,
+                        html,
+                        &duplicates,
+                        fetch_time_ms,
+                    );
+                }
+            }
+
+            // feed entries are only indexed if the article itself is not indexed from the
+            // crawl, so they never duplicate the full page. The robots headers of a feed
+            // response apply to the feed and not to its entries.
+            for (entry, fetch_time_ms) in feed_entries {
+                let html =
+                    Html::parse_without_text(&entry.to_html(), entry.url.raw(), canonicalizer);
 
-                if let Err(err) = index.insert(webpage) {
-                    debug!("{:?}", err);
+                if canonicals.is_indexed(html.url()) || indexed_urls.contains(&html.url().full()) {
+                    trace!("skipping feed entry of indexed page: {:?}", html.url());
+                    continue;
                 }
+
+                indexed_urls.insert(html.url().full());
+
+                insert_page(
+                    worker,
+                    job,
+                    &mut index,
+                    &signal_aggregator,
+                    html,
+                    &[],
+                    fetch_time_ms,
+                );
             }
+
             if job.download_images {
                 info!("downloading images");
                 index.download_pending_images();
@@ -184,11 +436,13 @@ async fn async_process_job(job: &Job, worker: &IndexingWorker) -> Index {
 }
 
 fn process_job(job: &Job, worker: &IndexingWorker) -> Index {
+    let canonicalizer = Canonicalizer::new(job.tracking_parameters.clone());
+
     tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()
         .unwrap()
-        .block_on(async { async_process_job(job, worker).await })
+        .block_on(async { async_process_job(job, worker, &canonicalizer).await })
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -283,6 +537,11 @@ impl Indexer {
                             warc_paths: warc_paths.collect_vec(),
                             download_images: config.download_images.unwrap_or(true),
                             host_centrality_threshold: config.host_centrality_threshold,
+                            content_extractor: config.content_extractor.unwrap_or_default(),
+                            tracking_parameters: config
+                                .tracking_parameters
+                                .clone()
+                                .unwrap_or_else(default_tracking_parameters),
                             base_path: config
                                 .index_base_path
                                 .clone()
@@ -356,6 +615,11 @@ impl Indexer {
                 warc_paths: warc_paths.collect_vec(),
                 download_images: config.download_images.unwrap_or(true),
                 host_centrality_threshold: config.host_centrality_threshold,
+                content_extractor: config.content_extractor.unwrap_or_default(),
+                tracking_parameters: config
+                    .tracking_parameters
+                    .clone()
+                    .unwrap_or_else(default_tracking_parameters),
                 base_path: config
                     .output_path
                     .clone()
@@ -391,4 +655,84 @@ impl Indexer {
 
         Ok(())
     }
+
+    /// Print clusters of near-duplicate pages in the index, largest clusters first.
+    pub fn duplicate_clusters(index_path: String, limit: usize) -> Result<()> {
+        let index = Index::open(index_path)?;
+        let clusters = index.inverted_index.near_duplicate_clusters()?;
+        let num_clusters = clusters.len();
+
+        for cluster in clusters
+            .into_iter()
+            .sorted_by_key(|cluster| std::cmp::Reverse(cluster.len()))
+            .take(limit)
+        {
+            println!("{} pages", cluster.len());
+
+            for page in cluster {
+                println!("\t{}\t{}", page.url, page.title);
+            }
+
+            println!();
+        }
+
+        if num_clusters > limit {
+            println!("... and {} more clusters", num_clusters - limit);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(url: &str, canonical: &str) -> Html {
+        Html::parse_without_text(
+            &format!(r#"<html><head><link rel="canonical" href="{canonical}"></head></html>"#),
+            url,
+            &Canonicalizer::default(),
+        )
+    }
+
+    #[test]
+    fn duplicates_folded_into_canonical() {
+        let pages = [
+            page("https://a.com/article?ref=1", "https://a.com/article"),
+            page("https://a.com/article", "https://a.com/article"),
+            page("https://a.com/article?ref=2", "https://a.com/article"),
+            page("https://a.com/print", "https://a.com/missing"),
+            page("https://b.com/page", "https://a.com/article"),
+        ];
+
+        let mut canonicals = Canonicals::default();
+        for page in &pages {
+            canonicals.insert(page);
+        }
+
+        let canonical: Url = "https://a.com/article".to_string().into();
+        assert!(canonicals.contains(&canonical));
+        assert_eq!(
+            canonicals
+                .duplicates(&canonical)
+                .iter()
+                .map(Url::full)
+                .collect::<Vec<_>>(),
+            vec!["https://a.com/article?ref=1", "https://a.com/article?ref=2"]
+        );
+
+        // the canonical of the print page is not in the job
+        let missing: Url = "https://a.com/missing".to_string().into();
+        assert!(!canonicals.contains(&missing));
+        assert_eq!(canonicals.duplicates(&missing).len(), 1);
+
+        // so a feed entry for it is skipped, as the print page is indexed in its place
+        assert!(canonicals.is_indexed(&missing));
+        assert!(!canonicals.is_indexed(&"https://a.com/other".to_string().into()));
+
+        // canonical urls on other domains are ignored
+        assert!(Canonicals::canonical(&pages[4]).is_none());
+        assert!(canonicals.contains(&"https://b.com/page".to_string().into()));
+    }
 }
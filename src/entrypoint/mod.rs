@@ -22,13 +22,15 @@ mod webgraph;
 
 use std::{fs::File, path::Path};
 
-pub use centrality::Centrality;
+pub use centrality::{Centrality, CentralityAlgorithm};
 pub use entity::EntityIndexer;
 use futures::{Stream, StreamExt};
 pub use indexer::Indexer;
 use tracing::debug;
 pub use webgraph::Webgraph;
 
+pub use crate::webgraph::{ExportFormat, GraphKind};
+
 use crate::{warc::WarcFile, WarcSource};
 
 async fn async_download_all_warc_files<'a>(
@@ -23,22 +23,30 @@ use crate::{
     inverted_index,
     search_prettifier::{self},
     searcher::{self, LocalSearcher},
-    sonic, Result, SearchServerConfig,
+    sonic,
+    synonyms::Synonyms,
+    Result, SearchServerConfig,
 };
 
 pub async fn run(config: SearchServerConfig) -> Result<()> {
     let addr: SocketAddr = config.host.parse().unwrap();
     let server = sonic::Server::bind(addr).await.unwrap();
-    tracing::info!("listening on {}", addr);
+    tracing::info!("listening on {}", server.local_addr().unwrap());
 
     let entity_index = config
         .entity_index_path
         .map(|path| EntityIndex::open(path).unwrap());
     let bangs = config.bangs_path.map(Bangs::from_path);
+    let synonyms = config.synonyms_path.map(Synonyms::from_path).transpose()?;
     let search_index = Index::open(config.index_path)?;
 
-    let local_searcher = LocalSearcher::new(search_index, entity_index, bangs);
+    let local_searcher = LocalSearcher::new(search_index, entity_index, bangs, synonyms);
 
+    serve(server, local_searcher).await
+}
+
+/// Answer the requests of a [`searcher::DistributedSearcher`] with the results of the local index.
+pub(crate) async fn serve(server: sonic::Server, local_searcher: LocalSearcher) -> Result<()> {
     loop {
         if let Ok(req) = server.accept::<searcher::distributed::Request>().await {
             match &req.body {
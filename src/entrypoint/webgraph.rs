@@ -17,10 +17,17 @@ use crate::{
     directory::DirEntry,
     entrypoint::async_download_all_warc_files,
     mapreduce::{Manager, Map, Reduce, StatelessWorker, Worker},
-    warc::WarcFile,
-    webgraph::{self, FrozenWebgraph, Node, WebgraphBuilder},
-    webpage::Html,
-    HttpConfig, LocalConfig, Result, WarcSource, WebgraphLocalConfig, WebgraphMasterConfig,
+    warc::{WarcFile, WarcRecord},
+    webgraph::{
+        self, EdgeProperties, ExportFormat, FrozenWebgraph, FullEdge, GraphKind, Node,
+        WebgraphBuilder,
+    },
+    webpage::{
+        default_tracking_parameters, Canonicalizer, Html, Link, LinkContext, LinkRel, RobotsMeta,
+        Url,
+    },
+    CanonicalizerConfig, HttpConfig, LocalConfig, Result, WarcSource, WebgraphLocalConfig,
+    WebgraphMasterConfig,
 };
 use futures::StreamExt;
 use itertools::Itertools;
@@ -44,6 +51,7 @@ struct Job {
     config: JobConfig,
     warc_paths: Vec<String>,
     graph_base_path: String,
+    tracking_parameters: Vec<String>,
 }
 
 fn open_graph<P: AsRef<Path>>(path: P) -> webgraph::Webgraph {
@@ -53,7 +61,68 @@ fn open_graph<P: AsRef<Path>>(path: P) -> webgraph::Webgraph {
         .open()
 }
 
-async fn async_process_job(job: &Job) -> webgraph::Webgraph {
+/// Insert the links of the page in `record`, replacing the links from older crawls of the page.
+fn insert_record(
+    graph: &mut webgraph::Webgraph,
+    record: &WarcRecord,
+    canonicalizer: &Canonicalizer,
+) {
+    let webpage =
+        Html::parse_without_text(&record.response.body, &record.request.url, canonicalizer);
+    // a user agent in a header only applies to the directives in that header
+    let robots = record
+        .response
+        .headers
+        .get("x-robots-tag")
+        .into_iter()
+        .flatten()
+        .map(|directives| RobotsMeta::parse(directives))
+        .fold(webpage.robots(), RobotsMeta::merge);
+
+    // advertised feeds are linked so they can be discovered, but the page does not
+    // vouch for them, so they don't count towards centrality or backlink text
+    let feeds = webpage.feeds().into_iter().map(|feed| Link {
+        source: webpage.url().clone(),
+        destination: feed.url,
+        text: feed.title.unwrap_or_default(),
+        rel: LinkRel {
+            nofollow: true,
+            ..LinkRel::default()
+        },
+        context: LinkContext::default(),
+    });
+
+    let links: Vec<_> = webpage
+        .links()
+        .into_iter()
+        .chain(feeds)
+        .filter(|link| matches!(link.destination.protocol(), "http" | "https"))
+        .filter(|link| link.source.domain() != link.destination.domain())
+        .map(|link| {
+            trace!("inserting link {:?}", link);
+            (
+                Node::from(canonicalizer.canonicalize(&link.destination)),
+                link.text,
+                EdgeProperties {
+                    rel: LinkRel {
+                        nofollow: link.rel.nofollow || robots.nofollow,
+                        ..link.rel
+                    },
+                    context: link.context,
+                },
+            )
+        })
+        .collect();
+
+    // the crawl date orders the versions of a page, so a re-crawl replaces its links
+    graph.replace_outgoing(
+        Node::from(webpage.url()),
+        links,
+        record.response.date_ms.unwrap_or_default(),
+    );
+}
+
+async fn async_process_job(job: &Job, canonicalizer: &Canonicalizer) -> webgraph::Webgraph {
     let name = job.warc_paths.first().unwrap().split('/').last().unwrap();
 
     info!("processing {}", name);
@@ -77,20 +146,7 @@ async fn async_process_job(job: &Job) -> webgraph::Webgraph {
 
         if let Ok(file) = WarcFile::open(path) {
             for record in file.records().flatten() {
-                let webpage = Html::parse_without_text(&record.response.body, &record.request.url);
-                for link in webpage
-                    .links()
-                    .into_iter()
-                    .filter(|link| matches!(link.destination.protocol(), "http" | "https"))
-                    .filter(|link| link.source.domain() != link.destination.domain())
-                {
-                    trace!("inserting link {:?}", link);
-                    graph.insert(
-                        Node::from(link.source),
-                        Node::from(link.destination),
-                        link.text,
-                    );
-                }
+                insert_record(&mut graph, &record, canonicalizer);
             }
         }
 
@@ -105,11 +161,13 @@ async fn async_process_job(job: &Job) -> webgraph::Webgraph {
 }
 
 fn process_job(job: &Job) -> webgraph::Webgraph {
+    let canonicalizer = Canonicalizer::new(job.tracking_parameters.clone());
+
     tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()
         .unwrap()
-        .block_on(async { async_process_job(job).await })
+        .block_on(async { async_process_job(job, &canonicalizer).await })
 }
 
 impl Map<StatelessWorker, FrozenWebgraph> for Job {
@@ -209,6 +267,10 @@ impl Webgraph {
                                 .graph_base_path
                                 .clone()
                                 .unwrap_or_else(|| "data/webgraph".to_string()),
+                            tracking_parameters: config
+                                .tracking_parameters
+                                .clone()
+                                .unwrap_or_else(default_tracking_parameters),
                         })
                         .collect::<Vec<_>>()
                         .into_iter(),
@@ -269,6 +331,10 @@ impl Webgraph {
                     .graph_base_path
                     .clone()
                     .unwrap_or_else(|| "data/webgraph".to_string()),
+                tracking_parameters: config
+                    .tracking_parameters
+                    .clone()
+                    .unwrap_or_else(default_tracking_parameters),
             })
             .collect_vec()
             .into_par_iter()
@@ -285,4 +351,244 @@ impl Webgraph {
 
         Ok(())
     }
+
+    fn open_read_only<P: AsRef<Path>>(path: P) -> webgraph::Webgraph {
+        WebgraphBuilder::new(path)
+            .with_host_graph()
+            .with_full_graph()
+            .read_only(true)
+            .open()
+    }
+
+    fn print_edges(edges: Vec<FullEdge>, limit: usize) {
+        let num_edges = edges.len();
+
+        for edge in edges.into_iter().take(limit) {
+            println!(
+                "{} -> {}\t{}",
+                edge.from.name,
+                edge.to.name,
+                edge.label.replace('\n', " ")
+            );
+        }
+
+        if num_edges > limit {
+            println!("... and {} more", num_edges - limit);
+        }
+    }
+
+    /// Url nodes are canonicalised the same way as when the graph was built, so urls can
+    /// be looked up the way they are found on the web.
+    fn url_node(node: String, config: &CanonicalizerConfig) -> Node {
+        let canonicalizer = Canonicalizer::new(
+            config
+                .tracking_parameters
+                .clone()
+                .unwrap_or_else(default_tracking_parameters),
+        );
+
+        Node::from(canonicalizer.canonicalize(&Url::from(node)))
+    }
+
+    pub fn inspect_ingoing(
+        webgraph_path: String,
+        node: String,
+        kind: GraphKind,
+        limit: usize,
+        config: &CanonicalizerConfig,
+    ) {
+        let graph = Self::open_read_only(webgraph_path);
+
+        let edges = match kind {
+            GraphKind::Host => graph.host_ingoing_edges(Node::from(node)),
+            GraphKind::Full => graph.ingoing_edges(Self::url_node(node, config)),
+        };
+
+        Self::print_edges(edges, limit);
+    }
+
+    pub fn inspect_outgoing(
+        webgraph_path: String,
+        node: String,
+        kind: GraphKind,
+        limit: usize,
+        config: &CanonicalizerConfig,
+    ) {
+        let graph = Self::open_read_only(webgraph_path);
+
+        let edges = match kind {
+            GraphKind::Host => graph.host_outgoing_edges(Node::from(node)),
+            GraphKind::Full => graph.outgoing_edges(Self::url_node(node, config)),
+        };
+
+        Self::print_edges(edges, limit);
+    }
+
+    pub fn inspect_stats(webgraph_path: String, kind: GraphKind) {
+        let graph = Self::open_read_only(webgraph_path);
+
+        if let Some(stats) = graph.stats(kind) {
+            println!("nodes: {}", stats.num_nodes);
+            println!("edges: {}", stats.num_edges);
+
+            println!();
+            println!("in-degree\tnodes");
+            for (degree, count) in stats.in_degrees {
+                println!("{}\t{}", degree, count);
+            }
+
+            println!();
+            println!("out-degree\tnodes");
+            for (degree, count) in stats.out_degrees {
+                println!("{}\t{}", degree, count);
+            }
+        }
+    }
+
+    pub fn export(
+        webgraph_path: String,
+        output_path: String,
+        kind: GraphKind,
+        format: ExportFormat,
+    ) -> Result<()> {
+        let graph = Self::open_read_only(webgraph_path);
+        info!("exporting {:?} graph as {:?}", kind, format);
+        graph.export(kind, format, output_path)?;
+
+        Ok(())
+    }
+
+    /// Remove edges that have not been seen in a crawl since `timestamp` (ms since epoch).
+    pub fn garbage_collect(webgraph_path: String, timestamp: u64) {
+        let mut graph = open_graph(webgraph_path);
+        let removed = graph.remove_edges_older_than(timestamp);
+
+        info!("removed {} stale edges", removed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use crate::warc::{Metadata, Request, Response};
+
+    use super::*;
+
+    fn record(link: &str, date_ms: u64, fetch_time_ms: usize) -> WarcRecord {
+        record_with_body(
+            format!(
+                r#"
+            <html>
+                <head>
+                    <title>Links</title>
+                </head>
+                <body>
+                    <a href="{link}">link</a>
+                </body>
+            </html>
+            "#
+            ),
+            date_ms,
+            fetch_time_ms,
+        )
+    }
+
+    fn record_with_body(body: String, date_ms: u64, fetch_time_ms: usize) -> WarcRecord {
+        WarcRecord {
+            request: Request {
+                url: "https://www.a.com/".to_string(),
+            },
+            response: Response {
+                body,
+                raw_body: None,
+                payload_type: None,
+                headers: BTreeMap::new(),
+                date_ms: Some(date_ms),
+            },
+            metadata: Metadata { fetch_time_ms },
+        }
+    }
+
+    fn outgoing(graph: &webgraph::Webgraph) -> Vec<Node> {
+        graph
+            .outgoing_edges(Node::from("https://www.a.com"))
+            .into_iter()
+            .map(|edge| edge.to)
+            .collect()
+    }
+
+    #[test]
+    fn recrawl_replaces_links() {
+        let mut graph = WebgraphBuilder::new_memory()
+            .with_full_graph()
+            .with_host_graph()
+            .open();
+
+        let canonicalizer = Canonicalizer::default();
+        let old_link = Node::from("https://www.b.com");
+        let new_link = Node::from("https://www.c.com");
+
+        // the old crawl was a slower fetch, which must not make it look newer
+        let b = record("https://www.b.com/", 1_000, 5_000);
+        insert_record(&mut graph, &b, &canonicalizer);
+        let c = record("https://www.c.com/", 2_000, 10);
+        insert_record(&mut graph, &c, &canonicalizer);
+        assert_eq!(outgoing(&graph), vec![new_link.clone()]);
+
+        insert_record(&mut graph, &b, &canonicalizer);
+        assert_eq!(outgoing(&graph), vec![new_link.clone()]);
+
+        assert_eq!(graph.remove_edges_older_than(1_500), 1);
+        assert_eq!(outgoing(&graph), vec![new_link]);
+        assert!(graph
+            .host_outgoing_edges(Node::from("https://www.a.com"))
+            .into_iter()
+            .all(|edge| edge.to != old_link.clone().into_host()));
+    }
+
+    #[test]
+    fn links_canonicalised_with_job_parameters() {
+        let mut graph = WebgraphBuilder::new_memory()
+            .with_full_graph()
+            .with_host_graph()
+            .open();
+
+        let canonicalizer = Canonicalizer::new(vec!["session".to_string()]);
+        let record = record("https://www.b.com/page?session=1&id=2", 1_000, 10);
+        insert_record(&mut graph, &record, &canonicalizer);
+
+        assert_eq!(
+            outgoing(&graph),
+            vec![Node::from("https://www.b.com/page?id=2")]
+        );
+    }
+
+    #[test]
+    fn feed_links_are_not_endorsements() {
+        let mut graph = WebgraphBuilder::new_memory()
+            .with_full_graph()
+            .with_host_graph()
+            .open();
+
+        let record = record_with_body(
+            r#"
+            <html>
+                <head>
+                    <link rel="alternate" type="application/rss+xml" href="https://feeds.b.com/rss" />
+                </head>
+                <body></body>
+            </html>
+            "#
+            .to_string(),
+            1_000,
+            10,
+        );
+        insert_record(&mut graph, &record, &Canonicalizer::default());
+
+        let edges = graph.outgoing_edges(Node::from("https://www.a.com"));
+        assert_eq!(edges.len(), 1);
+        assert_eq!(edges[0].to, Node::from("https://feeds.b.com/rss"));
+        assert!(!edges[0].properties.is_endorsement());
+    }
 }
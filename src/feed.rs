@@ -0,0 +1,477 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! RSS and Atom feeds. Feed entries whose article was not crawled are indexed
+//! as small webpages of their own, where the publication date of the entry is
+//! used as the update time.
+
+use chrono::{DateTime, FixedOffset};
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader,
+};
+
+use crate::{webpage::Url, Error, Result};
+
+/// How many characters from the start of a document we look at when sniffing for a feed.
+const SNIFF_LEN: usize = 512;
+
+/// Elements whose text we use. Everything nested inside them (e.g. the markup of
+/// atom xhtml content) is part of their text.
+const FIELDS: &[&[u8]] = &[
+    b"title",
+    b"link",
+    b"guid",
+    b"id",
+    b"description",
+    b"summary",
+    b"encoded",
+    b"content",
+    b"pubdate",
+    b"published",
+    b"issued",
+    b"date",
+    b"updated",
+    b"modified",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedKind {
+    Rss,
+    Atom,
+}
+
+impl FeedKind {
+    pub fn from_mime(mime: &str) -> Option<Self> {
+        let mime = mime.split(';').next().unwrap_or_default().trim();
+
+        if mime.eq_ignore_ascii_case("application/rss+xml")
+            || mime.eq_ignore_ascii_case("application/rdf+xml")
+        {
+            Some(FeedKind::Rss)
+        } else if mime.eq_ignore_ascii_case("application/atom+xml") {
+            Some(FeedKind::Atom)
+        } else {
+            None
+        }
+    }
+}
+
+/// A feed advertised by a page with `<link rel="alternate" type="application/rss+xml">`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedLink {
+    pub url: Url,
+    pub kind: FeedKind,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedEntry {
+    pub url: Url,
+    pub title: Option<String>,
+    /// Html content of the entry. The full content if the feed has it, otherwise the summary.
+    pub content: Option<String>,
+    pub published: Option<DateTime<FixedOffset>>,
+}
+
+impl FeedEntry {
+    /// A minimal html document for the entry, so it can be indexed like any other page.
+    pub fn to_html(&self) -> String {
+        let title = html_escape::encode_text(self.title.as_deref().unwrap_or_default());
+        let published = self
+            .published
+            .map(|time| {
+                format!(
+                    r#"<meta property="og:updated_time" content="{}" />"#,
+                    time.to_rfc3339()
+                )
+            })
+            .unwrap_or_default();
+        let content = self.content.as_deref().unwrap_or_default();
+
+        format!(
+            "<html><head><title>{title}</title>{published}</head><body><article>{content}</article></body></html>"
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Feed {
+    pub kind: FeedKind,
+    pub title: Option<String>,
+    pub entries: Vec<FeedEntry>,
+}
+
+#[derive(Default)]
+struct EntryBuilder {
+    link: Option<String>,
+    guid: Option<String>,
+    title: Option<String>,
+    summary: Option<String>,
+    content: Option<String>,
+    published: Option<DateTime<FixedOffset>>,
+    updated: Option<DateTime<FixedOffset>>,
+}
+
+impl EntryBuilder {
+    fn build(self, base: &Url) -> Option<FeedEntry> {
+        let link = self.link.or_else(|| {
+            self.guid
+                .filter(|guid| guid.starts_with("http://") || guid.starts_with("https://"))
+        })?;
+
+        let mut url: Url = link.into();
+        if !url.is_full_path() {
+            url.prefix_with(base);
+        }
+
+        Some(FeedEntry {
+            url,
+            title: self.title,
+            content: self.content.or(self.summary),
+            published: self.published.or(self.updated),
+        })
+    }
+}
+
+fn parse_date(date: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(date)
+        .or_else(|_| DateTime::parse_from_rfc2822(date))
+        .ok()
+}
+
+/// Separate the text of nested elements, since the reader trims the whitespace between them.
+fn push_separator(text: &mut String) {
+    if !text.is_empty() && !text.ends_with(' ') {
+        text.push(' ');
+    }
+}
+
+/// The href of an atom `<link>` if it points to the html version of the entry.
+fn alternate_href(element: &BytesStart, reader: &Reader<&[u8]>) -> Option<String> {
+    let mut href = None;
+    let mut rel = None;
+
+    for attribute in element.attributes().flatten() {
+        match attribute.key {
+            b"href" => href = attribute.unescape_and_decode_value(reader).ok(),
+            b"rel" => rel = attribute.unescape_and_decode_value(reader).ok(),
+            _ => {}
+        }
+    }
+
+    match rel {
+        Some(rel) if !rel.eq_ignore_ascii_case("alternate") => None,
+        _ => href.filter(|href| !href.trim().is_empty()),
+    }
+}
+
+impl Feed {
+    /// Whether the response is a feed, either by its payload type or by sniffing the body.
+    pub fn is_feed(payload_type: Option<&str>, body: &str) -> bool {
+        if let Some(payload_type) = payload_type {
+            if FeedKind::from_mime(payload_type).is_some() {
+                return true;
+            }
+
+            if payload_type.contains("html") {
+                return false;
+            }
+        }
+
+        let start: String = body.trim_start().chars().take(SNIFF_LEN).collect();
+
+        start.contains("<rss") || start.contains("<feed") || start.contains("<rdf:RDF")
+    }
+
+    pub fn parse(xml: &str, url: &Url) -> Result<Self> {
+        let mut reader = Reader::from_str(xml);
+        reader.trim_text(true);
+
+        let mut buf = Vec::new();
+        let mut kind = None;
+        let mut title = None;
+        let mut entries = Vec::new();
+        let mut entry: Option<EntryBuilder> = None;
+        let mut text = String::new();
+        // number of open elements since the start of the current field
+        let mut field_depth = 0;
+
+        loop {
+            let event = match reader.read_event(&mut buf) {
+                Ok(event) => event,
+                // keep the entries we have if a feed breaks halfway through
+                Err(_) if kind.is_some() => break,
+                Err(err) => return Err(err.into()),
+            };
+
+            match event {
+                Event::Start(_) if field_depth > 0 => {
+                    field_depth += 1;
+                    push_separator(&mut text);
+                }
+                Event::Start(ref element) => {
+                    text.clear();
+
+                    let name = element.local_name().to_ascii_lowercase();
+
+                    if FIELDS.contains(&name.as_slice()) {
+                        field_depth = 1;
+                    }
+
+                    match name.as_slice() {
+                        b"rss" | b"rdf" => {
+                            kind.get_or_insert(FeedKind::Rss);
+                        }
+                        b"feed" => {
+                            kind.get_or_insert(FeedKind::Atom);
+                        }
+                        b"item" | b"entry" => entry = Some(EntryBuilder::default()),
+                        b"link" => {
+                            if let Some(entry) = entry.as_mut() {
+                                if entry.link.is_none() {
+                                    entry.link = alternate_href(element, &reader);
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Event::Empty(_) if field_depth > 0 => push_separator(&mut text),
+                Event::Empty(ref element) if element.local_name() == b"link" => {
+                    if let Some(entry) = entry.as_mut() {
+                        if entry.link.is_none() {
+                            entry.link = alternate_href(element, &reader);
+                        }
+                    }
+                }
+                Event::Text(ref content) => text.push_str(
+                    &content
+                        .unescape_and_decode(&reader)
+                        .unwrap_or_else(|_| String::from_utf8_lossy(content).into_owned()),
+                ),
+                Event::CData(ref content) => text.push_str(&String::from_utf8_lossy(content)),
+                Event::End(_) if field_depth > 1 => {
+                    field_depth -= 1;
+                    push_separator(&mut text);
+                }
+                Event::End(ref element) => {
+                    field_depth = 0;
+
+                    let value = text.trim().to_string();
+                    text.clear();
+
+                    let name = element.local_name().to_ascii_lowercase();
+
+                    if matches!(name.as_slice(), b"item" | b"entry") {
+                        if let Some(finished) = entry.take().and_then(|entry| entry.build(url)) {
+                            entries.push(finished);
+                        }
+                    }
+
+                    match (entry.as_mut(), name.as_slice()) {
+                        (_, _) if value.is_empty() => {}
+                        (Some(entry), b"title") => entry.title = Some(value),
+                        (Some(entry), b"link") => {
+                            entry.link.get_or_insert(value);
+                        }
+                        (Some(entry), b"guid" | b"id") => entry.guid = Some(value),
+                        (Some(entry), b"description" | b"summary") => {
+                            entry.summary.get_or_insert(value);
+                        }
+                        (Some(entry), b"encoded" | b"content") => entry.content = Some(value),
+                        (Some(entry), b"pubdate" | b"published" | b"issued" | b"date") => {
+                            if entry.published.is_none() {
+                                entry.published = parse_date(&value);
+                            }
+                        }
+                        (Some(entry), b"updated" | b"modified") => {
+                            entry.updated = parse_date(&value);
+                        }
+                        (None, b"title") => {
+                            title.get_or_insert(value);
+                        }
+                        _ => {}
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+
+            buf.clear();
+        }
+
+        match kind {
+            Some(kind) => Ok(Feed {
+                kind,
+                title,
+                entries,
+            }),
+            None => Err(Error::ParsingError(
+                "document is not an RSS or Atom feed".to_string(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RSS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:content="http://purl.org/rss/1.0/modules/content/">
+    <channel>
+        <title>Example news</title>
+        <link>https://example.com/</link>
+        <description>All the news</description>
+        <image>
+            <title>Logo</title>
+            <url>https://example.com/logo.png</url>
+        </image>
+        <item>
+            <title>First &amp; foremost</title>
+            <link>https://example.com/first?utm_source=rss</link>
+            <description><![CDATA[<p>The <b>first</b> story</p>]]></description>
+            <pubDate>Tue, 21 Jun 2022 19:37:34 +0000</pubDate>
+        </item>
+        <item>
+            <title>Second</title>
+            <guid isPermaLink="true">https://example.com/second</guid>
+            <description>Summary</description>
+            <content:encoded><![CDATA[<p>The full second story</p>]]></content:encoded>
+        </item>
+        <item>
+            <title>Without any link</title>
+        </item>
+    </channel>
+</rss>"#;
+
+    const ATOM: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+    <title>Example blog</title>
+    <link href="https://blog.example.com/" />
+    <updated>2022-06-22T19:37:34Z</updated>
+    <entry>
+        <title>A post</title>
+        <link rel="self" href="/feed/a-post.xml" />
+        <link rel="alternate" href="/posts/a-post" />
+        <id>urn:uuid:60a76c80-d399-11d9-b91C-0003939e0af6</id>
+        <updated>2022-06-22T19:37:34Z</updated>
+        <published>2022-06-20T08:00:00+02:00</published>
+        <summary>A short summary</summary>
+    </entry>
+    <entry>
+        <title>An xhtml post</title>
+        <link href="https://blog.example.com/posts/xhtml" />
+        <content type="xhtml">
+            <div xmlns="http://www.w3.org/1999/xhtml">
+                <p>Some <b>bold</b> words<br/>and a new line</p>
+            </div>
+        </content>
+    </entry>
+</feed>"#;
+
+    #[test]
+    fn rss() {
+        let feed = Feed::parse(RSS, &"https://example.com/feed.xml".to_string().into()).unwrap();
+
+        assert_eq!(feed.kind, FeedKind::Rss);
+        assert_eq!(feed.title, Some("Example news".to_string()));
+        assert_eq!(feed.entries.len(), 2);
+
+        let first = &feed.entries[0];
+        assert_eq!(first.title, Some("First & foremost".to_string()));
+        assert_eq!(first.url.full(), "https://example.com/first?utm_source=rss");
+        assert_eq!(
+            first.content,
+            Some("<p>The <b>first</b> story</p>".to_string())
+        );
+        assert_eq!(
+            first.published,
+            Some(DateTime::parse_from_rfc3339("2022-06-21T19:37:34+00:00").unwrap())
+        );
+
+        let second = &feed.entries[1];
+        assert_eq!(second.url.full(), "https://example.com/second");
+        assert_eq!(
+            second.content,
+            Some("<p>The full second story</p>".to_string())
+        );
+        assert_eq!(second.published, None);
+    }
+
+    #[test]
+    fn atom() {
+        let feed = Feed::parse(
+            ATOM,
+            &"https://blog.example.com/feed.atom".to_string().into(),
+        )
+        .unwrap();
+
+        assert_eq!(feed.kind, FeedKind::Atom);
+        assert_eq!(feed.title, Some("Example blog".to_string()));
+        assert_eq!(feed.entries.len(), 2);
+
+        let entry = &feed.entries[0];
+        assert_eq!(entry.url.full(), "https://blog.example.com/posts/a-post");
+        assert_eq!(entry.content, Some("A short summary".to_string()));
+        assert_eq!(
+            entry.published,
+            Some(DateTime::parse_from_rfc3339("2022-06-20T08:00:00+02:00").unwrap())
+        );
+
+        let xhtml = &feed.entries[1];
+        assert_eq!(xhtml.title, Some("An xhtml post".to_string()));
+        assert_eq!(
+            xhtml.content,
+            Some("Some bold words and a new line".to_string())
+        );
+    }
+
+    #[test]
+    fn not_a_feed() {
+        assert!(Feed::parse(
+            "<html><body>hello</body></html>",
+            &"https://example.com".to_string().into()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn sniffing() {
+        assert!(Feed::is_feed(Some("application/rss+xml"), ""));
+        assert!(Feed::is_feed(
+            Some("application/atom+xml; charset=utf-8"),
+            ""
+        ));
+        assert!(Feed::is_feed(Some("text/xml"), RSS));
+        assert!(Feed::is_feed(None, ATOM));
+        assert!(!Feed::is_feed(Some("text/html"), RSS));
+        assert!(!Feed::is_feed(None, "<html><body>hello</body></html>"));
+    }
+
+    #[test]
+    fn entry_html() {
+        let feed = Feed::parse(RSS, &"https://example.com/feed.xml".to_string().into()).unwrap();
+        let html = feed.entries[0].to_html();
+
+        assert!(html.contains("<title>First &amp; foremost</title>"));
+        assert!(html.contains(
+            r#"<meta property="og:updated_time" content="2022-06-21T19:37:34+00:00" />"#
+        ));
+        assert!(html.contains("<article><p>The <b>first</b> story</p></article>"));
+    }
+}
@@ -16,10 +16,13 @@
 
 use std::{collections::HashMap, sync::Arc};
 
-use crate::{searcher::SearchQuery, webpage::region::Region};
+use crate::{
+    searcher::{self, Cursor, SearchQuery},
+    webpage::region::Region,
+};
 
 use super::State;
-use axum::{extract, response::IntoResponse, Extension, Json};
+use axum::{extract, http::StatusCode, response::IntoResponse, Extension, Json};
 
 #[allow(clippy::unused_async)]
 pub async fn search(
@@ -29,6 +32,7 @@ pub async fn search(
     let query = params.get("q").cloned().unwrap_or_default();
 
     let skip_pages = params.get("p").and_then(|p| p.parse().ok());
+    let cursor = params.get("c").and_then(|c| Cursor::decode(c));
 
     let selected_region = params.get("gl").and_then(|gl| {
         if let Ok(region) = Region::from_gl(gl) {
@@ -46,10 +50,12 @@ pub async fn search(
             goggle_program: None,
             site_rankings: None,
             skip_pages,
+            cursor,
         })
         .await
     {
-        Ok(result) => Json(result),
+        Ok(result) => Json(result).into_response(),
+        Err(searcher::distributed::Error::PageTooDeep) => StatusCode::BAD_REQUEST.into_response(),
         Err(_) => panic!("Search failed"), // TODO: show 500 status to user here
     }
 }
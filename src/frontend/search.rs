@@ -21,7 +21,7 @@ use axum::Extension;
 use crate::{
     ranking::site_rankings::SiteRankings,
     search_prettifier::{thousand_sep_number, DisplayedEntity, DisplayedWebpage},
-    searcher::{self, PrettifiedSearchResult, SearchQuery},
+    searcher::{self, Cursor, PrettifiedSearchResult, SearchQuery, MAX_SKIP_PAGES},
     webpage::region::{Region, ALL_REGIONS},
 };
 
@@ -66,7 +66,11 @@ pub async fn route(
 ) -> impl IntoResponse {
     let query = params.get("q").cloned().unwrap_or_default();
 
-    let skip_pages = params.get("p").and_then(|p| p.parse().ok());
+    let page: usize = params.get("p").and_then(|p| p.parse().ok()).unwrap_or(0);
+    let cursor = params.get("c").and_then(|c| Cursor::decode(c));
+
+    // the cursor already points past the previous pages
+    let skip_pages = if cursor.is_some() { None } else { Some(page) };
 
     let mut goggle = None;
     let mut current_goggle_url = None;
@@ -117,6 +121,7 @@ pub async fn route(
             goggle_program: goggle,
             skip_pages,
             site_rankings,
+            cursor,
         })
         .await
     {
@@ -145,20 +150,25 @@ pub async fn route(
                     })
                     .collect();
 
-                let current_page = skip_pages.unwrap_or(0) + 1;
+                let current_page = page + 1;
 
                 let mut next_page_params = params.clone();
-                next_page_params.insert("p".to_string(), (skip_pages.unwrap_or(0) + 1).to_string());
+                next_page_params.insert("p".to_string(), (page + 1).to_string());
+                match result.cursor {
+                    Some(cursor) => next_page_params.insert("c".to_string(), cursor),
+                    None => next_page_params.remove("c"),
+                };
                 let next_page_url = uri.path().to_string()
                     + "?"
                     + serde_urlencoded::to_string(&next_page_params)
                         .unwrap()
                         .as_str();
 
-                let prev_page_url = if current_page > 1 {
+                // cursors only point forward, so deep pages have no way back
+                let prev_page_url = if page > 0 && page - 1 <= MAX_SKIP_PAGES {
                     let mut prev_page_params = params;
-                    prev_page_params
-                        .insert("p".to_string(), (skip_pages.unwrap_or(0) - 1).to_string());
+                    prev_page_params.remove("c");
+                    prev_page_params.insert("p".to_string(), (page - 1).to_string());
                     Some(
                         uri.path().to_string()
                             + "?"
@@ -192,6 +202,19 @@ pub async fn route(
             }
         },
         Err(searcher::distributed::Error::EmptyQuery) => Redirect::to("/").into_response(),
+        Err(searcher::distributed::Error::PageTooDeep) => {
+            // deep pages are only reachable with the cursor of the page before
+            let mut params = params;
+            params.remove("c");
+            params.insert("p".to_string(), MAX_SKIP_PAGES.to_string());
+
+            Redirect::to(
+                &(uri.path().to_string()
+                    + "?"
+                    + serde_urlencoded::to_string(&params).unwrap().as_str()),
+            )
+            .into_response()
+        }
         Err(_) => panic!("Search failed"), // TODO: show 500 status to user here
     }
 }
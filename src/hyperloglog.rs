@@ -0,0 +1,171 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use serde::{Deserialize, Serialize};
+
+pub const MIN_PRECISION: u8 = 4;
+pub const MAX_PRECISION: u8 = 16;
+
+/// Cardinality estimator from "HyperLogLog: the analysis of a near-optimal
+/// cardinality estimation algorithm" by Flajolet et al.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HyperLogLog {
+    precision: u8,
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    /// Create a counter with `2^precision` registers.
+    /// The relative standard error of the estimate is roughly `1.04 / sqrt(2^precision)`.
+    pub fn new(precision: u8) -> Self {
+        assert!(
+            (MIN_PRECISION..=MAX_PRECISION).contains(&precision),
+            "precision must be between {} and {}",
+            MIN_PRECISION,
+            MAX_PRECISION
+        );
+
+        Self {
+            precision,
+            registers: vec![0; 1 << precision],
+        }
+    }
+
+    fn hash<T: Hash>(item: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        item.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    pub fn add<T: Hash>(&mut self, item: &T) {
+        let hash = Self::hash(item);
+        let p = self.precision as u32;
+
+        let idx = (hash >> (64 - p)) as usize;
+        let rest = (hash << p) | (1 << (p - 1));
+        let rank = (rest.leading_zeros() + 1) as u8;
+
+        if rank > self.registers[idx] {
+            self.registers[idx] = rank;
+        }
+    }
+
+    /// Merge the registers of `other` into this counter.
+    /// Returns whether any register was changed.
+    pub fn merge(&mut self, other: &HyperLogLog) -> bool {
+        debug_assert_eq!(self.precision, other.precision);
+
+        let mut changed = false;
+
+        for (reg, other_reg) in self.registers.iter_mut().zip(other.registers.iter()) {
+            if *other_reg > *reg {
+                *reg = *other_reg;
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    fn alpha(&self) -> f64 {
+        match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            m => 0.7213 / (1.0 + 1.079 / m as f64),
+        }
+    }
+
+    /// Estimated number of distinct items added to the counter.
+    pub fn size(&self) -> f64 {
+        let m = self.registers.len() as f64;
+
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|reg| 2f64.powi(-(*reg as i32)))
+            .sum();
+
+        let estimate = self.alpha() * m * m / sum;
+
+        let zeros = self.registers.iter().filter(|reg| **reg == 0).count();
+
+        if estimate <= 2.5 * m && zeros > 0 {
+            // linear counting is more precise for small cardinalities
+            m * (m / zeros as f64).ln()
+        } else {
+            estimate
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty() {
+        let hll = HyperLogLog::new(8);
+        assert_eq!(hll.size(), 0.0);
+    }
+
+    #[test]
+    fn duplicates_are_not_counted() {
+        let mut hll = HyperLogLog::new(8);
+
+        for _ in 0..100 {
+            hll.add(&"a");
+            hll.add(&"b");
+        }
+
+        assert!((hll.size() - 2.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn large_cardinality() {
+        let mut hll = HyperLogLog::new(12);
+
+        for i in 0..100_000_u64 {
+            hll.add(&i);
+        }
+
+        let size = hll.size();
+        assert!((size - 100_000.0).abs() / 100_000.0 < 0.05);
+    }
+
+    #[test]
+    fn merge() {
+        let mut a = HyperLogLog::new(10);
+        let mut b = HyperLogLog::new(10);
+
+        for i in 0..100_u64 {
+            a.add(&i);
+        }
+
+        for i in 50..150_u64 {
+            b.add(&i);
+        }
+
+        assert!(a.merge(&b));
+        assert!(!a.merge(&b));
+        assert!((a.size() - 150.0).abs() / 150.0 < 0.05);
+    }
+}
@@ -26,7 +26,9 @@ use tantivy::collector::Collector;
 use tantivy::schema::Schema;
 use tantivy::tokenizer::TokenizerManager;
 use uuid::Uuid;
+use whatlang::Lang;
 
+use crate::collector::Page;
 use crate::directory::{self, DirEntry};
 use crate::image_downloader::{ImageDownloadJob, ImageDownloader};
 use crate::image_store::{FaviconStore, Image, ImageStore, PrimaryImageStore};
@@ -34,6 +36,7 @@ use crate::inverted_index::{self, InitialSearchResult, InvertedIndex, SearchResu
 use crate::query::Query;
 use crate::spell::{Dictionary, LogarithmicEdit, SpellChecker, TermSplitter};
 use crate::subdomain_count::SubdomainCounter;
+use crate::tokenizer;
 use crate::webpage::region::{Region, RegionCount};
 use crate::webpage::{Url, Webpage};
 use crate::Result;
@@ -121,14 +124,14 @@ impl Index {
 
     pub fn search<C>(&self, query: &Query, collector: C) -> Result<SearchResult>
     where
-        C: Collector<Fruit = Vec<inverted_index::WebsitePointer>>,
+        C: Collector<Fruit = Page>,
     {
         self.inverted_index.search(query, collector)
     }
 
     pub fn search_initial<C>(&self, query: &Query, collector: C) -> Result<InitialSearchResult>
     where
-        C: Collector<Fruit = Vec<inverted_index::WebsitePointer>>,
+        C: Collector<Fruit = Page>,
     {
         self.inverted_index.search_initial(query, collector)
     }
@@ -141,6 +144,15 @@ impl Index {
         self.inverted_index.retrieve_websites(websites, query)
     }
 
+    pub fn localize(
+        &self,
+        webpages: &mut [inverted_index::RetrievedWebpage],
+        region: &Region,
+        query: &Query,
+    ) -> Result<()> {
+        self.inverted_index.localize(webpages, region, query)
+    }
+
     pub fn merge(mut self, other: Self) -> Self {
         self.inverted_index.merge(other.inverted_index);
 
@@ -227,16 +239,16 @@ impl Index {
         let mut corrections: Vec<String> = Vec::new();
 
         for term in terms {
-            match spellchecker.correct(term.to_ascii_lowercase().as_str()) {
-                Some(correction) => corrections.push(correction.to_ascii_lowercase()),
-                None => corrections.push(term.to_ascii_lowercase()),
+            match spellchecker.correct(tokenizer::normalize(term).as_str()) {
+                Some(correction) => corrections.push(tokenizer::normalize(&correction)),
+                None => corrections.push(tokenizer::normalize(term)),
             }
         }
 
         if corrections
             .iter()
             .cloned()
-            .zip(terms.iter().map(|term| term.to_ascii_lowercase()))
+            .zip(terms.iter().map(|term| tokenizer::normalize(term)))
             .all(|(correction, term)| correction == term)
         {
             None
@@ -250,7 +262,7 @@ impl Index {
         let mut corrections: Vec<String> = Vec::new();
 
         for term in terms {
-            let t = term.to_ascii_lowercase();
+            let t = tokenizer::normalize(term);
             let split = splitter.split(t.as_str());
             if split.is_empty() {
                 corrections.push(t);
@@ -263,8 +275,8 @@ impl Index {
 
         if corrections
             .iter()
-            .map(|s| s.to_ascii_lowercase())
-            .zip(terms.iter().map(|term| term.to_ascii_lowercase()))
+            .map(|s| tokenizer::normalize(s))
+            .zip(terms.iter().map(|term| tokenizer::normalize(term)))
             .all(|(correction, term)| correction == term)
         {
             None
@@ -273,7 +285,13 @@ impl Index {
         }
     }
 
-    pub fn spell_correction(&self, terms: &[String]) -> Option<String> {
+    pub fn spell_correction(&self, terms: &[String], lang: Option<Lang>) -> Option<String> {
+        // edit distances and word splitting make no sense for scripts without
+        // whitespace between words
+        if matches!(lang, Some(Lang::Cmn | Lang::Jpn | Lang::Kor | Lang::Tha)) {
+            return None;
+        }
+
         self.spell_check(terms).or_else(|| self.split_words(terms))
     }
 
@@ -380,7 +398,7 @@ mod tests {
         assert_eq!(result.documents[0].url, "https://www.example.com");
 
         assert_eq!(
-            index.spell_correction(&["thiss".to_string()]),
+            index.spell_correction(&["thiss".to_string()], None),
             Some("this".to_string())
         );
     }
@@ -410,11 +428,11 @@ mod tests {
         index.commit().unwrap();
 
         assert_eq!(
-            index.spell_correction(&["th".to_string(), "best".to_string()]),
+            index.spell_correction(&["th".to_string(), "best".to_string()], None),
             Some("the best".to_string())
         );
         assert_eq!(
-            index.spell_correction(&["the".to_string(), "best".to_string()]),
+            index.spell_correction(&["the".to_string(), "best".to_string()], None),
             None
         );
     }
@@ -19,19 +19,22 @@ use serde::{Deserialize, Serialize};
 use tantivy::collector::{Collector, Count};
 use tantivy::directory::MmapDirectory;
 use tantivy::merge_policy::NoMergePolicy;
-use tantivy::schema::Schema;
+use tantivy::query::TermQuery;
+use tantivy::schema::{IndexRecordOption, Schema};
 use tantivy::tokenizer::TokenizerManager;
 use tantivy::{Document, IndexReader, IndexWriter, SegmentMeta};
 
-use crate::collector::Hashes;
+use crate::collector::{Hashes, Page};
 use crate::fastfield_cache::FastFieldCache;
 use crate::image_store::Image;
 use crate::query::Query;
 use crate::schema::{FastField, Field, TextField, ALL_FIELDS};
+use crate::schema_org::{self, SchemaOrg};
+use crate::simhash::SimHashIndex;
 use crate::snippet;
-use crate::tokenizer::Identity;
+use crate::tokenizer::{Code, Diacritics, Identity};
 use crate::webpage::region::Region;
-use crate::webpage::{StoredPrimaryImage, Webpage};
+use crate::webpage::{HrefLang, StoredPrimaryImage, Webpage};
 use crate::Result;
 use crate::{schema::create_schema, tokenizer::Tokenizer};
 use std::fs;
@@ -42,6 +45,8 @@ use std::sync::{Arc, Weak};
 pub struct InitialSearchResult {
     pub num_websites: usize,
     pub top_websites: Vec<WebsitePointer>,
+    /// Where the page ends in the ranking, see [`Page::end`].
+    pub page_end: Option<WebsitePointer>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -49,9 +54,11 @@ pub struct WebsitePointer {
     pub score: f64,
     pub hashes: Hashes,
     pub address: DocAddress,
+    /// Number of lower ranked results from the same site folded under this one.
+    pub more_from_site: usize,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub struct DocAddress {
     pub segment: u32,
     pub doc_id: u32,
@@ -127,6 +134,16 @@ impl InvertedIndex {
             .tokenizers()
             .register(tokenizer.as_str(), tokenizer);
 
+        let tokenizer = Tokenizer::Code(Code::default());
+        tantivy_index
+            .tokenizers()
+            .register(tokenizer.as_str(), tokenizer);
+
+        let tokenizer = Tokenizer::Diacritics(Diacritics::default());
+        tantivy_index
+            .tokenizers()
+            .register(tokenizer.as_str(), tokenizer);
+
         let writer = tantivy_index.writer_with_num_threads(1, 1_000_000_000)?;
 
         let merge_policy = NoMergePolicy::default();
@@ -178,14 +195,15 @@ impl InvertedIndex {
 
     pub fn search_initial<C>(&self, query: &Query, collector: C) -> Result<InitialSearchResult>
     where
-        C: Collector<Fruit = Vec<WebsitePointer>>,
+        C: Collector<Fruit = Page>,
     {
         let searcher = self.reader.searcher();
 
-        let (count, docs) = searcher.search(query, &(Count, collector))?;
+        let (count, page) = searcher.search(query, &(Count, collector))?;
         Ok(InitialSearchResult {
             num_websites: count,
-            top_websites: docs,
+            top_websites: page.websites,
+            page_end: page.end,
         })
     }
 
@@ -195,45 +213,90 @@ impl InvertedIndex {
         query: &Query,
     ) -> Result<Vec<RetrievedWebpage>> {
         let searcher = self.reader.searcher();
-        let mut webpages: Vec<RetrievedWebpage> = websites
+
+        websites
             .iter()
             .map(|website| self.retrieve_doc(website.address, &searcher))
             .filter_map(|page| page.ok())
-            .map(|mut doc| {
-                if let Some(image) = doc.primary_image.as_ref() {
-                    if !query.simple_terms().into_iter().all(|term| {
-                        image
-                            .title_terms
-                            .contains(term.to_ascii_lowercase().as_str())
-                            || image
-                                .description_terms
-                                .contains(term.to_ascii_lowercase().as_str())
-                    }) {
-                        doc.primary_image = None;
-                    }
-                }
+            .map(|doc| Self::prepare_for_query(doc, query, &searcher))
+            .collect()
+    }
 
-                doc
-            })
-            .collect();
+    /// Only keep the primary image if it is relevant to the query and generate the snippet.
+    fn prepare_for_query(
+        mut doc: RetrievedWebpage,
+        query: &Query,
+        searcher: &tantivy::Searcher,
+    ) -> Result<RetrievedWebpage> {
+        if let Some(image) = doc.primary_image.as_ref() {
+            if !query.simple_terms().into_iter().all(|term| {
+                image
+                    .title_terms
+                    .contains(term.to_ascii_lowercase().as_str())
+                    || image
+                        .description_terms
+                        .contains(term.to_ascii_lowercase().as_str())
+            }) {
+                doc.primary_image = None;
+            }
+        }
+
+        doc.snippet = snippet::generate(
+            query,
+            &doc.body,
+            &doc.dirty_body,
+            &doc.description,
+            &doc.region,
+            searcher,
+        )?;
 
-        for page in &mut webpages {
-            page.snippet = snippet::generate(
-                query,
-                &page.body,
-                &page.dirty_body,
-                &page.description,
-                &page.region,
-                &searcher,
-            )?;
+        Ok(doc)
+    }
+
+    /// Replace the pages with their language version for `region` if that version is in
+    /// the index, so the title and snippet match the page that is linked to.
+    pub fn localize(
+        &self,
+        webpages: &mut [RetrievedWebpage],
+        region: &Region,
+        query: &Query,
+    ) -> Result<()> {
+        let searcher = self.reader.searcher();
+        let url_field = self
+            .schema
+            .get_field(Field::Text(TextField::UrlNoTokenizer).name())
+            .unwrap();
+
+        for page in webpages {
+            let alternate =
+                match page.hreflang.iter().find(|alternate| {
+                    Region::from_hreflang(&alternate.lang).as_ref() == Some(region)
+                }) {
+                    Some(alternate) if alternate.url != page.url => alternate.url.clone(),
+                    _ => continue,
+                };
+
+            let url_query = TermQuery::new(
+                tantivy::Term::from_field_text(url_field, &alternate),
+                IndexRecordOption::Basic,
+            );
+
+            if let Some((_, address)) = searcher
+                .search(&url_query, &tantivy::collector::TopDocs::with_limit(1))?
+                .into_iter()
+                .next()
+            {
+                let doc = RetrievedWebpage::from(searcher.doc(address)?);
+                *page = Self::prepare_for_query(doc, query, &searcher)?;
+            }
         }
 
-        Ok(webpages)
+        Ok(())
     }
 
     pub fn search<C>(&self, query: &Query, collector: C) -> Result<SearchResult>
     where
-        C: Collector<Fruit = Vec<WebsitePointer>>,
+        C: Collector<Fruit = Page>,
     {
         let initial_result = self.search_initial(query, collector)?;
         let websites = self.retrieve_websites(&initial_result.top_websites, query)?;
@@ -305,6 +368,62 @@ impl InvertedIndex {
         Ok(RetrievedWebpage::from(doc))
     }
 
+    /// Groups documents whose simhashes are (transitively) near-duplicates of each other.
+    /// Documents without any near-duplicates are not part of the result.
+    pub fn near_duplicate_clusters(&self) -> Result<Vec<Vec<RetrievedWebpage>>> {
+        let searcher = self.reader.searcher();
+        let mut simhashes = SimHashIndex::default();
+
+        for (segment, reader) in searcher.segment_readers().iter().enumerate() {
+            let segment_cache = self.fastfield_cache.get_segment(&reader.segment_id());
+            let doc_cache = segment_cache.get_doc_cache(&FastField::SimHash);
+
+            for doc_id in reader.doc_ids_alive() {
+                simhashes.insert(
+                    doc_cache.get_u64(&doc_id).unwrap(),
+                    DocAddress {
+                        segment: segment as u32,
+                        doc_id,
+                    },
+                );
+            }
+        }
+
+        let mut visited = vec![false; simhashes.len()];
+        let mut clusters = Vec::new();
+
+        for id in 0..simhashes.len() {
+            if visited[id] {
+                continue;
+            }
+
+            visited[id] = true;
+            let mut cluster = vec![id];
+            let mut stack = vec![id];
+
+            while let Some(next) = stack.pop() {
+                for other in simhashes.near_duplicate_ids(simhashes.get(next).0) {
+                    if !visited[other] {
+                        visited[other] = true;
+                        cluster.push(other);
+                        stack.push(other);
+                    }
+                }
+            }
+
+            if cluster.len() > 1 {
+                clusters.push(
+                    cluster
+                        .into_iter()
+                        .map(|id| self.retrieve_doc(simhashes.get(id).1, &searcher))
+                        .collect::<Result<Vec<_>>>()?,
+                );
+            }
+        }
+
+        Ok(clusters)
+    }
+
     pub fn merge(mut self, mut other: InvertedIndex) -> Self {
         other.commit().expect("failed to commit index");
         self.commit().expect("failed to commit index");
@@ -385,6 +504,11 @@ pub struct RetrievedWebpage {
     pub primary_image: Option<StoredPrimaryImage>,
     pub updated_time: Option<NaiveDateTime>,
     pub region: Region,
+    pub hreflang: Vec<HrefLang>,
+    #[serde(with = "schema_org::as_json")]
+    pub schema_org: Vec<SchemaOrg>,
+    /// Number of results from the same site folded under this one.
+    pub more_from_site: usize,
 }
 
 impl From<Document> for RetrievedWebpage {
@@ -450,6 +574,26 @@ impl From<Document> for RetrievedWebpage {
                         .expect("All body field should be text")
                         .to_string()
                 }
+                Field::Text(TextField::HrefLang) => {
+                    webpage.hreflang = {
+                        let bytes = value
+                            .value
+                            .as_bytes()
+                            .expect("Hreflang field should be bytes");
+
+                        bincode::deserialize(bytes).unwrap_or_default()
+                    }
+                }
+                Field::Text(TextField::SchemaOrgJson) => {
+                    webpage.schema_org = {
+                        let bytes = value
+                            .value
+                            .as_bytes()
+                            .expect("Schema.org field should be bytes");
+
+                        serde_json::from_slice(bytes).unwrap_or_default()
+                    }
+                }
                 Field::Fast(FastField::Region) => {
                     webpage.region = {
                         let id = value.value.as_u64().unwrap();
@@ -470,7 +614,7 @@ mod tests {
 
     use crate::{
         ranking::{Ranker, SignalAggregator},
-        webpage::{region::RegionCount, Html, Link},
+        webpage::{region::RegionCount, Html, Link, LinkContext, LinkRel},
     };
 
     use super::*;
@@ -705,9 +849,15 @@ mod tests {
                     source: "https://www.a.com".to_string().into(),
                     destination: "https://www.b.com".to_string().into(),
                     text: "B site is great".to_string(),
+                    rel: LinkRel::default(),
+                    context: LinkContext::default(),
                 }],
                 host_centrality: 1.0,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 fetch_time_ms: 500,
                 pre_computed_score: 0.0,
                 primary_image: None,
@@ -1053,4 +1203,56 @@ mod tests {
         assert_eq!(result.documents.len(), 1);
         assert_eq!(result.documents[0].url, "https://www.example.com");
     }
+
+    #[test]
+    fn near_duplicate_clusters() {
+        let mut index = InvertedIndex::temporary().expect("Unable to open index");
+
+        let weather = "the weather in the mountains is cold and windy during the winter and the roads are often closed for days so travellers should always check the forecast before they leave";
+
+        for (content, url) in [
+            (weather, "https://www.example.com"),
+            (weather, "https://www.mirror.com"),
+            (
+                "Rust is a multi-paradigm programming language designed for performance and safety, especially safe concurrency, and it is syntactically similar to C++",
+                "https://www.other.com",
+            ),
+        ] {
+            index
+                .insert(Webpage::new(
+                    &format!(
+                        r#"
+                            <html>
+                                <head>
+                                    <title>Test website</title>
+                                </head>
+                                <body>
+                                    {content}
+                                </body>
+                            </html>
+                        "#
+                    ),
+                    url,
+                ))
+                .expect("failed to insert webpage");
+        }
+        index.commit().expect("failed to commit index");
+
+        let clusters = index
+            .near_duplicate_clusters()
+            .expect("failed to find clusters");
+
+        assert_eq!(clusters.len(), 1);
+
+        let mut urls: Vec<_> = clusters[0].iter().map(|page| page.url.clone()).collect();
+        urls.sort();
+
+        assert_eq!(
+            urls,
+            vec![
+                "https://www.example.com".to_string(),
+                "https://www.mirror.com".to_string()
+            ]
+        );
+    }
 }
@@ -28,6 +28,7 @@ where
     fn insert_raw(&self, key: Vec<u8>, value: Vec<u8>);
     fn flush(&self);
     fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (K, V)> + 'a>;
+    fn iter_raw<'a>(&'a self) -> Box<dyn Iterator<Item = (Vec<u8>, Vec<u8>)> + 'a>;
 
     fn get(&self, key: &K) -> Option<V> {
         let key_bytes = bincode::serialize(key).expect("failed to serialize key");
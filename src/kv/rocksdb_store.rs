@@ -82,6 +82,14 @@ where
             value: PhantomData::default(),
         })
     }
+
+    fn iter_raw<'a>(&'a self) -> Box<dyn Iterator<Item = (Vec<u8>, Vec<u8>)> + 'a> {
+        Box::new(
+            self.iterator(IteratorMode::Start)
+                .filter_map(|r| r.ok())
+                .map(|(key, value)| (key.to_vec(), value.to_vec())),
+        )
+    }
 }
 
 pub struct IntoIter<'a, K, V>
@@ -39,7 +39,9 @@ mod directory;
 mod entity_index;
 mod exponential_backoff;
 mod fastfield_cache;
+mod feed;
 mod frontend;
+mod hyperloglog;
 mod image_downloader;
 mod image_store;
 pub mod index;
@@ -51,10 +53,12 @@ mod schema;
 mod schema_org;
 mod search_prettifier;
 pub mod searcher;
+mod simhash;
 mod snippet;
 mod sonic;
 mod spell;
 mod subdomain_count;
+mod synonyms;
 mod tokenizer;
 #[allow(unused)]
 mod ttl_cache;
@@ -71,7 +75,9 @@ pub struct IndexingMasterConfig {
     batch_size: Option<usize>,
     download_images: Option<bool>,
     host_centrality_threshold: Option<f64>,
+    content_extractor: Option<webpage::Extractor>,
     index_base_path: Option<String>,
+    tracking_parameters: Option<Vec<String>>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -84,7 +90,16 @@ pub struct IndexingLocalConfig {
     output_path: Option<String>,
     download_images: Option<bool>,
     host_centrality_threshold: Option<f64>,
+    content_extractor: Option<webpage::Extractor>,
     centrality_store_path: String,
+    tracking_parameters: Option<Vec<String>>,
+}
+
+/// The url canonicalisation of a webgraph or indexing config. Tools that look up urls in
+/// the webgraph read it from the config the graph was built with.
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct CanonicalizerConfig {
+    tracking_parameters: Option<Vec<String>>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -94,6 +109,7 @@ pub struct WebgraphMasterConfig {
     workers: Vec<String>,
     graph_base_path: Option<String>,
     batch_size: Option<usize>,
+    tracking_parameters: Option<Vec<String>>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -102,6 +118,15 @@ pub struct WebgraphLocalConfig {
     warc_source: WarcSource,
     graph_base_path: Option<String>,
     batch_size: Option<usize>,
+    tracking_parameters: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct CentralityMasterConfig {
+    webgraph_path: String,
+    output_path: String,
+    workers: Vec<String>,
+    batch_size: Option<usize>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -154,6 +179,7 @@ pub struct SearchServerConfig {
     pub index_path: String,
     pub entity_index_path: Option<String>,
     pub bangs_path: Option<String>,
+    pub synonyms_path: Option<String>,
     pub host: String,
 }
 
@@ -213,6 +239,9 @@ pub enum Error {
     #[error("Query cannot be completely empty")]
     EmptyQuery,
 
+    #[error("Page can only be reached with a cursor")]
+    PageTooDeep,
+
     #[error("Unknown region")]
     UnknownRegion,
 
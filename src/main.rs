@@ -14,9 +14,9 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{ArgEnum, Parser, Subcommand};
 use cuely::entrypoint::{self, frontend, search_server};
-use cuely::{FrontendConfig, SearchServerConfig};
+use cuely::{CanonicalizerConfig, FrontendConfig, SearchServerConfig};
 use serde::de::DeserializeOwned;
 use std::fs;
 use std::path::Path;
@@ -38,8 +38,8 @@ enum Commands {
         options: IndexingOptions,
     },
     Centrality {
-        webgraph_path: String,
-        output_path: String,
+        #[clap(subcommand)]
+        options: CentralityOptions,
     },
     Webgraph {
         #[clap(subcommand)]
@@ -48,16 +48,133 @@ enum Commands {
     SearchServer {
         config_path: String,
     },
+    /// Report clusters of near-duplicate pages in an index.
+    DuplicateCluster {
+        index_path: String,
+        #[clap(long, default_value_t = 100)]
+        limit: usize,
+    },
     Frontend {
         config_path: String,
     },
 }
 
 #[derive(Subcommand)]
-enum WebgraphOptions {
+enum CentralityOptions {
+    Local {
+        webgraph_path: String,
+        output_path: String,
+        #[clap(long, arg_enum, default_value = "exact")]
+        algorithm: CentralityAlgorithm,
+        /// Number of HyperLogLog registers is 2^precision. Only used by the HyperBall algorithm.
+        #[clap(
+            long,
+            default_value_t = 8,
+            parse(try_from_str = entrypoint::CentralityAlgorithm::parse_precision)
+        )]
+        precision: u8,
+        /// File with trusted hosts (one per line) used as seeds for trustrank.
+        #[clap(long)]
+        trusted_hosts: Option<String>,
+        /// File with known spam hosts (one per line) used as seeds for anti-trustrank.
+        #[clap(long)]
+        spam_hosts: Option<String>,
+    },
+    /// Distribute exact harmonic centrality between workers that each open the whole webgraph.
+    /// Use `local` for HyperBall, PageRank and TrustRank.
     Master { config_path: String },
-    Worker { address: String },
-    Local { config_path: String },
+    Worker {
+        address: String,
+        webgraph_path: String,
+    },
+}
+
+#[derive(ArgEnum, Clone)]
+enum CentralityAlgorithm {
+    Exact,
+    #[clap(name = "hyperball")]
+    HyperBall,
+}
+
+#[derive(Subcommand)]
+enum WebgraphOptions {
+    Master {
+        config_path: String,
+    },
+    Worker {
+        address: String,
+    },
+    Local {
+        config_path: String,
+    },
+    Inspect {
+        #[clap(subcommand)]
+        options: InspectOptions,
+    },
+    /// Remove edges that have not been seen since the timestamp (ms since epoch).
+    Gc {
+        webgraph_path: String,
+        older_than: u64,
+    },
+}
+
+#[derive(Subcommand)]
+enum InspectOptions {
+    /// List links pointing to a url (or host with --host) together with their anchor text.
+    Ingoing {
+        webgraph_path: String,
+        node: String,
+        #[clap(long)]
+        host: bool,
+        #[clap(long, default_value_t = 100)]
+        limit: usize,
+        /// Config the webgraph was built with. Its tracking parameters are used to canonicalise the url.
+        #[clap(long)]
+        config_path: Option<String>,
+    },
+    /// List links from a url (or host with --host) together with their anchor text.
+    Outgoing {
+        webgraph_path: String,
+        node: String,
+        #[clap(long)]
+        host: bool,
+        #[clap(long, default_value_t = 100)]
+        limit: usize,
+        /// Config the webgraph was built with. Its tracking parameters are used to canonicalise the url.
+        #[clap(long)]
+        config_path: Option<String>,
+    },
+    /// Print number of nodes, edges and the degree distributions.
+    Stats {
+        webgraph_path: String,
+        #[clap(long)]
+        host: bool,
+    },
+    Export {
+        webgraph_path: String,
+        output_path: String,
+        #[clap(long, arg_enum, default_value = "edge-list")]
+        format: ExportFormat,
+        #[clap(long)]
+        host: bool,
+    },
+}
+
+#[derive(ArgEnum, Clone)]
+enum ExportFormat {
+    EdgeList,
+    #[clap(name = "graphml")]
+    GraphML,
+    #[clap(name = "bv")]
+    BV,
+}
+
+fn graph_kind(host: bool) -> entrypoint::GraphKind {
+    if host {
+        entrypoint::GraphKind::Host
+    } else {
+        entrypoint::GraphKind::Full
+    }
 }
 
 #[derive(Subcommand)]
@@ -84,6 +201,10 @@ fn load_toml_config<T: DeserializeOwned, P: AsRef<Path>>(path: P) -> T {
     toml::from_str(&raw_config).expect("Failed to parse config")
 }
 
+fn load_canonicalizer_config(config_path: Option<String>) -> CanonicalizerConfig {
+    config_path.map(load_toml_config).unwrap_or_default()
+}
+
 fn main() -> Result<()> {
     let subscriber = FmtSubscriber::builder()
         .with_max_level(Level::INFO)
@@ -114,10 +235,41 @@ fn main() -> Result<()> {
                 output_path,
             } => entrypoint::EntityIndexer::run(wikipedia_dump_path, output_path)?,
         },
-        Commands::Centrality {
-            webgraph_path,
-            output_path,
-        } => entrypoint::Centrality::run(webgraph_path, output_path),
+        Commands::Centrality { options } => match options {
+            CentralityOptions::Local {
+                webgraph_path,
+                output_path,
+                algorithm,
+                precision,
+                trusted_hosts,
+                spam_hosts,
+            } => {
+                let algorithm = match algorithm {
+                    CentralityAlgorithm::Exact => entrypoint::CentralityAlgorithm::Exact,
+                    CentralityAlgorithm::HyperBall => {
+                        entrypoint::CentralityAlgorithm::HyperBall { precision }
+                    }
+                };
+
+                entrypoint::Centrality::run(
+                    webgraph_path,
+                    output_path,
+                    algorithm,
+                    trusted_hosts,
+                    spam_hosts,
+                )
+            }
+            CentralityOptions::Master { config_path } => {
+                let config = load_toml_config(config_path);
+                entrypoint::Centrality::run_master(&config)?;
+            }
+            CentralityOptions::Worker {
+                address,
+                webgraph_path,
+            } => {
+                entrypoint::Centrality::run_worker(address, webgraph_path)?;
+            }
+        },
         Commands::Webgraph { options } => match options {
             WebgraphOptions::Master { config_path } => {
                 let config = load_toml_config(config_path);
@@ -130,7 +282,65 @@ fn main() -> Result<()> {
                 let config = load_toml_config(config_path);
                 entrypoint::Webgraph::run_locally(&config)?;
             }
+            WebgraphOptions::Gc {
+                webgraph_path,
+                older_than,
+            } => entrypoint::Webgraph::garbage_collect(webgraph_path, older_than),
+            WebgraphOptions::Inspect { options } => match options {
+                InspectOptions::Ingoing {
+                    webgraph_path,
+                    node,
+                    host,
+                    limit,
+                    config_path,
+                } => entrypoint::Webgraph::inspect_ingoing(
+                    webgraph_path,
+                    node,
+                    graph_kind(host),
+                    limit,
+                    &load_canonicalizer_config(config_path),
+                ),
+                InspectOptions::Outgoing {
+                    webgraph_path,
+                    node,
+                    host,
+                    limit,
+                    config_path,
+                } => entrypoint::Webgraph::inspect_outgoing(
+                    webgraph_path,
+                    node,
+                    graph_kind(host),
+                    limit,
+                    &load_canonicalizer_config(config_path),
+                ),
+                InspectOptions::Stats {
+                    webgraph_path,
+                    host,
+                } => entrypoint::Webgraph::inspect_stats(webgraph_path, graph_kind(host)),
+                InspectOptions::Export {
+                    webgraph_path,
+                    output_path,
+                    format,
+                    host,
+                } => {
+                    let format = match format {
+                        ExportFormat::EdgeList => entrypoint::ExportFormat::EdgeList,
+                        ExportFormat::GraphML => entrypoint::ExportFormat::GraphML,
+                        ExportFormat::BV => entrypoint::ExportFormat::BV,
+                    };
+
+                    entrypoint::Webgraph::export(
+                        webgraph_path,
+                        output_path,
+                        graph_kind(host),
+                        format,
+                    )?;
+                }
+            },
         },
+        Commands::DuplicateCluster { index_path, limit } => {
+            entrypoint::Indexer::duplicate_clusters(index_path, limit)?;
+        }
         Commands::Frontend { config_path } => {
             let config: FrontendConfig = load_toml_config(&config_path);
 
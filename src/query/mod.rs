@@ -16,8 +16,9 @@
 
 use crate::{
     ranking::goggles::Goggle,
-    ranking::SignalAggregator,
+    ranking::{Signal, SignalAggregator},
     schema::{Field, TextField},
+    synonyms::Synonyms,
     Result,
 };
 use std::{collections::HashMap, sync::Arc};
@@ -26,6 +27,7 @@ use tantivy::{
     schema::Schema,
     tokenizer::TokenizerManager,
 };
+use whatlang::Lang;
 
 pub mod intersection;
 pub mod parser;
@@ -39,44 +41,72 @@ pub struct Query {
     #[allow(clippy::vec_box)]
     terms: Vec<Box<Term>>,
     simple_terms_text: Vec<String>,
+    proximity_terms: Vec<Vec<tantivy::Term>>,
     tantivy_query: Box<BooleanQuery>,
+    lang: Option<Lang>,
 }
 
-fn proximity_queries(
-    simple_terms_text: Vec<String>,
+/// The language of the query, if it can be reliably detected, and the languages
+/// the stemmed fields should be searched with. Short queries are hard to classify,
+/// so unless the detection is reliable we also stem as English, which is what pages
+/// without a detected language are indexed with.
+fn detect_langs(text: &str) -> (Option<Lang>, Vec<Lang>) {
+    match whatlang::detect(text) {
+        Some(info) if info.is_reliable() => (Some(info.lang()), vec![info.lang()]),
+        Some(info) if info.lang() != Lang::Eng => (None, vec![info.lang(), Lang::Eng]),
+        _ => (None, vec![Lang::Eng]),
+    }
+}
+
+/// The terms of the query in each of the fields where it matters that they are close to each other.
+fn proximity_terms(
+    simple_terms_text: &[String],
     schema: &Arc<Schema>,
     tokenizer_manager: &TokenizerManager,
-) -> Vec<(Occur, Box<dyn tantivy::query::Query + 'static>)> {
-    let mut proximity_queries: Vec<(Occur, Box<dyn tantivy::query::Query + 'static>)> = Vec::new();
-
+) -> Vec<Vec<tantivy::Term>> {
     let proxmity_fields = [
         Field::Text(TextField::Title),
         Field::Text(TextField::CleanBody),
     ];
 
-    for field in &proxmity_fields {
-        let tantivy_field = schema.get_field(field.name()).unwrap();
-        let tantivy_entry = schema.get_field_entry(tantivy_field);
+    if simple_terms_text.len() < 2 {
+        return Vec::new();
+    }
+
+    proxmity_fields
+        .iter()
+        .map(|field| {
+            let tantivy_field = schema.get_field(field.name()).unwrap();
+            let tantivy_entry = schema.get_field_entry(tantivy_field);
 
-        for (boost, slop) in [(6, 0), (5, 1), (4, 2), (3, 4), (2, 16), (1, 32)] {
             let mut terms = Vec::new();
 
-            let mut num_terms = 0;
-            for term in &simple_terms_text {
+            for term in simple_terms_text {
                 let analyzer = Term::get_tantivy_analyzer(tantivy_entry, tokenizer_manager);
-                num_terms += 1;
-                terms.append(&mut Term::process_tantivy_term(
-                    term,
-                    analyzer,
-                    tantivy_field,
-                ));
+                terms.extend(
+                    Term::process_tantivy_term(term, analyzer, tantivy_field)
+                        .into_iter()
+                        .filter_map(|mut forms| forms.pop()),
+                );
             }
 
-            if num_terms < 2 {
-                continue;
-            }
+            terms
+        })
+        .collect()
+}
+
+fn proximity_queries(
+    proximity_terms: &[Vec<tantivy::Term>],
+) -> Vec<(Occur, Box<dyn tantivy::query::Query + 'static>)> {
+    let mut proximity_queries: Vec<(Occur, Box<dyn tantivy::query::Query + 'static>)> = Vec::new();
+
+    for terms in proximity_terms {
+        if terms.len() < 2 {
+            continue;
+        }
 
-            let terms = terms.into_iter().enumerate().collect();
+        for (boost, slop) in [(6, 0), (5, 1), (4, 2), (3, 4), (2, 16), (1, 32)] {
+            let terms = terms.iter().cloned().enumerate().collect();
 
             proximity_queries.push((
                 Occur::Should,
@@ -98,6 +128,24 @@ impl Query {
         schema: Arc<Schema>,
         tokenizer_manager: &TokenizerManager,
         aggregator: &SignalAggregator,
+    ) -> Result<Query> {
+        Query::parse_with_synonyms(
+            query,
+            schema,
+            tokenizer_manager,
+            aggregator,
+            &Synonyms::default(),
+        )
+    }
+
+    /// Parse the query and expand its terms with their synonyms,
+    /// unless they are in quotes.
+    pub fn parse_with_synonyms(
+        query: &str,
+        schema: Arc<Schema>,
+        tokenizer_manager: &TokenizerManager,
+        aggregator: &SignalAggregator,
+        synonyms: &Synonyms,
     ) -> Result<Query> {
         let parsed_terms = parser::parse(query);
 
@@ -119,15 +167,10 @@ impl Query {
 
         let field_boost = aggregator.field_boosts();
 
-        let mut queries: Vec<(Occur, Box<dyn tantivy::query::Query + 'static>)> = terms
-            .iter()
-            .flat_map(|term| term.as_tantivy_query(&fields, tokenizer_manager, field_boost))
-            .collect();
-
         let simple_terms_text: Vec<String> = terms
             .iter()
             .filter_map(|term| {
-                if let Term::Simple(term) = term.as_ref() {
+                if let Term::Simple(term) | Term::Exact(term) = term.as_ref() {
                     Some(term.clone())
                 } else {
                     None
@@ -135,18 +178,38 @@ impl Query {
             })
             .collect();
 
-        queries.append(&mut proximity_queries(
-            simple_terms_text.clone(),
-            &schema,
-            tokenizer_manager,
-        ));
+        let (lang, langs) = detect_langs(&simple_terms_text.join(" "));
+
+        let mut queries: Vec<(Occur, Box<dyn tantivy::query::Query + 'static>)> = terms
+            .iter()
+            .flat_map(|term| {
+                term.as_tantivy_query(
+                    &fields,
+                    tokenizer_manager,
+                    field_boost,
+                    aggregator.term_scoring(),
+                    &langs,
+                    synonyms,
+                )
+            })
+            .collect();
+
+        let proximity_terms = proximity_terms(&simple_terms_text, &schema, tokenizer_manager);
+
+        // the phrase queries reward close terms in every matching page, and the proximity
+        // signal reranks the top candidates. `@proximity = 0` turns off both
+        if aggregator.coefficients().get(&Signal::Proximity) != 0.0 {
+            queries.append(&mut proximity_queries(&proximity_terms));
+        }
 
         let tantivy_query = Box::new(BooleanQuery::new(queries));
 
         Ok(Query {
             terms,
             simple_terms_text,
+            proximity_terms,
             tantivy_query,
+            lang,
         })
     }
 
@@ -164,6 +227,11 @@ impl Query {
         self.simple_terms_text.clone()
     }
 
+    /// The terms of the query for each field used by the proximity signal.
+    pub fn proximity_terms(&self) -> &[Vec<tantivy::Term>] {
+        &self.proximity_terms
+    }
+
     pub fn terms(&self) -> &[Box<Term>] {
         &self.terms
     }
@@ -171,6 +239,11 @@ impl Query {
     pub fn is_empty(&self) -> bool {
         self.terms.is_empty()
     }
+
+    /// The language of the query, if it could be reliably detected.
+    pub fn lang(&self) -> Option<Lang> {
+        self.lang
+    }
 }
 
 impl tantivy::query::Query for Query {
@@ -193,7 +266,7 @@ mod tests {
         inverted_index::InvertedIndex,
         ranking::Ranker,
         schema::create_schema,
-        webpage::{region::RegionCount, Webpage},
+        webpage::{region::RegionCount, Canonicalizer, Document, FileType, Webpage},
     };
 
     use super::*;
@@ -556,6 +629,465 @@ mod tests {
         assert_eq!(result.documents[0].url, "https://www.first.com/forum");
     }
 
+    #[test]
+    fn filetype_query() {
+        let mut index = InvertedIndex::temporary().expect("Unable to open index");
+
+        let text = "Test protocol\n\nThis is a test specification for the test protocol";
+        let mut webpage = Webpage::new("", "https://www.first.com/spec.txt");
+        webpage.html = Document::parse(FileType::Text, text.as_bytes(), text)
+            .expect("failed to parse document")
+            .into_html("https://www.first.com/spec.txt", &Canonicalizer::default());
+        webpage.html.parse_text();
+
+        index.insert(webpage).expect("failed to insert webpage");
+        index
+            .insert(Webpage::new(
+                r#"
+                        <html>
+                            <head>
+                                <title>Test test</title>
+                            </head>
+                            <body>
+                                This is a test website
+                            </body>
+                        </html>
+                    "#,
+                "https://www.second.com",
+            ))
+            .expect("failed to insert webpage");
+        index.commit().expect("failed to commit index");
+
+        let ranker = Ranker::new(
+            RegionCount::default(),
+            SignalAggregator::default(),
+            index.fastfield_cache(),
+        );
+
+        for (query, expected) in [
+            ("test filetype:txt", vec!["https://www.first.com/spec.txt"]),
+            ("test filetype:html", vec!["https://www.second.com"]),
+            ("test filetype:pdf", vec![]),
+        ] {
+            let query = Query::parse(
+                query,
+                index.schema(),
+                index.tokenizers(),
+                &SignalAggregator::default(),
+            )
+            .expect("Failed to parse query");
+
+            let result = index
+                .search(&query, ranker.collector())
+                .expect("Search failed");
+
+            assert_eq!(
+                result
+                    .documents
+                    .iter()
+                    .map(|webpage| webpage.url.as_str())
+                    .collect::<Vec<_>>(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn heading_and_code_query() {
+        let mut index = InvertedIndex::temporary().expect("Unable to open index");
+
+        index
+            .insert(Webpage::new(
+                r#"
+                        <html>
+                            <head>
+                                <title>Rust collections</title>
+                            </head>
+                            <body>
+                                <h2>Maps</h2>
+                                <p>A test of the map type</p>
+                                <pre>let map = BTreeMap::new();</pre>
+                            </body>
+                        </html>
+                    "#,
+                "https://www.first.com",
+            ))
+            .expect("failed to insert webpage");
+        index
+            .insert(Webpage::new(
+                r#"
+                        <html>
+                            <head>
+                                <title>Rust collections</title>
+                            </head>
+                            <body>
+                                <h2>Vectors</h2>
+                                <p>A test of maps and the BTreeMap type</p>
+                            </body>
+                        </html>
+                    "#,
+                "https://www.second.com",
+            ))
+            .expect("failed to insert webpage");
+        index.commit().expect("failed to commit index");
+
+        let ranker = Ranker::new(
+            RegionCount::default(),
+            SignalAggregator::default(),
+            index.fastfield_cache(),
+        );
+
+        for (query, expected) in [
+            ("test inheading:maps", vec!["https://www.first.com"]),
+            ("test inheading:vectors", vec!["https://www.second.com"]),
+            ("test incode:btreemap", vec!["https://www.first.com"]),
+            ("test incode:BTreeMap::new", vec!["https://www.first.com"]),
+            ("test incode:new", vec!["https://www.first.com"]),
+        ] {
+            let query = Query::parse(
+                query,
+                index.schema(),
+                index.tokenizers(),
+                &SignalAggregator::default(),
+            )
+            .expect("Failed to parse query");
+
+            let result = index
+                .search(&query, ranker.collector())
+                .expect("Search failed");
+
+            assert_eq!(
+                result
+                    .documents
+                    .iter()
+                    .map(|webpage| webpage.url.as_str())
+                    .collect::<Vec<_>>(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn code_phrase_query() {
+        let mut index = InvertedIndex::temporary().expect("Unable to open index");
+
+        index
+            .insert(Webpage::new(
+                r#"
+                        <html>
+                            <head>
+                                <title>Rust collections</title>
+                            </head>
+                            <body>
+                                <pre>let map = BTreeMap::new();</pre>
+                            </body>
+                        </html>
+                    "#,
+                "https://www.first.com",
+            ))
+            .expect("failed to insert webpage");
+        index.commit().expect("failed to commit index");
+
+        let ranker = Ranker::new(
+            RegionCount::default(),
+            SignalAggregator::default(),
+            index.fastfield_cache(),
+        );
+
+        for (query, expected) in [
+            ("incode:map=BTreeMap", vec!["https://www.first.com"]),
+            ("incode:map=BTreeMap::new", vec!["https://www.first.com"]),
+            ("incode:let=map=tree", vec!["https://www.first.com"]),
+            ("incode:BTreeMap=map", vec![]),
+        ] {
+            let query = Query::parse(
+                query,
+                index.schema(),
+                index.tokenizers(),
+                &SignalAggregator::default(),
+            )
+            .expect("Failed to parse query");
+
+            let result = index
+                .search(&query, ranker.collector())
+                .expect("Search failed");
+
+            assert_eq!(
+                result
+                    .documents
+                    .iter()
+                    .map(|webpage| webpage.url.as_str())
+                    .collect::<Vec<_>>(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn segmented_query() {
+        let mut index = InvertedIndex::temporary().expect("Unable to open index");
+
+        index
+            .insert(Webpage::new(
+                r#"
+                        <html>
+                            <head>
+                                <title>京都の紅葉が見頃を迎える</title>
+                            </head>
+                            <body>
+                                <p>京都市内の寺社では、今週に入って紅葉が見頃を迎え、多くの観光客が訪れている。気象台によると、今年は朝晩の冷え込みが続いたため、例年よりも色づきが鮮やかだという。</p>
+                            </body>
+                        </html>
+                    "#,
+                "https://www.first.com",
+            ))
+            .expect("failed to insert webpage");
+        index
+            .insert(Webpage::new(
+                r#"
+                        <html>
+                            <head>
+                                <title>Visiting Japan in the autumn</title>
+                            </head>
+                            <body>
+                                <p>We spent two weeks travelling by train and the leaves were turning red and gold everywhere we went.</p>
+                                <p>The sign at the station read 東京タワーまで徒歩十分, so we walked.</p>
+                            </body>
+                        </html>
+                    "#,
+                "https://www.second.com",
+            ))
+            .expect("failed to insert webpage");
+        index.commit().expect("failed to commit index");
+
+        let ranker = Ranker::new(
+            RegionCount::default(),
+            SignalAggregator::default(),
+            index.fastfield_cache(),
+        );
+
+        for (query, expected) in [
+            ("紅葉", vec!["https://www.first.com"]),
+            ("観光客 気象台", vec!["https://www.first.com"]),
+            ("東京", vec!["https://www.second.com"]),
+            ("徒歩", vec!["https://www.second.com"]),
+            ("寺", vec!["https://www.first.com"]),
+            ("塔", vec![]),
+        ] {
+            let query = Query::parse(
+                query,
+                index.schema(),
+                index.tokenizers(),
+                &SignalAggregator::default(),
+            )
+            .expect("Failed to parse query");
+
+            let result = index
+                .search(&query, ranker.collector())
+                .expect("Search failed");
+
+            assert_eq!(
+                result
+                    .documents
+                    .iter()
+                    .map(|webpage| webpage.url.as_str())
+                    .collect::<Vec<_>>(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn diacritics_and_compatibility_characters() {
+        let mut index = InvertedIndex::temporary().expect("Unable to open index");
+
+        index
+            .insert(Webpage::new(
+                r#"
+                        <html>
+                            <head>
+                                <title>Café Søstrene</title>
+                            </head>
+                            <body>
+                                <p>The best coffee in town is served at this small café run by two sisters.</p>
+                            </body>
+                        </html>
+                    "#,
+                "https://www.first.com",
+            ))
+            .expect("failed to insert webpage");
+        index
+            .insert(Webpage::new(
+                r#"
+                        <html>
+                            <head>
+                                <title>Cafe Central</title>
+                            </head>
+                            <body>
+                                <p>A cafe with a long history and cakes that are known all over the city.</p>
+                            </body>
+                        </html>
+                    "#,
+                "https://www.second.com",
+            ))
+            .expect("failed to insert webpage");
+        index.commit().expect("failed to commit index");
+
+        let ranker = Ranker::new(
+            RegionCount::default(),
+            SignalAggregator::default(),
+            index.fastfield_cache(),
+        );
+
+        for (query, expected) in [
+            ("cafe", 2),
+            ("café", 2),
+            ("ＣＡＦＥ", 2),
+            ("sostrene", 1),
+            ("søstrene", 1),
+            ("central", 1),
+        ] {
+            let query = Query::parse(
+                query,
+                index.schema(),
+                index.tokenizers(),
+                &SignalAggregator::default(),
+            )
+            .expect("Failed to parse query");
+
+            let result = index
+                .search(&query, ranker.collector())
+                .expect("Search failed");
+
+            assert_eq!(result.documents.len(), expected);
+        }
+    }
+
+    #[test]
+    fn diacritics_keep_field_lengths() {
+        let mut index = InvertedIndex::temporary().expect("Unable to open index");
+
+        for (title, url) in [
+            ("Café Lumière", "https://www.first.com"),
+            ("Cafe Lumiere", "https://www.other.com"),
+        ] {
+            index
+                .insert(Webpage::new(
+                    &format!(
+                        r#"
+                        <html>
+                            <head>
+                                <title>{title}</title>
+                            </head>
+                            <body>
+                                <p>Coffee and cakes by the river.</p>
+                            </body>
+                        </html>
+                    "#
+                    ),
+                    url,
+                ))
+                .expect("failed to insert webpage");
+        }
+        index.commit().expect("failed to commit index");
+
+        let ranker = Ranker::new(
+            RegionCount::default(),
+            SignalAggregator::default(),
+            index.fastfield_cache(),
+        );
+
+        let search = |query: &str| {
+            let query = Query::parse(
+                query,
+                index.schema(),
+                index.tokenizers(),
+                &SignalAggregator::default(),
+            )
+            .expect("Failed to parse query");
+
+            index
+                .search_initial(&query, ranker.collector())
+                .expect("Search failed")
+                .top_websites
+        };
+
+        let result = search("cafe");
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].score, result[1].score);
+
+        let result = search("café");
+        assert_eq!(result.len(), 2);
+        assert!(result[0].score > result[1].score);
+
+        // the exact spelling is preferred when the query has diacritics
+        let query = Query::parse(
+            "café",
+            index.schema(),
+            index.tokenizers(),
+            &SignalAggregator::default(),
+        )
+        .expect("Failed to parse query");
+        let result = index
+            .search(&query, ranker.collector())
+            .expect("Search failed");
+        assert_eq!(result.documents[0].url, "https://www.first.com");
+    }
+
+    #[test]
+    fn query_language() {
+        assert_eq!(
+            detect_langs("die Kinder spielen jeden Nachmittag in den Gärten hinter dem Haus"),
+            (Some(Lang::Deu), vec![Lang::Deu])
+        );
+        assert!(detect_langs("rust").1.contains(&Lang::Eng));
+    }
+
+    #[test]
+    fn stemmed_in_query_language() {
+        let mut index = InvertedIndex::temporary().expect("Unable to open index");
+
+        index
+            .insert(Webpage::new(
+                r#"
+                        <html>
+                            <head>
+                                <title>Spielen im Freien</title>
+                            </head>
+                            <body>
+                                <p>Die Kinder spielen jeden Nachmittag in dem Garten hinter dem Haus, wenn das Wetter es zulässt. Im Sommer verbringen sie dort fast den ganzen Tag und kommen erst am Abend wieder herein.</p>
+                            </body>
+                        </html>
+                    "#,
+                "https://www.first.com",
+            ))
+            .expect("failed to insert webpage");
+        index.commit().expect("failed to commit index");
+
+        let ranker = Ranker::new(
+            RegionCount::default(),
+            SignalAggregator::default(),
+            index.fastfield_cache(),
+        );
+
+        let query = Query::parse(
+            "die Kinder spielen jeden Nachmittag in den Gärten hinter dem Haus",
+            index.schema(),
+            index.tokenizers(),
+            &SignalAggregator::default(),
+        )
+        .expect("Failed to parse query");
+
+        assert_eq!(query.lang(), Some(Lang::Deu));
+
+        let result = index
+            .search(&query, ranker.collector())
+            .expect("Search failed");
+
+        assert_eq!(result.documents.len(), 1);
+        assert_eq!(result.documents[0].url, "https://www.first.com");
+    }
+
     #[test]
     fn empty_query() {
         let schema = Arc::new(create_schema());
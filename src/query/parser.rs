@@ -15,25 +15,37 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use tantivy::{
-    query::{BooleanQuery, BoostQuery, Occur, PhraseQuery, TermQuery},
+    query::{BooleanQuery, BoostQuery, EmptyQuery, Occur, PhraseQuery, TermQuery},
     schema::IndexRecordOption,
     tokenizer::{TextAnalyzer, TokenizerManager},
 };
 
 use crate::{
     bangs::BANG_PREFIX,
-    ranking::FieldBoost,
+    ranking::{
+        bm25::{Bm25FField, Bm25FQuery, TermScoring},
+        FieldBoost,
+    },
     schema::{Field, TextField, ALL_FIELDS},
+    synonyms::Synonyms,
+    tokenizer::Stemmed,
+    webpage::FileType,
 };
+use whatlang::Lang;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Term {
     Simple(String),
+    /// A term in quotes, which is not expanded with synonyms.
+    Exact(String),
     Not(Box<Term>),
     Site(String),
     Title(String),
     Body(String),
     Url(String),
+    FileType(String),
+    Heading(String),
+    Code(String),
     PossibleBang(String),
 }
 
@@ -41,21 +53,32 @@ impl ToString for Term {
     fn to_string(&self) -> String {
         match self {
             Term::Simple(term) => term.clone(),
+            Term::Exact(term) => "\"".to_string() + term.as_str() + "\"",
             Term::Not(term) => "-".to_string() + term.to_string().as_str(),
             Term::Site(site) => "site:".to_string() + site.as_str(),
             Term::Title(title) => "intitle:".to_string() + title.as_str(),
             Term::Body(body) => "inbody:".to_string() + body.as_str(),
             Term::Url(url) => "inurl:".to_string() + url.as_str(),
+            Term::FileType(filetype) => "filetype:".to_string() + filetype.as_str(),
+            Term::Heading(heading) => "inheading:".to_string() + heading.as_str(),
+            Term::Code(code) => "incode:".to_string() + code.as_str(),
             Term::PossibleBang(bang) => "!".to_string() + bang.as_str(),
         }
     }
 }
 
+/// Synonyms only match when the original term does not, or add to its score,
+/// but should never score as high as the term itself.
+const SYNONYM_BOOST: f32 = 0.5;
+
 fn simple_into_tantivy(
     term: &str,
+    synonyms: &[String],
     fields: &[(tantivy::schema::Field, &tantivy::schema::FieldEntry)],
     tokenizer_manager: &TokenizerManager,
     field_boost: &FieldBoost,
+    term_scoring: TermScoring,
+    langs: &[Lang],
 ) -> Vec<(Occur, Box<dyn tantivy::query::Query + 'static>)> {
     let (backlink_field, backlink_field_entry) = fields
         .iter()
@@ -67,16 +90,41 @@ fn simple_into_tantivy(
         })
         .unwrap();
 
+    let mut query: Box<dyn tantivy::query::Query + 'static> =
+        Box::new(BooleanQuery::new(Term::into_tantivy_simple(
+            term,
+            fields,
+            tokenizer_manager,
+            field_boost,
+            term_scoring,
+            langs,
+        )));
+
+    if !synonyms.is_empty() {
+        let mut alternatives = vec![(Occur::Should, query)];
+
+        for synonym in synonyms {
+            alternatives.push((
+                Occur::Should,
+                Box::new(BoostQuery::new(
+                    Box::new(BooleanQuery::new(Term::into_tantivy_simple(
+                        synonym,
+                        fields,
+                        tokenizer_manager,
+                        field_boost,
+                        term_scoring,
+                        langs,
+                    ))),
+                    SYNONYM_BOOST,
+                )),
+            ));
+        }
+
+        query = Box::new(BooleanQuery::new(alternatives));
+    }
+
     vec![
-        (
-            Occur::Must,
-            Box::new(BooleanQuery::new(Term::into_tantivy_simple(
-                term,
-                fields,
-                tokenizer_manager,
-                field_boost,
-            ))),
-        ),
+        (Occur::Must, query),
         (
             Occur::Should,
             Box::new(Term::tantivy_term_query(
@@ -91,20 +139,44 @@ fn simple_into_tantivy(
 }
 
 impl Term {
+    /// `langs` are the languages the query might be written in, used to stem the terms.
     pub fn as_tantivy_query(
         &self,
         fields: &[(tantivy::schema::Field, &tantivy::schema::FieldEntry)],
         tokenizer_manager: &TokenizerManager,
         field_boost: &FieldBoost,
+        term_scoring: TermScoring,
+        langs: &[Lang],
+        synonyms: &Synonyms,
     ) -> Vec<(Occur, Box<dyn tantivy::query::Query + 'static>)> {
         match self {
-            Term::Simple(term) => simple_into_tantivy(term, fields, tokenizer_manager, field_boost),
+            Term::Simple(term) => simple_into_tantivy(
+                term,
+                synonyms.get(term),
+                fields,
+                tokenizer_manager,
+                field_boost,
+                term_scoring,
+                langs,
+            ),
+            Term::Exact(term) => simple_into_tantivy(
+                term,
+                &[],
+                fields,
+                tokenizer_manager,
+                field_boost,
+                term_scoring,
+                langs,
+            ),
             Term::Not(subterm) => vec![(
                 Occur::MustNot,
                 Box::new(BooleanQuery::new(subterm.as_tantivy_query(
                     fields,
                     tokenizer_manager,
                     field_boost,
+                    term_scoring,
+                    langs,
+                    synonyms,
                 ))),
             )],
             Term::Site(site) => vec![(
@@ -161,12 +233,71 @@ impl Term {
                     Term::tantivy_term_query(field, entry, tokenizer_manager, field_boost, url),
                 )]
             }
+            Term::FileType(filetype) => {
+                let (field, entry) = fields
+                    .iter()
+                    .find(|(field, _)| {
+                        matches!(
+                            ALL_FIELDS[field.field_id() as usize],
+                            Field::Text(TextField::FileType)
+                        )
+                    })
+                    .unwrap();
+                vec![(
+                    Occur::Must,
+                    Term::tantivy_term_query(
+                        field,
+                        entry,
+                        tokenizer_manager,
+                        field_boost,
+                        filetype,
+                    ),
+                )]
+            }
+            Term::Heading(heading) => {
+                let (field, entry) = fields
+                    .iter()
+                    .find(|(field, _)| {
+                        matches!(
+                            ALL_FIELDS[field.field_id() as usize],
+                            Field::Text(TextField::Headings)
+                        )
+                    })
+                    .unwrap();
+                vec![(
+                    Occur::Must,
+                    Term::tantivy_term_query(field, entry, tokenizer_manager, field_boost, heading),
+                )]
+            }
+            Term::Code(code) => {
+                let (field, entry) = fields
+                    .iter()
+                    .find(|(field, _)| {
+                        matches!(
+                            ALL_FIELDS[field.field_id() as usize],
+                            Field::Text(TextField::Code)
+                        )
+                    })
+                    .unwrap();
+                vec![(
+                    Occur::Must,
+                    Term::tantivy_term_query(field, entry, tokenizer_manager, field_boost, code),
+                )]
+            }
             Term::PossibleBang(text) => {
                 let mut term = String::new();
                 term.push(BANG_PREFIX);
                 term.push_str(text);
 
-                simple_into_tantivy(&term, fields, tokenizer_manager, field_boost)
+                simple_into_tantivy(
+                    &term,
+                    &[],
+                    fields,
+                    tokenizer_manager,
+                    field_boost,
+                    term_scoring,
+                    langs,
+                )
             }
         }
     }
@@ -176,18 +307,94 @@ impl Term {
         fields: &[(tantivy::schema::Field, &tantivy::schema::FieldEntry)],
         tokenizer_manager: &TokenizerManager,
         field_boost: &FieldBoost,
+        term_scoring: TermScoring,
+        langs: &[Lang],
     ) -> Vec<(Occur, Box<dyn tantivy::query::Query + 'static>)> {
-        fields
+        let mut queries = Vec::new();
+        let mut bm25f_fields = Vec::new();
+
+        for (field, entry) in fields
             .iter()
             .filter(|(field, _)| ALL_FIELDS[field.field_id() as usize].is_searchable())
-            .into_iter()
-            .map(|(field, entry)| {
-                (
-                    Occur::Should,
-                    Term::tantivy_term_query(field, entry, tokenizer_manager, field_boost, term),
-                )
-            })
-            .collect()
+        {
+            if term_scoring == TermScoring::Bm25F {
+                let weight =
+                    field_boost.get(&ALL_FIELDS[field.field_id() as usize].as_text().unwrap());
+
+                // phrases and negatively boosted fields are still scored per field
+                if weight > 0.0 {
+                    if let Some(terms) =
+                        Term::single_word_forms(field, entry, tokenizer_manager, term, langs)
+                    {
+                        bm25f_fields.push(Bm25FField {
+                            terms,
+                            weight: weight as f32,
+                        });
+                        continue;
+                    }
+                }
+            }
+
+            let query = if Term::is_stemmed(entry) {
+                Term::tantivy_stemmed_query(field, field_boost, term, langs)
+            } else {
+                Term::tantivy_term_query(field, entry, tokenizer_manager, field_boost, term)
+            };
+
+            queries.push((Occur::Should, query));
+        }
+
+        if !bm25f_fields.is_empty() {
+            queries.push((
+                Occur::Should,
+                Box::new(Bm25FQuery::new(bm25f_fields)) as Box<dyn tantivy::query::Query>,
+            ));
+        }
+
+        queries
+    }
+
+    /// All forms of the term in the field, if the term is a single word in the field.
+    fn single_word_forms(
+        field: &tantivy::schema::Field,
+        entry: &tantivy::schema::FieldEntry,
+        tokenizer_manager: &TokenizerManager,
+        term: &str,
+        langs: &[Lang],
+    ) -> Option<Vec<tantivy::Term>> {
+        let processed: Vec<Vec<Vec<tantivy::Term>>> = if Term::is_stemmed(entry) {
+            langs
+                .iter()
+                .map(|lang| {
+                    let analyzer = TextAnalyzer::from(Stemmed::with_forced_language(*lang));
+                    Term::process_tantivy_term(term, Some(analyzer), *field)
+                })
+                .collect()
+        } else {
+            let analyzer = Term::get_tantivy_analyzer(entry, tokenizer_manager);
+            vec![Term::process_tantivy_term(term, analyzer, *field)]
+        };
+
+        let mut forms = Vec::new();
+
+        for mut positions in processed {
+            if positions.len() != 1 {
+                return None;
+            }
+
+            // the parts of a code identifier share its position, before the identifier itself
+            if let Some(form) = positions.pop().unwrap().pop() {
+                if !forms.contains(&form) {
+                    forms.push(form);
+                }
+            }
+        }
+
+        if forms.is_empty() {
+            None
+        } else {
+            Some(forms)
+        }
     }
 
     fn into_tantivy_site(
@@ -223,12 +430,66 @@ impl Term {
         term: &str,
     ) -> Box<dyn tantivy::query::Query + 'static> {
         let analyzer = Term::get_tantivy_analyzer(entry, tokenizer_manager);
-        let mut processed_terms = Term::process_tantivy_term(term, analyzer, *field);
+        let processed_terms = Term::process_tantivy_term(term, analyzer, *field);
+
+        Term::boosted_query(field, field_boost, processed_terms)
+    }
+
+    /// Query a stemmed field with the stemmer of each language the term might be written in.
+    fn tantivy_stemmed_query(
+        field: &tantivy::schema::Field,
+        field_boost: &FieldBoost,
+        term: &str,
+        langs: &[Lang],
+    ) -> Box<dyn tantivy::query::Query + 'static> {
+        let mut stemmed: Vec<Vec<Vec<tantivy::Term>>> = Vec::new();
+
+        for lang in langs {
+            let analyzer = TextAnalyzer::from(Stemmed::with_forced_language(*lang));
+            let processed_terms = Term::process_tantivy_term(term, Some(analyzer), *field);
 
+            if !stemmed.contains(&processed_terms) {
+                stemmed.push(processed_terms);
+            }
+        }
+
+        if stemmed.len() == 1 {
+            Term::boosted_query(field, field_boost, stemmed.pop().unwrap())
+        } else {
+            Box::new(BooleanQuery::new(
+                stemmed
+                    .into_iter()
+                    .map(|processed_terms| {
+                        (
+                            Occur::Should,
+                            Term::boosted_query(field, field_boost, processed_terms),
+                        )
+                    })
+                    .collect(),
+            ))
+        }
+    }
+
+    fn boosted_query(
+        field: &tantivy::schema::Field,
+        field_boost: &FieldBoost,
+        mut processed_terms: Vec<Vec<tantivy::Term>>,
+    ) -> Box<dyn tantivy::query::Query + 'static> {
         let processed_query = if processed_terms.len() > 1 {
-            Box::new(PhraseQuery::new(processed_terms)) as Box<dyn tantivy::query::Query>
+            // a character in Chinese, Japanese, Korean or Thai text shares its position
+            // with the bigram it starts, and the parts of a code identifier share the
+            // position of the identifier, which is the last term at the position
+            let terms = processed_terms
+                .into_iter()
+                .filter_map(|mut forms| forms.pop())
+                .collect();
+
+            Box::new(PhraseQuery::new(terms)) as Box<dyn tantivy::query::Query>
+        } else if processed_terms.is_empty() {
+            // e.g. punctuation for the code tokenizer
+            Box::new(EmptyQuery)
         } else {
-            let term = processed_terms.pop().unwrap();
+            let term = processed_terms.pop().unwrap().pop().unwrap();
             Box::new(TermQuery::new(
                 term,
                 IndexRecordOption::WithFreqsAndPositions,
@@ -241,6 +502,19 @@ impl Term {
         Box::new(BoostQuery::new(processed_query, boost))
     }
 
+    fn tokenizer_name(entry: &tantivy::schema::FieldEntry) -> Option<&str> {
+        match entry.field_type() {
+            tantivy::schema::FieldType::Str(options) => options
+                .get_indexing_options()
+                .map(|indexing_options| indexing_options.tokenizer()),
+            _ => None,
+        }
+    }
+
+    fn is_stemmed(entry: &tantivy::schema::FieldEntry) -> bool {
+        Term::tokenizer_name(entry) == Some(Stemmed::as_str())
+    }
+
     pub fn get_tantivy_analyzer(
         entry: &tantivy::schema::FieldEntry,
         tokenizer_manager: &tantivy::tokenizer::TokenizerManager,
@@ -256,22 +530,29 @@ impl Term {
         }
     }
 
+    /// The terms at each position of the analyzed text. A position has more than one
+    /// term when a character in Chinese, Japanese, Korean or Thai text is followed by
+    /// the bigram it starts.
     pub fn process_tantivy_term(
         term: &str,
         analyzer: Option<TextAnalyzer>,
         tantivy_field: tantivy::schema::Field,
-    ) -> Vec<tantivy::Term> {
+    ) -> Vec<Vec<tantivy::Term>> {
         match analyzer {
-            None => vec![tantivy::Term::from_field_text(tantivy_field, term)],
+            None => vec![vec![tantivy::Term::from_field_text(tantivy_field, term)]],
             Some(tokenizer) => {
-                let mut terms: Vec<tantivy::Term> = Vec::new();
+                let mut terms: Vec<(usize, Vec<tantivy::Term>)> = Vec::new();
                 let mut token_stream = tokenizer.token_stream(term);
                 token_stream.process(&mut |token| {
                     let term = tantivy::Term::from_field_text(tantivy_field, &token.text);
-                    terms.push(term);
+
+                    match terms.last_mut() {
+                        Some((position, forms)) if *position == token.position => forms.push(term),
+                        _ => terms.push((token.position, vec![term])),
+                    }
                 });
 
-                terms
+                terms.into_iter().map(|(_, forms)| forms).collect()
             }
         }
     }
@@ -309,6 +590,24 @@ fn parse_term(term: &str) -> Box<Term> {
         } else {
             Box::new(Term::Simple(term.to_string()))
         }
+    } else if let Some(filetype) = term.strip_prefix("filetype:") {
+        // unknown file types could never match, so they are searched as normal terms
+        match FileType::from_name(filetype) {
+            Some(filetype) => Box::new(Term::FileType(filetype.as_str().to_string())),
+            None => Box::new(Term::Simple(term.to_string())),
+        }
+    } else if let Some(heading) = term.strip_prefix("inheading:") {
+        if !heading.is_empty() {
+            Box::new(Term::Heading(heading.to_string()))
+        } else {
+            Box::new(Term::Simple(term.to_string()))
+        }
+    } else if let Some(code) = term.strip_prefix("incode:") {
+        if !code.is_empty() {
+            Box::new(Term::Code(code.to_string()))
+        } else {
+            Box::new(Term::Simple(term.to_string()))
+        }
     } else if let Some(bang) = term.strip_prefix(BANG_PREFIX) {
         Box::new(Term::PossibleBang(bang.to_string()))
     } else {
@@ -316,9 +615,39 @@ fn parse_term(term: &str) -> Box<Term> {
     }
 }
 
+/// Terms inside quotes are taken as they are, without operators or synonyms.
 #[allow(clippy::vec_box)]
 pub fn parse(query: &str) -> Vec<Box<Term>> {
-    query.split_whitespace().map(parse_term).collect()
+    let mut terms = Vec::new();
+    let mut in_quotes = false;
+
+    for term in query.split_whitespace() {
+        let mut exact = term;
+
+        if !in_quotes {
+            match term.strip_prefix('"') {
+                Some(stripped) => {
+                    exact = stripped;
+                    in_quotes = true;
+                }
+                None => {
+                    terms.push(parse_term(term));
+                    continue;
+                }
+            }
+        }
+
+        if let Some(stripped) = exact.strip_suffix('"') {
+            exact = stripped;
+            in_quotes = false;
+        }
+
+        if !exact.is_empty() {
+            terms.push(Box::new(Term::Exact(exact.to_string())));
+        }
+    }
+
+    terms
 }
 
 #[cfg(test)]
@@ -398,4 +727,75 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn filetype() {
+        assert_eq!(
+            parse("rust specification filetype:PDF"),
+            vec![
+                Box::new(Term::Simple("rust".to_string())),
+                Box::new(Term::Simple("specification".to_string())),
+                Box::new(Term::FileType("pdf".to_string()))
+            ]
+        );
+        assert_eq!(
+            parse("readme filetype:markdown"),
+            vec![
+                Box::new(Term::Simple("readme".to_string())),
+                Box::new(Term::FileType("md".to_string()))
+            ]
+        );
+        assert_eq!(
+            parse("filetype:"),
+            vec![Box::new(Term::Simple("filetype:".to_string()))]
+        );
+        assert_eq!(
+            parse("installer filetype:exe"),
+            vec![
+                Box::new(Term::Simple("installer".to_string())),
+                Box::new(Term::Simple("filetype:exe".to_string()))
+            ]
+        );
+    }
+
+    #[test]
+    fn heading_and_code() {
+        assert_eq!(
+            parse("borrowing inheading:lifetimes incode:HashMap"),
+            vec![
+                Box::new(Term::Simple("borrowing".to_string())),
+                Box::new(Term::Heading("lifetimes".to_string())),
+                Box::new(Term::Code("HashMap".to_string()))
+            ]
+        );
+        assert_eq!(
+            parse("incode:"),
+            vec![Box::new(Term::Simple("incode:".to_string()))]
+        );
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(
+            parse("\"k8s\" pods"),
+            vec![
+                Box::new(Term::Exact("k8s".to_string())),
+                Box::new(Term::Simple("pods".to_string()))
+            ]
+        );
+
+        assert_eq!(
+            parse("js \"site:example.com promise\""),
+            vec![
+                Box::new(Term::Simple("js".to_string())),
+                Box::new(Term::Exact("site:example.com".to_string())),
+                Box::new(Term::Exact("promise".to_string()))
+            ]
+        );
+
+        assert_eq!(
+            parse("\" nyc"),
+            vec![Box::new(Term::Exact("nyc".to_string()))]
+        );
+    }
 }
@@ -3,8 +3,12 @@
 use serde::{Deserialize, Serialize};
 
 use tantivy::fieldnorm::FieldNormReader;
-use tantivy::query::Explanation;
-use tantivy::{Score, Searcher, Term};
+use tantivy::postings::SegmentPostings;
+use tantivy::query::{EmptyScorer, Explanation, Scorer};
+use tantivy::schema::IndexRecordOption;
+use tantivy::{
+    DocId, DocSet, Postings, Score, Searcher, SegmentReader, TantivyError, Term, TERMINATED,
+};
 
 const K1: Score = 1.2;
 const B: Score = 0.75;
@@ -15,8 +19,12 @@ pub(crate) fn idf(doc_freq: u64, doc_count: u64) -> Score {
     (1.0 + x).ln()
 }
 
+fn length_normalization(fieldnorm: u32, average_fieldnorm: Score) -> Score {
+    1.0 - B + B * fieldnorm as Score / average_fieldnorm
+}
+
 fn cached_tf_component(fieldnorm: u32, average_fieldnorm: Score) -> Score {
-    K1 * (1.0 - B + B * fieldnorm as Score / average_fieldnorm)
+    K1 * length_normalization(fieldnorm, average_fieldnorm)
 }
 
 fn compute_tf_cache(average_fieldnorm: Score) -> [Score; 256] {
@@ -28,6 +36,52 @@ fn compute_tf_cache(average_fieldnorm: Score) -> [Score; 256] {
     cache
 }
 
+fn compute_length_normalization_cache(average_fieldnorm: Score) -> [Score; 256] {
+    let mut cache: [Score; 256] = [0.0; 256];
+    for (fieldnorm_id, cache_mut) in cache.iter_mut().enumerate() {
+        let fieldnorm = FieldNormReader::id_to_fieldnorm(fieldnorm_id as u8);
+        *cache_mut = length_normalization(fieldnorm, average_fieldnorm);
+    }
+    cache
+}
+
+fn average_fieldnorm(
+    searcher: &Searcher,
+    field: tantivy::schema::Field,
+    total_num_docs: u64,
+) -> tantivy::Result<Score> {
+    let mut total_num_tokens = 0u64;
+    for segment_reader in searcher.segment_readers() {
+        let inverted_index = segment_reader.inverted_index(field)?;
+        total_num_tokens += inverted_index.total_num_tokens();
+    }
+
+    Ok(total_num_tokens as Score / total_num_docs.max(1) as Score)
+}
+
+/// How the scores of a query term in the different fields are combined.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TermScoring {
+    /// Term frequencies are weighted by the field boosts and summed across the fields
+    /// before saturation (BM25F), so a term occurring in several fields
+    /// with the same text (e.g. title and stemmed title) is not counted several times.
+    #[default]
+    Bm25F,
+    /// Each field is scored with BM25 on its own and the field scores are summed.
+    PerField,
+}
+
+impl TermScoring {
+    /// The term scoring set with `! term_scoring: <name>` in a goggle.
+    pub fn from_name(name: &str) -> Option<TermScoring> {
+        match name {
+            "bm25f" => Some(TermScoring::Bm25F),
+            "per_field" => Some(TermScoring::PerField),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Bm25Params {
     pub idf: Score,
@@ -158,3 +212,468 @@ impl Bm25Weight {
         explanation
     }
 }
+
+/// A query term in one of the fields combined by [`Bm25FQuery`]. There are several terms
+/// when the field has more than one form of the word (e.g. stemmed in different languages),
+/// in which case the most frequent form is used.
+#[derive(Clone, Debug)]
+pub struct Bm25FField {
+    pub terms: Vec<Term>,
+    pub weight: Score,
+}
+
+/// Scores a single word across several fields with BM25F.
+#[derive(Clone, Debug)]
+pub struct Bm25FQuery {
+    fields: Vec<Bm25FField>,
+}
+
+impl Bm25FQuery {
+    pub fn new(fields: Vec<Bm25FField>) -> Self {
+        assert!(
+            fields.iter().all(|field| field.weight > 0.0),
+            "BM25F requires positive field weights"
+        );
+
+        Self { fields }
+    }
+}
+
+impl tantivy::query::Query for Bm25FQuery {
+    fn weight(
+        &self,
+        searcher: &Searcher,
+        scoring_enabled: bool,
+    ) -> tantivy::Result<Box<dyn tantivy::query::Weight>> {
+        let total_num_docs: u64 = searcher
+            .segment_readers()
+            .iter()
+            .map(|segment_reader| u64::from(segment_reader.max_doc()))
+            .sum();
+
+        // the field weights are relative to the highest weight, which scales the final score,
+        // so a match in only the highest weighted field scores as in the per-field mode
+        let max_weight = self
+            .fields
+            .iter()
+            .map(|field| field.weight)
+            .fold(0.0, Score::max);
+
+        let mut doc_freq = 0;
+        let mut fields = Vec::with_capacity(self.fields.len());
+
+        for field in &self.fields {
+            for term in &field.terms {
+                doc_freq = doc_freq.max(searcher.doc_freq(term)?);
+            }
+
+            let average_fieldnorm = match field.terms.first() {
+                Some(term) => average_fieldnorm(searcher, term.field(), total_num_docs)?,
+                None => continue,
+            };
+
+            fields.push(Bm25FFieldWeight {
+                terms: field.terms.clone(),
+                weight: field.weight / max_weight,
+                cache: compute_length_normalization_cache(average_fieldnorm),
+            });
+        }
+
+        Ok(Box::new(Bm25FWeight {
+            idf: idf(doc_freq, total_num_docs),
+            max_weight,
+            fields,
+            scoring_enabled,
+        }))
+    }
+
+    fn query_terms<'a>(&'a self, visitor: &mut dyn FnMut(&'a Term, bool)) {
+        for field in &self.fields {
+            for term in &field.terms {
+                visitor(term, false);
+            }
+        }
+    }
+}
+
+struct Bm25FFieldWeight {
+    terms: Vec<Term>,
+    weight: Score,
+    cache: [Score; 256],
+}
+
+struct Bm25FWeight {
+    idf: Score,
+    max_weight: Score,
+    fields: Vec<Bm25FFieldWeight>,
+    scoring_enabled: bool,
+}
+
+impl Bm25FWeight {
+    fn fieldnorm_reader(
+        &self,
+        reader: &SegmentReader,
+        field: tantivy::schema::Field,
+    ) -> tantivy::Result<FieldNormReader> {
+        if self.scoring_enabled {
+            if let Some(fieldnorm_reader) = reader.fieldnorms_readers().get_field(field)? {
+                return Ok(fieldnorm_reader);
+            }
+        }
+        Ok(FieldNormReader::constant(reader.max_doc(), 1))
+    }
+
+    fn bm25f_scorer(
+        &self,
+        reader: &SegmentReader,
+        boost: Score,
+    ) -> tantivy::Result<Option<Bm25FScorer>> {
+        let mut fields = Vec::with_capacity(self.fields.len());
+
+        for field in &self.fields {
+            let mut postings = Vec::with_capacity(field.terms.len());
+
+            for term in &field.terms {
+                if let Some(term_postings) = reader
+                    .inverted_index(term.field())?
+                    .read_postings(term, IndexRecordOption::WithFreqs)?
+                {
+                    postings.push(term_postings);
+                }
+            }
+
+            if let Some(term) = field.terms.first() {
+                if !postings.is_empty() {
+                    fields.push(Bm25FFieldScorer {
+                        postings,
+                        fieldnorm_reader: self.fieldnorm_reader(reader, term.field())?,
+                        weight: field.weight,
+                        cache: field.cache,
+                    });
+                }
+            }
+        }
+
+        if fields.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(Bm25FScorer::new(
+            self.idf * (1.0 + K1) * self.max_weight * boost,
+            fields,
+        )))
+    }
+}
+
+impl tantivy::query::Weight for Bm25FWeight {
+    fn scorer(&self, reader: &SegmentReader, boost: Score) -> tantivy::Result<Box<dyn Scorer>> {
+        if let Some(scorer) = self.bm25f_scorer(reader, boost)? {
+            Ok(Box::new(scorer))
+        } else {
+            Ok(Box::new(EmptyScorer))
+        }
+    }
+
+    fn explain(&self, reader: &SegmentReader, doc: DocId) -> tantivy::Result<Explanation> {
+        let mut scorer = match self.bm25f_scorer(reader, 1.0)? {
+            Some(scorer) if scorer.seek(doc) == doc => scorer,
+            _ => {
+                return Err(TantivyError::InvalidArgument(format!(
+                    "Document #({}) does not match",
+                    doc
+                )))
+            }
+        };
+
+        let term_freq = scorer.weighted_term_freq();
+
+        let mut tf_explanation =
+            Explanation::new("freq / (freq + k1)", term_freq / (term_freq + K1));
+        tf_explanation.add_const(
+            "freq, sum of weighted and length normalized occurrences of term within the fields",
+            term_freq,
+        );
+        tf_explanation.add_const("k1, term saturation parameter", K1);
+        tf_explanation.add_const("b, length normalization parameter", B);
+
+        let mut explanation = Explanation::new("BM25F, product of...", scorer.score());
+        explanation.add_detail(Explanation::new("(K1+1)", K1 + 1.0));
+        explanation.add_detail(Explanation::new(
+            "idf, computed from the field where the term is most frequent",
+            self.idf,
+        ));
+        explanation.add_detail(Explanation::new("highest field weight", self.max_weight));
+        explanation.add_detail(tf_explanation);
+        Ok(explanation)
+    }
+}
+
+struct Bm25FFieldScorer {
+    postings: Vec<SegmentPostings>,
+    fieldnorm_reader: FieldNormReader,
+    weight: Score,
+    cache: [Score; 256],
+}
+
+impl Bm25FFieldScorer {
+    fn doc(&self) -> DocId {
+        self.postings
+            .iter()
+            .map(|postings| postings.doc())
+            .min()
+            .unwrap_or(TERMINATED)
+    }
+
+    fn weighted_term_freq(&self, doc: DocId) -> Score {
+        let term_freq = self
+            .postings
+            .iter()
+            .filter(|postings| postings.doc() == doc)
+            .map(|postings| postings.term_freq())
+            .max()
+            .unwrap_or(0);
+
+        if term_freq == 0 {
+            return 0.0;
+        }
+
+        let norm = self.cache[self.fieldnorm_reader.fieldnorm_id(doc) as usize];
+        self.weight * term_freq as Score / norm
+    }
+}
+
+struct Bm25FScorer {
+    weight: Score,
+    fields: Vec<Bm25FFieldScorer>,
+    doc: DocId,
+}
+
+impl Bm25FScorer {
+    fn new(weight: Score, fields: Vec<Bm25FFieldScorer>) -> Self {
+        let mut scorer = Self {
+            weight,
+            fields,
+            doc: TERMINATED,
+        };
+
+        scorer.doc = scorer.min_doc();
+        scorer
+    }
+
+    fn min_doc(&self) -> DocId {
+        self.fields
+            .iter()
+            .map(|field| field.doc())
+            .min()
+            .unwrap_or(TERMINATED)
+    }
+
+    fn weighted_term_freq(&self) -> Score {
+        self.fields
+            .iter()
+            .map(|field| field.weighted_term_freq(self.doc))
+            .sum()
+    }
+}
+
+impl Scorer for Bm25FScorer {
+    fn score(&mut self) -> Score {
+        let term_freq = self.weighted_term_freq();
+        self.weight * term_freq / (term_freq + K1)
+    }
+}
+
+impl DocSet for Bm25FScorer {
+    fn advance(&mut self) -> DocId {
+        if self.doc == TERMINATED {
+            return TERMINATED;
+        }
+
+        for field in &mut self.fields {
+            for postings in &mut field.postings {
+                if postings.doc() == self.doc {
+                    postings.advance();
+                }
+            }
+        }
+
+        self.doc = self.min_doc();
+        self.doc
+    }
+
+    fn seek(&mut self, target: DocId) -> DocId {
+        for field in &mut self.fields {
+            for postings in &mut field.postings {
+                if postings.doc() < target {
+                    postings.seek(target);
+                }
+            }
+        }
+
+        self.doc = self.min_doc();
+        self.doc
+    }
+
+    fn doc(&self) -> DocId {
+        self.doc
+    }
+
+    fn size_hint(&self) -> u32 {
+        self.fields
+            .iter()
+            .flat_map(|field| field.postings.iter())
+            .map(|postings| postings.size_hint())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        inverted_index::InvertedIndex,
+        query::Query,
+        ranking::{Ranker, SignalAggregator},
+        webpage::{region::RegionCount, Webpage},
+    };
+
+    use super::*;
+
+    fn search(index: &InvertedIndex, query: &str, term_scoring: TermScoring) -> Vec<String> {
+        let mut aggregator = SignalAggregator::default();
+        aggregator.set_term_scoring(term_scoring);
+
+        let query = Query::parse(query, index.schema(), index.tokenizers(), &aggregator)
+            .expect("Failed to parse query");
+        let ranker = Ranker::new(RegionCount::default(), aggregator, index.fastfield_cache());
+
+        index
+            .search(&query, ranker.collector())
+            .expect("Search failed")
+            .documents
+            .into_iter()
+            .map(|webpage| webpage.url)
+            .collect()
+    }
+
+    #[test]
+    fn bm25f_and_per_field_query_set() {
+        let mut index = InvertedIndex::temporary().expect("Unable to open index");
+
+        for (title, body, url) in [
+            (
+                "Learn Rust programming",
+                "Rust is a programming language empowering everyone to build reliable and efficient software. Rust programs are fast.",
+                "https://www.rust-lang.org/learn",
+            ),
+            (
+                "Sourdough bread recipe",
+                "Bake a sourdough bread with flour, water and salt. No programming skills needed, but the bread needs a long fermentation.",
+                "https://www.bakery.com/sourdough",
+            ),
+            (
+                "Growing tomatoes in the garden",
+                "Tomatoes grow best in a sunny garden with plenty of water. Serve them on fresh bread.",
+                "https://www.garden.com/tomatoes",
+            ),
+        ] {
+            index
+                .insert(Webpage::new(
+                    &format!(
+                        r#"
+                        <html>
+                            <head>
+                                <title>{title}</title>
+                            </head>
+                            <body>
+                                {body}
+                            </body>
+                        </html>
+                    "#
+                    ),
+                    url,
+                ))
+                .expect("failed to insert webpage");
+        }
+        index.commit().expect("failed to commit index");
+
+        for (query, expected) in [
+            ("rust programming", "https://www.rust-lang.org/learn"),
+            ("programming", "https://www.rust-lang.org/learn"),
+            ("sourdough bread", "https://www.bakery.com/sourdough"),
+            ("bread", "https://www.bakery.com/sourdough"),
+            ("garden tomatoes", "https://www.garden.com/tomatoes"),
+            ("tomatoes", "https://www.garden.com/tomatoes"),
+        ] {
+            let mut bm25f = search(&index, query, TermScoring::Bm25F);
+            let mut per_field = search(&index, query, TermScoring::PerField);
+
+            assert_eq!(bm25f.first().map(String::as_str), Some(expected), "{query}");
+            assert_eq!(
+                per_field.first().map(String::as_str),
+                Some(expected),
+                "{query}"
+            );
+
+            // both modes match the same pages
+            bm25f.sort();
+            per_field.sort();
+            assert_eq!(bm25f, per_field, "{query}");
+        }
+    }
+
+    #[test]
+    fn bm25f_saturates_across_fields() {
+        let mut index = InvertedIndex::temporary().expect("Unable to open index");
+
+        // the titles and bodies have the same lengths, so only the term frequencies differ
+        for (title, body, url) in [
+            (
+                "Ferment guide",
+                "we keep notes about the things we cook at home and share them with friends and family every week too",
+                "https://www.a.com/notes",
+            ),
+            (
+                "Kitchen notes",
+                "ferment the cabbage with salt then ferment it for a week and ferment again if the taste is too mild",
+                "https://www.b.com/notes",
+            ),
+            (
+                "Garden diary",
+                "tomatoes grow best in a sunny garden with plenty of water and some shade in the late afternoon every day",
+                "https://www.c.com/notes",
+            ),
+        ] {
+            index
+                .insert(Webpage::new(
+                    &format!(
+                        r#"
+                        <html>
+                            <head>
+                                <title>{title}</title>
+                            </head>
+                            <body>
+                                {body}
+                            </body>
+                        </html>
+                    "#
+                    ),
+                    url,
+                ))
+                .expect("failed to insert webpage");
+        }
+        index.commit().expect("failed to commit index");
+
+        // per field, the title match is scored with the full title boost on top of the stemmed
+        // title. BM25F adds up the weighted term frequencies of the fields before saturation,
+        // so the term three times in the body counts for more than once in the title
+        assert_eq!(
+            search(&index, "ferment", TermScoring::PerField),
+            vec!["https://www.a.com/notes", "https://www.b.com/notes"]
+        );
+        assert_eq!(
+            search(&index, "ferment", TermScoring::Bm25F),
+            vec!["https://www.b.com/notes", "https://www.a.com/notes"]
+        );
+    }
+}
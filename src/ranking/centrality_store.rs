@@ -46,6 +46,10 @@ impl CentralityStore {
         self.flush();
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = (String, f64)> + '_ {
+        self.inner.iter()
+    }
+
     pub fn flush(&self) {
         self.inner.flush();
     }
@@ -95,10 +95,13 @@ pub enum RawPatternPart {
 #[derive(Debug, PartialEq, Eq)]
 pub enum RawPatternOption {
     Site(String),
+    Schema(String),
     InUrl,
     InTitle,
     InDescription,
     InContent,
+    InHeading,
+    InCode,
     Action(RawAction),
 }
 
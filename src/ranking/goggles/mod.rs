@@ -24,7 +24,9 @@ use std::convert::TryFrom;
 
 use crate::{
     query::union::UnionQuery,
+    ranking::bm25::TermScoring,
     schema::{Field, TextField},
+    synonyms::Synonyms,
     Result,
 };
 use itertools::Itertools;
@@ -34,7 +36,7 @@ use tantivy::{
 };
 
 use self::{
-    ast::{RawAction, RawGoggle, RawInstruction, RawPatternOption, RawPatternPart},
+    ast::{Comment, RawAction, RawGoggle, RawInstruction, RawPatternOption, RawPatternPart},
     const_query::ConstQuery,
     pattern_query::PatternQuery,
 };
@@ -57,9 +59,27 @@ impl TryFrom<RawGoggle> for Goggle {
             instructions.push(Instruction::try_from(inst)?);
         }
 
+        let mut aggregator = SignalAggregator::try_from(raw.alterations)?;
+        let mut synonyms = Synonyms::default();
+
+        for comment in raw.comments {
+            if let Comment::Header { key, value } = comment {
+                match key.as_str() {
+                    "synonym" => synonyms.add(&value),
+                    "term_scoring" => {
+                        if let Some(term_scoring) = TermScoring::from_name(value.trim()) {
+                            aggregator.set_term_scoring(term_scoring);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
         Ok(Self {
-            aggregator: SignalAggregator::try_from(raw.alterations)?,
+            aggregator,
             instructions,
+            synonyms,
         })
     }
 }
@@ -100,10 +120,13 @@ impl TryFrom<RawPatternOption> for PatternOption {
     fn try_from(value: RawPatternOption) -> Result<Self> {
         let res = match value {
             RawPatternOption::Site(site) => PatternOption::Site(site),
+            RawPatternOption::Schema(schema) => PatternOption::Schema(schema),
             RawPatternOption::InUrl => PatternOption::InUrl,
             RawPatternOption::InTitle => PatternOption::InTitle,
             RawPatternOption::InDescription => PatternOption::InDescription,
             RawPatternOption::InContent => PatternOption::InContent,
+            RawPatternOption::InHeading => PatternOption::InHeading,
+            RawPatternOption::InCode => PatternOption::InCode,
             RawPatternOption::Action(action) => PatternOption::Action(action.try_into()?),
         };
 
@@ -142,10 +165,14 @@ pub enum PatternPart {
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum PatternOption {
     Site(String),
+    /// Only match pages with a schema.org item of this `@type`.
+    Schema(String),
     InUrl,
     InTitle,
     InDescription,
     InContent,
+    InHeading,
+    InCode,
     Action(Action),
 }
 
@@ -160,6 +187,8 @@ pub enum Action {
 pub struct Goggle {
     pub aggregator: SignalAggregator,
     pub instructions: Vec<Instruction>,
+    /// Added to the synonyms of the search server when expanding the query.
+    pub synonyms: Synonyms,
 }
 
 impl Goggle {
@@ -189,7 +218,7 @@ impl Goggle {
     }
 }
 
-fn process_site(site: &str, field: tantivy::schema::Field) -> Box<dyn tantivy::query::Query> {
+fn term_query(site: &str, field: tantivy::schema::Field) -> Box<dyn tantivy::query::Query> {
     let term = tantivy::Term::from_field_text(field, site);
     Box::new(TermQuery::new(
         term,
@@ -223,12 +252,22 @@ impl Instruction {
                     subqueries.push((
                         Occur::Must,
                         UnionQuery::from(vec![
-                            process_site(site, domain_field),
-                            process_site(site, site_field),
+                            term_query(site, domain_field),
+                            term_query(site, site_field),
                         ])
                         .box_clone(),
                     ));
                 }
+                PatternOption::Schema(schema_type) => {
+                    let schema_field = schema
+                        .get_field(Field::Text(TextField::SchemaOrgType).name())
+                        .unwrap();
+
+                    subqueries.push((
+                        Occur::Must,
+                        term_query(&schema_type.to_ascii_lowercase(), schema_field),
+                    ));
+                }
                 PatternOption::InUrl if field.is_none() => {
                     field = Some(
                         schema
@@ -257,6 +296,20 @@ impl Instruction {
                             .unwrap(),
                     )
                 }
+                PatternOption::InHeading if field.is_none() => {
+                    field = Some(
+                        schema
+                            .get_field(Field::Text(TextField::Headings).name())
+                            .unwrap(),
+                    )
+                }
+                PatternOption::InCode if field.is_none() => {
+                    field = Some(
+                        schema
+                            .get_field(Field::Text(TextField::Code).name())
+                            .unwrap(),
+                    )
+                }
                 PatternOption::Action(pattern_action) if action.is_none() => {
                     action = Some(*pattern_action)
                 }
@@ -358,6 +411,10 @@ mod tests {
                 backlinks: vec![],
                 host_centrality: 0.0,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 fetch_time_ms: 500,
                 pre_computed_score: 0.0,
                 primary_image: None,
@@ -383,6 +440,10 @@ mod tests {
                 backlinks: vec![],
                 host_centrality: 0.0001,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 primary_image: None,
                 pre_computed_score: 0.0,
                 fetch_time_ms: 500,
@@ -399,6 +460,7 @@ mod tests {
                 goggle_program: None,
                 skip_pages: None,
                 site_rankings: None,
+                cursor: None,
             })
             .unwrap()
             .into_websites()
@@ -422,6 +484,7 @@ mod tests {
                 ),
                 skip_pages: None,
                 site_rankings: None,
+                cursor: None,
             })
             .unwrap()
             .into_websites()
@@ -444,6 +507,7 @@ mod tests {
                 ),
                 skip_pages: None,
                 site_rankings: None,
+                cursor: None,
             })
             .unwrap()
             .into_websites()
@@ -456,6 +520,51 @@ mod tests {
         assert_eq!(res[1].url, "https://www.b.com");
     }
 
+    #[test]
+    fn structural_field_boosts() {
+        let default = SignalAggregator::default();
+        assert_eq!(default.field_boosts().get(&TextField::Headings), 6.0);
+        assert_eq!(default.field_boosts().get(&TextField::MainHeadings), 4.0);
+        assert_eq!(default.field_boosts().get(&TextField::Code), 3.0);
+        assert_eq!(default.field_boosts().get(&TextField::Tables), 1.0);
+
+        let goggle = parse(
+            r#"
+                @field_headings = 1
+                @field_main_headings = 2
+                @field_code = 10
+                @field_tables = 0
+            "#,
+        )
+        .unwrap();
+        let boosts = goggle.aggregator.field_boosts();
+
+        assert_eq!(boosts.get(&TextField::Headings), 1.0);
+        assert_eq!(boosts.get(&TextField::MainHeadings), 2.0);
+        assert_eq!(boosts.get(&TextField::Code), 10.0);
+        assert_eq!(boosts.get(&TextField::Tables), 0.0);
+    }
+
+    #[test]
+    fn term_scoring() {
+        assert_eq!(
+            parse("! name: Default").unwrap().aggregator.term_scoring(),
+            TermScoring::Bm25F
+        );
+        assert_eq!(
+            parse(
+                r#"
+                    ! name: Per field
+                    ! term_scoring: per_field
+                "#
+            )
+            .unwrap()
+            .aggregator
+            .term_scoring(),
+            TermScoring::PerField
+        );
+    }
+
     #[test]
     fn quickstart_as_query() {
         parse(include_str!("../../../testcases/goggles/quickstart.goggle"))
@@ -488,6 +597,10 @@ mod tests {
                 backlinks: vec![],
                 host_centrality: 0.0,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 fetch_time_ms: 500,
                 pre_computed_score: 0.0,
                 primary_image: None,
@@ -513,6 +626,10 @@ mod tests {
                 backlinks: vec![],
                 host_centrality: 0.0001,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 primary_image: None,
                 pre_computed_score: 0.0,
                 fetch_time_ms: 500,
@@ -531,6 +648,7 @@ mod tests {
                 ),
                 skip_pages: None,
                 site_rankings: None,
+                cursor: None,
             })
             .unwrap()
             .into_websites()
@@ -547,6 +665,7 @@ mod tests {
                 ),
                 skip_pages: None,
                 site_rankings: None,
+                cursor: None,
             })
             .unwrap()
             .into_websites()
@@ -563,6 +682,7 @@ mod tests {
                 ),
                 skip_pages: None,
                 site_rankings: None,
+                cursor: None,
             })
             .unwrap()
             .into_websites()
@@ -596,6 +716,10 @@ mod tests {
                 backlinks: vec![],
                 host_centrality: 0.0,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 fetch_time_ms: 500,
                 pre_computed_score: 0.0,
                 primary_image: None,
@@ -621,6 +745,10 @@ mod tests {
                 backlinks: vec![],
                 host_centrality: 0.0001,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 primary_image: None,
                 pre_computed_score: 0.0,
                 fetch_time_ms: 500,
@@ -646,6 +774,10 @@ mod tests {
                 backlinks: vec![],
                 host_centrality: 0.0001,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 primary_image: None,
                 pre_computed_score: 0.0,
                 fetch_time_ms: 500,
@@ -669,6 +801,7 @@ mod tests {
                 ),
                 skip_pages: None,
                 site_rankings: None,
+                cursor: None,
             })
             .unwrap()
             .into_websites()
@@ -679,4 +812,129 @@ mod tests {
         assert_eq!(res.len(), 2);
         assert_eq!(res[0].url, "https://www.a.com/this/is/a/pattern");
     }
+
+    #[test]
+    fn synonyms() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        index
+            .insert(Webpage::new(
+                &format!(
+                    r#"
+                    <html>
+                        <head>
+                            <title>Kubernetes pods explained</title>
+                        </head>
+                        <body>
+                            Kubernetes runs the containers of a pod together. {CONTENT}
+                        </body>
+                    </html>
+                "#
+                ),
+                "https://www.a.com",
+            ))
+            .expect("failed to insert webpage");
+
+        index.commit().expect("failed to commit index");
+        let searcher = LocalSearcher::from(index);
+
+        let search = |query: &str| {
+            searcher
+                .search(&SearchQuery {
+                    original: query.to_string(),
+                    selected_region: None,
+                    goggle_program: Some(
+                        r#"
+                            ! name: Synonyms
+                            ! synonym: k8s, kubernetes
+                        "#
+                        .to_string(),
+                    ),
+                    skip_pages: None,
+                    site_rankings: None,
+                    cursor: None,
+                })
+                .unwrap()
+                .into_websites()
+                .unwrap()
+                .webpages
+                .documents
+        };
+
+        let res = search("k8s pods");
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].url, "https://www.a.com");
+        // the synonyms of the goggle are highlighted in the snippet as well
+        assert!(
+            res[0].snippet.contains("<b>Kubernetes</b>"),
+            "{}",
+            res[0].snippet
+        );
+
+        assert!(search("\"k8s\" pods").is_empty());
+    }
+
+    #[test]
+    fn schema_type() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        index
+            .insert(Webpage::new(
+                &format!(
+                    r#"
+                    <html>
+                        <head>
+                            <title>Website A</title>
+                        </head>
+                        <body>
+                            {CONTENT}
+                        </body>
+                    </html>
+                "#
+                ),
+                "https://www.a.com",
+            ))
+            .expect("failed to insert webpage");
+        index
+            .insert(Webpage::new(
+                &format!(
+                    r#"
+                    <html>
+                        <head>
+                            <title>Website B</title>
+                            <script type="application/ld+json">
+                                {{"@context": "https://schema.org", "@type": "Recipe", "name": "Pancakes"}}
+                            </script>
+                        </head>
+                        <body>
+                            {CONTENT}
+                        </body>
+                    </html>
+                "#
+                ),
+                "https://www.b.com",
+            ))
+            .expect("failed to insert webpage");
+
+        index.commit().expect("failed to commit index");
+        let searcher = LocalSearcher::from(index);
+
+        let res = searcher
+            .search(&SearchQuery {
+                original: "website".to_string(),
+                selected_region: None,
+                goggle_program: Some("$discard\n$schema=Recipe".to_string()),
+                skip_pages: None,
+                site_rankings: None,
+                cursor: None,
+            })
+            .unwrap()
+            .into_websites()
+            .unwrap()
+            .webpages
+            .documents;
+
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].url, "https://www.b.com");
+    }
 }
@@ -48,9 +48,18 @@ impl PatternQuery {
             match pattern {
                 PatternPart::Raw(text) => {
                     let mut stream = tokenizer::Normal::default().token_stream(text);
+                    let mut last_position = None;
 
                     while let Some(token) = stream.next() {
                         let term = tantivy::Term::from_field_text(field, &token.text);
+
+                        // a character in Chinese, Japanese, Korean or Thai text is followed by
+                        // the bigram it starts at the same position, and the bigram is kept
+                        if last_position == Some(token.position) {
+                            raw_terms.pop();
+                        }
+
+                        last_position = Some(token.position);
                         raw_terms.push(term);
                     }
                 }
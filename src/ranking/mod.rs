@@ -14,23 +14,24 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-mod bm25;
+pub(crate) mod bm25;
 pub mod centrality_store;
 pub mod goggles;
 mod initial;
+pub(crate) mod proximity;
 pub mod signal;
 pub mod site_rankings;
 
 use std::sync::Arc;
 
 use initial::InitialScoreTweaker;
+use proximity::Proximity;
 use tantivy::collector::Collector;
 
 use crate::{
-    collector::{MaxDocsConsidered, TopDocs},
+    collector::{MaxDocsConsidered, Page, SearchAfter, TopDocs},
     fastfield_cache::FastFieldCache,
-    inverted_index,
-    searcher::NUM_RESULTS_PER_PAGE,
+    searcher::{MAX_RESULTS_PER_SITE, NUM_RESULTS_PER_PAGE},
     webpage::region::{Region, RegionCount},
 };
 
@@ -40,10 +41,13 @@ pub struct Ranker {
     region_count: Arc<RegionCount>,
     selected_region: Option<Region>,
     max_docs: Option<MaxDocsConsidered>,
+    num_results: usize,
     offset: Option<usize>,
+    search_after: Option<SearchAfter>,
     aggregator: SignalAggregator,
     fastfield_cache: Arc<FastFieldCache>,
-    de_rank_similar: bool,
+    build_page: bool,
+    proximity_terms: Vec<Vec<tantivy::Term>>,
 }
 
 impl Ranker {
@@ -55,11 +59,14 @@ impl Ranker {
         Ranker {
             region_count: Arc::new(region_count),
             selected_region: None,
+            num_results: NUM_RESULTS_PER_PAGE,
             offset: None,
+            search_after: None,
             aggregator,
             max_docs: None,
-            de_rank_similar: true,
+            build_page: true,
             fastfield_cache,
+            proximity_terms: Vec::new(),
         }
     }
 
@@ -68,11 +75,21 @@ impl Ranker {
         self
     }
 
+    pub fn with_num_results(mut self, num_results: usize) -> Self {
+        self.num_results = num_results;
+        self
+    }
+
     pub fn with_offset(mut self, offset: usize) -> Self {
         self.offset = Some(offset);
         self
     }
 
+    pub fn with_search_after(mut self, search_after: SearchAfter) -> Self {
+        self.search_after = Some(search_after);
+        self
+    }
+
     pub fn with_max_docs(mut self, total_docs: usize, segments: usize) -> Self {
         self.max_docs = Some(MaxDocsConsidered {
             total_docs,
@@ -81,11 +98,19 @@ impl Ranker {
         self
     }
 
-    pub fn de_rank_similar(&mut self, de_rank_similar: bool) {
-        self.de_rank_similar = de_rank_similar;
+    /// The query terms of each field the proximity signal is computed for.
+    pub fn with_proximity_terms(mut self, terms: Vec<Vec<tantivy::Term>>) -> Self {
+        self.proximity_terms = terms;
+        self
+    }
+
+    /// Whether similar results are de-ranked and results from the same site are capped.
+    /// Shards leave this to the searcher that merges their results into the page.
+    pub fn build_page(&mut self, build_page: bool) {
+        self.build_page = build_page;
     }
 
-    pub fn collector(&self) -> impl Collector<Fruit = Vec<inverted_index::WebsitePointer>> {
+    pub fn collector(&self) -> impl Collector<Fruit = Page> {
         let score_tweaker = InitialScoreTweaker::new(
             Arc::clone(&self.region_count),
             self.selected_region,
@@ -94,20 +119,33 @@ impl Ranker {
         );
 
         let mut collector =
-            TopDocs::with_limit(NUM_RESULTS_PER_PAGE, Arc::clone(&self.fastfield_cache));
+            TopDocs::with_limit(self.num_results, Arc::clone(&self.fastfield_cache));
 
-        if self.de_rank_similar {
-            collector = collector.and_de_rank_similar()
+        if self.build_page {
+            collector = collector
+                .and_de_rank_similar()
+                .and_max_results_per_site(MAX_RESULTS_PER_SITE);
         }
 
         if let Some(offset) = self.offset {
             collector = collector.and_offset(offset);
         }
 
+        if let Some(search_after) = self.search_after {
+            collector = collector.and_search_after(search_after);
+        }
+
         if let Some(max_docs) = &self.max_docs {
             collector = collector.and_max_docs(max_docs.clone());
         }
 
+        if let Some(proximity) = Proximity::new(
+            self.proximity_terms.clone(),
+            self.aggregator.coefficients().get(&Signal::Proximity),
+        ) {
+            collector = collector.and_proximity(proximity);
+        }
+
         collector.tweak_score(score_tweaker)
     }
 }
@@ -116,8 +154,8 @@ impl Ranker {
 mod tests {
     use crate::{
         index::Index,
-        searcher::{LocalSearcher, SearchQuery},
-        webpage::{Html, Link, Webpage},
+        searcher::{InitialSearchResult, LocalSearcher, SearchQuery},
+        webpage::{Html, Link, LinkContext, LinkRel, Webpage},
     };
 
     const CONTENT: &str = "this is the best example website ever this is the best example website ever this is the best example website ever this is the best example website ever this is the best example website ever this is the best example website ever";
@@ -148,6 +186,10 @@ mod tests {
                 host_centrality: 0.0,
                 fetch_time_ms: 500,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 pre_computed_score: 0.0,
                 primary_image: None,
             })
@@ -173,6 +215,10 @@ mod tests {
                 host_centrality: 5.0,
                 fetch_time_ms: 500,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 pre_computed_score: 0.0,
                 primary_image: None,
             })
@@ -187,6 +233,7 @@ mod tests {
                 goggle_program: None,
                 skip_pages: None,
                 site_rankings: None,
+                cursor: None,
             })
             .expect("Search failed")
             .into_websites()
@@ -222,6 +269,10 @@ mod tests {
                 host_centrality: 0.0,
                 fetch_time_ms: 500,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 pre_computed_score: 0.0,
                 primary_image: None,
             })
@@ -247,6 +298,10 @@ mod tests {
                 host_centrality: 0.0,
                 fetch_time_ms: 500,
                 page_centrality: 5.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 pre_computed_score: 0.0,
                 primary_image: None,
             })
@@ -261,6 +316,90 @@ mod tests {
                 goggle_program: None,
                 skip_pages: None,
                 site_rankings: None,
+                cursor: None,
+            })
+            .expect("Search failed")
+            .into_websites()
+            .unwrap();
+        assert_eq!(result.webpages.documents.len(), 2);
+        assert_eq!(result.webpages.documents[0].url, "https://www.b.com");
+        assert_eq!(result.webpages.documents[1].url, "https://www.a.com");
+    }
+
+    #[test]
+    fn host_antitrustrank_ranking() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        index
+            .insert(Webpage {
+                html: Html::parse(
+                    &format!(
+                        r#"
+                    <html>
+                        <head>
+                            <title>Website A</title>
+                        </head>
+                        <body>
+                            {CONTENT}
+                            example example example
+                        </body>
+                    </html>
+                "#
+                    ),
+                    "https://www.a.com",
+                ),
+                backlinks: vec![],
+                host_centrality: 0.0,
+                fetch_time_ms: 500,
+                page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 1.0,
+                pre_computed_score: 0.0,
+                primary_image: None,
+            })
+            .expect("failed to insert webpage");
+        index
+            .insert(Webpage {
+                html: Html::parse(
+                    &format!(
+                        r#"
+                    <html>
+                        <head>
+                            <title>Website B</title>
+                        </head>
+                        <body>
+                            {CONTENT}
+                        </body>
+                    </html>
+                "#
+                    ),
+                    "https://www.b.com",
+                ),
+                backlinks: vec![],
+                host_centrality: 0.0,
+                fetch_time_ms: 500,
+                page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
+                pre_computed_score: 0.0,
+                primary_image: None,
+            })
+            .expect("failed to insert webpage");
+
+        index.commit().expect("failed to commit index");
+        let searcher = LocalSearcher::from(index);
+        let result = searcher
+            .search(&SearchQuery {
+                original: "example".to_string(),
+                selected_region: None,
+                goggle_program: None,
+                skip_pages: None,
+                site_rankings: None,
+                cursor: None,
             })
             .expect("Search failed")
             .into_websites()
@@ -295,6 +434,10 @@ mod tests {
                 host_centrality: 0.0,
                 fetch_time_ms: 500,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 pre_computed_score: 0.0,
                 primary_image: None,
             })
@@ -320,6 +463,10 @@ mod tests {
                 host_centrality: 0.0,
                 fetch_time_ms: 500,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 pre_computed_score: 0.0,
                 primary_image: None,
             })
@@ -343,6 +490,10 @@ mod tests {
                 host_centrality: 0.003,
                 fetch_time_ms: 500,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 pre_computed_score: 0.0,
                 primary_image: None,
             })
@@ -357,6 +508,7 @@ mod tests {
                 goggle_program: None,
                 skip_pages: None,
                 site_rankings: None,
+                cursor: None,
             })
             .expect("Search failed")
             .into_websites()
@@ -392,6 +544,10 @@ mod tests {
                 host_centrality: 0.092,
                 fetch_time_ms: 500,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 pre_computed_score: 0.0,
                 primary_image: None,
             })
@@ -419,6 +575,10 @@ mod tests {
                 fetch_time_ms: 500,
                 pre_computed_score: 0.0,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 primary_image: None,
             })
             .expect("failed to insert webpage");
@@ -432,6 +592,7 @@ mod tests {
                 goggle_program: None,
                 skip_pages: None,
                 site_rankings: None,
+                cursor: None,
             })
             .expect("Search failed")
             .into_websites()
@@ -463,6 +624,10 @@ mod tests {
                 host_centrality: 0.0,
                 fetch_time_ms: 500,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 pre_computed_score: 0.0,
                 primary_image: None,
             })
@@ -498,6 +663,10 @@ mod tests {
                 host_centrality: 0.003,
                 fetch_time_ms: 500,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 pre_computed_score: 0.0,
                 primary_image: None,
     })
@@ -512,6 +681,7 @@ mod tests {
                 goggle_program: None,
                 skip_pages: None,
                 site_rankings: None,
+                cursor: None,
             })
             .expect("Search failed")
             .into_websites()
@@ -544,10 +714,16 @@ mod tests {
                     source: "https://www.second.com".to_string().into(),
                     destination: "https://www.first.com".to_string().into(),
                     text: "test this is the best test site".to_string(),
+                    rel: LinkRel::default(),
+                    context: LinkContext::default(),
                 }],
                 host_centrality: 0.0,
                 fetch_time_ms: 500,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 pre_computed_score: 0.0,
                 primary_image: None,
             })
@@ -572,6 +748,10 @@ mod tests {
                 fetch_time_ms: 500,
                 pre_computed_score: 0.0,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 primary_image: None,
             })
             .expect("failed to insert webpage");
@@ -585,6 +765,7 @@ mod tests {
                 goggle_program: None,
                 skip_pages: None,
                 site_rankings: None,
+                cursor: None,
             })
             .expect("Search failed")
             .into_websites()
@@ -618,6 +799,10 @@ mod tests {
                 fetch_time_ms: 20,
                 pre_computed_score: 0.0,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 primary_image: None,
             })
             .expect("failed to insert webpage");
@@ -641,6 +826,10 @@ mod tests {
                 host_centrality: 1.0,
                 fetch_time_ms: 20,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 pre_computed_score: 0.0,
                 primary_image: None,
             })
@@ -666,6 +855,10 @@ mod tests {
                 host_centrality: 1.02,
                 fetch_time_ms: 500,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 pre_computed_score: 0.0,
                 primary_image: None,
             })
@@ -673,7 +866,7 @@ mod tests {
 
         index.commit().unwrap();
 
-        let searcher = LocalSearcher::new(index, None, None);
+        let searcher = LocalSearcher::new(index, None, None, None);
 
         let res = searcher
             .search(&SearchQuery {
@@ -688,6 +881,7 @@ mod tests {
                 ),
                 skip_pages: None,
                 site_rankings: None,
+                cursor: None,
             })
             .unwrap()
             .into_websites()
@@ -709,6 +903,7 @@ mod tests {
                 ),
                 skip_pages: None,
                 site_rankings: None,
+                cursor: None,
             })
             .unwrap()
             .into_websites()
@@ -729,6 +924,7 @@ mod tests {
                 ),
                 skip_pages: None,
                 site_rankings: None,
+                cursor: None,
             })
             .unwrap()
             .into_websites()
@@ -738,6 +934,91 @@ mod tests {
         assert_eq!(&res.webpages.documents[0].url, "https://www.centrality.com");
     }
 
+    #[test]
+    fn main_headings_ranking() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        index
+            .insert(Webpage {
+                html: Html::parse(
+                    &format!(
+                        r#"
+                    <html>
+                        <head>
+                            <title>Website</title>
+                        </head>
+                        <body>
+                            <h2>Quokka facts</h2>
+                            <p>{CONTENT}</p>
+                        </body>
+                    </html>
+                "#
+                    ),
+                    "https://www.h2.com",
+                ),
+                backlinks: vec![],
+                host_centrality: 0.0,
+                fetch_time_ms: 500,
+                page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
+                pre_computed_score: 0.0,
+                primary_image: None,
+            })
+            .expect("failed to insert webpage");
+        index
+            .insert(Webpage {
+                html: Html::parse(
+                    &format!(
+                        r#"
+                    <html>
+                        <head>
+                            <title>Website</title>
+                        </head>
+                        <body>
+                            <h1>Quokka facts</h1>
+                            <p>{CONTENT}</p>
+                        </body>
+                    </html>
+                "#
+                    ),
+                    "https://www.h1.com",
+                ),
+                backlinks: vec![],
+                host_centrality: 0.0,
+                fetch_time_ms: 500,
+                page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
+                pre_computed_score: 0.0,
+                primary_image: None,
+            })
+            .expect("failed to insert webpage");
+
+        index.commit().expect("failed to commit index");
+        let searcher = LocalSearcher::from(index);
+        let result = searcher
+            .search(&SearchQuery {
+                original: "quokka".to_string(),
+                selected_region: None,
+                goggle_program: None,
+                skip_pages: None,
+                site_rankings: None,
+                cursor: None,
+            })
+            .expect("Search failed")
+            .into_websites()
+            .unwrap();
+
+        assert_eq!(result.webpages.documents.len(), 2);
+        assert_eq!(result.webpages.documents[0].url, "https://www.h1.com");
+        assert_eq!(result.webpages.documents[1].url, "https://www.h2.com");
+    }
+
     #[test]
     fn term_proximity_ranking() {
         let mut index = Index::temporary().expect("Unable to open index");
@@ -763,6 +1044,10 @@ mod tests {
                 pre_computed_score: 0.0,
                 fetch_time_ms: 500,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 primary_image: None,
             })
             .expect("failed to insert webpage");
@@ -788,6 +1073,10 @@ mod tests {
                 fetch_time_ms: 500,
                 pre_computed_score: 0.0,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 primary_image: None,
             })
             .expect("failed to insert webpage");
@@ -813,11 +1102,15 @@ mod tests {
                 fetch_time_ms: 500,
                 pre_computed_score: 0.0,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 primary_image: None,
             })
             .expect("failed to insert webpage");
         index.commit().expect("failed to commit index");
-        let searcher = LocalSearcher::new(index, None, None);
+        let searcher = LocalSearcher::new(index, None, None, None);
 
         let result = searcher
             .search(&SearchQuery {
@@ -826,6 +1119,7 @@ mod tests {
                 goggle_program: None,
                 skip_pages: None,
                 site_rankings: None,
+                cursor: None,
             })
             .expect("Search failed")
             .into_websites()
@@ -839,6 +1133,88 @@ mod tests {
         assert_eq!(result.documents[2].url, "https://www.third.com");
     }
 
+    #[test]
+    fn proximity_signal() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        // the same words, so the pages only differ in how close the query terms are
+        for (title, body, url) in [
+            (
+                "Lake house one",
+                "kayak by the lake and many other things to do rental",
+                "https://www.away.com",
+            ),
+            (
+                "Lake house two",
+                "kayak rental by the lake and many other things to do",
+                "https://www.near.com",
+            ),
+        ] {
+            index
+                .insert(Webpage {
+                    html: Html::parse(
+                        &format!(
+                            r#"
+                        <html>
+                            <head>
+                                <title>{title}</title>
+                            </head>
+                            <body>
+                                {body}
+                            </body>
+                        </html>
+                    "#
+                        ),
+                        url,
+                    ),
+                    backlinks: vec![],
+                    host_centrality: 1.0,
+                    fetch_time_ms: 500,
+                    pre_computed_score: 0.0,
+                    page_centrality: 0.0,
+                    host_pagerank: 0.0,
+                    page_pagerank: 0.0,
+                    host_trustrank: 0.0,
+                    host_antitrustrank: 0.0,
+                    primary_image: None,
+                })
+                .expect("failed to insert webpage");
+        }
+        index.commit().expect("failed to commit index");
+        let searcher = LocalSearcher::new(index, None, None, None);
+
+        let query = |goggle_program: Option<&str>| SearchQuery {
+            original: "kayak rental".to_string(),
+            selected_region: None,
+            goggle_program: goggle_program.map(str::to_string),
+            skip_pages: None,
+            site_rankings: None,
+            cursor: None,
+        };
+
+        let result = searcher
+            .search(&query(None))
+            .expect("Search failed")
+            .into_websites()
+            .unwrap()
+            .webpages;
+
+        assert_eq!(result.documents.len(), 2);
+        assert_eq!(result.documents[0].url, "https://www.near.com");
+        assert_eq!(result.documents[1].url, "https://www.away.com");
+
+        let result = match searcher
+            .search_initial(&query(Some("@proximity = 0")), true)
+            .expect("Search failed")
+        {
+            InitialSearchResult::Websites(result) => result.websites.top_websites,
+            InitialSearchResult::Bang(_) => panic!("unexpected bang"),
+        };
+
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].score, result[1].score);
+    }
+
     #[test]
     fn fetch_time_ranking() {
         let mut index = Index::temporary().expect("Unable to open index");
@@ -865,6 +1241,10 @@ mod tests {
                 fetch_time_ms: 0,
                 pre_computed_score: 0.0,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 primary_image: None,
             })
             .expect("failed to insert webpage");
@@ -889,12 +1269,16 @@ mod tests {
                 host_centrality: 1.0,
                 fetch_time_ms: 5000,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 pre_computed_score: 0.0,
                 primary_image: None,
             })
             .expect("failed to insert webpage");
         index.commit().expect("failed to commit index");
-        let searcher = LocalSearcher::new(index, None, None);
+        let searcher = LocalSearcher::new(index, None, None, None);
 
         let result = searcher
             .search(&SearchQuery {
@@ -903,6 +1287,7 @@ mod tests {
                 goggle_program: None,
                 skip_pages: None,
                 site_rankings: None,
+                cursor: None,
             })
             .expect("Search failed")
             .into_websites()
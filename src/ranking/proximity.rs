@@ -0,0 +1,184 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use tantivy::{
+    postings::{Postings, SegmentPostings},
+    schema::IndexRecordOption,
+    DocId, DocSet, SegmentReader,
+};
+
+/// Number of candidates from each segment the proximity signal is computed for.
+/// Reading positions is too expensive to do for every matching document.
+pub const NUM_PROXIMITY_CANDIDATES: usize = 100;
+
+/// Rewards documents where the query terms are close to each other, using the minimal
+/// span of positions covering all the terms in each field.
+#[derive(Clone)]
+pub struct Proximity {
+    /// The terms of the query for each field the proximity is measured in.
+    fields: Vec<Vec<tantivy::Term>>,
+    coefficient: f64,
+}
+
+impl Proximity {
+    /// Returns `None` if there are not at least two terms in any of the fields.
+    pub fn new(fields: Vec<Vec<tantivy::Term>>, coefficient: f64) -> Option<Self> {
+        let fields: Vec<_> = fields
+            .into_iter()
+            .map(|terms| {
+                let mut unique = Vec::with_capacity(terms.len());
+
+                for term in terms {
+                    if !unique.contains(&term) {
+                        unique.push(term);
+                    }
+                }
+
+                unique
+            })
+            .filter(|terms| terms.len() > 1)
+            .collect();
+
+        if fields.is_empty() || coefficient == 0.0 {
+            None
+        } else {
+            Some(Self {
+                fields,
+                coefficient,
+            })
+        }
+    }
+
+    pub fn for_segment(&self, segment_reader: &SegmentReader) -> tantivy::Result<SegmentProximity> {
+        let mut fields = Vec::with_capacity(self.fields.len());
+
+        for terms in &self.fields {
+            let mut postings = Vec::with_capacity(terms.len());
+
+            for term in terms {
+                match segment_reader
+                    .inverted_index(term.field())?
+                    .read_postings(term, IndexRecordOption::WithFreqsAndPositions)?
+                {
+                    Some(term_postings) => postings.push(term_postings),
+                    None => break,
+                }
+            }
+
+            // a field where one of the terms never occurs can never contribute to the score
+            if postings.len() == terms.len() {
+                fields.push(postings);
+            }
+        }
+
+        Ok(SegmentProximity {
+            fields,
+            num_fields: self.fields.len(),
+            coefficient: self.coefficient,
+            positions: Vec::new(),
+        })
+    }
+}
+
+pub struct SegmentProximity {
+    fields: Vec<Vec<SegmentPostings>>,
+    num_fields: usize,
+    coefficient: f64,
+    positions: Vec<Vec<u32>>,
+}
+
+impl SegmentProximity {
+    /// The weighted proximity score of the document.
+    /// Documents must be scored in increasing order of their ids.
+    pub fn score(&mut self, doc: DocId) -> f64 {
+        let mut score = 0.0;
+
+        for postings in &mut self.fields {
+            self.positions.resize(postings.len(), Vec::new());
+
+            let mut all_terms = true;
+            for (term_postings, positions) in postings.iter_mut().zip(self.positions.iter_mut()) {
+                positions.clear();
+
+                if term_postings.doc() > doc || term_postings.seek(doc) != doc {
+                    all_terms = false;
+                    break;
+                }
+
+                term_postings.positions(positions);
+            }
+
+            if !all_terms {
+                continue;
+            }
+
+            if let Some(span) = min_span(&self.positions) {
+                score += postings.len() as f64 / span as f64;
+            }
+        }
+
+        self.coefficient * score / self.num_fields as f64
+    }
+}
+
+/// Length of the shortest window that contains at least one position from each of the lists.
+/// The lists must be sorted.
+pub fn min_span(positions: &[Vec<u32>]) -> Option<u32> {
+    if positions.is_empty() || positions.iter().any(|list| list.is_empty()) {
+        return None;
+    }
+
+    let mut idx = vec![0; positions.len()];
+    let mut best = u32::MAX;
+
+    loop {
+        let mut min_list = 0;
+        let mut min = u32::MAX;
+        let mut max = 0;
+
+        for (i, list) in positions.iter().enumerate() {
+            let pos = list[idx[i]];
+
+            if pos < min {
+                min = pos;
+                min_list = i;
+            }
+
+            max = max.max(pos);
+        }
+
+        best = best.min(max - min + 1);
+
+        idx[min_list] += 1;
+        if idx[min_list] == positions[min_list].len() {
+            return Some(best);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn span() {
+        assert_eq!(min_span(&[vec![0, 10], vec![1, 20]]), Some(2));
+        assert_eq!(min_span(&[vec![5], vec![1, 9], vec![7]]), Some(5));
+        assert_eq!(min_span(&[vec![3], vec![0]]), Some(4));
+        assert_eq!(min_span(&[vec![3], vec![]]), None);
+        assert_eq!(min_span(&[]), None);
+    }
+}
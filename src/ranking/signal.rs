@@ -30,6 +30,7 @@ use crate::{
     webpage::region::{Region, RegionCount},
 };
 
+use crate::ranking::bm25::TermScoring;
 use crate::ranking::goggles::ast::{RawAlteration, Target};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -42,9 +43,14 @@ pub enum Signal {
     UpdateTimestamp,
     NumTrackers,
     Region,
+    HostPageRank,
+    PagePageRank,
+    HostTrustRank,
+    HostAntiTrustRank,
+    Proximity,
 }
 
-pub const ALL_SIGNALS: [Signal; 8] = [
+pub const ALL_SIGNALS: [Signal; 13] = [
     Signal::Bm25,
     Signal::HostCentrality,
     Signal::PageCentrality,
@@ -53,11 +59,16 @@ pub const ALL_SIGNALS: [Signal; 8] = [
     Signal::UpdateTimestamp,
     Signal::NumTrackers,
     Signal::Region,
+    Signal::HostPageRank,
+    Signal::PagePageRank,
+    Signal::HostTrustRank,
+    Signal::HostAntiTrustRank,
+    Signal::Proximity,
 ];
 
 impl Signal {
     fn is_computable_before_search(&self) -> bool {
-        !matches!(self, Signal::Bm25)
+        !matches!(self, Signal::Bm25 | Signal::Proximity)
     }
 
     fn value(
@@ -71,7 +82,14 @@ impl Signal {
     ) -> f64 {
         match self {
             Signal::Bm25 => bm25 as f64,
-            Signal::HostCentrality | Signal::PageCentrality => {
+            // computed by the collector for the top candidates, as it needs the term positions
+            Signal::Proximity => 0.0,
+            Signal::HostCentrality
+            | Signal::PageCentrality
+            | Signal::HostPageRank
+            | Signal::PagePageRank
+            | Signal::HostTrustRank
+            | Signal::HostAntiTrustRank => {
                 fastfield_value.unwrap() as f64 / CENTRALITY_SCALING as f64
             }
             Signal::IsHomepage => fastfield_value.unwrap() as f64,
@@ -128,6 +146,11 @@ impl Signal {
             Signal::UpdateTimestamp => 80.0,
             Signal::NumTrackers => 20.0,
             Signal::Region => 60.0,
+            Signal::HostPageRank => 256.0,
+            Signal::PagePageRank => 512.0,
+            Signal::HostTrustRank => 1024.0,
+            Signal::HostAntiTrustRank => -1024.0,
+            Signal::Proximity => 20.0,
         }
     }
 
@@ -135,6 +158,11 @@ impl Signal {
         match name.as_str() {
             "bm25" => Some(Signal::Bm25),
             "host_centrality" => Some(Signal::HostCentrality),
+            "host_pagerank" => Some(Signal::HostPageRank),
+            "page_pagerank" => Some(Signal::PagePageRank),
+            "host_trustrank" => Some(Signal::HostTrustRank),
+            "host_antitrustrank" => Some(Signal::HostAntiTrustRank),
+            "proximity" => Some(Signal::Proximity),
             _ => None,
         }
     }
@@ -149,6 +177,11 @@ impl Signal {
             Signal::UpdateTimestamp => Some(FastField::LastUpdated),
             Signal::NumTrackers => Some(FastField::NumTrackers),
             Signal::Region => Some(FastField::Region),
+            Signal::HostPageRank => Some(FastField::HostPageRank),
+            Signal::PagePageRank => Some(FastField::PagePageRank),
+            Signal::HostTrustRank => Some(FastField::HostTrustRank),
+            Signal::HostAntiTrustRank => Some(FastField::HostAntiTrustRank),
+            Signal::Proximity => None,
         }
     }
 }
@@ -217,6 +250,7 @@ pub struct SignalAggregator {
     fastfield_cache: Option<Arc<fastfield_cache::SegmentCache>>,
     signal_coefficients: SignalCoefficient,
     field_boost: FieldBoost,
+    term_scoring: TermScoring,
     fetch_time_ms_cache: [f64; 1000],
     update_time_cache: Vec<f64>,
 }
@@ -226,6 +260,7 @@ impl std::fmt::Debug for SignalAggregator {
         f.debug_struct("SignalAggregator")
             .field("signal_coefficients", &self.signal_coefficients)
             .field("field_boost", &self.field_boost)
+            .field("term_scoring", &self.term_scoring)
             .finish()
     }
 }
@@ -254,6 +289,7 @@ impl SignalAggregator {
             fastfield_cache: None,
             signal_coefficients,
             field_boost,
+            term_scoring: TermScoring::default(),
             fetch_time_ms_cache,
             update_time_cache,
         }
@@ -305,6 +341,18 @@ impl SignalAggregator {
                     Signal::PageCentrality => {
                         (webpage.page_centrality * (CENTRALITY_SCALING as f64)) as u64
                     }
+                    Signal::HostPageRank => {
+                        (webpage.host_pagerank * (CENTRALITY_SCALING as f64)) as u64
+                    }
+                    Signal::PagePageRank => {
+                        (webpage.page_pagerank * (CENTRALITY_SCALING as f64)) as u64
+                    }
+                    Signal::HostTrustRank => {
+                        (webpage.host_trustrank * (CENTRALITY_SCALING as f64)) as u64
+                    }
+                    Signal::HostAntiTrustRank => {
+                        (webpage.host_antitrustrank * (CENTRALITY_SCALING as f64)) as u64
+                    }
                     Signal::IsHomepage => webpage.html.url().is_homepage().into(),
                     Signal::FetchTimeMs => webpage.fetch_time_ms,
                     Signal::UpdateTimestamp => webpage
@@ -339,6 +387,14 @@ impl SignalAggregator {
     pub fn field_boosts(&self) -> &FieldBoost {
         &self.field_boost
     }
+
+    pub fn set_term_scoring(&mut self, term_scoring: TermScoring) {
+        self.term_scoring = term_scoring;
+    }
+
+    pub fn term_scoring(&self) -> TermScoring {
+        self.term_scoring
+    }
 }
 #[derive(Debug, PartialEq)]
 pub struct Alteration {
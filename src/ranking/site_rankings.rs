@@ -65,6 +65,7 @@ impl SiteRankings {
         Goggle {
             aggregator: SignalAggregator::default(),
             instructions,
+            ..Default::default()
         }
     }
 }
@@ -105,6 +106,10 @@ mod tests {
                 fetch_time_ms: 5000,
                 pre_computed_score: 0.0,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 primary_image: None,
             })
             .expect("failed to insert webpage");
@@ -129,12 +134,16 @@ mod tests {
                 host_centrality: 1.0,
                 fetch_time_ms: 0,
                 page_centrality: 0.0,
+                host_pagerank: 0.0,
+                page_pagerank: 0.0,
+                host_trustrank: 0.0,
+                host_antitrustrank: 0.0,
                 pre_computed_score: 0.0,
                 primary_image: None,
             })
             .expect("failed to insert webpage");
         index.commit().expect("failed to commit index");
-        let searcher = LocalSearcher::new(index, None, None);
+        let searcher = LocalSearcher::new(index, None, None, None);
 
         let result = searcher
             .search(&SearchQuery {
@@ -147,6 +156,7 @@ mod tests {
                     disliked: vec!["second.com".to_string()],
                     blocked: vec![],
                 }),
+                cursor: None,
             })
             .expect("Search failed")
             .into_websites()
@@ -169,6 +179,7 @@ mod tests {
                     disliked: vec!["second.com".to_string()],
                     blocked: vec!["first.com".to_string()],
                 }),
+                cursor: None,
             })
             .expect("Search failed")
             .into_websites()
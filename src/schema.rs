@@ -18,7 +18,7 @@ use tantivy::schema::{
     BytesOptions, Cardinality, IndexRecordOption, NumericOptions, TextFieldIndexing, TextOptions,
 };
 
-use crate::tokenizer::{Identity, Normal, Stemmed};
+use crate::tokenizer::{Code, Diacritics, Identity, Normal, Stemmed};
 
 pub const CENTRALITY_SCALING: u64 = 1_000_000_000;
 
@@ -30,6 +30,8 @@ pub enum TextField {
     StemmedCleanBody,
     AllBody,
     Url,
+    /// the full url as a single token. Used to look up a page by its url
+    UrlNoTokenizer,
     Site,
     Domain,
     SiteNoTokenizer,
@@ -43,6 +45,25 @@ pub enum TextField {
     BacklinkText,
     PrimaryImage,
     Description,
+    /// alternate language versions of the page (stored only)
+    HrefLang,
+    /// json encoded schema.org data (stored only)
+    SchemaOrgJson,
+    /// `html`, `pdf`, `txt` or `md`. Used by the `filetype:` operator
+    FileType,
+    /// text of the `<h1>` to `<h3>` headings. Used by the `inheading:` operator
+    Headings,
+    /// text of the `<h1>` headings, so the main headings count more than the others
+    MainHeadings,
+    /// text of `<pre>` and `<code>` blocks. Used by the `incode:` operator
+    Code,
+    /// text of the table cells
+    Tables,
+    /// words with diacritics from the title and clean body, in their original form.
+    /// The other fields only have the folded forms
+    Diacritics,
+    /// lowercased `@type` of the schema.org items. Used by the `$schema=` goggle option
+    SchemaOrgType,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -64,6 +85,12 @@ pub enum FastField {
     UrlHash,
     DomainHash,
     PreComputedScore,
+    HostPageRank,
+    PagePageRank,
+    HostTrustRank,
+    HostAntiTrustRank,
+    /// simhash of the clean text used to detect near-duplicates
+    SimHash,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -72,13 +99,14 @@ pub enum Field {
     Text(TextField),
 }
 
-pub static ALL_FIELDS: [Field; 33] = [
+pub static ALL_FIELDS: [Field; 48] = [
     Field::Text(TextField::Title),
     Field::Text(TextField::CleanBody),
     Field::Text(TextField::StemmedTitle),
     Field::Text(TextField::StemmedCleanBody),
     Field::Text(TextField::AllBody),
     Field::Text(TextField::Url),
+    Field::Text(TextField::UrlNoTokenizer),
     Field::Text(TextField::Site),
     Field::Text(TextField::Domain),
     Field::Text(TextField::SiteNoTokenizer),
@@ -89,6 +117,15 @@ pub static ALL_FIELDS: [Field; 33] = [
     Field::Text(TextField::BacklinkText),
     Field::Text(TextField::PrimaryImage),
     Field::Text(TextField::Description),
+    Field::Text(TextField::HrefLang),
+    Field::Text(TextField::SchemaOrgJson),
+    Field::Text(TextField::FileType),
+    Field::Text(TextField::Headings),
+    Field::Text(TextField::MainHeadings),
+    Field::Text(TextField::Code),
+    Field::Text(TextField::Tables),
+    Field::Text(TextField::Diacritics),
+    Field::Text(TextField::SchemaOrgType),
     // FAST FIELDS
     Field::Fast(FastField::IsHomepage),
     Field::Fast(FastField::HostCentrality),
@@ -107,6 +144,11 @@ pub static ALL_FIELDS: [Field; 33] = [
     Field::Fast(FastField::UrlHash),
     Field::Fast(FastField::DomainHash),
     Field::Fast(FastField::PreComputedScore),
+    Field::Fast(FastField::HostPageRank),
+    Field::Fast(FastField::PagePageRank),
+    Field::Fast(FastField::HostTrustRank),
+    Field::Fast(FastField::HostAntiTrustRank),
+    Field::Fast(FastField::SimHash),
 ];
 
 impl Field {
@@ -134,6 +176,9 @@ impl Field {
             Field::Text(TextField::Url) => {
                 IndexingOption::Text(self.default_text_options().set_stored())
             }
+            Field::Text(TextField::UrlNoTokenizer) => {
+                IndexingOption::Text(self.default_text_options_with_tokenizer(Identity::as_str()))
+            }
             Field::Text(TextField::Site) => IndexingOption::Text(self.default_text_options()),
             Field::Text(TextField::Domain) => IndexingOption::Text(self.default_text_options()),
             Field::Text(TextField::SiteNoTokenizer) => {
@@ -170,6 +215,26 @@ impl Field {
             Field::Text(TextField::Description) => {
                 IndexingOption::Text(self.default_text_options().set_stored())
             }
+            Field::Text(TextField::HrefLang) => {
+                IndexingOption::Bytes(BytesOptions::default().set_stored())
+            }
+            Field::Text(TextField::SchemaOrgJson) => {
+                IndexingOption::Bytes(BytesOptions::default().set_stored())
+            }
+            Field::Text(TextField::FileType) | Field::Text(TextField::SchemaOrgType) => {
+                IndexingOption::Text(self.default_text_options_with_tokenizer(Identity::as_str()))
+            }
+            Field::Text(TextField::Headings) => IndexingOption::Text(self.default_text_options()),
+            Field::Text(TextField::MainHeadings) => {
+                IndexingOption::Text(self.default_text_options())
+            }
+            Field::Text(TextField::Code) => {
+                IndexingOption::Text(self.default_text_options_with_tokenizer(Code::as_str()))
+            }
+            Field::Text(TextField::Tables) => IndexingOption::Text(self.default_text_options()),
+            Field::Text(TextField::Diacritics) => {
+                IndexingOption::Text(self.default_text_options_with_tokenizer(Diacritics::as_str()))
+            }
             Field::Fast(FastField::IsHomepage) => IndexingOption::Integer(
                 NumericOptions::default()
                     .set_fast(Cardinality::SingleValue)
@@ -185,6 +250,26 @@ impl Field {
                     .set_fast(Cardinality::SingleValue)
                     .set_indexed(),
             ),
+            Field::Fast(FastField::HostPageRank) => IndexingOption::Integer(
+                NumericOptions::default()
+                    .set_fast(Cardinality::SingleValue)
+                    .set_indexed(),
+            ),
+            Field::Fast(FastField::PagePageRank) => IndexingOption::Integer(
+                NumericOptions::default()
+                    .set_fast(Cardinality::SingleValue)
+                    .set_indexed(),
+            ),
+            Field::Fast(FastField::HostTrustRank) => IndexingOption::Integer(
+                NumericOptions::default()
+                    .set_fast(Cardinality::SingleValue)
+                    .set_indexed(),
+            ),
+            Field::Fast(FastField::HostAntiTrustRank) => IndexingOption::Integer(
+                NumericOptions::default()
+                    .set_fast(Cardinality::SingleValue)
+                    .set_indexed(),
+            ),
             Field::Fast(FastField::FetchTimeMs) => IndexingOption::Integer(
                 NumericOptions::default()
                     .set_fast(Cardinality::SingleValue)
@@ -242,6 +327,9 @@ impl Field {
             Field::Fast(FastField::TitleHash) => IndexingOption::Integer(
                 NumericOptions::default().set_fast(Cardinality::MultiValues),
             ),
+            Field::Fast(FastField::SimHash) => IndexingOption::Integer(
+                NumericOptions::default().set_fast(Cardinality::SingleValue),
+            ),
             Field::Fast(FastField::PreComputedScore) => IndexingOption::Float(
                 NumericOptions::default()
                     .set_fast(Cardinality::SingleValue)
@@ -256,6 +344,7 @@ impl Field {
             Field::Text(TextField::Title) => "title",
             Field::Text(TextField::CleanBody) => "body",
             Field::Text(TextField::Url) => "url",
+            Field::Text(TextField::UrlNoTokenizer) => "url_no_tokenizer",
             Field::Text(TextField::Site) => "site",
             Field::Text(TextField::Domain) => "domain",
             Field::Text(TextField::SiteNoTokenizer) => "site_no_tokenizer",
@@ -269,10 +358,23 @@ impl Field {
             }
             Field::Text(TextField::Description) => "description",
             Field::Text(TextField::PrimaryImage) => "primary_image_uuid",
+            Field::Text(TextField::HrefLang) => "hreflang",
+            Field::Text(TextField::SchemaOrgJson) => "schema_org_json",
+            Field::Text(TextField::FileType) => "filetype",
+            Field::Text(TextField::SchemaOrgType) => "schema_org_type",
+            Field::Text(TextField::Headings) => "headings",
+            Field::Text(TextField::MainHeadings) => "main_headings",
+            Field::Text(TextField::Code) => "code",
+            Field::Text(TextField::Tables) => "tables",
+            Field::Text(TextField::Diacritics) => "diacritics",
             Field::Text(TextField::TitleIfHomepage) => "title_if_homepage",
             Field::Text(TextField::AllBody) => "all_body",
             Field::Fast(FastField::HostCentrality) => "host_centrality",
             Field::Fast(FastField::PageCentrality) => "page_centrality",
+            Field::Fast(FastField::HostPageRank) => "host_pagerank",
+            Field::Fast(FastField::PagePageRank) => "page_pagerank",
+            Field::Fast(FastField::HostTrustRank) => "host_trustrank",
+            Field::Fast(FastField::HostAntiTrustRank) => "host_antitrustrank",
             Field::Fast(FastField::IsHomepage) => "is_homepage",
             Field::Fast(FastField::FetchTimeMs) => "fetch_time_ms",
             Field::Fast(FastField::LastUpdated) => "last_updated",
@@ -288,9 +390,11 @@ impl Field {
             Field::Fast(FastField::TitleHash) => "title_hash",
             Field::Fast(FastField::UrlHash) => "url_hash",
             Field::Fast(FastField::DomainHash) => "domain_hash",
+            Field::Fast(FastField::SimHash) => "simhash",
         }
     }
 
+    /// The default boost of the field. Goggles can change it with `@field_<name> = <boost>`.
     pub fn boost(&self) -> Option<f32> {
         match self {
             Field::Text(TextField::Site) => Some(3.0),
@@ -306,10 +410,21 @@ impl Field {
             Field::Text(TextField::Domain) => Some(1.0),
             Field::Text(TextField::AllBody) => Some(0.01),
             Field::Text(TextField::BacklinkText) => Some(4.0),
+            // `<h1>` headings are in both heading fields
+            Field::Text(TextField::Headings) => Some(6.0),
+            Field::Text(TextField::MainHeadings) => Some(4.0),
+            Field::Text(TextField::Code) => Some(3.0),
+            Field::Text(TextField::Tables) => Some(1.0),
+            Field::Text(TextField::Diacritics) => Some(3.0),
             Field::Text(TextField::SiteNoTokenizer)
             | Field::Text(TextField::DomainNoTokenizer)
+            | Field::Text(TextField::UrlNoTokenizer)
             | Field::Text(TextField::Description)
-            | Field::Text(TextField::PrimaryImage) => None,
+            | Field::Text(TextField::PrimaryImage)
+            | Field::Text(TextField::HrefLang)
+            | Field::Text(TextField::SchemaOrgJson)
+            | Field::Text(TextField::FileType)
+            | Field::Text(TextField::SchemaOrgType) => None,
             Field::Fast(_) => None,
         }
     }
@@ -317,7 +432,13 @@ impl Field {
     pub fn is_searchable(&self) -> bool {
         !matches!(
             self,
-            Field::Text(TextField::PrimaryImage) | Field::Text(TextField::BacklinkText)
+            Field::Text(TextField::PrimaryImage)
+                | Field::Text(TextField::BacklinkText)
+                | Field::Text(TextField::UrlNoTokenizer)
+                | Field::Text(TextField::HrefLang)
+                | Field::Text(TextField::SchemaOrgJson)
+                | Field::Text(TextField::FileType)
+                | Field::Text(TextField::SchemaOrgType)
         ) && !self.is_fast()
     }
 
@@ -330,6 +451,7 @@ impl Field {
             "title" => Some(Field::Text(TextField::Title)),
             "body" => Some(Field::Text(TextField::CleanBody)),
             "url" => Some(Field::Text(TextField::Url)),
+            "url_no_tokenizer" => Some(Field::Text(TextField::UrlNoTokenizer)),
             "site" => Some(Field::Text(TextField::Site)),
             "backlink_text" => Some(Field::Text(TextField::BacklinkText)),
             "stemmed_title" => Some(Field::Text(TextField::StemmedTitle)),
@@ -337,6 +459,15 @@ impl Field {
             "domain" => Some(Field::Text(TextField::Domain)),
             "domain_if_homepage" => Some(Field::Text(TextField::DomainIfHomepage)),
             "primary_image_uuid" => Some(Field::Text(TextField::PrimaryImage)),
+            "hreflang" => Some(Field::Text(TextField::HrefLang)),
+            "schema_org_json" => Some(Field::Text(TextField::SchemaOrgJson)),
+            "filetype" => Some(Field::Text(TextField::FileType)),
+            "schema_org_type" => Some(Field::Text(TextField::SchemaOrgType)),
+            "headings" => Some(Field::Text(TextField::Headings)),
+            "main_headings" => Some(Field::Text(TextField::MainHeadings)),
+            "code" => Some(Field::Text(TextField::Code)),
+            "tables" => Some(Field::Text(TextField::Tables)),
+            "diacritics" => Some(Field::Text(TextField::Diacritics)),
             "domain_name_if_homepage_no_tokenizer" => {
                 Some(Field::Text(TextField::DomainNameIfHomepageNoTokenizer))
             }
@@ -345,6 +476,10 @@ impl Field {
             "title_if_homepage" => Some(Field::Text(TextField::TitleIfHomepage)),
             "host_centrality" => Some(Field::Fast(FastField::HostCentrality)),
             "page_centrality" => Some(Field::Fast(FastField::PageCentrality)),
+            "host_pagerank" => Some(Field::Fast(FastField::HostPageRank)),
+            "page_pagerank" => Some(Field::Fast(FastField::PagePageRank)),
+            "host_trustrank" => Some(Field::Fast(FastField::HostTrustRank)),
+            "host_antitrustrank" => Some(Field::Fast(FastField::HostAntiTrustRank)),
             "is_homepage" => Some(Field::Fast(FastField::IsHomepage)),
             "fetch_time_ms" => Some(Field::Fast(FastField::FetchTimeMs)),
             "last_updated" => Some(Field::Fast(FastField::LastUpdated)),
@@ -355,6 +490,7 @@ impl Field {
             "pre_computed_score" => Some(Field::Fast(FastField::PreComputedScore)),
             "url_hash" => Some(Field::Fast(FastField::UrlHash)),
             "domain_hash" => Some(Field::Fast(FastField::DomainHash)),
+            "simhash" => Some(Field::Fast(FastField::SimHash)),
             "title_hash" => Some(Field::Fast(FastField::TitleHash)),
             _ => None,
         }
@@ -423,6 +559,11 @@ impl FastField {
             FastField::UrlHash => DataType::U64s,
             FastField::DomainHash => DataType::U64s,
             FastField::PreComputedScore => DataType::F64,
+            FastField::HostPageRank => DataType::U64,
+            FastField::PagePageRank => DataType::U64,
+            FastField::HostTrustRank => DataType::U64,
+            FastField::HostAntiTrustRank => DataType::U64,
+            FastField::SimHash => DataType::U64,
         }
     }
 }
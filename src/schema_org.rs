@@ -14,16 +14,122 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use std::collections::BTreeMap;
+
+use itertools::Itertools;
+use kuchiki::{ElementData, NodeRef};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 #[non_exhaustive]
-#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
 #[serde(tag = "@type")]
 pub enum SchemaOrg {
     ImageObject(ImageObject),
+    Article(Article),
+    NewsArticle(Article),
+    Recipe(Recipe),
+    Product(Product),
+    Event(Event),
+    Organization(Organization),
+    Person(Person),
+    FAQPage(FAQPage),
+    BreadcrumbList(BreadcrumbList),
+    SoftwareApplication(SoftwareApplication),
+}
+
+impl SchemaOrg {
+    fn from_item(item: &Item) -> Option<Self> {
+        item.types.iter().find_map(|tt| match tt.as_str() {
+            "ImageObject" => Some(SchemaOrg::ImageObject(item.into())),
+            "Article" | "BlogPosting" | "TechArticle" | "ScholarlyArticle" => {
+                Some(SchemaOrg::Article(item.into()))
+            }
+            "NewsArticle" | "ReportageNewsArticle" => Some(SchemaOrg::NewsArticle(item.into())),
+            "Recipe" => Some(SchemaOrg::Recipe(item.into())),
+            "Product" => Some(SchemaOrg::Product(item.into())),
+            "Event" => Some(SchemaOrg::Event(item.into())),
+            "Organization" | "Corporation" | "NewsMediaOrganization" => {
+                Some(SchemaOrg::Organization(item.into()))
+            }
+            "Person" => Some(SchemaOrg::Person(item.into())),
+            "FAQPage" => Some(SchemaOrg::FAQPage(item.into())),
+            "BreadcrumbList" => Some(SchemaOrg::BreadcrumbList(item.into())),
+            "SoftwareApplication" | "MobileApplication" | "WebApplication" => {
+                Some(SchemaOrg::SoftwareApplication(item.into()))
+            }
+            _ => None,
+        })
+    }
+
+    /// The `@type` the item was parsed as. Subtypes are folded into the type
+    /// they are parsed as (e.g. `BlogPosting` is an `Article`).
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            SchemaOrg::ImageObject(_) => "ImageObject",
+            SchemaOrg::Article(_) => "Article",
+            SchemaOrg::NewsArticle(_) => "NewsArticle",
+            SchemaOrg::Recipe(_) => "Recipe",
+            SchemaOrg::Product(_) => "Product",
+            SchemaOrg::Event(_) => "Event",
+            SchemaOrg::Organization(_) => "Organization",
+            SchemaOrg::Person(_) => "Person",
+            SchemaOrg::FAQPage(_) => "FAQPage",
+            SchemaOrg::BreadcrumbList(_) => "BreadcrumbList",
+            SchemaOrg::SoftwareApplication(_) => "SoftwareApplication",
+        }
+    }
+
+    /// Convert the parsed items into the schemas we know about. Items of unknown
+    /// types (e.g. `WebPage`) are searched for known nested items.
+    pub fn from_items(items: &[Item]) -> Vec<Self> {
+        fn collect(item: &Item, schemas: &mut Vec<SchemaOrg>) {
+            match SchemaOrg::from_item(item) {
+                Some(schema) => schemas.push(schema),
+                None => {
+                    for property in item.properties.values().flatten() {
+                        if let Property::Item(nested) = property {
+                            collect(nested, schemas);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut schemas = Vec::new();
+
+        for item in items {
+            collect(item, &mut schemas);
+        }
+
+        schemas
+    }
+}
+
+/// Internally tagged enums can't be deserialized by formats that are not
+/// self-describing (like bincode), so schemas are sent between services as json.
+pub mod as_json {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::SchemaOrg;
+
+    pub fn serialize<S: Serializer>(
+        schemas: &[SchemaOrg],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let json = serde_json::to_string(schemas).map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(&json)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<SchemaOrg>, D::Error> {
+        let json = String::deserialize(deserializer)?;
+        serde_json::from_str(&json).map_err(serde::de::Error::custom)
+    }
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
 pub struct ImageObject {
     pub name: Option<String>,
     pub description: Option<String>,
@@ -32,10 +138,609 @@ pub struct ImageObject {
     pub content_url: Option<String>,
 }
 
+impl From<&Item> for ImageObject {
+    fn from(item: &Item) -> Self {
+        Self {
+            name: item.text("name"),
+            description: item.text("description"),
+            author: item.text("author"),
+            content_url: item.url("contentUrl"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Article {
+    pub headline: Option<String>,
+    pub description: Option<String>,
+    pub author: Vec<String>,
+    pub date_published: Option<String>,
+    pub date_modified: Option<String>,
+    pub publisher: Option<String>,
+    pub image: Option<String>,
+}
+
+impl From<&Item> for Article {
+    fn from(item: &Item) -> Self {
+        Self {
+            headline: item.text("headline").or_else(|| item.text("name")),
+            description: item.text("description"),
+            author: item.texts("author"),
+            date_published: item.text("datePublished"),
+            date_modified: item.text("dateModified"),
+            publisher: item.text("publisher"),
+            image: item.url("image"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AggregateRating {
+    pub rating_value: Option<String>,
+    pub rating_count: Option<String>,
+}
+
+impl AggregateRating {
+    fn from_parent(item: &Item) -> Option<Self> {
+        item.items("aggregateRating").next().map(|rating| Self {
+            rating_value: rating.text("ratingValue"),
+            rating_count: rating
+                .text("ratingCount")
+                .or_else(|| rating.text("reviewCount")),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Offer {
+    pub price: Option<String>,
+    pub price_currency: Option<String>,
+    /// Local name of the availability, e.g. `InStock` for `https://schema.org/InStock`.
+    pub availability: Option<String>,
+}
+
+impl Offer {
+    fn from_parent(item: &Item) -> Vec<Self> {
+        item.items("offers")
+            .map(|offer| Self {
+                price: offer.text("price").or_else(|| offer.text("lowPrice")),
+                price_currency: offer.text("priceCurrency"),
+                availability: offer
+                    .url("availability")
+                    .map(|availability| local_name(&availability)),
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Recipe {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub author: Vec<String>,
+    pub image: Option<String>,
+    pub prep_time: Option<String>,
+    pub cook_time: Option<String>,
+    pub total_time: Option<String>,
+    pub recipe_yield: Option<String>,
+    pub ingredients: Vec<String>,
+    pub aggregate_rating: Option<AggregateRating>,
+}
+
+impl From<&Item> for Recipe {
+    fn from(item: &Item) -> Self {
+        let mut ingredients = item.texts("recipeIngredient");
+        if ingredients.is_empty() {
+            ingredients = item.texts("ingredients");
+        }
+
+        Self {
+            name: item.text("name"),
+            description: item.text("description"),
+            author: item.texts("author"),
+            image: item.url("image"),
+            prep_time: item.text("prepTime"),
+            cook_time: item.text("cookTime"),
+            total_time: item.text("totalTime"),
+            recipe_yield: item.text("recipeYield"),
+            ingredients,
+            aggregate_rating: AggregateRating::from_parent(item),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Product {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub brand: Option<String>,
+    pub image: Option<String>,
+    pub sku: Option<String>,
+    pub offers: Vec<Offer>,
+    pub aggregate_rating: Option<AggregateRating>,
+}
+
+impl From<&Item> for Product {
+    fn from(item: &Item) -> Self {
+        Self {
+            name: item.text("name"),
+            description: item.text("description"),
+            brand: item.text("brand"),
+            image: item.url("image"),
+            sku: item.text("sku"),
+            offers: Offer::from_parent(item),
+            aggregate_rating: AggregateRating::from_parent(item),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Event {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    pub location: Option<String>,
+    pub organizer: Option<String>,
+}
+
+impl From<&Item> for Event {
+    fn from(item: &Item) -> Self {
+        Self {
+            name: item.text("name"),
+            description: item.text("description"),
+            start_date: item.text("startDate"),
+            end_date: item.text("endDate"),
+            location: item.text("location"),
+            organizer: item.text("organizer"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Organization {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub url: Option<String>,
+    pub logo: Option<String>,
+    pub same_as: Vec<String>,
+}
+
+impl From<&Item> for Organization {
+    fn from(item: &Item) -> Self {
+        Self {
+            name: item.text("name"),
+            description: item.text("description"),
+            url: item.url("url"),
+            logo: item.url("logo"),
+            same_as: item.urls("sameAs"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Person {
+    pub name: Option<String>,
+    pub job_title: Option<String>,
+    pub works_for: Option<String>,
+    pub url: Option<String>,
+    pub same_as: Vec<String>,
+}
+
+impl From<&Item> for Person {
+    fn from(item: &Item) -> Self {
+        Self {
+            name: item.text("name"),
+            job_title: item.text("jobTitle"),
+            works_for: item.text("worksFor").or_else(|| item.text("affiliation")),
+            url: item.url("url"),
+            same_as: item.urls("sameAs"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct Question {
+    pub question: String,
+    pub answer: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct FAQPage {
+    pub questions: Vec<Question>,
+}
+
+impl From<&Item> for FAQPage {
+    fn from(item: &Item) -> Self {
+        Self {
+            questions: item
+                .items("mainEntity")
+                .filter_map(|question| {
+                    Some(Question {
+                        question: question.text("name")?,
+                        answer: question
+                            .items("acceptedAnswer")
+                            .find_map(|answer| answer.text("text")),
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct ListItem {
+    pub position: Option<u64>,
+    pub name: Option<String>,
+    pub url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct BreadcrumbList {
+    pub items: Vec<ListItem>,
+}
+
+impl From<&Item> for BreadcrumbList {
+    fn from(item: &Item) -> Self {
+        let mut items: Vec<_> = item
+            .items("itemListElement")
+            .map(|element| ListItem {
+                position: element
+                    .text("position")
+                    .and_then(|position| position.parse().ok()),
+                name: element.text("name").or_else(|| element.text("item")),
+                url: element.url("item").or_else(|| element.url("url")),
+            })
+            .collect();
+
+        items.sort_by_key(|item| item.position);
+
+        Self { items }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SoftwareApplication {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub operating_system: Option<String>,
+    pub application_category: Option<String>,
+    pub offers: Vec<Offer>,
+    pub aggregate_rating: Option<AggregateRating>,
+}
+
+impl From<&Item> for SoftwareApplication {
+    fn from(item: &Item) -> Self {
+        Self {
+            name: item.text("name"),
+            description: item.text("description"),
+            operating_system: item.text("operatingSystem"),
+            application_category: item
+                .url("applicationCategory")
+                .map(|category| local_name(&category)),
+            offers: Offer::from_parent(item),
+            aggregate_rating: AggregateRating::from_parent(item),
+        }
+    }
+}
+
+/// Syntax independent representation of a schema.org item. JSON-LD, microdata and RDFa
+/// are all parsed into items before being converted into a [`SchemaOrg`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Item {
+    pub types: Vec<String>,
+    pub properties: BTreeMap<String, Vec<Property>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Property {
+    Text(String),
+    Item(Item),
+}
+
+impl Property {
+    fn as_text(&self) -> Option<String> {
+        match self {
+            Property::Text(text) => Some(text.clone()),
+            Property::Item(item) => item.text("name"),
+        }
+    }
+
+    fn as_url(&self) -> Option<String> {
+        match self {
+            Property::Text(text) => Some(text.clone()),
+            Property::Item(item) => item
+                .text("url")
+                .or_else(|| item.text("contentUrl"))
+                .or_else(|| item.text("@id")),
+        }
+    }
+}
+
+impl Item {
+    fn values(&self, name: &str) -> impl Iterator<Item = &Property> {
+        self.properties.get(name).into_iter().flatten()
+    }
+
+    fn text(&self, name: &str) -> Option<String> {
+        self.values(name).find_map(Property::as_text)
+    }
+
+    fn texts(&self, name: &str) -> Vec<String> {
+        self.values(name).filter_map(Property::as_text).collect()
+    }
+
+    fn url(&self, name: &str) -> Option<String> {
+        self.values(name).find_map(Property::as_url)
+    }
+
+    fn urls(&self, name: &str) -> Vec<String> {
+        self.values(name).filter_map(Property::as_url).collect()
+    }
+
+    fn items(&self, name: &str) -> impl Iterator<Item = &Item> {
+        self.values(name).filter_map(|property| match property {
+            Property::Item(item) => Some(item),
+            Property::Text(_) => None,
+        })
+    }
+
+    pub fn from_json_ld(json: &Value) -> Vec<Item> {
+        match json {
+            Value::Array(values) => values.iter().flat_map(Item::from_json_ld).collect(),
+            Value::Object(map) => match map.get("@graph") {
+                Some(graph) => Item::from_json_ld(graph),
+                None => json_ld_properties(json)
+                    .into_iter()
+                    .filter_map(|property| match property {
+                        Property::Item(item) => Some(item),
+                        Property::Text(_) => None,
+                    })
+                    .collect(),
+            },
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn json_ld_properties(json: &Value) -> Vec<Property> {
+    match json {
+        Value::Null => Vec::new(),
+        Value::Bool(val) => vec![Property::Text(val.to_string())],
+        Value::Number(val) => vec![Property::Text(val.to_string())],
+        Value::String(text) => clean_text(text).map(Property::Text).into_iter().collect(),
+        Value::Array(values) => values.iter().flat_map(json_ld_properties).collect(),
+        Value::Object(map) => {
+            if let Some(value) = map.get("@value") {
+                return json_ld_properties(value);
+            }
+
+            let mut item = Item::default();
+
+            for (key, value) in map {
+                match key.as_str() {
+                    "@context" => {}
+                    "@type" => {
+                        item.types = json_ld_properties(value)
+                            .into_iter()
+                            .filter_map(|property| match property {
+                                Property::Text(tt) => Some(local_name(&tt)),
+                                Property::Item(_) => None,
+                            })
+                            .collect()
+                    }
+                    _ => {
+                        let values = json_ld_properties(value);
+
+                        if !values.is_empty() {
+                            item.properties
+                                .entry(local_name(key))
+                                .or_default()
+                                .extend(values);
+                        }
+                    }
+                }
+            }
+
+            vec![Property::Item(item)]
+        }
+    }
+}
+
+/// `https://schema.org/Product` and `schema:Product` both become `Product`.
+fn local_name(name: &str) -> String {
+    name.trim()
+        .rsplit(|c: char| c == '/' || c == ':' || c == '#')
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn clean_text(text: &str) -> Option<String> {
+    let text = text.split_whitespace().join(" ");
+
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Syntax {
+    Microdata,
+    Rdfa,
+}
+
+impl Syntax {
+    /// Types of the item if the element starts a new item.
+    fn item_types(self, element: &ElementData) -> Option<Vec<String>> {
+        let attributes = element.attributes.borrow();
+
+        let types = match self {
+            Syntax::Microdata => {
+                if !attributes.contains("itemscope") {
+                    return None;
+                }
+
+                attributes.get("itemtype").unwrap_or_default()
+            }
+            Syntax::Rdfa => attributes.get("typeof")?,
+        };
+
+        Some(types.split_whitespace().map(local_name).collect())
+    }
+
+    fn property_names(self, element: &ElementData) -> Vec<String> {
+        let attribute = match self {
+            Syntax::Microdata => "itemprop",
+            Syntax::Rdfa => "property",
+        };
+
+        element
+            .attributes
+            .borrow()
+            .get(attribute)
+            .map(|names| names.split_whitespace().map(local_name).collect())
+            .unwrap_or_default()
+    }
+
+    fn value(self, element: &ElementData, node: &NodeRef) -> Option<String> {
+        let attributes = element.attributes.borrow();
+
+        let value = match self {
+            Syntax::Microdata => match &*element.name.local {
+                "meta" => attributes.get("content"),
+                "audio" | "embed" | "iframe" | "img" | "source" | "track" | "video" => {
+                    attributes.get("src")
+                }
+                "a" | "area" | "link" => attributes.get("href"),
+                "object" => attributes.get("data"),
+                "data" | "meter" => attributes.get("value"),
+                "time" => attributes.get("datetime"),
+                _ => None,
+            }
+            .or_else(|| attributes.get("content")),
+            Syntax::Rdfa => ["content", "href", "src", "resource", "datetime"]
+                .into_iter()
+                .find_map(|name| attributes.get(name)),
+        };
+
+        match value {
+            Some(value) => clean_text(value),
+            None => clean_text(&node.text_contents()),
+        }
+    }
+
+    fn parse(self, root: &NodeRef) -> Vec<Item> {
+        let mut items = Vec::new();
+        self.find_items(root, &mut items);
+        items
+    }
+
+    /// Items that are not the property of another item.
+    fn find_items(self, node: &NodeRef, items: &mut Vec<Item>) {
+        for child in node.children() {
+            if let Some(element) = child.as_element() {
+                if let Some(types) = self.item_types(element) {
+                    if self.property_names(element).is_empty() {
+                        items.push(self.parse_item(&child, types));
+                    }
+                }
+            }
+
+            self.find_items(&child, items);
+        }
+    }
+
+    fn parse_item(self, node: &NodeRef, types: Vec<String>) -> Item {
+        let mut item = Item {
+            types,
+            properties: BTreeMap::new(),
+        };
+
+        self.collect_properties(node, &mut item);
+
+        item
+    }
+
+    fn collect_properties(self, node: &NodeRef, item: &mut Item) {
+        for child in node.children() {
+            let element = match child.as_element() {
+                Some(element) => element,
+                None => continue,
+            };
+
+            let names = self.property_names(element);
+            let types = self.item_types(element);
+
+            let value = match types {
+                Some(types) => {
+                    if names.is_empty() {
+                        // separate item, found by `find_items`
+                        continue;
+                    }
+
+                    Property::Item(self.parse_item(&child, types))
+                }
+                None => {
+                    self.collect_properties(&child, item);
+
+                    if names.is_empty() {
+                        continue;
+                    }
+
+                    match self.value(element, &child) {
+                        Some(value) => Property::Text(value),
+                        None => continue,
+                    }
+                }
+            };
+
+            for name in names {
+                item.properties.entry(name).or_default().push(value.clone());
+            }
+        }
+    }
+}
+
+pub fn parse_microdata(root: &NodeRef) -> Vec<Item> {
+    Syntax::Microdata.parse(root)
+}
+
+pub fn parse_rdfa(root: &NodeRef) -> Vec<Item> {
+    Syntax::Rdfa.parse(root)
+}
+
 #[cfg(test)]
 mod tests {
+    use kuchiki::traits::TendrilSink;
+
     use super::*;
 
+    fn parse_json_ld(json: &str) -> Vec<SchemaOrg> {
+        let json: Value = serde_json::from_str(json).unwrap();
+        SchemaOrg::from_items(&Item::from_json_ld(&json))
+    }
+
+    fn parse_html(html: &str) -> Vec<SchemaOrg> {
+        let root = kuchiki::parse_html().one(html);
+
+        let mut items = parse_microdata(&root);
+        items.extend(parse_rdfa(&root));
+
+        SchemaOrg::from_items(&items)
+    }
+
     #[test]
     fn image_object_example() {
         // example taken from https://schema.org/ImageObject
@@ -62,5 +767,189 @@ mod tests {
                 content_url: Some("mexico-beach.jpg".to_string()),
             }),
         );
+        assert_eq!(parse_json_ld(json), vec![parsed]);
+    }
+
+    #[test]
+    fn json_ld_graph_with_nested_objects() {
+        let json = r#"
+        {
+            "@context": "https://schema.org",
+            "@graph": [
+                {
+                    "@type": "WebPage",
+                    "breadcrumb": {
+                        "@type": "BreadcrumbList",
+                        "itemListElement": [
+                            {"@type": "ListItem", "position": 2, "name": "Tech", "item": "https://example.com/tech"},
+                            {"@type": "ListItem", "position": 1, "name": "News", "item": {"@id": "https://example.com/news"}}
+                        ]
+                    }
+                },
+                {
+                    "@type": ["NewsArticle"],
+                    "headline": "Something happened",
+                    "author": [{"@type": "Person", "name": "Jane Doe"}, "John Doe"],
+                    "datePublished": "2022-09-01T10:00:00Z",
+                    "publisher": {"@type": "Organization", "name": "Example News"}
+                }
+            ]
+        }
+        "#;
+
+        assert_eq!(
+            parse_json_ld(json),
+            vec![
+                SchemaOrg::BreadcrumbList(BreadcrumbList {
+                    items: vec![
+                        ListItem {
+                            position: Some(1),
+                            name: Some("News".to_string()),
+                            url: Some("https://example.com/news".to_string()),
+                        },
+                        ListItem {
+                            position: Some(2),
+                            name: Some("Tech".to_string()),
+                            url: Some("https://example.com/tech".to_string()),
+                        },
+                    ]
+                }),
+                SchemaOrg::NewsArticle(Article {
+                    headline: Some("Something happened".to_string()),
+                    description: None,
+                    author: vec!["Jane Doe".to_string(), "John Doe".to_string()],
+                    date_published: Some("2022-09-01T10:00:00Z".to_string()),
+                    date_modified: None,
+                    publisher: Some("Example News".to_string()),
+                    image: None,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn json_ld_product_and_faq() {
+        let json = r#"
+        [
+            {
+                "@context": "https://schema.org",
+                "@type": "Product",
+                "name": "Executive Anvil",
+                "brand": {"@type": "Brand", "name": "ACME"},
+                "offers": {
+                    "@type": "Offer",
+                    "price": 119.99,
+                    "priceCurrency": "USD",
+                    "availability": "https://schema.org/InStock"
+                },
+                "aggregateRating": {"@type": "AggregateRating", "ratingValue": "4.4", "reviewCount": "89"}
+            },
+            {
+                "@context": "https://schema.org",
+                "@type": "FAQPage",
+                "mainEntity": [{
+                    "@type": "Question",
+                    "name": "How long does shipping take?",
+                    "acceptedAnswer": {"@type": "Answer", "text": "About 3 days."}
+                }]
+            }
+        ]
+        "#;
+
+        assert_eq!(
+            parse_json_ld(json),
+            vec![
+                SchemaOrg::Product(Product {
+                    name: Some("Executive Anvil".to_string()),
+                    description: None,
+                    brand: Some("ACME".to_string()),
+                    image: None,
+                    sku: None,
+                    offers: vec![Offer {
+                        price: Some("119.99".to_string()),
+                        price_currency: Some("USD".to_string()),
+                        availability: Some("InStock".to_string()),
+                    }],
+                    aggregate_rating: Some(AggregateRating {
+                        rating_value: Some("4.4".to_string()),
+                        rating_count: Some("89".to_string()),
+                    }),
+                }),
+                SchemaOrg::FAQPage(FAQPage {
+                    questions: vec![Question {
+                        question: "How long does shipping take?".to_string(),
+                        answer: Some("About 3 days.".to_string()),
+                    }]
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn microdata_recipe() {
+        let html = r#"
+        <html>
+            <body>
+                <div itemscope itemtype="https://schema.org/Recipe">
+                    <h1 itemprop="name">Mom's World Famous Banana Bread</h1>
+                    By <span itemprop="author" itemscope itemtype="https://schema.org/Person">
+                        <span itemprop="name">John Smith</span>
+                    </span>
+                    <meta itemprop="prepTime" content="PT15M">
+                    <div>
+                        <span itemprop="recipeIngredient">3 bananas</span>
+                        <span itemprop="recipeIngredient">1 cup of sugar</span>
+                    </div>
+                </div>
+            </body>
+        </html>
+        "#;
+
+        assert_eq!(
+            parse_html(html),
+            vec![SchemaOrg::Recipe(Recipe {
+                name: Some("Mom's World Famous Banana Bread".to_string()),
+                description: None,
+                author: vec!["John Smith".to_string()],
+                image: None,
+                prep_time: Some("PT15M".to_string()),
+                cook_time: None,
+                total_time: None,
+                recipe_yield: None,
+                ingredients: vec!["3 bananas".to_string(), "1 cup of sugar".to_string()],
+                aggregate_rating: None,
+            })]
+        );
+    }
+
+    #[test]
+    fn rdfa_event() {
+        let html = r#"
+        <html>
+            <body vocab="https://schema.org/">
+                <div typeof="Event">
+                    <a property="url" href="https://example.com/concert">
+                        <span property="name">Summer concert</span>
+                    </a>
+                    <time property="startDate" datetime="2022-07-01T20:00">July 1st</time>
+                    <div property="location" typeof="Place">
+                        <span property="name">The park</span>
+                    </div>
+                </div>
+            </body>
+        </html>
+        "#;
+
+        assert_eq!(
+            parse_html(html),
+            vec![SchemaOrg::Event(Event {
+                name: Some("Summer concert".to_string()),
+                description: None,
+                start_date: Some("2022-07-01T20:00".to_string()),
+                end_date: None,
+                location: Some("The park".to_string()),
+                organizer: None,
+            })]
+        );
     }
 }
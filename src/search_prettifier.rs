@@ -16,13 +16,14 @@
 
 use std::collections::BTreeMap;
 
-use chrono::{NaiveDate, NaiveDateTime, Utc};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
 use itertools::{intersperse, Itertools};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     entity_index::{entity::Span, StoredEntity},
     inverted_index::{self, RetrievedWebpage},
+    schema_org::{BreadcrumbList, SchemaOrg},
     searcher::{self, LocalSearcher},
     webpage::Url,
 };
@@ -91,6 +92,302 @@ pub struct DisplayedWebpage {
     pub body: String,
     pub primary_image_uuid: Option<String>,
     pub last_updated: Option<String>,
+    pub rich_snippet: Option<RichSnippet>,
+    pub more_from_site: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Rating {
+    pub value: String,
+    pub count: Option<String>,
+}
+
+impl std::fmt::Display for Rating {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.count {
+            Some(count) => write!(f, "{} ({} ratings)", self.value, count),
+            None => write!(f, "{}", self.value),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct DisplayedQuestion {
+    pub question: String,
+    pub answer: Option<String>,
+}
+
+/// Extra information about a result taken from its schema.org data.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum RichSnippet {
+    Recipe {
+        cook_time: Option<String>,
+        rating: Option<Rating>,
+    },
+    Product {
+        price: String,
+        availability: Option<String>,
+        rating: Option<Rating>,
+    },
+    Event {
+        date: Option<String>,
+        venue: Option<String>,
+    },
+    Article {
+        author: Option<String>,
+        published: Option<String>,
+    },
+    Faq {
+        questions: Vec<DisplayedQuestion>,
+    },
+}
+
+const MAX_FAQ_QUESTIONS: usize = 3;
+
+impl RichSnippet {
+    fn from_schemas(schemas: &[SchemaOrg]) -> Option<Self> {
+        schemas.iter().find_map(RichSnippet::from_schema)
+    }
+
+    fn from_schema(schema: &SchemaOrg) -> Option<Self> {
+        match schema {
+            SchemaOrg::Recipe(recipe) => {
+                let cook_time = recipe.cook_time.as_deref().and_then(prettify_duration);
+                let rating = recipe.aggregate_rating.as_ref().and_then(|rating| {
+                    Some(Rating {
+                        value: html_escape(rating.rating_value.as_ref()?),
+                        count: rating.rating_count.as_deref().map(html_escape),
+                    })
+                });
+
+                if cook_time.is_none() && rating.is_none() {
+                    None
+                } else {
+                    Some(RichSnippet::Recipe { cook_time, rating })
+                }
+            }
+            SchemaOrg::Product(product) => {
+                let offer = product.offers.iter().find(|offer| offer.price.is_some())?;
+                let price = match &offer.price_currency {
+                    Some(currency) => format!("{} {}", offer.price.as_ref()?, currency),
+                    None => offer.price.clone()?,
+                };
+
+                Some(RichSnippet::Product {
+                    price: html_escape(&price),
+                    availability: offer.availability.as_deref().map(prettify_camel_case),
+                    rating: product.aggregate_rating.as_ref().and_then(|rating| {
+                        Some(Rating {
+                            value: html_escape(rating.rating_value.as_ref()?),
+                            count: rating.rating_count.as_deref().map(html_escape),
+                        })
+                    }),
+                })
+            }
+            SchemaOrg::Event(event) => {
+                let date = event.start_date.as_deref().and_then(prettify_schema_date);
+                let venue = event.location.as_deref().map(html_escape);
+
+                if date.is_none() && venue.is_none() {
+                    None
+                } else {
+                    Some(RichSnippet::Event { date, venue })
+                }
+            }
+            SchemaOrg::Article(article) | SchemaOrg::NewsArticle(article) => {
+                let author = if article.author.is_empty() {
+                    None
+                } else {
+                    Some(html_escape(&article.author.join(", ")))
+                };
+                let published = article
+                    .date_published
+                    .as_deref()
+                    .and_then(prettify_schema_date);
+
+                if author.is_none() && published.is_none() {
+                    None
+                } else {
+                    Some(RichSnippet::Article { author, published })
+                }
+            }
+            SchemaOrg::FAQPage(faq) => {
+                if faq.questions.is_empty() {
+                    return None;
+                }
+
+                Some(RichSnippet::Faq {
+                    questions: faq
+                        .questions
+                        .iter()
+                        .take(MAX_FAQ_QUESTIONS)
+                        .map(|question| DisplayedQuestion {
+                            question: html_escape(&question.question),
+                            answer: question.answer.as_deref().map(html_escape),
+                        })
+                        .collect(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Short facts shown below the snippet in the html template.
+    pub fn facts(&self) -> Vec<String> {
+        let mut facts = Vec::new();
+
+        match self {
+            RichSnippet::Recipe { cook_time, rating } => {
+                if let Some(cook_time) = cook_time {
+                    facts.push(format!("Cook time: {cook_time}"));
+                }
+                if let Some(rating) = rating {
+                    facts.push(format!("Rating: {rating}"));
+                }
+            }
+            RichSnippet::Product {
+                price,
+                availability,
+                rating,
+            } => {
+                facts.push(price.clone());
+                if let Some(availability) = availability {
+                    facts.push(availability.clone());
+                }
+                if let Some(rating) = rating {
+                    facts.push(format!("Rating: {rating}"));
+                }
+            }
+            RichSnippet::Event { date, venue } => {
+                if let Some(date) = date {
+                    facts.push(date.clone());
+                }
+                if let Some(venue) = venue {
+                    facts.push(venue.clone());
+                }
+            }
+            RichSnippet::Article { author, published } => {
+                if let Some(author) = author {
+                    facts.push(format!("By {author}"));
+                }
+                if let Some(published) = published {
+                    facts.push(published.clone());
+                }
+            }
+            RichSnippet::Faq { .. } => {}
+        }
+
+        facts
+    }
+
+    pub fn questions(&self) -> Vec<DisplayedQuestion> {
+        match self {
+            RichSnippet::Faq { questions } => questions.clone(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// ISO 8601 durations (e.g. `PT1H30M`) as `1 h 30 min`.
+fn prettify_duration(duration: &str) -> Option<String> {
+    let duration = duration.trim().strip_prefix('P')?;
+    let mut parts = Vec::new();
+    let mut in_time = false;
+    let mut num = String::new();
+
+    for c in duration.chars() {
+        match c {
+            'T' => in_time = true,
+            '0'..='9' | '.' => num.push(c),
+            _ => {
+                let unit = match (c, in_time) {
+                    ('D', false) => "d",
+                    ('H', true) => "h",
+                    ('M', true) => "min",
+                    ('S', true) => "s",
+                    _ => return None,
+                };
+
+                if num.is_empty() {
+                    return None;
+                }
+
+                if num.trim_start_matches('0').is_empty() {
+                    num.clear();
+                    continue;
+                }
+
+                parts.push(format!("{} {}", num.trim_start_matches('0'), unit));
+                num.clear();
+            }
+        }
+    }
+
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(" "))
+    }
+}
+
+fn prettify_schema_date(date: &str) -> Option<String> {
+    let date = date.trim();
+
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(date) {
+        return Some(format!("{}", datetime.format("%d. %b. %Y")));
+    }
+
+    if let Ok(datetime) = NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M") {
+        return Some(format!("{}", datetime.format("%d. %b. %Y %H:%M")));
+    }
+
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .map(|date| format!("{}", date.format("%d. %b. %Y")))
+}
+
+/// `InStock` becomes `In stock`.
+fn prettify_camel_case(s: &str) -> String {
+    let mut res = String::new();
+
+    for (i, c) in s.chars().enumerate() {
+        if i > 0 && c.is_uppercase() {
+            res.push(' ');
+            res.extend(c.to_lowercase());
+        } else {
+            res.push(c);
+        }
+    }
+
+    html_escape(&res)
+}
+
+fn breadcrumbs_url(url: &Url, breadcrumbs: &BreadcrumbList) -> Option<String> {
+    let names: Vec<_> = breadcrumbs
+        .items
+        .iter()
+        .filter(|item| {
+            !matches!(
+                item.url
+                    .as_ref()
+                    .map(|url| Url::from(url.clone()).is_homepage()),
+                Some(true)
+            )
+        })
+        .filter_map(|item| item.name.as_deref())
+        .map(html_escape)
+        .collect();
+
+    if names.is_empty() {
+        return None;
+    }
+
+    Some(format!(
+        "{}://{} › {}",
+        url.protocol(),
+        url.site(),
+        names.join(" › ")
+    ))
 }
 
 fn prettify_url(url: &Url) -> String {
@@ -140,7 +437,15 @@ impl From<RetrievedWebpage> for DisplayedWebpage {
 
         let url: Url = webpage.url.clone().into();
         let domain = url.domain().to_string();
-        let pretty_url = prettify_url(&url);
+        let pretty_url = webpage
+            .schema_org
+            .iter()
+            .find_map(|schema| match schema {
+                SchemaOrg::BreadcrumbList(breadcrumbs) => breadcrumbs_url(&url, breadcrumbs),
+                _ => None,
+            })
+            .unwrap_or_else(|| prettify_url(&url));
+        let rich_snippet = RichSnippet::from_schemas(&webpage.schema_org);
 
         let title = html_escape(&webpage.title);
 
@@ -160,6 +465,8 @@ impl From<RetrievedWebpage> for DisplayedWebpage {
             body: webpage.body,
             primary_image_uuid: webpage.primary_image.map(|image| image.uuid.to_string()),
             last_updated,
+            rich_snippet,
+            more_from_site: webpage.more_from_site,
         }
     }
 }
@@ -354,7 +661,10 @@ fn entity_link_to_html(span: Span, trunace_to: usize) -> String {
 mod tests {
     use chrono::{DateTime, Utc};
 
-    use crate::entity_index::entity::Link;
+    use crate::{
+        entity_index::entity::Link,
+        schema_org::{AggregateRating, Article, ListItem, Offer, Product, Recipe},
+    };
 
     use super::*;
 
@@ -448,4 +758,117 @@ mod tests {
         assert_eq!(thousand_sep_number(512_854), "512.854".to_string());
         assert_eq!(thousand_sep_number(9_512_854), "9.512.854".to_string());
     }
+
+    #[test]
+    fn durations() {
+        assert_eq!(prettify_duration("PT1H30M"), Some("1 h 30 min".to_string()));
+        assert_eq!(prettify_duration("PT45M"), Some("45 min".to_string()));
+        assert_eq!(prettify_duration("P1DT0H"), Some("1 d".to_string()));
+        assert_eq!(prettify_duration("PT"), None);
+        assert_eq!(prettify_duration("45 minutes"), None);
+    }
+
+    #[test]
+    fn rich_snippets_from_schemas() {
+        let webpage = RetrievedWebpage {
+            url: "https://www.example.com/recipes/bread".to_string(),
+            schema_org: vec![
+                SchemaOrg::BreadcrumbList(BreadcrumbList {
+                    items: vec![
+                        ListItem {
+                            position: Some(1),
+                            name: Some("Home".to_string()),
+                            url: Some("https://www.example.com/".to_string()),
+                        },
+                        ListItem {
+                            position: Some(2),
+                            name: Some("Recipes".to_string()),
+                            url: Some("https://www.example.com/recipes".to_string()),
+                        },
+                    ],
+                }),
+                SchemaOrg::Recipe(Recipe {
+                    name: Some("Bread".to_string()),
+                    description: None,
+                    author: Vec::new(),
+                    image: None,
+                    prep_time: None,
+                    cook_time: Some("PT1H".to_string()),
+                    total_time: Some("PT2H30M".to_string()),
+                    recipe_yield: None,
+                    ingredients: Vec::new(),
+                    aggregate_rating: Some(AggregateRating {
+                        rating_value: Some("4.5".to_string()),
+                        rating_count: Some("12".to_string()),
+                    }),
+                }),
+            ],
+            ..Default::default()
+        };
+
+        let displayed = DisplayedWebpage::from(webpage);
+
+        assert_eq!(displayed.pretty_url, "https://www.example.com › Recipes");
+        assert_eq!(
+            displayed.rich_snippet,
+            Some(RichSnippet::Recipe {
+                cook_time: Some("1 h".to_string()),
+                rating: Some(Rating {
+                    value: "4.5".to_string(),
+                    count: Some("12".to_string()),
+                }),
+            })
+        );
+        assert_eq!(
+            displayed.rich_snippet.unwrap().facts(),
+            vec![
+                "Cook time: 1 h".to_string(),
+                "Rating: 4.5 (12 ratings)".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn product_and_article_snippets() {
+        let product = SchemaOrg::Product(Product {
+            name: Some("Anvil".to_string()),
+            description: None,
+            brand: None,
+            image: None,
+            sku: None,
+            offers: vec![Offer {
+                price: Some("119.99".to_string()),
+                price_currency: Some("USD".to_string()),
+                availability: Some("InStock".to_string()),
+            }],
+            aggregate_rating: None,
+        });
+
+        assert_eq!(
+            RichSnippet::from_schema(&product),
+            Some(RichSnippet::Product {
+                price: "119.99 USD".to_string(),
+                availability: Some("In stock".to_string()),
+                rating: None,
+            })
+        );
+
+        let article = SchemaOrg::NewsArticle(Article {
+            headline: None,
+            description: None,
+            author: vec!["Jane Doe".to_string(), "John Doe".to_string()],
+            date_published: Some("2022-09-01T10:00:00Z".to_string()),
+            date_modified: None,
+            publisher: None,
+            image: None,
+        });
+
+        assert_eq!(
+            RichSnippet::from_schema(&article),
+            Some(RichSnippet::Article {
+                author: Some("Jane Doe, John Doe".to_string()),
+                published: Some("01. Sep. 2022".to_string()),
+            })
+        );
+    }
 }
@@ -15,11 +15,14 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use crate::{
-    collector::{self, BucketCollector},
+    collector,
     exponential_backoff::ExponentialBackoff,
     inverted_index::{self, RetrievedWebpage},
     search_prettifier::DisplayedWebpage,
-    searcher::{PrettifiedWebsitesResult, SearchResult, WebsitesResult, NUM_RESULTS_PER_PAGE},
+    searcher::{
+        Cursor, PrettifiedWebsitesResult, SearchResult, WebsitesResult, MAX_RESULTS_PER_SITE,
+        NUM_RESULTS_PER_PAGE,
+    },
 };
 
 use std::{net::SocketAddr, time::Instant};
@@ -48,6 +51,9 @@ pub enum Error {
 
     #[error("Query cannot be empty")]
     EmptyQuery,
+
+    #[error("Page can only be reached with a cursor")]
+    PageTooDeep,
 }
 
 impl RemoteSearcher {
@@ -88,7 +94,7 @@ impl RemoteSearcher {
     async fn retrieve_websites(
         &self,
         pointers: &[inverted_index::WebsitePointer],
-        original_query: &str,
+        query: &SearchQuery,
     ) -> Result<Vec<RetrievedWebpage>> {
         for timeout in ExponentialBackoff::from_millis(30).take(5) {
             if let Ok(connection) = sonic::Connection::create_with_timeout(self.addr, timeout).await
@@ -96,7 +102,7 @@ impl RemoteSearcher {
                 if let Ok(sonic::Response::Content(body)) = connection
                     .send(Request::RetrieveWebites {
                         websites: pointers.to_vec(),
-                        query: original_query.to_string(),
+                        query: query.clone(),
                     })
                     .await
                 {
@@ -111,7 +117,7 @@ impl RemoteSearcher {
     async fn retrieve_websites_prettified(
         &self,
         pointers: &[inverted_index::WebsitePointer],
-        original_query: &str,
+        query: &SearchQuery,
     ) -> Result<Vec<DisplayedWebpage>> {
         for timeout in ExponentialBackoff::from_millis(30).take(5) {
             if let Ok(connection) = sonic::Connection::create_with_timeout(self.addr, timeout).await
@@ -119,7 +125,7 @@ impl RemoteSearcher {
                 if let Ok(sonic::Response::Content(body)) = connection
                     .send(Request::RetrievePrettifiedWebites {
                         websites: pointers.to_vec(),
-                        query: original_query.to_string(),
+                        query: query.clone(),
                     })
                     .await
                 {
@@ -156,11 +162,20 @@ impl Shard {
         }
     }
 
+    /// The query with the cursor as seen by the shard.
+    fn local_query(&self, query: &SearchQuery) -> SearchQuery {
+        let mut query = query.clone();
+        query.cursor = query.cursor.map(|cursor| cursor.for_shard(self.id.0));
+        query
+    }
+
     async fn search(&self, query: &SearchQuery) -> Result<InitialSearchResultShard> {
+        let query = self.local_query(query);
+
         match self
             .replicas
             .iter()
-            .map(|remote| remote.search(query))
+            .map(|remote| remote.search(&query))
             .collect::<FuturesUnordered<_>>()
             .next()
             .await
@@ -177,10 +192,12 @@ impl Shard {
         &self,
         query: &SearchQuery,
     ) -> Result<InitialPrettifiedSearchResultShard> {
+        let query = self.local_query(query);
+
         match self
             .replicas
             .iter()
-            .map(|remote| remote.search_prettified(query))
+            .map(|remote| remote.search_prettified(&query))
             .collect::<FuturesUnordered<_>>()
             .next()
             .await
@@ -196,12 +213,12 @@ impl Shard {
     async fn retrieve_websites(
         &self,
         pointers: &[inverted_index::WebsitePointer],
-        original_query: &str,
+        query: &SearchQuery,
     ) -> Result<Vec<RetrievedWebpage>> {
         match self
             .replicas
             .iter()
-            .map(|remote| remote.retrieve_websites(pointers, original_query))
+            .map(|remote| remote.retrieve_websites(pointers, query))
             .collect::<FuturesUnordered<_>>()
             .next()
             .await
@@ -214,12 +231,12 @@ impl Shard {
     async fn retrieve_websites_prettified(
         &self,
         pointers: &[inverted_index::WebsitePointer],
-        original_query: &str,
+        query: &SearchQuery,
     ) -> Result<Vec<DisplayedWebpage>> {
         match self
             .replicas
             .iter()
-            .map(|remote| remote.retrieve_websites_prettified(pointers, original_query))
+            .map(|remote| remote.retrieve_websites_prettified(pointers, query))
             .collect::<FuturesUnordered<_>>()
             .next()
             .await
@@ -246,11 +263,11 @@ pub enum Request {
     SearchPrettified(SearchQuery),
     RetrieveWebites {
         websites: Vec<inverted_index::WebsitePointer>,
-        query: String,
+        query: SearchQuery,
     },
     RetrievePrettifiedWebites {
         websites: Vec<inverted_index::WebsitePointer>,
-        query: String,
+        query: SearchQuery,
     },
 }
 
@@ -269,8 +286,11 @@ impl collector::Doc for ScoredWebsitePointer {
         &self.local_pointer.score
     }
 
-    fn id(&self) -> &tantivy::DocId {
-        &self.local_pointer.address.doc_id
+    fn tiebreak(&self) -> collector::Tiebreak {
+        collector::Tiebreak {
+            shard: Some(self.shard.0),
+            ..collector::Tiebreak::from(self.local_pointer.address)
+        }
     }
 
     fn hashes(&self) -> collector::Hashes {
@@ -278,6 +298,46 @@ impl collector::Doc for ScoredWebsitePointer {
     }
 }
 
+impl collector::SiteDoc for ScoredWebsitePointer {
+    fn more_from_site(&mut self) -> &mut usize {
+        &mut self.local_pointer.more_from_site
+    }
+}
+
+/// Merge the results of the shards into the page after `skip_pages` pages and the cursor of the page.
+fn combine_results(
+    shard_results: Vec<(ShardId, Vec<inverted_index::WebsitePointer>)>,
+    skip_pages: usize,
+) -> (Vec<ScoredWebsitePointer>, Option<Cursor>) {
+    let mut websites: Vec<_> = shard_results
+        .into_iter()
+        .flat_map(|(shard, websites)| {
+            websites
+                .into_iter()
+                .map(move |website| ScoredWebsitePointer {
+                    local_pointer: website,
+                    shard: shard.clone(),
+                })
+        })
+        .collect();
+    websites.sort_by(collector::rank_order);
+
+    let page: Vec<_> = websites
+        .into_iter()
+        .skip(skip_pages * NUM_RESULTS_PER_PAGE)
+        .take(NUM_RESULTS_PER_PAGE)
+        .collect();
+
+    let cursor = page
+        .last()
+        .map(|end| Cursor::new(&end.local_pointer, Some(end.shard.0)));
+
+    (
+        collector::build_page(page, true, Some(MAX_RESULTS_PER_SITE)),
+        cursor,
+    )
+}
+
 impl DistributedSearcher {
     pub fn new(shards: Vec<Shard>) -> Self {
         Self { shards }
@@ -290,6 +350,10 @@ impl DistributedSearcher {
             return Err(Error::EmptyQuery);
         }
 
+        if !query.is_page_reachable() {
+            return Err(Error::PageTooDeep);
+        }
+
         // search shards
         let initial_results = self
             .shards
@@ -340,28 +404,20 @@ impl DistributedSearcher {
             .sum();
 
         // combine results
-        let top_n = NUM_RESULTS_PER_PAGE + query.skip_pages.unwrap_or(0);
-        let mut collector = BucketCollector::new(top_n);
-
-        for result in initial_results {
-            if let InitialSearchResult::Websites(local_result) = result.local_result {
-                for website in local_result.websites.top_websites {
-                    let pointer = ScoredWebsitePointer {
-                        local_pointer: website,
-                        shard: result.shard.clone(),
-                    };
-
-                    collector.insert(pointer);
-                }
-            }
-        }
-
-        let top_websites = collector
-            .into_sorted_vec(true)
-            .into_iter()
-            .skip(query.skip_pages.unwrap_or(0))
-            .take(NUM_RESULTS_PER_PAGE)
-            .collect::<Vec<_>>();
+        let (top_websites, cursor) = combine_results(
+            initial_results
+                .into_iter()
+                .filter_map(|result| {
+                    if let InitialSearchResult::Websites(local_result) = result.local_result {
+                        Some((result.shard, local_result.websites.top_websites))
+                    } else {
+                        None
+                    }
+                })
+                .collect(),
+            query.num_skipped_pages(),
+        );
+        let cursor = cursor.map(|cursor| cursor.encode());
 
         // retrieve webpages
         let mut retrieved_webpages = Vec::new();
@@ -378,8 +434,9 @@ impl DistributedSearcher {
                 .map(|(idx, pointer)| (idx, pointer.local_pointer.clone()))
                 .unzip();
 
-            if let Ok(websites) = shard.retrieve_websites(&pointers, &query.original).await {
-                for (index, website) in indexes.into_iter().zip(websites.into_iter()) {
+            if let Ok(websites) = shard.retrieve_websites(&pointers, query).await {
+                for (index, mut website) in indexes.into_iter().zip(websites.into_iter()) {
+                    website.more_from_site = top_websites[index].local_pointer.more_from_site;
                     retrieved_webpages[index] = Some(website);
                 }
             }
@@ -394,6 +451,7 @@ impl DistributedSearcher {
         }
 
         Ok(SearchResult::Websites(WebsitesResult {
+            cursor,
             spell_corrected_query,
             webpages: inverted_index::SearchResult {
                 num_docs,
@@ -411,6 +469,10 @@ impl DistributedSearcher {
             return Err(Error::EmptyQuery);
         }
 
+        if !query.is_page_reachable() {
+            return Err(Error::PageTooDeep);
+        }
+
         // search shards
         let initial_results = self
             .shards
@@ -461,28 +523,22 @@ impl DistributedSearcher {
             .sum();
 
         // combine results
-        let top_n = NUM_RESULTS_PER_PAGE + query.skip_pages.unwrap_or(0);
-        let mut collector = BucketCollector::new(top_n);
-
-        for result in initial_results {
-            if let InitialPrettifiedSearchResult::Websites(local_result) = result.local_result {
-                for website in local_result.websites.top_websites {
-                    let pointer = ScoredWebsitePointer {
-                        local_pointer: website,
-                        shard: result.shard.clone(),
-                    };
-
-                    collector.insert(pointer);
-                }
-            }
-        }
-
-        let top_websites = collector
-            .into_sorted_vec(true)
-            .into_iter()
-            .skip(query.skip_pages.unwrap_or(0))
-            .take(NUM_RESULTS_PER_PAGE)
-            .collect::<Vec<_>>();
+        let (top_websites, cursor) = combine_results(
+            initial_results
+                .into_iter()
+                .filter_map(|result| {
+                    if let InitialPrettifiedSearchResult::Websites(local_result) =
+                        result.local_result
+                    {
+                        Some((result.shard, local_result.websites.top_websites))
+                    } else {
+                        None
+                    }
+                })
+                .collect(),
+            query.num_skipped_pages(),
+        );
+        let cursor = cursor.map(|cursor| cursor.encode());
 
         // retrieve webpages
         let mut retrieved_webpages = Vec::new();
@@ -499,11 +555,9 @@ impl DistributedSearcher {
                 .map(|(idx, pointer)| (idx, pointer.local_pointer.clone()))
                 .unzip();
 
-            if let Ok(websites) = shard
-                .retrieve_websites_prettified(&pointers, &query.original)
-                .await
-            {
-                for (index, website) in indexes.into_iter().zip(websites.into_iter()) {
+            if let Ok(websites) = shard.retrieve_websites_prettified(&pointers, query).await {
+                for (index, mut website) in indexes.into_iter().zip(websites.into_iter()) {
+                    website.more_from_site = top_websites[index].local_pointer.more_from_site;
                     retrieved_webpages[index] = Some(website);
                 }
             }
@@ -518,6 +572,7 @@ impl DistributedSearcher {
         }
 
         Ok(PrettifiedSearchResult::Websites(PrettifiedWebsitesResult {
+            cursor,
             spell_corrected_query,
             num_docs,
             webpages: retrieved_webpages,
@@ -526,3 +581,141 @@ impl DistributedSearcher {
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::{
+        entrypoint::search_server,
+        index::Index,
+        searcher::{LocalSearcher, MAX_SKIP_PAGES},
+        webpage::{Html, Webpage},
+    };
+
+    use super::*;
+
+    const NUM_SHARDS: usize = 2;
+    const NUM_PAGES: usize = 3;
+    const NUM_WEBSITES: usize = NUM_PAGES * NUM_RESULTS_PER_PAGE;
+
+    async fn searcher() -> DistributedSearcher {
+        let mut indexes: Vec<_> = (0..NUM_SHARDS)
+            .map(|_| Index::temporary().expect("Unable to open index"))
+            .collect();
+
+        for i in 0..NUM_WEBSITES {
+            indexes[i % NUM_SHARDS]
+                .insert(Webpage {
+                    html: Html::parse(
+                        &format!(
+                            r#"
+            <html>
+                <head>
+                    <title>Example website {}</title>
+                </head>
+                <body>
+                    test
+                </body>
+            </html>
+            "#,
+                            // pairs of results with the same title straddle the page boundaries
+                            (i + 1) / 2
+                        ),
+                        &format!("https://www.{i}.com"),
+                    ),
+                    backlinks: vec![],
+                    // results on both shards have the same score
+                    host_centrality: ((NUM_WEBSITES - i) / 4) as f64,
+                    fetch_time_ms: 500,
+                    page_centrality: 0.0,
+                    host_pagerank: 0.0,
+                    page_pagerank: 0.0,
+                    host_trustrank: 0.0,
+                    host_antitrustrank: 0.0,
+                    pre_computed_score: 0.0,
+                    primary_image: None,
+                })
+                .expect("failed to insert webpage");
+        }
+
+        let mut shards = Vec::new();
+
+        for (id, mut index) in indexes.into_iter().enumerate() {
+            index.commit().unwrap();
+
+            let server = sonic::Server::bind("127.0.0.1:0").await.unwrap();
+            let addr = server.local_addr().unwrap();
+            tokio::spawn(search_server::serve(server, LocalSearcher::from(index)));
+
+            shards.push(Shard::new(id as u32, vec![addr.to_string()]));
+        }
+
+        DistributedSearcher::new(shards)
+    }
+
+    async fn search(
+        searcher: &DistributedSearcher,
+        skip_pages: usize,
+        cursor: Option<&str>,
+    ) -> Result<(Vec<String>, Option<String>)> {
+        match searcher
+            .search_api(&SearchQuery {
+                original: "test".to_string(),
+                selected_region: None,
+                goggle_program: None,
+                skip_pages: Some(skip_pages),
+                site_rankings: None,
+                cursor: cursor.and_then(Cursor::decode),
+            })
+            .await?
+        {
+            SearchResult::Websites(result) => Ok((
+                result
+                    .webpages
+                    .documents
+                    .into_iter()
+                    .map(|page| page.url)
+                    .collect(),
+                result.cursor,
+            )),
+            SearchResult::Bang(_) => panic!("unexpected bang"),
+        }
+    }
+
+    #[tokio::test]
+    async fn cursor_pages() {
+        let searcher = searcher().await;
+
+        let mut seen = HashSet::new();
+        let mut cursor = None;
+
+        for p in 0..NUM_PAGES {
+            let (urls, next_cursor) = search(&searcher, p, cursor.as_deref()).await.unwrap();
+
+            assert_eq!(urls.len(), NUM_RESULTS_PER_PAGE);
+
+            // the page is the same whether it is reached by its offset or by a cursor
+            let (offset_urls, _) = search(&searcher, p, None).await.unwrap();
+            assert_eq!(urls, offset_urls);
+
+            for url in urls {
+                assert!(seen.insert(url));
+            }
+
+            cursor = next_cursor;
+        }
+
+        assert_eq!(seen.len(), NUM_WEBSITES);
+
+        let (urls, _) = search(&searcher, NUM_PAGES, cursor.as_deref())
+            .await
+            .unwrap();
+        assert!(urls.is_empty());
+
+        assert!(matches!(
+            search(&searcher, MAX_SKIP_PAGES + 1, None).await,
+            Err(Error::PageTooDeep)
+        ));
+    }
+}
@@ -14,6 +14,7 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use std::borrow::Cow;
 use std::str::FromStr;
 use std::time::Instant;
 
@@ -25,23 +26,27 @@ use crate::entity_index::{EntityIndex, StoredEntity};
 use crate::image_store::Image;
 use crate::index::Index;
 use crate::query::Query;
-use crate::ranking::goggles;
+use crate::ranking::goggles::{self, Goggle};
 use crate::ranking::{Ranker, SignalAggregator};
+use crate::synonyms::Synonyms;
 use crate::webpage::region::Region;
 use crate::webpage::Url;
 use crate::{inverted_index, Error, Result};
 
-use super::{InitialSearchResult, SearchQuery, SearchResult, WebsitesResult, NUM_RESULTS_PER_PAGE};
+use super::{
+    Cursor, InitialSearchResult, SearchQuery, SearchResult, WebsitesResult, NUM_RESULTS_PER_PAGE,
+};
 
 pub struct LocalSearcher {
     index: Index,
     entity_index: Option<EntityIndex>,
     bangs: Option<Bangs>,
+    synonyms: Synonyms,
 }
 
 impl From<Index> for LocalSearcher {
     fn from(index: Index) -> Self {
-        Self::new(index, None, None)
+        Self::new(index, None, None, None)
     }
 }
 
@@ -53,26 +58,51 @@ pub struct InitialWebsiteResult {
 }
 
 impl LocalSearcher {
-    pub fn new(index: Index, entity_index: Option<EntityIndex>, bangs: Option<Bangs>) -> Self {
+    pub fn new(
+        index: Index,
+        entity_index: Option<EntityIndex>,
+        bangs: Option<Bangs>,
+        synonyms: Option<Synonyms>,
+    ) -> Self {
         LocalSearcher {
             index,
             entity_index,
             bangs,
+            synonyms: synonyms.unwrap_or_default(),
         }
     }
 
+    /// The synonyms of the search server, extended with the ones from the goggle.
+    fn synonyms(&self, goggle: Option<&Goggle>) -> Cow<'_, Synonyms> {
+        match goggle {
+            Some(goggle) if !goggle.synonyms.is_empty() => {
+                let mut synonyms = self.synonyms.clone();
+                synonyms.extend(&goggle.synonyms);
+                Cow::Owned(synonyms)
+            }
+            _ => Cow::Borrowed(&self.synonyms),
+        }
+    }
+
+    /// Without `build_page` the results up to the end of the page are returned by their score,
+    /// so the [`DistributedSearcher`](super::DistributedSearcher) can build the page from the
+    /// results of all the shards.
     pub fn search_initial(
         &self,
         query: &SearchQuery,
-        de_rank_similar: bool,
+        build_page: bool,
     ) -> Result<InitialSearchResult> {
+        if !query.is_page_reachable() {
+            return Err(Error::PageTooDeep);
+        }
+
         let raw_query = query.original.clone();
         let goggle = query
             .goggle_program
             .as_ref()
             .and_then(|program| goggles::parse(program).ok());
 
-        let mut parsed_query = Query::parse(
+        let mut parsed_query = Query::parse_with_synonyms(
             &query.original,
             self.index.schema(),
             self.index.tokenizers(),
@@ -80,6 +110,7 @@ impl LocalSearcher {
                 .as_ref()
                 .map(|goggle| &goggle.aggregator)
                 .unwrap_or(&SignalAggregator::default()),
+            &self.synonyms(goggle.as_ref()),
         )?;
 
         if parsed_query.is_empty() {
@@ -110,8 +141,16 @@ impl LocalSearcher {
             self.index.inverted_index.fastfield_cache(),
         );
 
-        if let Some(skip_pages) = query.skip_pages {
+        let skip_pages = query.num_skipped_pages();
+
+        if build_page {
             ranker = ranker.with_offset(NUM_RESULTS_PER_PAGE * skip_pages);
+        } else {
+            ranker = ranker.with_num_results(NUM_RESULTS_PER_PAGE * (skip_pages + 1));
+        }
+
+        if let Some(cursor) = &query.cursor {
+            ranker = ranker.with_search_after(cursor.search_after());
         }
 
         if let Some(region) = query.selected_region {
@@ -121,12 +160,15 @@ impl LocalSearcher {
         }
 
         ranker = ranker.with_max_docs(10_000_000, self.index.num_segments());
-        ranker.de_rank_similar(de_rank_similar);
+        ranker = ranker.with_proximity_terms(parsed_query.proximity_terms().to_vec());
+        ranker.build_page(build_page);
 
         let webpages = self
             .index
             .search_initial(&parsed_query, ranker.collector())?;
-        let correction = self.index.spell_correction(&parsed_query.simple_terms());
+        let correction = self
+            .index
+            .spell_correction(&parsed_query.simple_terms(), parsed_query.lang());
 
         let entity = self
             .entity_index
@@ -140,37 +182,72 @@ impl LocalSearcher {
         }))
     }
 
-    pub fn retrieve_websites(
-        &self,
-        websites: &[inverted_index::WebsitePointer],
-        query: &str,
-    ) -> Result<Vec<inverted_index::RetrievedWebpage>> {
-        let query = Query::parse(
-            query,
+    /// The query used to generate snippets for the retrieved websites.
+    fn retrieval_query(&self, query: &SearchQuery) -> Result<Query> {
+        let goggle = query
+            .goggle_program
+            .as_ref()
+            .and_then(|program| goggles::parse(program).ok());
+
+        // synonyms are highlighted in the snippets as well
+        let query = Query::parse_with_synonyms(
+            &query.original,
             self.index.schema(),
             self.index.tokenizers(),
             &SignalAggregator::default(),
+            &self.synonyms(goggle.as_ref()),
         )?;
 
         if query.is_empty() {
             return Err(Error::EmptyQuery);
         }
 
+        Ok(query)
+    }
+
+    pub fn retrieve_websites(
+        &self,
+        websites: &[inverted_index::WebsitePointer],
+        query: &SearchQuery,
+    ) -> Result<Vec<inverted_index::RetrievedWebpage>> {
+        let query = self.retrieval_query(query)?;
+
         self.index.retrieve_websites(websites, &query)
     }
 
     pub fn search(&self, query: &SearchQuery) -> Result<SearchResult> {
         let start = Instant::now();
 
-        let query_text = query.original.clone();
         let initial_result = self.search_initial(query, true)?;
 
         match initial_result {
             InitialSearchResult::Websites(search_result) => {
-                let retrieved_sites =
-                    self.retrieve_websites(&search_result.websites.top_websites, &query_text)?;
+                let mut retrieved_sites =
+                    self.retrieve_websites(&search_result.websites.top_websites, query)?;
+
+                if let Some(region) = &query.selected_region {
+                    self.index.localize(
+                        &mut retrieved_sites,
+                        region,
+                        &self.retrieval_query(query)?,
+                    )?;
+                }
+
+                for (site, pointer) in retrieved_sites
+                    .iter_mut()
+                    .zip(&search_result.websites.top_websites)
+                {
+                    site.more_from_site = pointer.more_from_site;
+                }
+
+                let cursor = search_result
+                    .websites
+                    .page_end
+                    .as_ref()
+                    .map(|end| Cursor::new(end, None).encode());
 
                 Ok(SearchResult::Websites(WebsitesResult {
+                    cursor,
                     spell_corrected_query: search_result.spell_corrected_query,
                     webpages: inverted_index::SearchResult {
                         num_docs: search_result.websites.num_websites,
@@ -220,6 +297,9 @@ impl SearchResult {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
+
+    use crate::searcher::MAX_SKIP_PAGES;
     use crate::webpage::{Html, Webpage};
 
     use super::*;
@@ -251,6 +331,10 @@ mod tests {
                     host_centrality: (NUM_WEBSITES - i) as f64,
                     fetch_time_ms: 500,
                     page_centrality: 0.0,
+                    host_pagerank: 0.0,
+                    page_pagerank: 0.0,
+                    host_trustrank: 0.0,
+                    host_antitrustrank: 0.0,
                     pre_computed_score: 0.0,
                     primary_image: None,
                 })
@@ -259,7 +343,7 @@ mod tests {
 
         index.commit().unwrap();
 
-        let searcher = LocalSearcher::new(index, None, None);
+        let searcher = LocalSearcher::new(index, None, None, None);
 
         for p in 0..NUM_PAGES {
             let urls: Vec<_> = searcher
@@ -269,6 +353,7 @@ mod tests {
                     goggle_program: None,
                     skip_pages: Some(p),
                     site_rankings: None,
+                    cursor: None,
                 })
                 .unwrap()
                 .into_websites()
@@ -289,4 +374,374 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn localized_result() {
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        for (html, url) in [
+            (
+                r#"
+            <html>
+                <head>
+                    <title>English article</title>
+                    <link rel="alternate" hreflang="da-dk" href="https://www.example.com/da/article" />
+                </head>
+                <body>
+                    test
+                </body>
+            </html>
+            "#,
+                "https://www.example.com/article",
+            ),
+            (
+                r#"
+            <html>
+                <head>
+                    <title>Dansk artikel</title>
+                </head>
+                <body>
+                    prøve
+                </body>
+            </html>
+            "#,
+                "https://www.example.com/da/article",
+            ),
+        ] {
+            index
+                .insert(Webpage {
+                    html: Html::parse(html, url),
+                    backlinks: vec![],
+                    host_centrality: 0.0,
+                    fetch_time_ms: 500,
+                    page_centrality: 0.0,
+                    host_pagerank: 0.0,
+                    page_pagerank: 0.0,
+                    host_trustrank: 0.0,
+                    host_antitrustrank: 0.0,
+                    pre_computed_score: 0.0,
+                    primary_image: None,
+                })
+                .expect("failed to insert webpage");
+        }
+
+        index.commit().unwrap();
+
+        let searcher = LocalSearcher::new(index, None, None, None);
+
+        let search = |selected_region| {
+            searcher
+                .search(&SearchQuery {
+                    original: "test".to_string(),
+                    selected_region,
+                    goggle_program: None,
+                    skip_pages: None,
+                    site_rankings: None,
+                    cursor: None,
+                })
+                .unwrap()
+                .into_websites()
+                .unwrap()
+                .webpages
+                .documents
+        };
+
+        let result = search(None);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].url, "https://www.example.com/article");
+        assert_eq!(result[0].title, "English article");
+
+        // the danish version is shown with its own title
+        let result = search(Some(Region::Denmark));
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].url, "https://www.example.com/da/article");
+        assert_eq!(result[0].title, "Dansk artikel");
+    }
+
+    #[test]
+    fn cursor_pages() {
+        const NUM_PAGES: usize = MAX_SKIP_PAGES + 5;
+        const NUM_WEBSITES: usize = NUM_PAGES * NUM_RESULTS_PER_PAGE;
+
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        for i in 0..NUM_WEBSITES {
+            index
+                .insert(Webpage {
+                    html: Html::parse(
+                        r#"
+            <html>
+                <head>
+                    <title>Example website</title>
+                </head>
+                <body>
+                    test
+                </body>
+            </html>
+            "#,
+                        &format!("https://www.{i}.com"),
+                    ),
+                    backlinks: vec![],
+                    host_centrality: (NUM_WEBSITES - i) as f64,
+                    fetch_time_ms: 500,
+                    page_centrality: 0.0,
+                    host_pagerank: 0.0,
+                    page_pagerank: 0.0,
+                    host_trustrank: 0.0,
+                    host_antitrustrank: 0.0,
+                    pre_computed_score: 0.0,
+                    primary_image: None,
+                })
+                .expect("failed to insert webpage");
+        }
+
+        index.commit().unwrap();
+
+        let searcher = LocalSearcher::new(index, None, None, None);
+        let mut cursor = None;
+
+        for p in 0..NUM_PAGES {
+            let result = searcher
+                .search(&SearchQuery {
+                    original: "test".to_string(),
+                    selected_region: None,
+                    goggle_program: None,
+                    skip_pages: None,
+                    site_rankings: None,
+                    cursor: cursor.as_deref().and_then(Cursor::decode),
+                })
+                .unwrap()
+                .into_websites()
+                .unwrap();
+
+            let urls: Vec<_> = result
+                .webpages
+                .documents
+                .into_iter()
+                .map(|page| page.url)
+                .collect();
+
+            assert_eq!(urls.len(), NUM_RESULTS_PER_PAGE);
+
+            for (i, url) in urls.into_iter().enumerate() {
+                assert_eq!(
+                    url,
+                    format!("https://www.{}.com", i + (p * NUM_RESULTS_PER_PAGE))
+                )
+            }
+
+            cursor = result.cursor;
+            assert!(cursor.is_some());
+        }
+    }
+
+    #[test]
+    fn cursor_pages_with_proximity() {
+        const NUM_PAGES: usize = 8;
+        const NUM_WEBSITES: usize = NUM_PAGES * NUM_RESULTS_PER_PAGE;
+
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        for i in 0..NUM_WEBSITES {
+            // more pages than proximity candidates, where some of the lower ranked pages
+            // have the query terms next to each other
+            let body = if i % 3 == 0 {
+                "kayak rental by the lake and many other things to do"
+            } else {
+                "kayak by the lake and many other things to do rental"
+            };
+
+            index
+                .insert(Webpage {
+                    html: Html::parse(
+                        &format!(
+                            r#"
+            <html>
+                <head>
+                    <title>Lake house {i}</title>
+                </head>
+                <body>
+                    {body}
+                </body>
+            </html>
+            "#
+                        ),
+                        &format!("https://www.{i}.com"),
+                    ),
+                    backlinks: vec![],
+                    host_centrality: (NUM_WEBSITES - i) as f64,
+                    fetch_time_ms: 500,
+                    page_centrality: 0.0,
+                    host_pagerank: 0.0,
+                    page_pagerank: 0.0,
+                    host_trustrank: 0.0,
+                    host_antitrustrank: 0.0,
+                    pre_computed_score: 0.0,
+                    primary_image: None,
+                })
+                .expect("failed to insert webpage");
+        }
+
+        index.commit().unwrap();
+
+        let searcher = LocalSearcher::new(index, None, None, None);
+
+        let search = |skip_pages, cursor: Option<&str>| {
+            searcher
+                .search(&SearchQuery {
+                    original: "kayak rental".to_string(),
+                    selected_region: None,
+                    goggle_program: None,
+                    skip_pages,
+                    site_rankings: None,
+                    cursor: cursor.and_then(Cursor::decode),
+                })
+                .unwrap()
+                .into_websites()
+                .unwrap()
+        };
+
+        let mut cursor = None;
+        let mut seen = HashSet::new();
+
+        for p in 0..NUM_PAGES {
+            let with_cursor = search(None, cursor.as_deref());
+            let with_offset = search(Some(p), None);
+
+            let urls: Vec<_> = with_cursor
+                .webpages
+                .documents
+                .into_iter()
+                .map(|page| page.url)
+                .collect();
+
+            assert_eq!(urls.len(), NUM_RESULTS_PER_PAGE);
+            assert_eq!(
+                urls,
+                with_offset
+                    .webpages
+                    .documents
+                    .into_iter()
+                    .map(|page| page.url)
+                    .collect::<Vec<_>>()
+            );
+
+            for url in urls {
+                assert!(seen.insert(url));
+            }
+
+            cursor = with_cursor.cursor;
+        }
+
+        assert_eq!(seen.len(), NUM_WEBSITES);
+    }
+
+    #[test]
+    fn similar_results_across_pages() {
+        const NUM_PAGES: usize = 4;
+        const NUM_WEBSITES: usize = NUM_PAGES * NUM_RESULTS_PER_PAGE;
+
+        let mut index = Index::temporary().expect("Unable to open index");
+
+        for i in 0..NUM_WEBSITES {
+            index
+                .insert(Webpage {
+                    html: Html::parse(
+                        &format!(
+                            r#"
+            <html>
+                <head>
+                    <title>Example website {}</title>
+                </head>
+                <body>
+                    test
+                </body>
+            </html>
+            "#,
+                            // pairs of results with the same title straddle the page boundaries
+                            (i + 1) / 2
+                        ),
+                        &format!("https://www.{i}.com"),
+                    ),
+                    backlinks: vec![],
+                    host_centrality: (NUM_WEBSITES - i) as f64,
+                    fetch_time_ms: 500,
+                    page_centrality: 0.0,
+                    host_pagerank: 0.0,
+                    page_pagerank: 0.0,
+                    host_trustrank: 0.0,
+                    host_antitrustrank: 0.0,
+                    pre_computed_score: 0.0,
+                    primary_image: None,
+                })
+                .expect("failed to insert webpage");
+        }
+
+        index.commit().unwrap();
+
+        let searcher = LocalSearcher::new(index, None, None, None);
+
+        let search = |skip_pages, cursor: Option<&str>| {
+            searcher
+                .search(&SearchQuery {
+                    original: "test".to_string(),
+                    selected_region: None,
+                    goggle_program: None,
+                    skip_pages,
+                    site_rankings: None,
+                    cursor: cursor.and_then(Cursor::decode),
+                })
+                .unwrap()
+                .into_websites()
+                .unwrap()
+        };
+
+        let mut seen = HashSet::new();
+        let mut cursor = None;
+
+        for p in 0..NUM_PAGES {
+            let result = search(Some(p), cursor.as_deref());
+            let urls: Vec<_> = result
+                .webpages
+                .documents
+                .into_iter()
+                .map(|page| page.url)
+                .collect();
+
+            assert_eq!(urls.len(), NUM_RESULTS_PER_PAGE);
+
+            // the page is the same whether it is reached by its offset or by a cursor
+            let offset_urls: Vec<_> = search(Some(p), None)
+                .webpages
+                .documents
+                .into_iter()
+                .map(|page| page.url)
+                .collect();
+            assert_eq!(urls, offset_urls);
+
+            for url in urls {
+                assert!(seen.insert(url));
+            }
+
+            cursor = result.cursor;
+        }
+
+        assert_eq!(seen.len(), NUM_WEBSITES);
+        assert!(search(Some(NUM_PAGES), cursor.as_deref())
+            .webpages
+            .documents
+            .is_empty());
+
+        assert!(matches!(
+            searcher.search(&SearchQuery {
+                original: "test".to_string(),
+                selected_region: None,
+                goggle_program: None,
+                skip_pages: Some(MAX_SKIP_PAGES + 1),
+                site_rankings: None,
+                cursor: None,
+            }),
+            Err(Error::PageTooDeep)
+        ));
+    }
 }
@@ -23,14 +23,19 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     bangs::BangHit,
+    collector::{SearchAfter, Ties},
     entity_index::StoredEntity,
-    inverted_index,
+    inverted_index::{self, WebsitePointer},
     ranking::site_rankings::SiteRankings,
     search_prettifier::{self, DisplayedEntity, DisplayedWebpage},
     webpage::region::Region,
 };
 
 pub const NUM_RESULTS_PER_PAGE: usize = 20;
+/// Results from the same site beyond this on a page are folded under the top result from the site.
+pub const MAX_RESULTS_PER_SITE: usize = 3;
+/// Pages further than this are only reachable with a cursor.
+pub const MAX_SKIP_PAGES: usize = 10;
 
 #[derive(Debug, Serialize)]
 pub struct WebsitesResult {
@@ -38,6 +43,8 @@ pub struct WebsitesResult {
     pub webpages: inverted_index::SearchResult,
     pub entity: Option<StoredEntity>,
     pub search_duration_ms: u128,
+    /// Encoded cursor for the next page.
+    pub cursor: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -59,6 +66,8 @@ pub struct PrettifiedWebsitesResult {
     pub num_docs: usize,
     pub entity: Option<DisplayedEntity>,
     pub search_duration_ms: u128,
+    /// Encoded cursor for the next page.
+    pub cursor: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -80,10 +89,83 @@ pub struct SearchQuery {
     pub goggle_program: Option<String>,
     pub skip_pages: Option<usize>,
     pub site_rankings: Option<SiteRankings>,
+    /// Only return results after the cursor of a previous page.
+    pub cursor: Option<Cursor>,
 }
 
 impl SearchQuery {
     pub fn is_empty(&self) -> bool {
         self.original.is_empty()
     }
+
+    /// Pages beyond [`MAX_SKIP_PAGES`] can only be reached with a cursor.
+    pub fn is_page_reachable(&self) -> bool {
+        self.cursor.is_some() || self.skip_pages.unwrap_or(0) <= MAX_SKIP_PAGES
+    }
+
+    /// The cursor already points past the previous pages.
+    fn num_skipped_pages(&self) -> usize {
+        if self.cursor.is_some() {
+            0
+        } else {
+            self.skip_pages.unwrap_or(0)
+        }
+    }
+}
+
+/// The position in the ranking where a page ends, which the next page starts after.
+/// Results with the same score are ordered by shard and then by their address in the shard,
+/// as in [`collector::rank_order`](crate::collector::rank_order).
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Cursor {
+    position: SearchAfter,
+    /// `None` if the position is on the searcher that is given the cursor.
+    shard: Option<u32>,
+}
+
+impl Cursor {
+    /// The cursor of a page that ends at `end` on `shard`.
+    fn new(end: &WebsitePointer, shard: Option<u32>) -> Self {
+        Self {
+            position: SearchAfter {
+                score: end.score,
+                ties: Ties::After(end.address),
+            },
+            shard,
+        }
+    }
+
+    fn search_after(&self) -> SearchAfter {
+        self.position
+    }
+
+    /// The cursor as seen by the searcher of `shard`.
+    fn for_shard(&self, shard: u32) -> Self {
+        let ties = match self.shard {
+            Some(cursor_shard) if cursor_shard < shard => Ties::All,
+            Some(cursor_shard) if cursor_shard > shard => Ties::None,
+            _ => self.position.ties,
+        };
+
+        Self {
+            position: SearchAfter {
+                score: self.position.score,
+                ties,
+            },
+            shard: None,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        base64::encode_config(
+            bincode::serialize(self).expect("cursor should be serializable"),
+            base64::URL_SAFE_NO_PAD,
+        )
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        base64::decode_config(cursor, base64::URL_SAFE_NO_PAD)
+            .ok()
+            .and_then(|bytes| bincode::deserialize(&bytes).ok())
+    }
 }
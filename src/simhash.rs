@@ -0,0 +1,185 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! 64-bit SimHash fingerprints for near-duplicate detection.
+//!
+//! Documents whose fingerprints differ in at most [`MAX_DISTANCE`] bits are
+//! considered near-duplicates. Since the fingerprint is split into
+//! [`NUM_BLOCKS`] blocks, two such fingerprints are guaranteed to agree on at
+//! least one block, which lets us find candidates by exact block lookups.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::prehashed::{hash, split_u128};
+
+const SHINGLE_SIZE: usize = 3;
+/// Texts with fewer distinct shingles than this are too short to be fingerprinted
+/// reliably, and would otherwise collapse every short page on the same template.
+const MIN_SHINGLES: usize = 16;
+pub const MAX_DISTANCE: u32 = 3;
+pub const NUM_BLOCKS: usize = (MAX_DISTANCE + 1) as usize;
+const BLOCK_BITS: usize = 64 / NUM_BLOCKS;
+
+/// Fingerprint of the set of shingles in the text. Short texts get the fingerprint 0,
+/// which should be treated as "no fingerprint" by callers.
+pub fn simhash(text: &str) -> u64 {
+    let words: Vec<_> = text
+        .split_whitespace()
+        .map(|word| word.to_lowercase())
+        .collect();
+
+    let shingles: HashSet<_> = words
+        .windows(SHINGLE_SIZE)
+        .map(|shingle| shingle.join(" "))
+        .collect();
+
+    if shingles.len() < MIN_SHINGLES {
+        return 0;
+    }
+
+    let mut weights = [0i64; 64];
+
+    for shingle in shingles {
+        let feature = split_u128(hash(shingle).0)[1];
+
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if feature & (1 << bit) != 0 {
+                *weight += 1;
+            } else {
+                *weight -= 1;
+            }
+        }
+    }
+
+    weights
+        .iter()
+        .enumerate()
+        .filter(|(_, weight)| **weight > 0)
+        .fold(0, |fingerprint, (bit, _)| fingerprint | (1 << bit))
+}
+
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+pub fn is_near_duplicate(a: u64, b: u64) -> bool {
+    a != 0 && b != 0 && hamming_distance(a, b) <= MAX_DISTANCE
+}
+
+/// The (block index, block value) keys a fingerprint is bucketed under.
+pub fn blocks(fingerprint: u64) -> impl Iterator<Item = (usize, u64)> {
+    (0..NUM_BLOCKS).map(move |block| {
+        let mask = (1 << BLOCK_BITS) - 1;
+        (block, (fingerprint >> (block * BLOCK_BITS)) & mask)
+    })
+}
+
+/// Index of fingerprints that can be queried for near-duplicates.
+#[derive(Default)]
+pub struct SimHashIndex<T> {
+    blocks: HashMap<(usize, u64), Vec<usize>>,
+    entries: Vec<(u64, T)>,
+}
+
+impl<T> SimHashIndex<T> {
+    pub fn insert(&mut self, fingerprint: u64, value: T) {
+        if fingerprint == 0 {
+            return;
+        }
+
+        let id = self.entries.len();
+        self.entries.push((fingerprint, value));
+
+        for key in blocks(fingerprint) {
+            self.blocks.entry(key).or_default().push(id);
+        }
+    }
+
+    /// Ids of all inserted entries that are near-duplicates of `fingerprint`.
+    pub fn near_duplicate_ids(&self, fingerprint: u64) -> Vec<usize> {
+        let mut res: Vec<usize> = blocks(fingerprint)
+            .filter_map(|key| self.blocks.get(&key))
+            .flatten()
+            .copied()
+            .filter(|id| is_near_duplicate(self.entries[*id].0, fingerprint))
+            .collect();
+
+        res.sort_unstable();
+        res.dedup();
+
+        res
+    }
+
+    pub fn count_near_duplicates(&self, fingerprint: u64) -> usize {
+        self.near_duplicate_ids(fingerprint).len()
+    }
+
+    pub fn get(&self, id: usize) -> &(u64, T) {
+        &self.entries[id]
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "The quick brown fox jumps over the lazy dog while the farmer watches \
+        from the porch and wonders whether the dog will ever wake up before the sun sets \
+        behind the hills on this long and quiet summer evening in the countryside. The farmer \
+        has lived on this land for most of his life and knows every field and every fence that \
+        runs along the old road to the village. In the mornings he feeds the animals and in the \
+        afternoons he repairs whatever the wind has broken during the night. His neighbours say \
+        that he rarely leaves the farm except on market days when he sells eggs and vegetables \
+        in the square";
+
+    #[test]
+    fn identical_texts() {
+        assert_eq!(simhash(TEXT), simhash(TEXT));
+        assert_ne!(simhash(TEXT), 0);
+        assert_eq!(simhash(""), 0);
+        assert_eq!(simhash(&"the same short sentence ".repeat(10)), 0);
+    }
+
+    #[test]
+    fn similar_texts_are_close() {
+        let copy = TEXT.replace("summer", "autumn");
+        let other = "Rust is a multi-paradigm programming language designed for performance \
+            and safety, especially safe concurrency, and it is syntactically similar to C++";
+
+        assert!(is_near_duplicate(simhash(TEXT), simhash(&copy)));
+        assert!(hamming_distance(simhash(TEXT), simhash(other)) > MAX_DISTANCE);
+    }
+
+    #[test]
+    fn index_finds_near_duplicates() {
+        let mut index = SimHashIndex::default();
+
+        index.insert(0b1111, "a");
+        index.insert(0b1111 | (1 << 40), "b");
+        index.insert(u64::MAX, "c");
+        index.insert(0, "empty");
+
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.near_duplicate_ids(0b0111), vec![0, 1]);
+        assert_eq!(index.count_near_duplicates(u64::MAX - 1), 1);
+        assert_eq!(index.count_near_duplicates(0), 0);
+        assert_eq!(index.get(1).1, "b");
+    }
+}
@@ -257,34 +257,36 @@ impl SnippetGenerator {
 
 use crate::{query::Query, schema::Field, Result};
 
+/// Generates the snippet from the main content of the page, as found by the
+/// [`Extractor`](crate::webpage::Extractor) of the index. Pages where the extractor kept
+/// nothing fall back to their description and then to the text of all paragraphs.
 pub fn generate(
     query: &Query,
-    text: &str,
-    dirty_text: &str,
+    main_content: &str,
+    all_text: &str,
     description: &Option<String>,
     region: &Region,
     searcher: &tantivy::Searcher,
 ) -> Result<String> {
-    let lang = match region.lang() {
-        Some(lang) => lang,
-        None => whatlang::detect_lang(text).unwrap_or(Lang::Eng),
+    // the query terms are stemmed in the language of the query, so the text must be as well
+    let lang = |text: &str| {
+        query
+            .lang()
+            .or_else(|| region.lang())
+            .or_else(|| whatlang::detect_lang(main_content))
+            .unwrap_or(Lang::Eng)
     };
 
-    let tokenizer = Stemmed::with_forced_language(lang).into();
+    let tokenizer = Stemmed::with_forced_language(lang(main_content)).into();
     let generator = SnippetGenerator::create(searcher, query, tokenizer)?;
 
-    let mut snippet = generator.snippet(text);
+    let mut snippet = generator.snippet(main_content);
 
     if snippet.fragment.is_empty() {
-        if text.is_empty() {
+        if main_content.is_empty() {
             match description {
                 Some(desc) => {
-                    let lang = match region.lang() {
-                        Some(lang) => lang,
-                        None => whatlang::detect_lang(desc).unwrap_or(Lang::Eng),
-                    };
-
-                    let tokenizer = Stemmed::with_forced_language(lang).into();
+                    let tokenizer = Stemmed::with_forced_language(lang(desc)).into();
                     let generator = SnippetGenerator::create(searcher, query, tokenizer)?;
 
                     snippet = generator.snippet(desc);
@@ -293,23 +295,18 @@ pub fn generate(
                     }
                 }
                 None => {
-                    let lang = match region.lang() {
-                        Some(lang) => lang,
-                        None => whatlang::detect_lang(dirty_text).unwrap_or(Lang::Eng),
-                    };
-
-                    let tokenizer = Stemmed::with_forced_language(lang).into();
+                    let tokenizer = Stemmed::with_forced_language(lang(all_text)).into();
                     let generator = SnippetGenerator::create(searcher, query, tokenizer)?;
 
-                    snippet = generator.snippet(dirty_text);
+                    snippet = generator.snippet(all_text);
 
                     if snippet.fragment.is_empty() {
-                        snippet.fragment = dirty_text.chars().take(DEFAULT_MAX_NUM_CHARS).collect();
+                        snippet.fragment = all_text.chars().take(DEFAULT_MAX_NUM_CHARS).collect();
                     }
                 }
             }
         } else {
-            snippet.fragment = text.chars().take(DEFAULT_MAX_NUM_CHARS).collect();
+            snippet.fragment = main_content.chars().take(DEFAULT_MAX_NUM_CHARS).collect();
         }
     }
 
@@ -374,6 +371,7 @@ Survey in 2016, 2017, and 2018."#;
                 goggle_program: None,
                 skip_pages: None,
                 site_rankings: None,
+                cursor: None,
             })
             .expect("Search failed")
             .into_websites()
@@ -417,6 +415,7 @@ Survey in 2016, 2017, and 2018."#;
                 goggle_program: None,
                 skip_pages: None,
                 site_rankings: None,
+                cursor: None,
             })
             .expect("Search failed")
             .into_websites()
@@ -615,6 +614,7 @@ Survey in 2016, 2017, and 2018."#;
                 goggle_program: None,
                 skip_pages: None,
                 site_rankings: None,
+                cursor: None,
             })
             .expect("Search failed")
             .into_websites()
@@ -14,7 +14,7 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use std::time::Duration;
+use std::{net::SocketAddr, time::Duration};
 
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use thiserror::Error;
@@ -86,6 +86,10 @@ impl Server {
         Ok(Self { listener })
     }
 
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
     pub async fn accept<T>(&self) -> Result<Request<T>>
     where
         T: Serialize + DeserializeOwned,
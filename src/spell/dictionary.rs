@@ -14,7 +14,7 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 use crate::spell::distance::LevenshteinDistance;
-use crate::tokenizer::Normal;
+use crate::tokenizer::{self, Normalizer, Simple};
 use crate::webpage::Webpage;
 use fst::map::Union;
 use fst::{Automaton, IntoStreamer, Map, MapBuilder, Streamer};
@@ -25,7 +25,7 @@ use std::hash::{Hash, Hasher};
 use std::ops::AddAssign;
 use std::path::Path;
 use std::{cmp, io, mem};
-use tantivy::tokenizer::Tokenizer;
+use tantivy::tokenizer::{LowerCaser, TextAnalyzer};
 use thiserror::Error;
 
 pub trait EditStrategy: Send + Sync {
@@ -267,9 +267,8 @@ impl<const TOP_N: usize> Dictionary<TOP_N> {
     pub fn insert(&mut self, term: &str) {
         self.cache
             .entry(
-                term.chars()
-                    .into_iter()
-                    .map(|c| c.to_ascii_lowercase())
+                tokenizer::normalize(term)
+                    .chars()
                     .filter(|c| !matches!(c, ',' | '.' | '\\' | '=' | '*' | '(' | ')'))
                     .collect(),
             )
@@ -335,9 +334,21 @@ impl<const TOP_N: usize> Dictionary<TOP_N> {
     pub fn insert_page(&mut self, webpage: &Webpage) {
         let text = webpage.html.clean_text().unwrap_or_default();
 
-        let mut stream = Normal::default().token_stream(text.as_str());
+        // words are inserted with their diacritics, so corrections are spelled correctly
+        let mut stream = TextAnalyzer::from(Simple)
+            .filter(LowerCaser)
+            .filter(Normalizer::keep_diacritics())
+            .token_stream(text.as_str());
+        let mut last_position = None;
 
         while let Some(token) = stream.next() {
+            // a character in Chinese, Japanese, Korean or Thai text shares its position
+            // with the bigram it starts, and only the character is inserted
+            if last_position == Some(token.position) {
+                continue;
+            }
+
+            last_position = Some(token.position);
             self.insert(&token.text);
         }
     }
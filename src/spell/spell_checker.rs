@@ -15,6 +15,7 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 use crate::spell::dictionary::EditStrategy;
 use crate::spell::{Dictionary, DictionaryResult};
+use crate::tokenizer;
 use std::iter::FromIterator;
 
 pub struct SpellChecker<'a, T: EditStrategy, const DICT_N: usize> {
@@ -63,7 +64,10 @@ impl<'a, T: EditStrategy, const DICT_N: usize> SpellChecker<'a, T, DICT_N> {
 
     pub fn correct(&self, term: &str) -> Option<String> {
         if let Some(correction) = self.correct_top(term, 1).into_iter().next() {
-            if correction.to_ascii_lowercase() == term.to_ascii_lowercase() {
+            // 'cafe' should not be corrected to 'café'
+            if tokenizer::fold(&tokenizer::normalize(&correction))
+                == tokenizer::fold(&tokenizer::normalize(term))
+            {
                 None
             } else {
                 Some(correction)
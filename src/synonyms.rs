@@ -0,0 +1,171 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::{tokenizer, Result};
+
+/// Synonyms and abbreviations that query terms are expanded with.
+///
+/// Every line is either a group of equivalent terms (`k8s, kubernetes`), where each term
+/// expands to all the others, or a one-way mapping (`nyc => new york city, new york`),
+/// where only the terms on the left expand to the terms on the right.
+/// Empty lines and lines starting with `#` are ignored.
+///
+/// Query terms are single words, so only single words are looked up,
+/// but they can expand to phrases.
+#[derive(Debug, Default, Clone)]
+pub struct Synonyms {
+    expansions: HashMap<String, Vec<String>>,
+}
+
+fn split_terms(terms: &str) -> Vec<String> {
+    terms
+        .split(',')
+        .map(|term| tokenizer::normalize(term.trim()))
+        .filter(|term| !term.is_empty())
+        .collect()
+}
+
+impl Synonyms {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+
+        Ok(Self::parse(&text))
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let mut synonyms = Self::default();
+
+        for line in text.lines() {
+            synonyms.add(line);
+        }
+
+        synonyms
+    }
+
+    /// Add a single line in the synonym file format.
+    pub fn add(&mut self, line: &str) {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            return;
+        }
+
+        match line.split_once("=>") {
+            Some((from, to)) => {
+                let to = split_terms(to);
+
+                for from in split_terms(from) {
+                    self.insert(from, &to);
+                }
+            }
+            None => {
+                let group = split_terms(line);
+
+                for term in &group {
+                    self.insert(term.clone(), &group);
+                }
+            }
+        }
+    }
+
+    fn insert(&mut self, term: String, expansions: &[String]) {
+        let existing = self.expansions.entry(term.clone()).or_default();
+
+        for expansion in expansions {
+            if *expansion != term && !existing.contains(expansion) {
+                existing.push(expansion.clone());
+            }
+        }
+    }
+
+    pub fn extend(&mut self, other: &Synonyms) {
+        for (term, expansions) in &other.expansions {
+            self.insert(term.clone(), expansions);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.expansions.is_empty()
+    }
+
+    /// The terms and phrases the term should be expanded with.
+    pub fn get(&self, term: &str) -> &[String] {
+        self.expansions
+            .get(&tokenizer::normalize(term))
+            .map(|expansions| expansions.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bidirectional() {
+        let synonyms = Synonyms::parse("k8s, kubernetes");
+
+        assert_eq!(synonyms.get("k8s"), &["kubernetes".to_string()]);
+        assert_eq!(synonyms.get("Kubernetes"), &["k8s".to_string()]);
+        assert!(synonyms.get("docker").is_empty());
+    }
+
+    #[test]
+    fn one_way() {
+        let synonyms = Synonyms::parse(
+            r#"
+            # abbreviations
+            nyc => new york city, new york
+            "#,
+        );
+
+        assert_eq!(
+            synonyms.get("nyc"),
+            &["new york city".to_string(), "new york".to_string()]
+        );
+        assert!(synonyms.get("new york").is_empty());
+        assert!(synonyms.get("york").is_empty());
+    }
+
+    #[test]
+    fn merged_entries() {
+        let mut synonyms = Synonyms::parse("js, javascript\njs => ecmascript");
+        synonyms.extend(&Synonyms::parse("pg, postgres, postgresql"));
+
+        assert_eq!(
+            synonyms.get("js"),
+            &["javascript".to_string(), "ecmascript".to_string()]
+        );
+        assert_eq!(
+            synonyms.get("pg"),
+            &["postgres".to_string(), "postgresql".to_string()]
+        );
+    }
+
+    #[test]
+    fn common_words_not_expanded() {
+        let synonyms = Synonyms::parse(include_str!("../data/synonyms.txt"));
+
+        for term in "contact us in la".split_whitespace() {
+            assert!(synonyms.get(term).is_empty());
+        }
+
+        assert_eq!(synonyms.get("usa"), &["united states".to_string()]);
+        assert_eq!(synonyms.get("l.a."), &["los angeles".to_string()]);
+    }
+}
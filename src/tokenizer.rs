@@ -14,11 +14,16 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use std::{collections::VecDeque, ops::Range};
+
 use logos::{Lexer, Logos};
 use tantivy::tokenizer::{
-    BoxTokenStream, Language, LowerCaser, Stemmer, StopWordFilter, TextAnalyzer,
+    BoxTokenStream, Language, LowerCaser, Stemmer, StopWordFilter, TextAnalyzer, TokenFilter,
 };
 
+use unicode_normalization::{
+    char::decompose_canonical, char::is_combining_mark, UnicodeNormalization,
+};
 use whatlang::Lang;
 
 struct MyStemmer(Stemmer);
@@ -51,6 +56,8 @@ pub enum Tokenizer {
     Normal(Normal),
     Identity(Identity),
     Stemmed(Stemmed),
+    Code(Code),
+    Diacritics(Diacritics),
 }
 
 impl Tokenizer {
@@ -62,6 +69,8 @@ impl Tokenizer {
             Tokenizer::Normal(_) => Normal::as_str(),
             Tokenizer::Stemmed(_) => Stemmed::as_str(),
             Tokenizer::Identity(_) => Identity::as_str(),
+            Tokenizer::Code(_) => Code::as_str(),
+            Tokenizer::Diacritics(_) => Diacritics::as_str(),
         }
     }
 }
@@ -85,6 +94,7 @@ impl Normal {
     pub fn with_stopwords(stopwords: Vec<String>) -> Self {
         Self {
             stopwords: Some(stopwords),
+            ..Default::default()
         }
     }
 }
@@ -114,19 +124,47 @@ impl Identity {
     }
 }
 
+/// Keeps only the words with diacritics, in their original form. The other tokenizers
+/// fold the diacritics, so this allows pages with the exact spelling of a query
+/// like `café` to be boosted without adding tokens to the other fields.
+#[derive(Clone, Default)]
+pub struct Diacritics {}
+
+impl Diacritics {
+    pub fn as_str() -> &'static str {
+        "diacritics_tokenizer"
+    }
+}
+
 impl tantivy::tokenizer::Tokenizer for Tokenizer {
     fn token_stream<'a>(&self, text: &'a str) -> tantivy::tokenizer::BoxTokenStream<'a> {
         match self {
             Tokenizer::Normal(tokenizer) => tokenizer.token_stream(text),
             Tokenizer::Stemmed(tokenizer) => tokenizer.token_stream(text),
             Tokenizer::Identity(tokenizer) => tokenizer.token_stream(text),
+            Tokenizer::Code(tokenizer) => tokenizer.token_stream(text),
+            Tokenizer::Diacritics(tokenizer) => tokenizer.token_stream(text),
         }
     }
 }
 
+/// Tokenizer for source code. Identifiers are kept whole (`hashmap::entry`) and are
+/// followed by their path, snake_case and camelCase parts (`hashmap`, `hash`, `map`, `entry`).
+/// Language names like `c++`, `c#` and `f#` are kept as well.
+#[derive(Clone, Default)]
+pub struct Code {}
+
+impl Code {
+    pub fn as_str() -> &'static str {
+        "code_tokenizer"
+    }
+}
+
 impl tantivy::tokenizer::Tokenizer for Normal {
     fn token_stream<'a>(&self, text: &'a str) -> tantivy::tokenizer::BoxTokenStream<'a> {
-        let mut analyzer = TextAnalyzer::from(Simple).filter(LowerCaser);
+        let mut analyzer = TextAnalyzer::from(Simple)
+            .filter(LowerCaser)
+            .filter(Normalizer::default());
 
         if let Some(stopwords) = &self.stopwords {
             analyzer = analyzer.filter(StopWordFilter::remove(stopwords.clone()));
@@ -140,6 +178,7 @@ impl tantivy::tokenizer::Tokenizer for Stemmed {
     fn token_stream<'a>(&self, text: &'a str) -> tantivy::tokenizer::BoxTokenStream<'a> {
         let analyzer = TextAnalyzer::from(Simple)
             .filter(LowerCaser)
+            .filter(Normalizer::default())
             .filter(StopWordFilter::remove(vec![]));
 
         let lang = match self.force_language {
@@ -154,6 +193,25 @@ impl tantivy::tokenizer::Tokenizer for Stemmed {
     }
 }
 
+impl tantivy::tokenizer::Tokenizer for Code {
+    fn token_stream<'a>(&self, text: &'a str) -> tantivy::tokenizer::BoxTokenStream<'a> {
+        BoxTokenStream::from(CodeTokenStream {
+            tokens: code_tokens(text).into_iter(),
+            token: None,
+        })
+    }
+}
+
+impl tantivy::tokenizer::Tokenizer for Diacritics {
+    fn token_stream<'a>(&self, text: &'a str) -> tantivy::tokenizer::BoxTokenStream<'a> {
+        TextAnalyzer::from(Simple)
+            .filter(LowerCaser)
+            .filter(Normalizer::keep_diacritics())
+            .filter(WithDiacritics)
+            .token_stream(text)
+    }
+}
+
 impl tantivy::tokenizer::Tokenizer for Identity {
     fn token_stream<'a>(&self, text: &'a str) -> tantivy::tokenizer::BoxTokenStream<'a> {
         BoxTokenStream::from(IdentityTokenStream::from(text.to_string()))
@@ -201,6 +259,317 @@ impl tantivy::tokenizer::TokenStream for IdentityTokenStream {
     }
 }
 
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn starts_with_identifier(text: &str) -> bool {
+    matches!(text.chars().next(), Some(c) if is_identifier_char(c))
+}
+
+/// Length of a `::` or `.` separating two parts of a path.
+fn path_separator_len(text: &str) -> Option<usize> {
+    ["::", "."]
+        .into_iter()
+        .find(|sep| text.starts_with(sep) && starts_with_identifier(&text[sep.len()..]))
+        .map(str::len)
+}
+
+/// Length of the `++` or `#` in language names like `c++` and `f#`.
+fn language_suffix_len(text: &str) -> Option<usize> {
+    ["++", "#"]
+        .into_iter()
+        .find(|suffix| text.starts_with(suffix) && !starts_with_identifier(&text[suffix.len()..]))
+        .map(str::len)
+}
+
+/// Split an identifier into its snake_case and camelCase parts.
+fn word_parts(word: &str) -> Vec<Range<usize>> {
+    let chars: Vec<(usize, char)> = word.char_indices().collect();
+    let mut parts = Vec::new();
+    let mut part_start = None;
+
+    for (idx, &(offset, c)) in chars.iter().enumerate() {
+        if c == '_' {
+            if let Some(start) = part_start.take() {
+                parts.push(start..offset);
+            }
+            continue;
+        }
+
+        let prev = idx.checked_sub(1).map(|prev| chars[prev].1);
+        let next = chars.get(idx + 1).map(|(_, c)| *c);
+
+        let is_boundary = match prev {
+            Some(prev) if c.is_uppercase() => {
+                prev.is_lowercase()
+                    || prev.is_numeric()
+                    || (prev.is_uppercase() && matches!(next, Some(next) if next.is_lowercase()))
+            }
+            _ => false,
+        };
+
+        if is_boundary {
+            if let Some(start) = part_start.take() {
+                parts.push(start..offset);
+            }
+        }
+
+        if part_start.is_none() {
+            part_start = Some(offset);
+        }
+    }
+
+    if let Some(start) = part_start {
+        parts.push(start..word.len());
+    }
+
+    parts
+}
+
+fn push_code_token(
+    tokens: &mut Vec<tantivy::tokenizer::Token>,
+    text: &str,
+    range: Range<usize>,
+    position: usize,
+) {
+    tokens.push(tantivy::tokenizer::Token {
+        offset_from: range.start,
+        offset_to: range.end,
+        position,
+        text: text[range].to_lowercase(),
+        ..Default::default()
+    });
+}
+
+/// Push the parts of a single path segment, and the segment itself if `include_segment` is set.
+fn push_segment(
+    tokens: &mut Vec<tantivy::tokenizer::Token>,
+    text: &str,
+    segment: Range<usize>,
+    include_segment: bool,
+    position: usize,
+) {
+    if include_segment {
+        push_code_token(tokens, text, segment.clone(), position);
+    }
+
+    let parts = word_parts(&text[segment.clone()]);
+
+    if parts != [0..segment.len()] {
+        for part in parts {
+            push_code_token(
+                tokens,
+                text,
+                segment.start + part.start..segment.start + part.end,
+                position,
+            );
+        }
+    }
+}
+
+/// The segments and parts of an identifier share its position, like the bigrams of segmented
+/// text, so phrases match regardless of how many parts the identifiers have. The whole
+/// identifier is the last token at the position.
+fn code_tokens(text: &str) -> Vec<tantivy::tokenizer::Token> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while let Some(c) = text[pos..].chars().next() {
+        if !is_identifier_char(c) {
+            pos += c.len_utf8();
+            continue;
+        }
+
+        let start = pos;
+        let mut segments = Vec::new();
+
+        loop {
+            let segment_start = pos;
+            pos += text[pos..]
+                .find(|c| !is_identifier_char(c))
+                .unwrap_or(text.len() - pos);
+            segments.push(segment_start..pos);
+
+            match path_separator_len(&text[pos..]) {
+                Some(len) => pos += len,
+                None => break,
+            }
+        }
+
+        let mut has_suffix = false;
+        if segments.len() == 1 {
+            if let Some(len) = language_suffix_len(&text[pos..]) {
+                pos += len;
+                has_suffix = true;
+            }
+        }
+
+        let position = tokens.last().map_or(0, |token| token.position + 1);
+
+        let include_segments = segments.len() > 1 || has_suffix;
+        for segment in segments {
+            push_segment(&mut tokens, text, segment, include_segments, position);
+        }
+
+        push_code_token(&mut tokens, text, start..pos, position);
+    }
+
+    tokens
+}
+
+pub struct CodeTokenStream {
+    tokens: std::vec::IntoIter<tantivy::tokenizer::Token>,
+    token: Option<tantivy::tokenizer::Token>,
+}
+
+impl tantivy::tokenizer::TokenStream for CodeTokenStream {
+    fn advance(&mut self) -> bool {
+        self.token = self.tokens.next();
+        self.token.is_some()
+    }
+
+    fn token(&self) -> &tantivy::tokenizer::Token {
+        self.token.as_ref().unwrap()
+    }
+
+    fn token_mut(&mut self) -> &mut tantivy::tokenizer::Token {
+        self.token.as_mut().unwrap()
+    }
+}
+
+/// Unicode compatibility normalisation (NFKC) followed by lowercasing, so fullwidth
+/// letters, ligatures and the like are treated as their ordinary forms.
+pub fn normalize(text: &str) -> String {
+    text.nfkc().flat_map(char::to_lowercase).collect()
+}
+
+/// Remove diacritics from latin letters (`café` becomes `cafe`). Letters in other
+/// scripts are kept as they are, since their combining marks are usually not optional.
+/// The text is expected to be lowercase.
+pub fn fold(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            'ø' => res.push('o'),
+            'æ' => res.push_str("ae"),
+            'œ' => res.push_str("oe"),
+            'ß' => res.push_str("ss"),
+            'ł' => res.push('l'),
+            'đ' | 'ð' => res.push('d'),
+            'þ' => res.push_str("th"),
+            'ı' => res.push('i'),
+            '\u{00C0}'..='\u{024F}' | '\u{1E00}'..='\u{1EFF}' => {
+                decompose_canonical(c, |c| {
+                    if !is_combining_mark(c) {
+                        res.push(c);
+                    }
+                });
+            }
+            c => res.push(c),
+        }
+    }
+
+    res
+}
+
+/// Normalises each token with `normalize` and folds its diacritics with `fold`, so `cafe`
+/// and `café` are the same term. Each word is still a single token, which keeps the
+/// field lengths used for scoring the same regardless of diacritics.
+#[derive(Clone, Default)]
+pub struct Normalizer {
+    keep_diacritics: bool,
+}
+
+impl Normalizer {
+    /// Only normalise the tokens and keep their diacritics.
+    pub fn keep_diacritics() -> Self {
+        Self {
+            keep_diacritics: true,
+        }
+    }
+}
+
+impl TokenFilter for Normalizer {
+    fn transform<'a>(&self, token_stream: BoxTokenStream<'a>) -> BoxTokenStream<'a> {
+        BoxTokenStream::from(NormalizerTokenStream {
+            tail: token_stream,
+            keep_diacritics: self.keep_diacritics,
+        })
+    }
+}
+
+pub struct NormalizerTokenStream<'a> {
+    tail: BoxTokenStream<'a>,
+    keep_diacritics: bool,
+}
+
+impl<'a> tantivy::tokenizer::TokenStream for NormalizerTokenStream<'a> {
+    fn advance(&mut self) -> bool {
+        if !self.tail.advance() {
+            return false;
+        }
+
+        let token = self.tail.token_mut();
+
+        // ascii is already normalised and has nothing to fold
+        if !token.text.is_ascii() {
+            token.text = normalize(&token.text);
+
+            if !self.keep_diacritics {
+                token.text = fold(&token.text);
+            }
+        }
+
+        true
+    }
+
+    fn token(&self) -> &tantivy::tokenizer::Token {
+        self.tail.token()
+    }
+
+    fn token_mut(&mut self) -> &mut tantivy::tokenizer::Token {
+        self.tail.token_mut()
+    }
+}
+
+/// Removes the tokens that `fold` leaves unchanged.
+#[derive(Clone)]
+pub struct WithDiacritics;
+
+impl TokenFilter for WithDiacritics {
+    fn transform<'a>(&self, token_stream: BoxTokenStream<'a>) -> BoxTokenStream<'a> {
+        BoxTokenStream::from(WithDiacriticsTokenStream { tail: token_stream })
+    }
+}
+
+pub struct WithDiacriticsTokenStream<'a> {
+    tail: BoxTokenStream<'a>,
+}
+
+impl<'a> tantivy::tokenizer::TokenStream for WithDiacriticsTokenStream<'a> {
+    fn advance(&mut self) -> bool {
+        while self.tail.advance() {
+            let text = &self.tail.token().text;
+
+            if !text.is_ascii() && fold(text) != *text {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn token(&self) -> &tantivy::tokenizer::Token {
+        self.tail.token()
+    }
+
+    fn token_mut(&mut self) -> &mut tantivy::tokenizer::Token {
+        self.tail.token_mut()
+    }
+}
+
 #[derive(Logos, Debug, PartialEq)]
 enum Token {
     #[regex("[\\w|\\p{Han}|\\p{Hiragana}|\\p{Katakana}|\\p{Cyrillic}|\\p{Arabic}]+")]
@@ -211,37 +580,117 @@ enum Token {
     Error,
 }
 
+fn is_segmented_char(c: char) -> bool {
+    matches!(c,
+        '\u{0E00}'..='\u{0E7F}' // Thai
+        | '\u{1100}'..='\u{11FF}' // Hangul Jamo
+        | '\u{3040}'..='\u{30FF}' // Hiragana and Katakana
+        | '\u{3400}'..='\u{4DBF}' // CJK Unified Ideographs Extension A
+        | '\u{4E00}'..='\u{9FFF}' // CJK Unified Ideographs
+        | '\u{AC00}'..='\u{D7AF}' // Hangul Syllables
+        | '\u{F900}'..='\u{FAFF}' // CJK Compatibility Ideographs
+        | '\u{20000}'..='\u{2FA1F}' // CJK Unified Ideographs Extension B to F
+    )
+}
+
+/// Split a word into runs of Chinese, Japanese, Korean and Thai characters and runs of other
+/// characters, which are kept as they are. Each character in a run of the former is followed by
+/// the bigram it starts, so both single character and multi character queries can match.
+/// The flag tells whether the part starts a new position or shares the one before it.
+fn segment(word: &str) -> Vec<(Range<usize>, bool)> {
+    let chars: Vec<(usize, char)> = word.char_indices().collect();
+    let offset = |idx: usize| chars.get(idx).map_or(word.len(), |(offset, _)| *offset);
+
+    let mut res = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let start = i;
+        let segmented = is_segmented_char(chars[i].1);
+
+        while i < chars.len() && is_segmented_char(chars[i].1) == segmented {
+            i += 1;
+        }
+
+        if segmented {
+            for j in start..i {
+                res.push((offset(j)..offset(j + 1), true));
+
+                if j + 1 < i {
+                    res.push((offset(j)..offset(j + 2), false));
+                }
+            }
+        } else {
+            res.push((offset(start)..offset(i), true));
+        }
+    }
+
+    res
+}
+
+/// Splits text on whitespace and punctuation, and splits Chinese, Japanese, Korean and
+/// Thai text into characters and character bigrams.
 #[derive(Clone)]
 pub struct Simple;
 
 pub struct SimpleTokenStream<'a> {
+    text: &'a str,
     lexer: Lexer<'a, Token>,
+    pending: VecDeque<(Range<usize>, bool)>,
     token: Option<tantivy::tokenizer::Token>,
     next_position: usize,
 }
 
-impl tantivy::tokenizer::Tokenizer for Simple {
-    fn token_stream<'a>(&self, text: &'a str) -> BoxTokenStream<'a> {
-        let lexer = Token::lexer(text);
-        BoxTokenStream::from(SimpleTokenStream {
-            lexer,
+impl<'a> SimpleTokenStream<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            lexer: Token::lexer(text),
+            pending: VecDeque::new(),
             token: None,
             next_position: 0,
-        })
+        }
+    }
+}
+
+impl tantivy::tokenizer::Tokenizer for Simple {
+    fn token_stream<'a>(&self, text: &'a str) -> BoxTokenStream<'a> {
+        BoxTokenStream::from(SimpleTokenStream::new(text))
     }
 }
 
 impl<'a> tantivy::tokenizer::TokenStream for SimpleTokenStream<'a> {
     fn advance(&mut self) -> bool {
-        self.token = self.lexer.next().map(|_| {
+        while self.pending.is_empty() {
+            if self.lexer.next().is_none() {
+                break;
+            }
+
             let span = self.lexer.span();
-            let pos = self.next_position;
-            self.next_position += 1;
+
+            self.pending
+                .extend(
+                    segment(self.lexer.slice())
+                        .into_iter()
+                        .map(|(range, new_position)| {
+                            (
+                                span.start + range.start..span.start + range.end,
+                                new_position,
+                            )
+                        }),
+                );
+        }
+
+        self.token = self.pending.pop_front().map(|(range, new_position)| {
+            if new_position {
+                self.next_position += 1;
+            }
+
             tantivy::tokenizer::Token {
-                offset_from: span.start,
-                offset_to: span.end,
-                position: pos,
-                text: self.lexer.slice().to_string(),
+                offset_from: range.start,
+                offset_to: range.end,
+                position: self.next_position - 1,
+                text: self.text[range].to_string(),
                 ..Default::default()
             }
         });
@@ -387,4 +836,149 @@ mod tests {
             ]
         );
     }
+
+    fn tokenize_code(s: &str) -> Vec<String> {
+        let mut res = Vec::new();
+        let mut stream = Code::default().token_stream(s);
+
+        while let Some(token) = stream.next() {
+            res.push(token.text.clone());
+        }
+
+        res
+    }
+
+    #[test]
+    fn code_identifiers() {
+        assert_eq!(
+            tokenize_code("HashMap::entry"),
+            vec!["hashmap", "hash", "map", "entry", "hashmap::entry"]
+        );
+        assert_eq!(
+            tokenize_code("let snake_case_name = 1;"),
+            vec!["let", "snake", "case", "name", "snake_case_name", "1"]
+        );
+        assert_eq!(
+            tokenize_code("use std::sync::Arc;"),
+            vec!["use", "std", "sync", "arc", "std::sync::arc"]
+        );
+        assert_eq!(
+            tokenize_code("HTTPServer.listen()"),
+            vec![
+                "httpserver",
+                "http",
+                "server",
+                "listen",
+                "httpserver.listen"
+            ]
+        );
+        assert_eq!(tokenize_code("__init__"), vec!["init", "__init__"]);
+        assert_eq!(tokenize_code("the end."), vec!["the", "end"]);
+    }
+
+    #[test]
+    fn code_language_names() {
+        assert_eq!(
+            tokenize_code("c++ and C# or f#"),
+            vec!["c", "c++", "and", "c", "c#", "or", "f", "f#"]
+        );
+        assert_eq!(tokenize_code("#include"), vec!["include"]);
+    }
+
+    #[test]
+    fn code_positions_and_offsets() {
+        let mut stream = Code::default().token_stream("x = fooBar");
+        let mut tokens = Vec::new();
+
+        while let Some(token) = stream.next() {
+            tokens.push((token.position, token.offset_from, token.offset_to));
+        }
+
+        assert_eq!(tokens, vec![(0, 0, 1), (1, 4, 7), (1, 7, 10), (1, 4, 10)]);
+    }
+
+    #[test]
+    fn segmentation() {
+        assert_eq!(
+            tokenize("京都の紅葉"),
+            vec!["京", "京都", "都", "都の", "の", "の紅", "紅", "紅葉", "葉"]
+        );
+        assert_eq!(
+            tokenize("東京2020 Olympics"),
+            vec!["東", "東京", "京", "2020", "olympics"]
+        );
+        assert_eq!(tokenize("한국어"), vec!["한", "한국", "국", "국어", "어"]);
+        assert_eq!(tokenize("ไทย"), vec!["ไ", "ไท", "ท", "ทย", "ย"]);
+        assert_eq!(tokenize("猫。"), vec!["猫", "。"]);
+        assert_eq!(
+            tokenize("autumn in 京都"),
+            vec!["autumn", "in", "京", "京都", "都"]
+        );
+    }
+
+    #[test]
+    fn segmented_positions() {
+        let mut stream = Normal::default().token_stream("a 京都市");
+        let mut tokens = Vec::new();
+
+        while let Some(token) = stream.next() {
+            tokens.push((token.text.clone(), token.position, token.offset_from));
+        }
+
+        assert_eq!(
+            tokens,
+            vec![
+                ("a".to_string(), 0, 0),
+                ("京".to_string(), 1, 2),
+                ("京都".to_string(), 1, 2),
+                ("都".to_string(), 2, 5),
+                ("都市".to_string(), 2, 5),
+                ("市".to_string(), 3, 8),
+            ]
+        );
+    }
+
+    #[test]
+    fn stemmed_segmentation() {
+        let mut res = Vec::new();
+        let mut stream = Stemmed::with_forced_language(Lang::Eng).token_stream("紅葉");
+
+        while let Some(token) = stream.next() {
+            res.push(token.text.clone());
+        }
+
+        assert_eq!(res, vec!["紅", "紅葉", "葉"]);
+    }
+
+    #[test]
+    fn diacritic_folding() {
+        assert_eq!(tokenize("Café"), vec!["cafe".to_string()]);
+        assert_eq!(
+            tokenize("Søren Kierkegaard"),
+            vec!["soren".to_string(), "kierkegaard".to_string()]
+        );
+        assert_eq!(tokenize("straße"), vec!["strasse".to_string()]);
+        assert_eq!(tokenize("москва"), vec!["москва".to_string()]);
+    }
+
+    #[test]
+    fn compatibility_normalization() {
+        assert_eq!(tokenize("ＡＢＣ１２３"), vec!["abc123".to_string()]);
+        assert_eq!(tokenize("ﬁnd"), vec!["find".to_string()]);
+    }
+
+    #[test]
+    fn diacritics_original_form() {
+        let mut stream = Diacritics::default().token_stream("a Naïve café test");
+        let mut positions = Vec::new();
+
+        while let Some(token) = stream.next() {
+            positions.push((token.text.clone(), token.position));
+        }
+
+        assert_eq!(
+            positions,
+            vec![("naïve".to_string(), 1), ("café".to_string(), 2)]
+        );
+    }
 }
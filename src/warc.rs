@@ -15,6 +15,7 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use crate::exponential_backoff::ExponentialBackoff;
+use crate::webpage::{FileType, Url};
 use crate::{Error, Result, WarcSource};
 use std::collections::BTreeMap;
 use std::fs::File;
@@ -22,6 +23,7 @@ use std::io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom, Write};
 use std::path::Path;
 use std::time::Duration;
 
+use chrono::DateTime;
 use flate2::read::MultiGzDecoder;
 use futures::StreamExt;
 use tokio::time::sleep;
@@ -191,22 +193,67 @@ impl Request {
 #[derive(Debug)]
 pub(crate) struct Response {
     pub(crate) body: String,
+    /// The undecoded body. Only kept for PDFs, which are the only binary documents we parse.
+    pub(crate) raw_body: Option<Vec<u8>>,
     pub(crate) payload_type: Option<String>,
+    /// HTTP headers with lowercase names. Repeated headers are kept in order.
+    pub(crate) headers: BTreeMap<String, Vec<String>>,
+    /// WARC-Date of the response in milliseconds since the epoch.
+    pub(crate) date_ms: Option<u64>,
 }
 
 impl Response {
     fn from_raw(record: RawWarcRecord) -> Result<Self> {
-        let content = decode(&record.content[..]);
-
-        let (_header, content) = content
-            .split_once("\r\n\r\n")
+        let header_end = record
+            .content
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
             .ok_or(Error::WarcParse("Invalid http body"))?;
 
+        let header = decode(&record.content[..header_end]);
+        let body = &record.content[header_end + 4..];
+
+        let payload_type = record.header.get("WARC-IDENTIFIED-PAYLOAD-TYPE").cloned();
+        let url: Url = record
+            .header
+            .get("WARC-TARGET-URI")
+            .cloned()
+            .unwrap_or_default()
+            .into();
+
+        let raw_body = (FileType::detect(payload_type.as_deref(), &url) == FileType::Pdf)
+            .then(|| body.to_vec());
+
+        let date_ms = record
+            .header
+            .get("WARC-DATE")
+            .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+            .and_then(|date| u64::try_from(date.timestamp_millis()).ok());
+
         Ok(Self {
-            body: content.to_string(),
-            payload_type: record.header.get("WARC-IDENTIFIED-PAYLOAD-TYPE").cloned(),
+            body: decode(body),
+            raw_body,
+            payload_type,
+            headers: Response::parse_headers(&header),
+            date_ms,
         })
     }
+
+    fn parse_headers(raw: &str) -> BTreeMap<String, Vec<String>> {
+        let mut headers: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+        // the first line is the status line
+        for line in raw.lines().skip(1) {
+            if let Some((name, value)) = line.split_once(':') {
+                headers
+                    .entry(name.trim().to_ascii_lowercase())
+                    .or_default()
+                    .push(value.trim().to_string());
+            }
+        }
+
+        headers
+    }
 }
 
 #[derive(Debug)]
@@ -407,6 +454,7 @@ mod tests {
                 \r\n\
                 warc/1.0\r\n\
                 warc-tYPE: response\r\n\
+                WARC-Date: 2022-01-16T13:47:33Z\r\n\
                 cONTENT-lENGTH: 29\r\n\
                 \r\n\
                 http-body\r\n\
@@ -431,6 +479,24 @@ mod tests {
         assert_eq!(records.len(), 1);
         assert_eq!(&records[0].request.url, "http://0575ls.cn/news-52300.htm");
         assert_eq!(&records[0].response.body, "body of response");
+        assert_eq!(records[0].response.raw_body, None);
+        assert_eq!(records[0].response.date_ms, Some(1642340853000));
         assert_eq!(records[0].metadata.fetch_time_ms, 937);
     }
+
+    #[test]
+    fn response_headers() {
+        let headers = Response::parse_headers(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nX-Robots-Tag: noindex\r\nx-robots-tag: nofollow",
+        );
+
+        assert_eq!(
+            headers.get("content-type"),
+            Some(&vec!["text/html".to_string()])
+        );
+        assert_eq!(
+            headers.get("x-robots-tag"),
+            Some(&vec!["noindex".to_string(), "nofollow".to_string()])
+        );
+    }
 }
@@ -0,0 +1,408 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use super::{graph_store::GraphStore, NodeID, Store};
+
+#[derive(Debug, Clone, Copy)]
+pub enum ExportFormat {
+    /// One edge per line: `from<TAB>to<TAB>label`.
+    EdgeList,
+    GraphML,
+    /// Boldi-Vigna compressed graph that can be loaded by the WebGraph framework.
+    /// Writes `<path>.graph`, `<path>.offsets`, `<path>.properties` and `<path>.ids`
+    /// (the name of node `i` is on line `i`).
+    BV,
+}
+
+fn clean(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect()
+}
+
+fn escape_xml(text: &str) -> String {
+    html_escape::encode_text(&clean(text)).to_string()
+}
+
+pub fn export<S: Store, P: AsRef<Path>>(
+    graph: &GraphStore<S>,
+    format: ExportFormat,
+    path: P,
+) -> io::Result<()> {
+    match format {
+        ExportFormat::EdgeList => edge_list(graph, path),
+        ExportFormat::GraphML => graphml(graph, path),
+        ExportFormat::BV => bv(graph, path),
+    }
+}
+
+fn name(graph: &GraphStore<impl Store>, id: NodeID) -> String {
+    graph.id2node(&id).map(|node| node.name).unwrap_or_default()
+}
+
+fn edge_list<S: Store, P: AsRef<Path>>(graph: &GraphStore<S>, path: P) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    for edge in graph.edges() {
+        writeln!(
+            writer,
+            "{}\t{}\t{}",
+            clean(&name(graph, edge.from)),
+            clean(&name(graph, edge.to)),
+            clean(&edge.label)
+        )?;
+    }
+
+    writer.flush()
+}
+
+fn graphml<S: Store, P: AsRef<Path>>(graph: &GraphStore<S>, path: P) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        writer,
+        r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+    )?;
+    writeln!(
+        writer,
+        r#"  <key id="name" for="node" attr.name="name" attr.type="string"/>"#
+    )?;
+    writeln!(
+        writer,
+        r#"  <key id="label" for="edge" attr.name="label" attr.type="string"/>"#
+    )?;
+    writeln!(writer, r#"  <graph id="G" edgedefault="directed">"#)?;
+
+    for id in graph.nodes() {
+        writeln!(
+            writer,
+            r#"    <node id="n{}"><data key="name">{}</data></node>"#,
+            id,
+            escape_xml(&name(graph, id))
+        )?;
+    }
+
+    for edge in graph.edges() {
+        writeln!(
+            writer,
+            r#"    <edge source="n{}" target="n{}"><data key="label">{}</data></edge>"#,
+            edge.from,
+            edge.to,
+            escape_xml(&edge.label)
+        )?;
+    }
+
+    writeln!(writer, "  </graph>")?;
+    writeln!(writer, "</graphml>")?;
+
+    writer.flush()
+}
+
+/// Writes bits most significant first, which is the bit order used by WebGraph.
+struct BitWriter<W: Write> {
+    inner: W,
+    current: u8,
+    num_bits: u8,
+    written_bits: u64,
+}
+
+impl<W: Write> BitWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            current: 0,
+            num_bits: 0,
+            written_bits: 0,
+        }
+    }
+
+    fn write_bit(&mut self, bit: bool) -> io::Result<()> {
+        self.current = (self.current << 1) | bit as u8;
+        self.num_bits += 1;
+        self.written_bits += 1;
+
+        if self.num_bits == 8 {
+            self.inner.write_all(&[self.current])?;
+            self.current = 0;
+            self.num_bits = 0;
+        }
+
+        Ok(())
+    }
+
+    /// Write the `len` lowest bits of `value`.
+    fn write_int(&mut self, value: u64, len: u32) -> io::Result<()> {
+        for i in (0..len).rev() {
+            self.write_bit((value >> i) & 1 == 1)?;
+        }
+
+        Ok(())
+    }
+
+    /// `n` zeros followed by a one.
+    fn write_unary(&mut self, n: u64) -> io::Result<()> {
+        for _ in 0..n {
+            self.write_bit(false)?;
+        }
+
+        self.write_bit(true)
+    }
+
+    fn write_gamma(&mut self, x: u64) -> io::Result<()> {
+        let x = x + 1;
+        let msb = 63 - x.leading_zeros();
+
+        self.write_unary(msb as u64)?;
+        self.write_int(x, msb)
+    }
+
+    fn write_zeta(&mut self, x: u64, k: u32) -> io::Result<()> {
+        let x = x + 1;
+        let msb = 63 - x.leading_zeros();
+        let h = msb / k;
+
+        self.write_unary(h as u64)?;
+
+        let left = 1_u64 << (h * k);
+        let m = x - left;
+
+        if m < left {
+            self.write_int(m, h * k + k - 1)
+        } else {
+            self.write_int(m + left, h * k + k)
+        }
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        while self.num_bits != 0 {
+            self.write_bit(false)?;
+        }
+
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+const ZETA_K: u32 = 3;
+
+fn int2nat(x: i64) -> u64 {
+    if x >= 0 {
+        (x as u64) << 1
+    } else {
+        ((-x as u64) << 1) - 1
+    }
+}
+
+/// Encode the successor lists as a BVGraph without references and intervals
+/// (window size 0 and min interval length 0). Outdegrees are gamma coded and
+/// residuals are zeta coded, which are the WebGraph defaults.
+fn write_bv<G, O>(
+    successors: impl Iterator<Item = Vec<NodeID>>,
+    graph: G,
+    offsets: O,
+) -> io::Result<(u64, u64)>
+where
+    G: Write,
+    O: Write,
+{
+    let mut graph = BitWriter::new(graph);
+    let mut offsets = BitWriter::new(offsets);
+
+    let mut num_nodes = 0;
+    let mut num_arcs = 0;
+    let mut prev_offset = 0;
+
+    offsets.write_gamma(0)?;
+
+    for (node, succ) in successors.enumerate() {
+        graph.write_gamma(succ.len() as u64)?;
+
+        if let Some(first) = succ.first() {
+            graph.write_zeta(int2nat(*first as i64 - node as i64), ZETA_K)?;
+
+            for (prev, next) in succ.iter().zip(succ.iter().skip(1)) {
+                graph.write_zeta(next - prev - 1, ZETA_K)?;
+            }
+        }
+
+        offsets.write_gamma(graph.written_bits - prev_offset)?;
+        prev_offset = graph.written_bits;
+
+        num_nodes += 1;
+        num_arcs += succ.len() as u64;
+    }
+
+    graph.finish()?;
+    offsets.finish()?;
+
+    Ok((num_nodes, num_arcs))
+}
+
+fn bv<S: Store, P: AsRef<Path>>(graph: &GraphStore<S>, path: P) -> io::Result<()> {
+    let path = path.as_ref();
+    let num_nodes = graph.num_nodes();
+
+    let mut ids = BufWriter::new(File::create(path.with_extension("ids"))?);
+    for id in 0..num_nodes {
+        writeln!(ids, "{}", clean(&name(graph, id)))?;
+    }
+    ids.flush()?;
+
+    let successors = (0..num_nodes).map(|id| {
+        let mut succ: Vec<NodeID> = graph
+            .outgoing_edges(id)
+            .into_iter()
+            .map(|edge| edge.to)
+            .collect();
+        succ.sort_unstable();
+        succ.dedup();
+        succ
+    });
+
+    let (num_nodes, num_arcs) = write_bv(
+        successors,
+        BufWriter::new(File::create(path.with_extension("graph"))?),
+        BufWriter::new(File::create(path.with_extension("offsets"))?),
+    )?;
+
+    let mut properties = BufWriter::new(File::create(path.with_extension("properties"))?);
+    writeln!(properties, "graphclass=it.unimi.dsi.webgraph.BVGraph")?;
+    writeln!(properties, "version=0")?;
+    writeln!(properties, "nodes={}", num_nodes)?;
+    writeln!(properties, "arcs={}", num_arcs)?;
+    writeln!(properties, "windowsize=0")?;
+    writeln!(properties, "maxrefcount=0")?;
+    writeln!(properties, "minintervallength=0")?;
+    writeln!(properties, "zetak={}", ZETA_K)?;
+    writeln!(properties, "compressionflags=")?;
+
+    properties.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct BitReader<'a> {
+        bytes: &'a [u8],
+        pos: usize,
+    }
+
+    impl<'a> BitReader<'a> {
+        fn read_bit(&mut self) -> bool {
+            let bit = (self.bytes[self.pos / 8] >> (7 - self.pos % 8)) & 1 == 1;
+            self.pos += 1;
+            bit
+        }
+
+        fn read_int(&mut self, len: u32) -> u64 {
+            (0..len).fold(0, |acc, _| (acc << 1) | self.read_bit() as u64)
+        }
+
+        fn read_unary(&mut self) -> u64 {
+            let mut n = 0;
+            while !self.read_bit() {
+                n += 1;
+            }
+            n
+        }
+
+        fn read_gamma(&mut self) -> u64 {
+            let msb = self.read_unary() as u32;
+            ((1 << msb) | self.read_int(msb)) - 1
+        }
+
+        fn read_zeta(&mut self, k: u32) -> u64 {
+            let h = self.read_unary() as u32;
+            let left = 1 << (h * k);
+            let m = self.read_int(h * k + k - 1);
+
+            if m < left {
+                m + left - 1
+            } else {
+                ((m << 1) | self.read_bit() as u64) - 1
+            }
+        }
+    }
+
+    #[test]
+    fn gamma_and_zeta_roundtrip() {
+        let values = [0, 1, 2, 3, 7, 8, 100, 1023, 1024, 123_456];
+
+        let mut writer = BitWriter::new(Vec::new());
+        for value in values {
+            writer.write_gamma(value).unwrap();
+            writer.write_zeta(value, ZETA_K).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+
+        let mut reader = BitReader {
+            bytes: &bytes,
+            pos: 0,
+        };
+        for value in values {
+            assert_eq!(reader.read_gamma(), value);
+            assert_eq!(reader.read_zeta(ZETA_K), value);
+        }
+    }
+
+    #[test]
+    fn bv_successor_lists() {
+        let successors = vec![vec![1, 2], vec![], vec![0, 1, 3], vec![2]];
+
+        let mut graph = Vec::new();
+        let mut offsets = Vec::new();
+        let (num_nodes, num_arcs) =
+            write_bv(successors.clone().into_iter(), &mut graph, &mut offsets).unwrap();
+
+        assert_eq!(num_nodes, 4);
+        assert_eq!(num_arcs, 6);
+
+        let mut reader = BitReader {
+            bytes: &graph,
+            pos: 0,
+        };
+
+        for (node, expected) in successors.into_iter().enumerate() {
+            let degree = reader.read_gamma() as usize;
+            let mut succ = Vec::new();
+
+            if degree > 0 {
+                let nat = reader.read_zeta(ZETA_K) as i64;
+                let first = if nat % 2 == 0 {
+                    nat / 2
+                } else {
+                    -(nat + 1) / 2
+                };
+                succ.push((node as i64 + first) as u64);
+
+                for _ in 1..degree {
+                    let gap = reader.read_zeta(ZETA_K);
+                    succ.push(succ.last().unwrap() + gap + 1);
+                }
+            }
+
+            assert_eq!(succ, expected);
+        }
+    }
+}
@@ -15,31 +15,127 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use std::{
-    collections::HashMap, hash::Hash, marker::PhantomData, ops::Div, path::Path, sync::Mutex,
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    marker::PhantomData,
+    ops::Div,
+    path::Path,
+    sync::Mutex,
 };
 
 use lru::LruCache;
 use serde::{de::DeserializeOwned, Serialize};
+use tracing::info;
 
-use super::{Edge, EdgeIterator, Node, NodeID, Store, StoredEdge};
+use super::{
+    Edge, EdgeIterator, EdgeProperties, LegacyStoredEdge, Node, NodeID, Store, StoredEdge,
+};
 use crate::kv::{rocksdb_store::RocksDbStore, Kv};
+
+/// Version of the stored edge layout. Graphs without a version are from before
+/// edges had a timestamp and properties.
+const EDGE_FORMAT_VERSION: u64 = 1;
+const EDGE_FORMAT_KEY: &str = "edge_format";
+
 pub(crate) struct Adjacency {
     pub(crate) tree: BlockedCachedTree<NodeID, Vec<StoredEdge>>,
 }
 
 impl Adjacency {
-    fn insert(&mut self, from: NodeID, to: NodeID, label: String) {
+    /// The edges of a node are kept sorted by target and label.
+    /// Edges without a timestamp are always inserted. Timestamped edges that are
+    /// already present only get their timestamp and properties updated.
+    fn insert(
+        &mut self,
+        from: NodeID,
+        to: NodeID,
+        label: String,
+        properties: EdgeProperties,
+        timestamp: u64,
+    ) {
         self.tree.insert(from, &mut |block| {
-            block.entry(from).or_default().push(StoredEdge {
-                other: to,
-                label: label.clone(),
-            })
+            let edges = block.entry(from).or_default();
+
+            let pos = match edges.binary_search_by(|edge| {
+                (edge.other, edge.label.as_str()).cmp(&(to, label.as_str()))
+            }) {
+                Ok(pos) if timestamp > 0 => {
+                    let edge = &mut edges[pos];
+
+                    if timestamp >= edge.timestamp {
+                        edge.timestamp = timestamp;
+                        edge.properties = properties;
+                    }
+                    return;
+                }
+                Ok(pos) | Err(pos) => pos,
+            };
+
+            edges.insert(
+                pos,
+                StoredEdge {
+                    other: to,
+                    label: label.clone(),
+                    timestamp,
+                    properties,
+                },
+            );
         });
     }
 
     fn edges(&mut self, node: NodeID) -> Vec<StoredEdge> {
         self.tree.get(&node).cloned().unwrap_or_default()
     }
+
+    fn is_empty(&self) -> bool {
+        self.tree.inner.store.iter_raw().next().is_none()
+    }
+
+    /// Rewrite all stored blocks from the legacy edge layout.
+    fn migrate_legacy(&mut self) {
+        let blocks: Vec<_> = self.tree.inner.store.iter_raw().collect();
+
+        for (key, value) in blocks {
+            let block: HashMap<NodeID, Vec<LegacyStoredEdge>> =
+                bincode::deserialize(&value).expect("failed to deserialize legacy edges");
+
+            let block: HashMap<NodeID, Vec<StoredEdge>> = block
+                .into_iter()
+                .map(|(node, edges)| {
+                    let mut edges: Vec<StoredEdge> = edges.into_iter().map(Into::into).collect();
+                    edges.sort_by(|a, b| (a.other, &a.label).cmp(&(b.other, &b.label)));
+
+                    (node, edges)
+                })
+                .collect();
+
+            self.tree.inner.store.insert_raw(
+                key,
+                bincode::serialize(&block).expect("failed to serialize edges"),
+            );
+        }
+
+        self.tree.inner.store.flush();
+    }
+
+    fn retain<F>(&mut self, node: NodeID, mut keep: F)
+    where
+        F: FnMut(&StoredEdge) -> bool,
+    {
+        if self.tree.get(&node).is_none() {
+            return;
+        }
+
+        self.tree.insert(node, &mut |block| {
+            if let Some(edges) = block.get_mut(&node) {
+                edges.retain(&mut keep);
+
+                if edges.is_empty() {
+                    block.remove(&node);
+                }
+            }
+        });
+    }
 }
 
 pub(crate) struct BlockedCachedTree<K, V>
@@ -150,6 +246,8 @@ pub struct GraphStore<S> {
     pub(crate) reversed_adjacency: Mutex<Adjacency>,
     pub(crate) node2id: Mutex<CachedTree<Node, NodeID>>,
     pub(crate) id2node: Mutex<BlockedCachedTree<NodeID, Node>>,
+    /// Timestamp of the newest version of a page whose outgoing edges have been recorded.
+    pub(crate) versions: Mutex<CachedTree<NodeID, u64>>,
     pub(crate) meta: Mutex<CachedTree<String, u64>>,
     pub(crate) store: PhantomData<S>,
 }
@@ -157,15 +255,56 @@ pub struct GraphStore<S> {
 impl<S: Store> GraphStore<S> {
     #[cfg(test)]
     pub(crate) fn temporary() -> GraphStore<S> {
-        S::temporary()
+        Self::open(crate::gen_temp_path())
     }
 
     pub fn open<P: AsRef<Path>>(path: P) -> Self {
-        S::open(path)
+        let store = S::open(path);
+        store.migrate();
+        store
     }
 
     pub fn open_read_only<P: AsRef<Path>>(path: P) -> Self {
-        S::open_read_only(path)
+        let store = S::open_read_only(path);
+
+        assert!(
+            store.edge_format() == Some(EDGE_FORMAT_VERSION) || store.is_empty(),
+            "the webgraph uses an old edge format and must be opened for writing once to migrate it"
+        );
+
+        store
+    }
+
+    fn edge_format(&self) -> Option<u64> {
+        self.meta
+            .lock()
+            .unwrap()
+            .get(&EDGE_FORMAT_KEY.to_string())
+            .cloned()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.adjacency.lock().unwrap().is_empty()
+            && self.reversed_adjacency.lock().unwrap().is_empty()
+    }
+
+    /// Bring the stored edges up to the current format.
+    fn migrate(&self) {
+        match self.edge_format() {
+            Some(EDGE_FORMAT_VERSION) => return,
+            Some(version) => panic!("unknown webgraph edge format {version}"),
+            None => {
+                if !self.is_empty() {
+                    info!("migrating webgraph edges from the legacy format");
+                    self.adjacency.lock().unwrap().migrate_legacy();
+                    self.reversed_adjacency.lock().unwrap().migrate_legacy();
+                }
+            }
+        }
+
+        let mut meta = self.meta.lock().unwrap();
+        meta.insert(EDGE_FORMAT_KEY.to_string(), EDGE_FORMAT_VERSION);
+        meta.flush();
     }
 
     fn next_id(&self) -> NodeID {
@@ -177,6 +316,20 @@ impl<S: Store> GraphStore<S> {
             .unwrap_or(0)
     }
 
+    /// Node ids are assigned consecutively, so all nodes in the graph have an id below this.
+    pub fn num_nodes(&self) -> u64 {
+        self.next_id()
+    }
+
+    /// Number of nodes with at least one edge. Ids are never reused,
+    /// so the nodes that lost all their edges still count towards [`Self::num_nodes`].
+    pub fn num_live_nodes(&self) -> usize {
+        self.edges()
+            .flat_map(|edge| [edge.from, edge.to])
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
     fn increment_next_id(&self) {
         let current_id = self.next_id();
         let next_id = current_id + 1;
@@ -219,6 +372,7 @@ impl<S: Store> GraphStore<S> {
                 from: node,
                 to: edge.other,
                 label: edge.label,
+                properties: edge.properties,
             })
             .collect()
     }
@@ -233,6 +387,7 @@ impl<S: Store> GraphStore<S> {
                 from: edge.other,
                 to: node,
                 label: edge.label,
+                properties: edge.properties,
             })
             .collect()
     }
@@ -248,17 +403,128 @@ impl<S: Store> GraphStore<S> {
     }
 
     pub fn insert(&mut self, from: Node, to: Node, label: String) {
+        self.insert_with_timestamp(from, to, label, EdgeProperties::default(), 0);
+    }
+
+    /// Insert an edge that was seen at `timestamp`. Re-inserting an existing edge
+    /// with a newer timestamp refreshes it instead of creating a duplicate.
+    pub fn insert_with_timestamp(
+        &mut self,
+        from: Node,
+        to: Node,
+        label: String,
+        properties: EdgeProperties,
+        timestamp: u64,
+    ) {
         let from_id = self.id_or_assign(from);
         let to_id = self.id_or_assign(to);
 
+        self.insert_ids(from_id, to_id, label, properties, timestamp);
+    }
+
+    fn insert_ids(
+        &mut self,
+        from: NodeID,
+        to: NodeID,
+        label: String,
+        properties: EdgeProperties,
+        timestamp: u64,
+    ) {
         self.adjacency
             .lock()
             .unwrap()
-            .insert(from_id, to_id, label.clone());
+            .insert(from, to, label.clone(), properties, timestamp);
         self.reversed_adjacency
             .lock()
             .unwrap()
-            .insert(to_id, from_id, label);
+            .insert(to, from, label, properties, timestamp);
+    }
+
+    /// Timestamp of the newest recorded version of the page.
+    fn version(&self, node: NodeID) -> Option<u64> {
+        self.versions.lock().unwrap().get(&node).cloned()
+    }
+
+    fn set_version(&self, node: NodeID, timestamp: u64) {
+        self.versions.lock().unwrap().insert(node, timestamp);
+    }
+
+    fn remove_outgoing_ids(&mut self, node: NodeID) {
+        let edges = self.adjacency.lock().unwrap().edges(node);
+
+        let mut reversed_adjacency = self.reversed_adjacency.lock().unwrap();
+        for edge in &edges {
+            reversed_adjacency.retain(edge.other, |reversed| reversed.other != node);
+        }
+
+        self.adjacency.lock().unwrap().retain(node, |_| false);
+    }
+
+    /// Replace the outgoing edges of `from` with `edges` seen at `timestamp`.
+    /// Nothing happens if the stored edges are from a newer version of the page.
+    /// The version is also recorded for pages without links, so an older
+    /// version of the page can not add its links back.
+    pub fn replace_outgoing(
+        &mut self,
+        from: Node,
+        edges: Vec<(Node, String, EdgeProperties)>,
+        timestamp: u64,
+    ) {
+        let from_id = self.id_or_assign(from);
+
+        if let Some(version) = self.version(from_id) {
+            if version > timestamp {
+                return;
+            }
+        }
+
+        self.remove_outgoing_ids(from_id);
+        self.set_version(from_id, timestamp);
+
+        for (to, label, properties) in edges {
+            let to_id = self.id_or_assign(to);
+            self.insert_ids(from_id, to_id, label, properties, timestamp);
+        }
+    }
+
+    /// Remove all edges with a timestamp older than `timestamp`.
+    /// Edges inserted without a timestamp (e.g. from before edges were timestamped)
+    /// are kept, as it is unknown when they were last seen.
+    /// Returns the number of removed edges.
+    pub fn remove_edges_older_than(&mut self, timestamp: u64) -> usize {
+        self.flush();
+
+        let stale: Vec<(NodeID, NodeID)> = self
+            .stored_edges()
+            .filter(|(_, edge)| is_stale(edge, timestamp))
+            .map(|(from, edge)| (from, edge.other))
+            .collect();
+
+        let mut adjacency = self.adjacency.lock().unwrap();
+        let mut reversed_adjacency = self.reversed_adjacency.lock().unwrap();
+
+        for (from, to) in &stale {
+            adjacency.retain(*from, |edge| {
+                edge.other != *to || !is_stale(edge, timestamp)
+            });
+            reversed_adjacency.retain(*to, |edge| {
+                edge.other != *from || !is_stale(edge, timestamp)
+            });
+        }
+
+        stale.len()
+    }
+
+    /// All stored edges grouped by their source node.
+    fn stored_edges(&self) -> impl Iterator<Item = (NodeID, StoredEdge)> + '_ {
+        self.nodes().flat_map(|node| {
+            self.adjacency
+                .lock()
+                .unwrap()
+                .edges(node)
+                .into_iter()
+                .map(move |edge| (node, edge))
+        })
     }
 
     pub fn node2id(&self, node: &Node) -> Option<NodeID> {
@@ -274,6 +540,7 @@ impl<S: Store> GraphStore<S> {
         self.reversed_adjacency.lock().unwrap().tree.inner.flush();
         self.node2id.lock().unwrap().flush();
         self.id2node.lock().unwrap().inner.flush();
+        self.versions.lock().unwrap().flush();
         self.meta.lock().unwrap().flush();
     }
 
@@ -283,22 +550,59 @@ impl<S: Store> GraphStore<S> {
         EdgeIterator::new(&self.adjacency)
     }
 
-    pub fn append(&mut self, other: GraphStore<S>) {
-        for edge in other.edges() {
-            let from = other.id2node(&edge.from).expect("node not found");
-            let to = other.id2node(&edge.to).expect("node not found");
+    /// Append all edges from `other`. If `replace_outgoing` is set, the outgoing edges of a
+    /// node in `other` replace the ones in `self` when they are from a newer version of the page.
+    pub fn append(&mut self, other: GraphStore<S>, replace_outgoing: bool) {
+        other.flush();
+
+        for node in other.nodes() {
+            let edges = other.adjacency.lock().unwrap().edges(node);
+            let other_version = if replace_outgoing {
+                other.version(node)
+            } else {
+                None
+            };
+
+            if edges.is_empty() && other_version.is_none() {
+                continue;
+            }
+
+            let from = other.id2node(&node).expect("node not found");
+            let from_id = self.id_or_assign(from);
+
+            if let Some(other_version) = other_version {
+                match self.version(from_id) {
+                    Some(version) if version > other_version => continue,
+                    Some(version) if version == other_version => {}
+                    _ => {
+                        self.remove_outgoing_ids(from_id);
+                        self.set_version(from_id, other_version);
+                    }
+                }
+            }
+
+            for edge in edges {
+                let to = other.id2node(&edge.other).expect("node not found");
+                let to_id = self.id_or_assign(to);
 
-            self.insert(from, to, edge.label);
+                self.insert_ids(from_id, to_id, edge.label, edge.properties, edge.timestamp);
+            }
         }
     }
 }
 
+/// Edges without a timestamp are never stale.
+fn is_stale(edge: &StoredEdge, timestamp: u64) -> bool {
+    edge.timestamp > 0 && edge.timestamp < timestamp
+}
+
 impl Store for RocksDbStore {
     fn open<P: AsRef<std::path::Path>>(path: P) -> GraphStore<Self> {
         let adjacency = RocksDbStore::open(path.as_ref().join("adjacency"));
         let reversed_adjacency = RocksDbStore::open(path.as_ref().join("reversed_adjacency"));
         let node2id = RocksDbStore::open(path.as_ref().join("node2id"));
         let id2node = RocksDbStore::open(path.as_ref().join("id2node"));
+        let versions = RocksDbStore::open(path.as_ref().join("versions"));
         let meta = RocksDbStore::open(path.as_ref().join("meta"));
 
         GraphStore {
@@ -319,6 +623,7 @@ impl Store for RocksDbStore {
                 inner: CachedTree::new(id2node, 100_000),
                 block_size: 1_024,
             }),
+            versions: Mutex::new(CachedTree::new(versions, 100_000)),
             meta: Mutex::new(CachedTree::new(meta, 1_000)),
             store: Default::default(),
         }
@@ -330,6 +635,7 @@ impl Store for RocksDbStore {
             RocksDbStore::open_read_only(path.as_ref().join("reversed_adjacency"));
         let node2id = RocksDbStore::open_read_only(path.as_ref().join("node2id"));
         let id2node = RocksDbStore::open_read_only(path.as_ref().join("id2node"));
+        let versions = RocksDbStore::open_read_only(path.as_ref().join("versions"));
         let meta = RocksDbStore::open_read_only(path.as_ref().join("meta"));
 
         GraphStore {
@@ -350,6 +656,7 @@ impl Store for RocksDbStore {
                 inner: CachedTree::new(id2node, 100_000),
                 block_size: 1_024,
             }),
+            versions: Mutex::new(CachedTree::new(versions, 100_000)),
             meta: Mutex::new(CachedTree::new(meta, 1_000)),
             store: Default::default(),
         }
@@ -404,12 +711,14 @@ mod test {
                 Edge {
                     from: a_id,
                     to: b_id,
-                    label: String::new()
+                    label: String::new(),
+                    properties: EdgeProperties::default(),
                 },
                 Edge {
                     from: a_id,
                     to: c_id,
-                    label: String::new()
+                    label: String::new(),
+                    properties: EdgeProperties::default(),
                 },
             ]
         );
@@ -419,7 +728,8 @@ mod test {
             vec![Edge {
                 from: b_id,
                 to: c_id,
-                label: String::new()
+                label: String::new(),
+                properties: EdgeProperties::default(),
             },]
         );
 
@@ -427,14 +737,16 @@ mod test {
             store.ingoing_edges(c_id),
             vec![
                 Edge {
-                    from: b_id,
+                    from: a_id,
                     to: c_id,
-                    label: String::new()
+                    label: String::new(),
+                    properties: EdgeProperties::default(),
                 },
                 Edge {
-                    from: a_id,
+                    from: b_id,
                     to: c_id,
-                    label: String::new()
+                    label: String::new(),
+                    properties: EdgeProperties::default(),
                 },
             ]
         );
@@ -444,7 +756,8 @@ mod test {
             vec![Edge {
                 from: c_id,
                 to: a_id,
-                label: String::new()
+                label: String::new(),
+                properties: EdgeProperties::default(),
             },]
         );
 
@@ -453,8 +766,120 @@ mod test {
             vec![Edge {
                 from: a_id,
                 to: b_id,
-                label: String::new()
+                label: String::new(),
+                properties: EdgeProperties::default(),
             },]
         );
     }
+
+    #[test]
+    fn timestamped_edges_are_deduplicated() {
+        let mut store: GraphStore<RocksDbStore> = GraphStore::temporary();
+
+        for timestamp in 1..=3 {
+            for target in ["C", "A", "B"] {
+                store.insert_with_timestamp(
+                    Node::from("X"),
+                    Node::from(target),
+                    String::new(),
+                    EdgeProperties::default(),
+                    timestamp,
+                );
+            }
+        }
+
+        let x_id = store.node2id(&Node::from("X")).unwrap();
+        let targets: Vec<_> = store
+            .adjacency
+            .lock()
+            .unwrap()
+            .edges(x_id)
+            .into_iter()
+            .map(|edge| (store.id2node(&edge.other).unwrap().name, edge.timestamp))
+            .collect();
+
+        assert_eq!(
+            targets,
+            vec![
+                ("C".to_string(), 3),
+                ("A".to_string(), 3),
+                ("B".to_string(), 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn migrate_legacy_edges() {
+        #[derive(Serialize, serde::Deserialize)]
+        struct OldEdge {
+            other: NodeID,
+            label: String,
+        }
+
+        let path = crate::gen_temp_path();
+
+        {
+            let node2id: Box<dyn Kv<Node, NodeID> + Send + Sync> =
+                RocksDbStore::open(path.join("node2id"));
+            let id2node: Box<dyn Kv<u64, HashMap<NodeID, Node>> + Send + Sync> =
+                RocksDbStore::open(path.join("id2node"));
+            let adjacency: Box<dyn Kv<u64, HashMap<NodeID, Vec<OldEdge>>> + Send + Sync> =
+                RocksDbStore::open(path.join("adjacency"));
+            let reversed_adjacency: Box<dyn Kv<u64, HashMap<NodeID, Vec<OldEdge>>> + Send + Sync> =
+                RocksDbStore::open(path.join("reversed_adjacency"));
+            let meta: Box<dyn Kv<String, u64> + Send + Sync> =
+                RocksDbStore::open(path.join("meta"));
+
+            node2id.insert(Node::from("A"), 0);
+            node2id.insert(Node::from("B"), 1);
+            id2node.insert(
+                0,
+                HashMap::from([(0, Node::from("A")), (1, Node::from("B"))]),
+            );
+            adjacency.insert(
+                0,
+                HashMap::from([(
+                    0,
+                    vec![OldEdge {
+                        other: 1,
+                        label: "link".to_string(),
+                    }],
+                )]),
+            );
+            reversed_adjacency.insert(
+                0,
+                HashMap::from([(
+                    1,
+                    vec![OldEdge {
+                        other: 0,
+                        label: "link".to_string(),
+                    }],
+                )]),
+            );
+            meta.insert("next_id".to_string(), 2);
+
+            node2id.flush();
+            id2node.flush();
+            adjacency.flush();
+            reversed_adjacency.flush();
+            meta.flush();
+        }
+
+        let mut store: GraphStore<RocksDbStore> = GraphStore::open(&path);
+
+        assert_eq!(
+            store.outgoing_edges(0),
+            vec![Edge {
+                from: 0,
+                to: 1,
+                label: "link".to_string(),
+                properties: EdgeProperties::default(),
+            }]
+        );
+        assert_eq!(store.ingoing_edges(1).len(), 1);
+
+        // edges without a timestamp are not garbage collected
+        assert_eq!(store.remove_edges_older_than(10), 0);
+        assert_eq!(store.outgoing_edges(0).len(), 1);
+    }
 }
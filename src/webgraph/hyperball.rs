@@ -0,0 +1,103 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+
+use tracing::info;
+
+use crate::hyperloglog::HyperLogLog;
+
+use super::{graph_store::GraphStore, Node, NodeID, Store};
+
+/// Approximate harmonic centrality using HyperBall
+/// (Boldi and Vigna, "In-core computation of geometric centralities with HyperBall").
+///
+/// Every node keeps a HyperLogLog counter of the nodes that can reach it within `t` steps.
+/// In round `t` each node merges the counters of its direct predecessors, so the graph only
+/// needs to be scanned once per round (bounded by the diameter) instead of a BFS per node.
+/// Links that are not endorsements (nofollow, sponsored, ugc) are ignored. HyperBall counts
+/// steps, so unlike the exact harmonic centrality every link has the same length
+/// regardless of where it is placed on the page.
+pub fn harmonic_centrality<S: Store>(graph: &GraphStore<S>, precision: u8) -> HashMap<NodeID, f64> {
+    let nodes: Vec<NodeID> = graph.nodes().collect();
+    info!("Found {} nodes in the graph", nodes.len());
+
+    if nodes.len() <= 1 {
+        return HashMap::new();
+    }
+
+    let num_slots = nodes.iter().max().map(|id| *id as usize + 1).unwrap_or(0);
+
+    let mut counters: Vec<HyperLogLog> = (0..num_slots)
+        .map(|id| {
+            let mut counter = HyperLogLog::new(precision);
+            counter.add(&(id as NodeID));
+            counter
+        })
+        .collect();
+
+    let mut prev_sizes: Vec<f64> = counters.iter().map(HyperLogLog::size).collect();
+    let mut centrality = vec![0.0; num_slots];
+
+    let mut t = 1;
+    loop {
+        let mut next_counters = counters.clone();
+        let mut changed = false;
+
+        for edge in graph
+            .edges()
+            .filter(|edge| edge.properties.is_endorsement())
+        {
+            changed |= next_counters[edge.to as usize].merge(&counters[edge.from as usize]);
+        }
+
+        if !changed {
+            break;
+        }
+
+        for (id, counter) in next_counters.iter().enumerate() {
+            let size = counter.size();
+
+            if size > prev_sizes[id] {
+                centrality[id] += (size - prev_sizes[id]) / t as f64;
+                prev_sizes[id] = size;
+            }
+        }
+
+        info!("HyperBall iteration {} done", t);
+
+        counters = next_counters;
+        t += 1;
+    }
+
+    let norm_factor = (nodes.len() - 1) as f64;
+
+    nodes
+        .into_iter()
+        .map(|id| (id, centrality[id as usize] / norm_factor))
+        .filter(|(_, centrality)| *centrality > 0.0)
+        .collect()
+}
+
+pub fn node_harmonic_centrality<S: Store>(
+    graph: &GraphStore<S>,
+    precision: u8,
+) -> HashMap<Node, f64> {
+    harmonic_centrality(graph, precision)
+        .into_iter()
+        .map(|(id, centrality)| (graph.id2node(&id).expect("unknown node"), centrality))
+        .collect()
+}
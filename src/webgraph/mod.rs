@@ -13,11 +13,15 @@
 //
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
+mod export;
 mod graph_store;
+mod hyperball;
+mod pagerank;
 
 use indicatif::{ProgressBar, ProgressIterator, ProgressStyle};
 use serde::{Deserialize, Serialize};
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+use std::ops::Range;
 use std::path::Path;
 use std::sync::Mutex;
 use std::{cmp, fs};
@@ -25,18 +29,87 @@ use tracing::info;
 
 use graph_store::GraphStore;
 
+pub use export::ExportFormat;
+
 use crate::directory::{self, DirEntry};
-use crate::webpage::Url;
+use crate::webpage::{LinkContext, LinkRel, Url};
 
 use self::graph_store::Adjacency;
 use crate::kv::rocksdb_store::RocksDbStore;
 
 type NodeID = u64;
 
+/// Boilerplate links (navigation, footer) only pass on this fraction of the rank.
+const BOILERPLATE_WEIGHT: f64 = 0.5;
+/// Links outside the main content that are not boilerplate either (e.g. sidebars).
+const OTHER_WEIGHT: f64 = 0.8;
+/// Length of a main content link when measuring distances for harmonic centrality.
+/// Links with a lower weight are longer, so the nodes behind them contribute less.
+const MAIN_LINK_LENGTH: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EdgeProperties {
+    pub rel: LinkRel,
+    pub context: LinkContext,
+}
+
+/// Edges we know nothing about (inserted without properties or stored before edges had
+/// properties) count as main content links, so they keep their full weight.
+impl Default for EdgeProperties {
+    fn default() -> Self {
+        Self {
+            rel: LinkRel::default(),
+            context: LinkContext::Main,
+        }
+    }
+}
+
+impl EdgeProperties {
+    /// Links that the source does not vouch for (nofollow, sponsored, ugc)
+    /// are ignored when calculating centrality.
+    pub fn is_endorsement(&self) -> bool {
+        self.rel.is_endorsement()
+    }
+
+    pub fn weight(&self) -> f64 {
+        match self.context {
+            LinkContext::Main => 1.0,
+            LinkContext::Navigation | LinkContext::Footer => BOILERPLATE_WEIGHT,
+            LinkContext::Other => OTHER_WEIGHT,
+        }
+    }
+
+    /// Length of the edge in harmonic centrality. A main content link has length [`MAIN_LINK_LENGTH`].
+    pub fn length(&self) -> usize {
+        (MAIN_LINK_LENGTH as f64 / self.weight()).round() as usize
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub(crate) struct StoredEdge {
     other: NodeID,
     label: String,
+    /// When the edge was last seen (e.g. fetch time of the source page). 0 if unknown.
+    timestamp: u64,
+    properties: EdgeProperties,
+}
+
+/// Layout of the edges in graphs from before edges had a timestamp and properties.
+#[derive(Deserialize)]
+struct LegacyStoredEdge {
+    other: NodeID,
+    label: String,
+}
+
+impl From<LegacyStoredEdge> for StoredEdge {
+    fn from(edge: LegacyStoredEdge) -> Self {
+        Self {
+            other: edge.other,
+            label: edge.label,
+            timestamp: 0,
+            properties: EdgeProperties::default(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
@@ -56,12 +129,15 @@ impl Node {
     }
 }
 
+/// Uses the name as is, which is what host nodes and stored names need.
 impl From<String> for Node {
     fn from(name: String) -> Self {
         Self { name }
     }
 }
 
+/// The url is used as is, so it should already be canonicalised with the
+/// [`crate::webpage::Canonicalizer`] of the pipeline.
 impl From<&Url> for Node {
     fn from(url: &Url) -> Self {
         Self {
@@ -78,9 +154,7 @@ impl From<&str> for Node {
 
 impl From<Url> for Node {
     fn from(url: Url) -> Self {
-        Self {
-            name: url.to_string(),
-        }
+        Self::from(&url)
     }
 }
 
@@ -129,6 +203,7 @@ impl<'a> EdgeIterator<'a> {
                     from: node_id,
                     to: edge.other,
                     label: edge.label,
+                    properties: edge.properties,
                 })
             })));
 
@@ -141,15 +216,17 @@ impl<'a> Iterator for EdgeIterator<'a> {
     type Item = Edge;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(res) = self.current_block.as_mut().and_then(|it| it.next()) {
-            return Some(res);
-        }
+        loop {
+            if let Some(res) = self.current_block.as_mut().and_then(|it| it.next()) {
+                return Some(res);
+            }
+
+            if self.current_block_idx >= self.blocks.len() {
+                return None;
+            }
 
-        if self.current_block.is_none() {
             self.load_next_block();
         }
-
-        self.current_block.as_mut().and_then(|it| it.next())
     }
 }
 
@@ -158,6 +235,23 @@ pub struct Edge {
     from: NodeID,
     to: NodeID,
     label: String,
+    properties: EdgeProperties,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GraphKind {
+    Host,
+    Full,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GraphStats {
+    pub num_nodes: u64,
+    pub num_edges: u64,
+    /// in-degree -> number of nodes with that in-degree
+    pub in_degrees: BTreeMap<usize, usize>,
+    /// out-degree -> number of nodes with that out-degree
+    pub out_degrees: BTreeMap<usize, usize>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -165,6 +259,7 @@ pub struct FullEdge {
     pub from: Node,
     pub to: Node,
     pub label: String,
+    pub properties: EdgeProperties,
 }
 
 pub struct WebgraphBuilder {
@@ -254,15 +349,62 @@ impl<S: Store> Webgraph<S> {
         }
     }
 
+    /// Record the outgoing links of a page that was fetched at `timestamp`.
+    /// The links replace whatever was stored for the page in the full graph, unless
+    /// the stored links are from a newer fetch. A host has many pages, so host edges
+    /// are only refreshed and stale ones must be removed with [`Self::remove_edges_older_than`].
+    pub fn replace_outgoing(
+        &mut self,
+        from: Node,
+        links: Vec<(Node, String, EdgeProperties)>,
+        timestamp: u64,
+    ) {
+        if let Some(host_graph) = &mut self.host_graph {
+            let host = from.clone().into_host();
+
+            for (to, label, properties) in &links {
+                host_graph.insert_with_timestamp(
+                    host.clone(),
+                    to.clone().into_host(),
+                    label.clone(),
+                    *properties,
+                    timestamp,
+                );
+            }
+        }
+
+        if let Some(full_graph) = &mut self.full_graph {
+            full_graph.replace_outgoing(from, links, timestamp);
+        }
+    }
+
+    /// Garbage collect edges that have not been seen since `timestamp`.
+    /// Returns the number of removed edges.
+    pub fn remove_edges_older_than(&mut self, timestamp: u64) -> usize {
+        let mut removed = 0;
+
+        if let Some(full_graph) = &mut self.full_graph {
+            removed += full_graph.remove_edges_older_than(timestamp);
+        }
+
+        if let Some(host_graph) = &mut self.host_graph {
+            removed += host_graph.remove_edges_older_than(timestamp);
+        }
+
+        self.flush();
+
+        removed
+    }
+
     pub fn merge(&mut self, other: Webgraph<S>) {
         match (&mut self.full_graph, other.full_graph) {
-            (Some(self_graph), Some(other_graph)) => self_graph.append(other_graph),
+            (Some(self_graph), Some(other_graph)) => self_graph.append(other_graph, true),
             (None, Some(other_graph)) => self.full_graph = Some(other_graph),
             (Some(_), None) | (None, None) => {}
         }
 
         match (&mut self.host_graph, other.host_graph) {
-            (Some(self_graph), Some(other_graph)) => self_graph.append(other_graph),
+            (Some(self_graph), Some(other_graph)) => self_graph.append(other_graph, false),
             (None, Some(other_graph)) => self.host_graph = Some(other_graph),
             (Some(_), None) | (None, None) => {}
         }
@@ -270,15 +412,17 @@ impl<S: Store> Webgraph<S> {
         self.flush();
     }
 
-    fn dijkstra<F1, F2>(
+    fn dijkstra<F1, F2, F3>(
         source: Node,
         node_edges: F1,
         edge_node: F2,
+        edge_length: F3,
         store: &GraphStore<S>,
     ) -> HashMap<NodeID, usize>
     where
         F1: Fn(NodeID) -> Vec<Edge>,
         F2: Fn(&Edge) -> NodeID,
+        F3: Fn(&Edge) -> usize,
     {
         let source_id = store.node2id(&source);
         if source_id.is_none() {
@@ -302,10 +446,11 @@ impl<S: Store> Webgraph<S> {
             }
 
             for edge in node_edges(v) {
-                if cost + 1 < *distances.get(&edge_node(&edge)).unwrap_or(&usize::MAX) {
-                    let next = cmp::Reverse((cost + 1, edge_node(&edge)));
+                let next_cost = cost + edge_length(&edge);
+                if next_cost < *distances.get(&edge_node(&edge)).unwrap_or(&usize::MAX) {
+                    let next = cmp::Reverse((next_cost, edge_node(&edge)));
                     queue.push(next);
-                    distances.insert(edge_node(&edge), cost + 1);
+                    distances.insert(edge_node(&edge), next_cost);
                 }
             }
         }
@@ -322,6 +467,7 @@ impl<S: Store> Webgraph<S> {
                     source,
                     |node_id| full_graph.outgoing_edges(node_id),
                     |edge| edge.to,
+                    |_| 1,
                     full_graph,
                 );
 
@@ -333,15 +479,26 @@ impl<S: Store> Webgraph<S> {
             .unwrap_or_default()
     }
 
-    #[allow(unused)]
-    fn raw_reversed_distances(&self, source: Node) -> HashMap<NodeID, usize> {
+    /// Only links the source vouches for count towards centrality.
+    fn endorsements(edges: Vec<Edge>) -> Vec<Edge> {
+        edges
+            .into_iter()
+            .filter(|edge| edge.properties.is_endorsement())
+            .collect()
+    }
+
+    fn raw_reversed_distances<F>(&self, source: Node, edge_length: F) -> HashMap<NodeID, usize>
+    where
+        F: Fn(&Edge) -> usize,
+    {
         self.full_graph
             .as_ref()
             .map(|full_graph| {
                 Webgraph::dijkstra(
                     source,
-                    |node| full_graph.ingoing_edges(node),
+                    |node| Webgraph::endorsements(full_graph.ingoing_edges(node)),
                     |edge| edge.from,
+                    edge_length,
                     full_graph,
                 )
             })
@@ -353,7 +510,7 @@ impl<S: Store> Webgraph<S> {
         self.full_graph
             .as_ref()
             .map(|full_graph| {
-                self.raw_reversed_distances(source)
+                self.raw_reversed_distances(source, |_| 1)
                     .into_iter()
                     .map(|(id, dist)| (full_graph.id2node(&id).expect("unknown node"), dist))
                     .collect()
@@ -370,6 +527,7 @@ impl<S: Store> Webgraph<S> {
                     source,
                     |node| host_graph.outgoing_edges(node),
                     |edge| edge.to,
+                    |_| 1,
                     host_graph,
                 );
 
@@ -381,14 +539,18 @@ impl<S: Store> Webgraph<S> {
             .unwrap_or_default()
     }
 
-    fn raw_host_reversed_distances(&self, source: Node) -> HashMap<NodeID, usize> {
+    fn raw_host_reversed_distances<F>(&self, source: Node, edge_length: F) -> HashMap<NodeID, usize>
+    where
+        F: Fn(&Edge) -> usize,
+    {
         self.host_graph
             .as_ref()
             .map(|host_graph| {
                 Webgraph::dijkstra(
                     source,
-                    |node| host_graph.ingoing_edges(node),
+                    |node| Webgraph::endorsements(host_graph.ingoing_edges(node)),
                     |edge| edge.from,
+                    edge_length,
                     host_graph,
                 )
             })
@@ -400,7 +562,7 @@ impl<S: Store> Webgraph<S> {
         self.host_graph
             .as_ref()
             .map(|host_graph| {
-                self.raw_host_reversed_distances(source)
+                self.raw_host_reversed_distances(source, |_| 1)
                     .into_iter()
                     .map(|(id, dist)| (host_graph.id2node(&id).expect("unknown node"), dist))
                     .collect()
@@ -414,6 +576,23 @@ impl<S: Store> Webgraph<S> {
     {
         let nodes: Vec<_> = graph.nodes().collect();
         info!("Found {} nodes in the graph", nodes.len());
+        let num_nodes = graph.num_live_nodes();
+
+        Webgraph::calculate_partial_centrality(graph, nodes, num_nodes, node_distances)
+    }
+
+    /// Calculate the harmonic centrality of `nodes` only. `num_nodes` is the total
+    /// number of nodes in the graph and is used for normalization.
+    /// `node_distances` measures distances in edge lengths (see [`EdgeProperties::length`]).
+    fn calculate_partial_centrality<F>(
+        graph: &GraphStore<S>,
+        nodes: Vec<NodeID>,
+        num_nodes: usize,
+        node_distances: F,
+    ) -> HashMap<Node, f64>
+    where
+        F: Fn(Node) -> HashMap<NodeID, usize>,
+    {
         let pb = ProgressBar::new(nodes.len() as u64);
         pb.set_style(
             ProgressStyle::default_bar()
@@ -422,16 +601,16 @@ impl<S: Store> Webgraph<S> {
                 )
                 .progress_chars("#>-"),
         );
-        let norm_factor = (nodes.len() - 1) as f64;
+        let norm_factor = (num_nodes.max(2) - 1) as f64;
         nodes
             .iter()
             .progress_with(pb)
-            .map(|node_id| {
-                let node = graph.id2node(node_id).expect("unknown node");
+            .filter_map(|node_id| graph.id2node(node_id).map(|node| (node_id, node)))
+            .map(|(node_id, node)| {
                 let centrality_values: HashMap<NodeID, f64> = node_distances(node.clone())
                     .into_iter()
                     .filter(|(other_id, _)| *other_id != *node_id)
-                    .map(|(other_node, dist)| (other_node, 1f64 / dist as f64))
+                    .map(|(other_node, dist)| (other_node, MAIN_LINK_LENGTH as f64 / dist as f64))
                     .collect();
 
                 let centrality = centrality_values
@@ -446,12 +625,61 @@ impl<S: Store> Webgraph<S> {
             .collect()
     }
 
+    pub fn num_nodes(&self) -> u64 {
+        self.full_graph
+            .as_ref()
+            .map(|full_graph| full_graph.num_nodes())
+            .unwrap_or_default()
+    }
+
+    pub fn host_num_nodes(&self) -> u64 {
+        self.host_graph
+            .as_ref()
+            .map(|host_graph| host_graph.num_nodes())
+            .unwrap_or_default()
+    }
+
+    /// Harmonic centrality for the nodes with an id in `nodes`.
+    /// Allows the computation to be split into independent jobs. The centrality is normalised by
+    /// the number of nodes that still have edges, as in [`Self::harmonic_centrality`].
+    pub fn partial_harmonic_centrality(&self, nodes: Range<u64>) -> HashMap<Node, f64> {
+        self.full_graph
+            .as_ref()
+            .map(|full_graph| {
+                Webgraph::calculate_partial_centrality(
+                    full_graph,
+                    nodes.collect(),
+                    full_graph.num_live_nodes(),
+                    |node| self.raw_reversed_distances(node, |edge| edge.properties.length()),
+                )
+            })
+            .unwrap_or_default()
+    }
+
+    /// Host harmonic centrality for the nodes with an id in `nodes`.
+    /// Allows the computation to be split into independent jobs.
+    pub fn partial_host_harmonic_centrality(&self, nodes: Range<u64>) -> HashMap<Node, f64> {
+        self.host_graph
+            .as_ref()
+            .map(|host_graph| {
+                Webgraph::calculate_partial_centrality(
+                    host_graph,
+                    nodes.collect(),
+                    host_graph.num_live_nodes(),
+                    |node| self.raw_host_reversed_distances(node, |edge| edge.properties.length()),
+                )
+            })
+            .unwrap_or_default()
+    }
+
     #[allow(unused)]
     pub fn harmonic_centrality(&self) -> HashMap<Node, f64> {
         self.full_graph
             .as_ref()
             .map(|full_graph| {
-                Webgraph::calculate_centrality(full_graph, |node| self.raw_reversed_distances(node))
+                Webgraph::calculate_centrality(full_graph, |node| {
+                    self.raw_reversed_distances(node, |edge| edge.properties.length())
+                })
             })
             .unwrap_or_default()
     }
@@ -461,12 +689,70 @@ impl<S: Store> Webgraph<S> {
             .as_ref()
             .map(|host_graph| {
                 Webgraph::calculate_centrality(host_graph, |node| {
-                    self.raw_host_reversed_distances(node)
+                    self.raw_host_reversed_distances(node, |edge| edge.properties.length())
                 })
             })
             .unwrap_or_default()
     }
 
+    pub fn approximate_harmonic_centrality(&self, precision: u8) -> HashMap<Node, f64> {
+        self.full_graph
+            .as_ref()
+            .map(|full_graph| hyperball::node_harmonic_centrality(full_graph, precision))
+            .unwrap_or_default()
+    }
+
+    pub fn host_approximate_harmonic_centrality(&self, precision: u8) -> HashMap<Node, f64> {
+        self.host_graph
+            .as_ref()
+            .map(|host_graph| hyperball::node_harmonic_centrality(host_graph, precision))
+            .unwrap_or_default()
+    }
+
+    pub fn pagerank(&self) -> HashMap<Node, f64> {
+        self.full_graph
+            .as_ref()
+            .map(|full_graph| {
+                pagerank::node_pagerank(full_graph, &[], pagerank::Direction::Forward)
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn host_pagerank(&self) -> HashMap<Node, f64> {
+        self.host_graph
+            .as_ref()
+            .map(|host_graph| {
+                pagerank::node_pagerank(host_graph, &[], pagerank::Direction::Forward)
+            })
+            .unwrap_or_default()
+    }
+
+    /// Pagerank where the random surfer only teleports to the trusted hosts.
+    /// Hosts that are far from the trusted seeds (like link farms) get a low score.
+    pub fn host_trustrank(&self, trusted: &[Node]) -> HashMap<Node, f64> {
+        let trusted: Vec<Node> = trusted.iter().cloned().map(Node::into_host).collect();
+
+        self.host_graph
+            .as_ref()
+            .map(|host_graph| {
+                pagerank::node_pagerank(host_graph, &trusted, pagerank::Direction::Forward)
+            })
+            .unwrap_or_default()
+    }
+
+    /// Trustrank on the reversed graph seeded with known spam hosts.
+    /// Hosts that link (directly or indirectly) to spam get a high score.
+    pub fn host_antitrustrank(&self, spam: &[Node]) -> HashMap<Node, f64> {
+        let spam: Vec<Node> = spam.iter().cloned().map(Node::into_host).collect();
+
+        self.host_graph
+            .as_ref()
+            .map(|host_graph| {
+                pagerank::node_pagerank(host_graph, &spam, pagerank::Direction::Backward)
+            })
+            .unwrap_or_default()
+    }
+
     pub fn flush(&self) {
         if let Some(full_graph) = &self.full_graph {
             full_graph.flush();
@@ -476,18 +762,41 @@ impl<S: Store> Webgraph<S> {
         }
     }
 
+    fn graph(&self, kind: GraphKind) -> Option<&GraphStore<S>> {
+        match kind {
+            GraphKind::Host => self.host_graph.as_ref(),
+            GraphKind::Full => self.full_graph.as_ref(),
+        }
+    }
+
+    fn full_edges(graph: &GraphStore<S>, edges: Vec<Edge>) -> Vec<FullEdge> {
+        edges
+            .into_iter()
+            .map(|edge| FullEdge {
+                from: graph.id2node(&edge.from).unwrap(),
+                to: graph.id2node(&edge.to).unwrap(),
+                label: edge.label,
+                properties: edge.properties,
+            })
+            .collect()
+    }
+
     pub fn ingoing_edges(&self, node: Node) -> Vec<FullEdge> {
         if let Some(graph) = &self.full_graph {
             if let Some(node_id) = graph.node2id(&node) {
-                graph
-                    .ingoing_edges(node_id)
-                    .into_iter()
-                    .map(|edge| FullEdge {
-                        from: graph.id2node(&edge.from).unwrap(),
-                        to: graph.id2node(&edge.to).unwrap(),
-                        label: edge.label,
-                    })
-                    .collect()
+                Webgraph::full_edges(graph, graph.ingoing_edges(node_id))
+            } else {
+                Vec::new()
+            }
+        } else {
+            Vec::new()
+        }
+    }
+
+    pub fn outgoing_edges(&self, node: Node) -> Vec<FullEdge> {
+        if let Some(graph) = &self.full_graph {
+            if let Some(node_id) = graph.node2id(&node) {
+                Webgraph::full_edges(graph, graph.outgoing_edges(node_id))
             } else {
                 Vec::new()
             }
@@ -495,6 +804,86 @@ impl<S: Store> Webgraph<S> {
             Vec::new()
         }
     }
+
+    pub fn host_ingoing_edges(&self, node: Node) -> Vec<FullEdge> {
+        if let Some(graph) = &self.host_graph {
+            if let Some(node_id) = graph.node2id(&node.into_host()) {
+                Webgraph::full_edges(graph, graph.ingoing_edges(node_id))
+            } else {
+                Vec::new()
+            }
+        } else {
+            Vec::new()
+        }
+    }
+
+    pub fn host_outgoing_edges(&self, node: Node) -> Vec<FullEdge> {
+        if let Some(graph) = &self.host_graph {
+            if let Some(node_id) = graph.node2id(&node.into_host()) {
+                Webgraph::full_edges(graph, graph.outgoing_edges(node_id))
+            } else {
+                Vec::new()
+            }
+        } else {
+            Vec::new()
+        }
+    }
+
+    pub fn stats(&self, kind: GraphKind) -> Option<GraphStats> {
+        self.graph(kind).map(|graph| {
+            let mut in_degree: HashMap<NodeID, usize> = HashMap::new();
+            let mut out_degree: HashMap<NodeID, usize> = HashMap::new();
+            let mut num_edges = 0;
+
+            for edge in graph.edges() {
+                *out_degree.entry(edge.from).or_default() += 1;
+                *in_degree.entry(edge.to).or_default() += 1;
+                num_edges += 1;
+            }
+
+            // ids are never reused, so nodes that lost all their edges during garbage
+            // collection are not counted
+            let num_nodes = out_degree
+                .keys()
+                .chain(in_degree.keys())
+                .collect::<HashSet<_>>()
+                .len() as u64;
+
+            let distribution = |degrees: HashMap<NodeID, usize>| {
+                let mut distribution: BTreeMap<usize, usize> = BTreeMap::new();
+
+                let num_zero = num_nodes as usize - degrees.len();
+                if num_zero > 0 {
+                    distribution.insert(0, num_zero);
+                }
+
+                for degree in degrees.into_values() {
+                    *distribution.entry(degree).or_default() += 1;
+                }
+
+                distribution
+            };
+
+            GraphStats {
+                num_nodes,
+                num_edges,
+                in_degrees: distribution(in_degree),
+                out_degrees: distribution(out_degree),
+            }
+        })
+    }
+
+    pub fn export<P: AsRef<Path>>(
+        &self,
+        kind: GraphKind,
+        format: ExportFormat,
+        path: P,
+    ) -> std::io::Result<()> {
+        match self.graph(kind) {
+            Some(graph) => export::export(graph, format, path),
+            None => Ok(()),
+        }
+    }
 }
 
 impl From<FrozenWebgraph> for Webgraph {
@@ -675,6 +1064,226 @@ mod test {
         );
     }
 
+    fn assert_close(exact: &HashMap<Node, f64>, approx: &HashMap<Node, f64>) {
+        for (node, exact_centrality) in exact {
+            let approx_centrality = approx.get(node).copied().unwrap_or(0.0);
+            assert!(
+                (exact_centrality - approx_centrality).abs() < 0.05,
+                "{:?}: exact {} approx {}",
+                node,
+                exact_centrality,
+                approx_centrality
+            );
+        }
+
+        for node in approx.keys() {
+            assert!(exact.contains_key(node));
+        }
+    }
+
+    #[test]
+    fn approximate_harmonic_centrality() {
+        let graph = test_graph();
+
+        let exact = graph.harmonic_centrality();
+        let approx = graph.approximate_harmonic_centrality(8);
+
+        assert_close(&exact, &approx);
+        assert_eq!(approx.get(&Node::from("D")), None);
+    }
+
+    #[test]
+    fn approximate_harmonic_centrality_larger_graph() {
+        let mut graph = WebgraphBuilder::new_memory()
+            .with_full_graph()
+            .with_host_graph()
+            .open();
+
+        for i in 0..50_u64 {
+            for j in [i + 1, i * 3 + 1, i * 7 + 2] {
+                if j % 50 != i {
+                    graph.insert(
+                        Node::from(format!("{}.com", i)),
+                        Node::from(format!("{}.com", j % 50)),
+                        String::new(),
+                    );
+                }
+            }
+        }
+
+        graph.flush();
+
+        assert_close(
+            &graph.harmonic_centrality(),
+            &graph.approximate_harmonic_centrality(10),
+        );
+        assert_close(
+            &graph.host_harmonic_centrality(),
+            &graph.host_approximate_harmonic_centrality(10),
+        );
+    }
+
+    #[test]
+    fn pagerank() {
+        let graph = test_graph();
+
+        let pagerank = graph.pagerank();
+
+        assert_eq!(pagerank.get(&Node::from("C")), Some(&1.0));
+        assert!(pagerank.get(&Node::from("A")).unwrap() > pagerank.get(&Node::from("B")).unwrap());
+        assert!(pagerank.get(&Node::from("B")).unwrap() > pagerank.get(&Node::from("D")).unwrap());
+
+        let host_pagerank = graph.host_pagerank();
+        assert_eq!(host_pagerank.get(&Node::from("C")), Some(&1.0));
+    }
+
+    #[test]
+    fn trustrank() {
+        let mut graph = WebgraphBuilder::new_memory()
+            .with_full_graph()
+            .with_host_graph()
+            .open();
+
+        // a.com is trusted and links to b.com. The link farm (farm1 and farm2)
+        // links heavily to spam.com which links back into the farm.
+        graph.insert(Node::from("a.com"), Node::from("b.com"), String::new());
+        graph.insert(Node::from("b.com"), Node::from("a.com"), String::new());
+        graph.insert(
+            Node::from("farm1.com"),
+            Node::from("spam.com"),
+            String::new(),
+        );
+        graph.insert(
+            Node::from("farm2.com"),
+            Node::from("spam.com"),
+            String::new(),
+        );
+        graph.insert(
+            Node::from("spam.com"),
+            Node::from("farm1.com"),
+            String::new(),
+        );
+        graph.insert(
+            Node::from("spam.com"),
+            Node::from("farm2.com"),
+            String::new(),
+        );
+
+        graph.flush();
+
+        let pagerank = graph.host_pagerank();
+        assert!(
+            pagerank.get(&Node::from("spam.com")).unwrap()
+                > pagerank.get(&Node::from("b.com")).unwrap()
+        );
+
+        let trustrank = graph.host_trustrank(&[Node::from("www.a.com")]);
+        assert!(trustrank.get(&Node::from("b.com")).unwrap() > &0.0);
+        assert_eq!(trustrank.get(&Node::from("spam.com")), None);
+
+        let antitrustrank = graph.host_antitrustrank(&[Node::from("spam.com")]);
+        assert!(antitrustrank.get(&Node::from("farm1.com")).unwrap() > &0.0);
+        assert_eq!(antitrustrank.get(&Node::from("a.com")), None);
+    }
+
+    #[test]
+    fn partial_harmonic_centrality() {
+        let graph = test_graph();
+
+        let full = graph.harmonic_centrality();
+
+        let num_nodes = graph.num_nodes();
+        assert_eq!(num_nodes, 4);
+
+        let mut partial = graph.partial_harmonic_centrality(0..2);
+        partial.extend(graph.partial_harmonic_centrality(2..num_nodes));
+
+        assert_eq!(full, partial);
+        assert_eq!(
+            graph.host_harmonic_centrality(),
+            graph.partial_host_harmonic_centrality(0..graph.host_num_nodes())
+        );
+    }
+
+    #[test]
+    fn outgoing_edges() {
+        let mut graph = WebgraphBuilder::new_memory()
+            .with_full_graph()
+            .with_host_graph()
+            .open();
+
+        graph.insert(
+            Node::from("a.com/1"),
+            Node::from("b.com/1"),
+            "first".to_string(),
+        );
+        graph.insert(
+            Node::from("www.a.com/2"),
+            Node::from("b.com/2"),
+            "second".to_string(),
+        );
+
+        graph.flush();
+
+        let edges = graph.outgoing_edges(Node::from("a.com/1"));
+        assert_eq!(edges.len(), 1);
+        assert_eq!(edges[0].to, Node::from("b.com/1"));
+        assert_eq!(edges[0].label, "first".to_string());
+
+        let mut labels: Vec<_> = graph
+            .host_outgoing_edges(Node::from("www.a.com"))
+            .into_iter()
+            .map(|edge| edge.label)
+            .collect();
+        labels.sort();
+        assert_eq!(labels, vec!["first".to_string(), "second".to_string()]);
+
+        assert_eq!(graph.host_ingoing_edges(Node::from("b.com")).len(), 2);
+        assert!(graph.host_ingoing_edges(Node::from("a.com")).is_empty());
+    }
+
+    #[test]
+    fn stats() {
+        let graph = test_graph();
+
+        let stats = graph.stats(GraphKind::Full).unwrap();
+
+        assert_eq!(stats.num_nodes, 4);
+        assert_eq!(stats.num_edges, 5);
+        assert_eq!(stats.in_degrees, BTreeMap::from([(0, 1), (1, 2), (3, 1)]));
+        assert_eq!(stats.out_degrees, BTreeMap::from([(1, 3), (2, 1)]));
+    }
+
+    #[test]
+    fn export_edge_list() {
+        let graph = test_graph();
+        let path = crate::gen_temp_path();
+
+        graph
+            .export(GraphKind::Full, ExportFormat::EdgeList, &path)
+            .unwrap();
+
+        let mut lines: Vec<_> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| line.to_string())
+            .collect();
+        lines.sort();
+
+        assert_eq!(
+            lines,
+            vec![
+                "A\tB\t".to_string(),
+                "A\tC\t".to_string(),
+                "B\tC\t".to_string(),
+                "C\tA\t".to_string(),
+                "D\tC\t".to_string(),
+            ]
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+
     #[test]
     fn www_subdomain_ignored() {
         let mut graph = WebgraphBuilder::new_memory()
@@ -715,6 +1324,275 @@ mod test {
         )
     }
 
+    #[test]
+    fn non_endorsements_ignored_in_centrality() {
+        let mut graph = WebgraphBuilder::new_memory()
+            .with_full_graph()
+            .with_host_graph()
+            .open();
+
+        graph.replace_outgoing(
+            Node::from("a.com"),
+            vec![(
+                Node::from("b.com"),
+                String::new(),
+                EdgeProperties::default(),
+            )],
+            1,
+        );
+        graph.replace_outgoing(
+            Node::from("c.com"),
+            vec![(
+                Node::from("d.com"),
+                String::new(),
+                EdgeProperties {
+                    rel: LinkRel::parse("nofollow"),
+                    context: LinkContext::Main,
+                },
+            )],
+            1,
+        );
+        graph.flush();
+
+        let centrality = graph.harmonic_centrality();
+        assert!(centrality.get(&Node::from("b.com")).unwrap() > &0.0);
+        assert_eq!(centrality.get(&Node::from("d.com")), None);
+
+        let centrality = graph.approximate_harmonic_centrality(10);
+        assert!(centrality.get(&Node::from("b.com")).unwrap() > &0.0);
+        assert_eq!(centrality.get(&Node::from("d.com")), None);
+
+        // the edge is still part of the graph
+        assert_eq!(outgoing_names(&graph, "c.com"), vec!["d.com"]);
+    }
+
+    #[test]
+    fn non_main_links_downweighted_in_centrality() {
+        let mut graph = WebgraphBuilder::new_memory()
+            .with_full_graph()
+            .with_host_graph()
+            .open();
+
+        graph.replace_outgoing(
+            Node::from("a.com"),
+            [
+                ("main.com", LinkContext::Main),
+                ("other.com", LinkContext::Other),
+                ("nav.com", LinkContext::Navigation),
+            ]
+            .into_iter()
+            .map(|(to, context)| {
+                (
+                    Node::from(to),
+                    String::new(),
+                    EdgeProperties {
+                        rel: LinkRel::default(),
+                        context,
+                    },
+                )
+            })
+            .collect(),
+            1,
+        );
+        graph.flush();
+
+        for centrality in [graph.harmonic_centrality(), graph.pagerank()] {
+            let main = centrality.get(&Node::from("main.com")).unwrap();
+            let other = centrality.get(&Node::from("other.com")).unwrap();
+            let nav = centrality.get(&Node::from("nav.com")).unwrap();
+
+            assert!(main > other);
+            assert!(other > nav);
+        }
+    }
+
+    fn outgoing_names(graph: &Webgraph, node: &str) -> Vec<String> {
+        let mut names: Vec<_> = graph
+            .outgoing_edges(Node::from(node))
+            .into_iter()
+            .map(|edge| edge.to.name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn replace_outgoing() {
+        let mut graph = WebgraphBuilder::new_memory()
+            .with_full_graph()
+            .with_host_graph()
+            .open();
+
+        graph.replace_outgoing(
+            Node::from("a.com/1"),
+            vec![
+                (
+                    Node::from("b.com/1"),
+                    "first".to_string(),
+                    EdgeProperties::default(),
+                ),
+                (
+                    Node::from("c.com/1"),
+                    "second".to_string(),
+                    EdgeProperties::default(),
+                ),
+            ],
+            1,
+        );
+        graph.flush();
+        assert_eq!(
+            outgoing_names(&graph, "a.com/1"),
+            vec!["b.com/1", "c.com/1"]
+        );
+
+        graph.replace_outgoing(
+            Node::from("a.com/1"),
+            vec![(
+                Node::from("b.com/1"),
+                "first".to_string(),
+                EdgeProperties::default(),
+            )],
+            2,
+        );
+        graph.flush();
+        assert_eq!(outgoing_names(&graph, "a.com/1"), vec!["b.com/1"]);
+        assert!(graph.ingoing_edges(Node::from("c.com/1")).is_empty());
+
+        // an older version of the page must not overwrite the newer one
+        graph.replace_outgoing(
+            Node::from("a.com/1"),
+            vec![(
+                Node::from("d.com/1"),
+                String::new(),
+                EdgeProperties::default(),
+            )],
+            1,
+        );
+        graph.flush();
+        assert_eq!(outgoing_names(&graph, "a.com/1"), vec!["b.com/1"]);
+
+        // the page no longer has any links, which must also be kept over older versions
+        graph.replace_outgoing(Node::from("a.com/1"), vec![], 3);
+        graph.replace_outgoing(
+            Node::from("a.com/1"),
+            vec![(
+                Node::from("b.com/1"),
+                String::new(),
+                EdgeProperties::default(),
+            )],
+            2,
+        );
+        graph.flush();
+        assert!(outgoing_names(&graph, "a.com/1").is_empty());
+    }
+
+    #[test]
+    fn merge_keeps_newest_page_version() {
+        let mut graph1 = WebgraphBuilder::new_memory()
+            .with_full_graph()
+            .with_host_graph()
+            .open();
+        graph1.replace_outgoing(
+            Node::from("a.com/1"),
+            vec![(
+                Node::from("b.com/1"),
+                String::new(),
+                EdgeProperties::default(),
+            )],
+            2,
+        );
+
+        let mut graph2 = WebgraphBuilder::new_memory()
+            .with_full_graph()
+            .with_host_graph()
+            .open();
+        graph2.replace_outgoing(
+            Node::from("a.com/1"),
+            vec![(
+                Node::from("c.com/1"),
+                String::new(),
+                EdgeProperties::default(),
+            )],
+            3,
+        );
+        graph2.replace_outgoing(
+            Node::from("b.com/1"),
+            vec![(
+                Node::from("c.com/1"),
+                String::new(),
+                EdgeProperties::default(),
+            )],
+            1,
+        );
+
+        graph1.merge(graph2);
+
+        assert_eq!(outgoing_names(&graph1, "a.com/1"), vec!["c.com/1"]);
+        assert_eq!(outgoing_names(&graph1, "b.com/1"), vec!["c.com/1"]);
+        assert!(graph1.ingoing_edges(Node::from("b.com/1")).is_empty());
+    }
+
+    #[test]
+    fn remove_edges_older_than() {
+        let mut graph = WebgraphBuilder::new_memory()
+            .with_full_graph()
+            .with_host_graph()
+            .open();
+
+        graph.replace_outgoing(
+            Node::from("a.com/1"),
+            vec![(
+                Node::from("b.com/1"),
+                String::new(),
+                EdgeProperties::default(),
+            )],
+            1,
+        );
+        graph.replace_outgoing(
+            Node::from("a.com/2"),
+            vec![(
+                Node::from("c.com/1"),
+                String::new(),
+                EdgeProperties::default(),
+            )],
+            1,
+        );
+        graph.replace_outgoing(
+            Node::from("a.com/2"),
+            vec![(
+                Node::from("c.com/1"),
+                String::new(),
+                EdgeProperties::default(),
+            )],
+            5,
+        );
+
+        // one full edge and one host edge have not been seen since timestamp 1
+        assert_eq!(graph.remove_edges_older_than(3), 2);
+
+        assert!(outgoing_names(&graph, "a.com/1").is_empty());
+        assert_eq!(outgoing_names(&graph, "a.com/2"), vec!["c.com/1"]);
+        assert_eq!(
+            graph
+                .host_outgoing_edges(Node::from("a.com"))
+                .into_iter()
+                .map(|edge| edge.to.name)
+                .collect::<Vec<_>>(),
+            vec!["c.com".to_string()]
+        );
+
+        let stats = graph.stats(GraphKind::Full).unwrap();
+        assert_eq!(stats.num_nodes, 2);
+        assert_eq!(stats.num_edges, 1);
+        assert_eq!(stats.in_degrees, BTreeMap::from([(0, 1), (1, 1)]));
+        assert_eq!(stats.out_degrees, BTreeMap::from([(0, 1), (1, 1)]));
+
+        // the centrality is normalised by the nodes that are left
+        let centrality = graph.partial_harmonic_centrality(0..graph.num_nodes());
+        assert_eq!(centrality, graph.harmonic_centrality());
+        assert_eq!(centrality.get(&Node::from("c.com/1")), Some(&1.0));
+    }
+
     #[test]
     fn serialize_deserialize_bincode() {
         let graph = test_graph();
@@ -0,0 +1,168 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::{HashMap, HashSet};
+
+use tracing::info;
+
+use super::{graph_store::GraphStore, Node, NodeID, Store};
+
+const DAMPING: f64 = 0.85;
+const MAX_ITERATIONS: usize = 100;
+const TOLERANCE: f64 = 1e-9;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Direction {
+    /// Rank flows along the links. Used for PageRank and TrustRank.
+    Forward,
+    /// Rank flows against the links. Used for anti-TrustRank, where pages linking
+    /// to known spam should be ranked high.
+    Backward,
+}
+
+/// Edges of the graph without self-loops and with parallel edges (same endpoints, different labels)
+/// collapsed into one. The edge iterator yields all outgoing edges of a node consecutively,
+/// so we only need to remember the targets of the current source node.
+/// Links that are not endorsements are dropped and boilerplate links get a lower weight.
+fn unique_edges<S: Store>(
+    graph: &GraphStore<S>,
+    direction: Direction,
+) -> impl Iterator<Item = (NodeID, NodeID, f64)> + '_ {
+    let mut current_from = None;
+    let mut seen = HashSet::new();
+
+    graph
+        .edges()
+        .filter(|edge| edge.from != edge.to)
+        .filter(|edge| edge.properties.is_endorsement())
+        .filter(move |edge| {
+            if current_from != Some(edge.from) {
+                current_from = Some(edge.from);
+                seen.clear();
+            }
+
+            seen.insert(edge.to)
+        })
+        .map(move |edge| match direction {
+            Direction::Forward => (edge.from, edge.to, edge.properties.weight()),
+            Direction::Backward => (edge.to, edge.from, edge.properties.weight()),
+        })
+}
+
+/// Computes (personalized) PageRank using power iteration.
+///
+/// If `seeds` is empty the random surfer teleports uniformly to any node (PageRank),
+/// otherwise only to the seed nodes (TrustRank when the seeds are trusted and the direction
+/// is forward, anti-TrustRank when the seeds are known spam and the direction is backward).
+/// The scores are scaled such that the highest ranked node has a score of 1.
+pub fn pagerank<S: Store>(
+    graph: &GraphStore<S>,
+    seeds: &[Node],
+    direction: Direction,
+) -> HashMap<NodeID, f64> {
+    let nodes: Vec<NodeID> = graph.nodes().collect();
+    info!("Found {} nodes in the graph", nodes.len());
+
+    if nodes.is_empty() {
+        return HashMap::new();
+    }
+
+    let num_slots = nodes.iter().max().map(|id| *id as usize + 1).unwrap_or(0);
+
+    let mut teleport = vec![0.0; num_slots];
+    let seed_ids: Vec<NodeID> = seeds
+        .iter()
+        .filter_map(|node| graph.node2id(node))
+        .collect();
+
+    if seeds.is_empty() {
+        for id in &nodes {
+            teleport[*id as usize] = 1.0 / nodes.len() as f64;
+        }
+    } else if seed_ids.is_empty() {
+        info!("None of the seeds are in the graph");
+        return HashMap::new();
+    } else {
+        for id in &seed_ids {
+            teleport[*id as usize] = 1.0 / seed_ids.len() as f64;
+        }
+    }
+
+    let mut out_weight = vec![0.0; num_slots];
+    for (from, _, weight) in unique_edges(graph, direction) {
+        out_weight[from as usize] += weight;
+    }
+
+    let mut rank = teleport.clone();
+
+    for iteration in 0..MAX_ITERATIONS {
+        let dangling: f64 = nodes
+            .iter()
+            .filter(|id| out_weight[**id as usize] == 0.0)
+            .map(|id| rank[*id as usize])
+            .sum();
+
+        let mut next_rank: Vec<f64> = teleport
+            .iter()
+            .map(|t| (1.0 - DAMPING) * t + DAMPING * dangling * t)
+            .collect();
+
+        for (from, to, weight) in unique_edges(graph, direction) {
+            next_rank[to as usize] +=
+                DAMPING * rank[from as usize] * weight / out_weight[from as usize];
+        }
+
+        let diff: f64 = rank
+            .iter()
+            .zip(next_rank.iter())
+            .map(|(a, b)| (a - b).abs())
+            .sum();
+
+        rank = next_rank;
+
+        info!("PageRank iteration {} done (diff: {})", iteration + 1, diff);
+
+        if diff < TOLERANCE {
+            break;
+        }
+    }
+
+    let max_rank = nodes
+        .iter()
+        .map(|id| rank[*id as usize])
+        .fold(0.0, f64::max);
+
+    if max_rank == 0.0 {
+        return HashMap::new();
+    }
+
+    nodes
+        .into_iter()
+        .map(|id| (id, rank[id as usize] / max_rank))
+        .filter(|(_, rank)| *rank > 0.0)
+        .collect()
+}
+
+pub fn node_pagerank<S: Store>(
+    graph: &GraphStore<S>,
+    seeds: &[Node],
+    direction: Direction,
+) -> HashMap<Node, f64> {
+    pagerank(graph, seeds, direction)
+        .into_iter()
+        .map(|(id, rank)| (graph.id2node(&id).expect("unknown node"), rank))
+        .collect()
+}
@@ -0,0 +1,528 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Documents that are not html (PDF, plain text and Markdown). They are converted
+//! into a small html page, so they go through the same indexing path as webpages.
+
+use std::panic;
+
+use chrono::{DateTime, FixedOffset, NaiveDateTime, TimeZone};
+use itertools::Itertools;
+use lopdf::{Dictionary, Object};
+use pulldown_cmark::{Event, HeadingLevel, Parser, Tag};
+
+use super::{Canonicalizer, Html, Url};
+use crate::{Error, Result};
+
+/// Plain text documents use their first line as title if it is at most this long.
+const MAX_TEXT_TITLE_LEN: usize = 100;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    #[default]
+    Html,
+    Pdf,
+    Text,
+    Markdown,
+}
+
+impl FileType {
+    /// The file type from the identified payload type of the response, falling back
+    /// to the extension of the url when the payload type is missing or too generic.
+    pub fn detect(payload_type: Option<&str>, url: &Url) -> Self {
+        let mime = payload_type
+            .and_then(|mime| mime.split(';').next())
+            .map(|mime| mime.trim().to_ascii_lowercase());
+
+        let extension = url
+            .without_query()
+            .rsplit('/')
+            .next()
+            .and_then(|name| name.rsplit_once('.'))
+            .map(|(_, extension)| extension.to_ascii_lowercase());
+
+        match (mime.as_deref(), extension.as_deref()) {
+            (Some("application/pdf"), _) => FileType::Pdf,
+            (Some("text/markdown" | "text/x-markdown"), _) => FileType::Markdown,
+            (Some("text/plain"), Some("md" | "markdown")) => FileType::Markdown,
+            (Some("text/plain"), _) => FileType::Text,
+            (None | Some("application/octet-stream"), Some("pdf")) => FileType::Pdf,
+            (None | Some("application/octet-stream"), Some("md" | "markdown")) => {
+                FileType::Markdown
+            }
+            (None | Some("application/octet-stream"), Some("txt")) => FileType::Text,
+            _ => FileType::Html,
+        }
+    }
+
+    /// The name used in the index and by the `filetype:` operator.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileType::Html => "html",
+            FileType::Pdf => "pdf",
+            FileType::Text => "txt",
+            FileType::Markdown => "md",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "html" | "htm" => Some(FileType::Html),
+            "pdf" => Some(FileType::Pdf),
+            "txt" | "text" => Some(FileType::Text),
+            "md" | "markdown" => Some(FileType::Markdown),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    pub filetype: FileType,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub modified: Option<DateTime<FixedOffset>>,
+    /// Html content of the `<body>`.
+    body: String,
+}
+
+impl Document {
+    /// `raw` is the undecoded response body and `text` the decoded one.
+    pub fn parse(filetype: FileType, raw: &[u8], text: &str) -> Result<Self> {
+        match filetype {
+            FileType::Pdf => Document::pdf(raw),
+            FileType::Text => Ok(Document::plain_text(text)),
+            FileType::Markdown => Ok(Document::markdown(text)),
+            FileType::Html => Err(Error::ParsingError(
+                "html is parsed with `Html`".to_string(),
+            )),
+        }
+    }
+
+    fn pdf(bytes: &[u8]) -> Result<Self> {
+        let doc = lopdf::Document::load_mem(bytes)
+            .map_err(|err| Error::ParsingError(format!("invalid pdf: {err}")))?;
+
+        // pdf-extract panics on some malformed fonts
+        let text = panic::catch_unwind(|| pdf_extract::extract_text_from_mem(bytes))
+            .map_err(|_| Error::ParsingError("pdf text extraction panicked".to_string()))?
+            .map_err(|err| Error::ParsingError(format!("pdf text extraction failed: {err}")))?;
+
+        let info = pdf_info(&doc);
+        let info_string = |key: &[u8]| {
+            info.and_then(|info| info.get(key).ok())
+                .and_then(|value| value.as_str().ok())
+                .map(decode_pdf_string)
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+
+        Ok(Document {
+            filetype: FileType::Pdf,
+            title: info_string(b"Title"),
+            author: info_string(b"Author"),
+            description: info_string(b"Subject"),
+            modified: info_string(b"ModDate")
+                .or_else(|| info_string(b"CreationDate"))
+                .and_then(|date| parse_pdf_date(&date)),
+            body: paragraphs_html(&text),
+        })
+    }
+
+    fn plain_text(text: &str) -> Self {
+        let title = text
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .filter(|line| line.chars().count() <= MAX_TEXT_TITLE_LEN)
+            .map(str::to_string);
+
+        Document {
+            filetype: FileType::Text,
+            title,
+            author: None,
+            description: None,
+            modified: None,
+            body: paragraphs_html(text),
+        }
+    }
+
+    fn markdown(markdown: &str) -> Self {
+        let mut body = String::new();
+        pulldown_cmark::html::push_html(&mut body, Parser::new(markdown));
+
+        Document {
+            filetype: FileType::Markdown,
+            title: markdown_title(markdown),
+            author: None,
+            description: None,
+            modified: None,
+            body,
+        }
+    }
+
+    pub fn into_html(self, url: &str, canonicalizer: &Canonicalizer) -> Html {
+        let mut head = String::new();
+
+        if let Some(title) = &self.title {
+            head.push_str(&format!(
+                "<title>{}</title>",
+                html_escape::encode_text(title)
+            ));
+        }
+
+        for (attribute, name, value) in [
+            ("name", "author", self.author.clone()),
+            ("property", "og:description", self.description.clone()),
+            (
+                "property",
+                "article:modified_time",
+                self.modified.map(|time| time.to_rfc3339()),
+            ),
+        ] {
+            if let Some(value) = value {
+                head.push_str(&format!(
+                    r#"<meta {attribute}="{name}" content="{}" />"#,
+                    html_escape::encode_double_quoted_attribute(&value)
+                ));
+            }
+        }
+
+        let mut html = Html::parse_without_text(
+            &format!("<html><head>{head}</head><body>{}</body></html>", self.body),
+            url,
+            canonicalizer,
+        );
+        html.filetype = self.filetype;
+
+        html
+    }
+}
+
+fn paragraphs_html(text: &str) -> String {
+    text.replace("\r\n", "\n")
+        .split("\n\n")
+        .map(|paragraph| paragraph.split_whitespace().join(" "))
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| format!("<p>{}</p>", html_escape::encode_text(&paragraph)))
+        .collect()
+}
+
+/// The first level 1 heading, or the first heading of any level if there is none.
+fn markdown_title(markdown: &str) -> Option<String> {
+    let mut first_heading = None;
+    let mut current: Option<(HeadingLevel, String)> = None;
+
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Start(Tag::Heading(level, _, _)) => current = Some((level, String::new())),
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, heading)) = current.as_mut() {
+                    heading.push_str(&text);
+                }
+            }
+            Event::End(Tag::Heading(..)) => {
+                if let Some((level, heading)) = current.take() {
+                    let heading = heading.trim().to_string();
+
+                    if heading.is_empty() {
+                        continue;
+                    }
+
+                    if level == HeadingLevel::H1 {
+                        return Some(heading);
+                    }
+
+                    first_heading.get_or_insert(heading);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    first_heading
+}
+
+fn pdf_info(doc: &lopdf::Document) -> Option<&Dictionary> {
+    match doc.trailer.get(b"Info").ok()? {
+        Object::Reference(id) => doc.get_object(*id).ok()?.as_dict().ok(),
+        object => object.as_dict().ok(),
+    }
+}
+
+/// PDF text strings are either UTF-16BE with a byte order mark or PDFDocEncoding,
+/// which is close enough to latin-1 for metadata (some producers write UTF-8 instead).
+fn decode_pdf_string(bytes: &[u8]) -> String {
+    if let Some(utf16) = bytes.strip_prefix(&[0xfe, 0xff]) {
+        let units: Vec<u16> = utf16
+            .chunks_exact(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect();
+
+        String::from_utf16_lossy(&units)
+    } else if let Ok(utf8) = std::str::from_utf8(bytes) {
+        utf8.to_string()
+    } else {
+        bytes.iter().map(|byte| *byte as char).collect()
+    }
+}
+
+/// Dates in PDF metadata look like `D:20220621193734+02'00'`, where everything
+/// after the day is optional.
+fn parse_pdf_date(date: &str) -> Option<DateTime<FixedOffset>> {
+    let date = date.trim();
+    let date = date.strip_prefix("D:").unwrap_or(date);
+
+    let digits: String = date
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .take(14)
+        .collect();
+
+    if digits.len() < 8 {
+        return None;
+    }
+
+    let naive = NaiveDateTime::parse_from_str(&format!("{digits:0<14}"), "%Y%m%d%H%M%S").ok()?;
+
+    let timezone = &date[digits.len()..];
+    let offset = match timezone.chars().next() {
+        Some(sign @ ('+' | '-')) => {
+            let digits: String = timezone.chars().filter(|c| c.is_ascii_digit()).collect();
+            let hours: i32 = digits.get(..2)?.parse().ok()?;
+            let minutes: i32 = digits
+                .get(2..4)
+                .and_then(|minutes| minutes.parse().ok())
+                .unwrap_or(0);
+            let seconds = hours * 3600 + minutes * 60;
+
+            if sign == '+' {
+                FixedOffset::east_opt(seconds)?
+            } else {
+                FixedOffset::west_opt(seconds)?
+            }
+        }
+        _ => FixedOffset::east_opt(0)?,
+    };
+
+    offset.from_local_datetime(&naive).single()
+}
+
+#[cfg(test)]
+mod tests {
+    use lopdf::{
+        content::{Content, Operation},
+        dictionary, Stream,
+    };
+
+    use super::*;
+
+    fn pdf(title: &str, text: &str) -> Vec<u8> {
+        let mut doc = lopdf::Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Courier",
+        });
+        let resources_id = doc.add_object(dictionary! {
+            "Font" => dictionary! {
+                "F1" => font_id,
+            },
+        });
+
+        let content = Content {
+            operations: vec![
+                Operation::new("BT", vec![]),
+                Operation::new("Tf", vec!["F1".into(), 24.into()]),
+                Operation::new("Td", vec![100.into(), 600.into()]),
+                Operation::new("Tj", vec![Object::string_literal(text)]),
+                Operation::new("ET", vec![]),
+            ],
+        };
+        let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => content_id,
+        });
+
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+                "Resources" => resources_id,
+                "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            }),
+        );
+
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        let info_id = doc.add_object(dictionary! {
+            "Title" => Object::string_literal(title),
+            "Author" => Object::string_literal("Jane Doe"),
+            "CreationDate" => Object::string_literal("D:20220621193734+02'00'"),
+        });
+
+        doc.trailer.set("Root", catalog_id);
+        doc.trailer.set("Info", info_id);
+
+        let mut bytes = Vec::new();
+        doc.save_to(&mut bytes).unwrap();
+
+        bytes
+    }
+
+    #[test]
+    fn detect_filetype() {
+        let url: Url = "https://example.com/paper.pdf".to_string().into();
+        assert_eq!(
+            FileType::detect(Some("application/pdf"), &url),
+            FileType::Pdf
+        );
+        assert_eq!(FileType::detect(None, &url), FileType::Pdf);
+        assert_eq!(FileType::detect(Some("text/html"), &url), FileType::Html);
+
+        let url: Url = "https://example.com/README.md?raw=true".to_string().into();
+        assert_eq!(
+            FileType::detect(Some("text/plain"), &url),
+            FileType::Markdown
+        );
+
+        let url: Url = "https://www.rfc-editor.org/rfc/rfc9110.txt"
+            .to_string()
+            .into();
+        assert_eq!(
+            FileType::detect(Some("text/plain; charset=utf-8"), &url),
+            FileType::Text
+        );
+
+        let url: Url = "https://example.com/".to_string().into();
+        assert_eq!(FileType::detect(None, &url), FileType::Html);
+    }
+
+    #[test]
+    fn filetype_names() {
+        for filetype in [
+            FileType::Html,
+            FileType::Pdf,
+            FileType::Text,
+            FileType::Markdown,
+        ] {
+            assert_eq!(FileType::from_name(filetype.as_str()), Some(filetype));
+        }
+
+        assert_eq!(FileType::from_name("PDF"), Some(FileType::Pdf));
+        assert_eq!(FileType::from_name("markdown"), Some(FileType::Markdown));
+        assert_eq!(FileType::from_name("docx"), None);
+    }
+
+    #[test]
+    fn plain_text() {
+        let text = "Network Working Group\r\n\r\nThis document specifies\nan Internet protocol.\r\n\r\n\r\nSecond paragraph.";
+        let doc = Document::parse(FileType::Text, text.as_bytes(), text).unwrap();
+
+        assert_eq!(doc.title, Some("Network Working Group".to_string()));
+
+        let html = doc.into_html("https://example.com/rfc.txt", &Canonicalizer::default());
+        assert_eq!(html.filetype(), FileType::Text);
+        assert_eq!(html.title(), Some("Network Working Group".to_string()));
+    }
+
+    #[test]
+    fn markdown() {
+        let markdown = "Some intro\n\n## Install\n\nRun `cargo build`.\n\n# The *real* title\n\nSee [the docs](https://docs.example.com).";
+        let doc = Document::parse(FileType::Markdown, markdown.as_bytes(), markdown).unwrap();
+
+        assert_eq!(doc.title, Some("The real title".to_string()));
+
+        let html = doc.into_html("https://example.com/README.md", &Canonicalizer::default());
+        assert_eq!(html.filetype(), FileType::Markdown);
+        assert_eq!(html.title(), Some("The real title".to_string()));
+        assert_eq!(
+            html.links()
+                .into_iter()
+                .map(|link| link.destination.full())
+                .collect::<Vec<_>>(),
+            vec!["https://docs.example.com".to_string()]
+        );
+
+        assert_eq!(
+            markdown_title("## Only a subheading\n\ntext"),
+            Some("Only a subheading".to_string())
+        );
+        assert_eq!(markdown_title("no headings"), None);
+    }
+
+    #[test]
+    fn pdf_document() {
+        let bytes = pdf("A study of search engines", "Search engines are useful");
+        let doc = Document::parse(FileType::Pdf, &bytes, "").unwrap();
+
+        assert_eq!(doc.title, Some("A study of search engines".to_string()));
+        assert_eq!(doc.author, Some("Jane Doe".to_string()));
+        assert_eq!(
+            doc.modified,
+            Some(DateTime::parse_from_rfc3339("2022-06-21T19:37:34+02:00").unwrap())
+        );
+
+        let mut html = doc.into_html("https://example.com/paper.pdf", &Canonicalizer::default());
+        html.parse_text();
+
+        assert_eq!(html.filetype(), FileType::Pdf);
+        assert_eq!(html.title(), Some("A study of search engines".to_string()));
+        assert!(html
+            .clean_text()
+            .unwrap()
+            .contains("Search engines are useful"));
+
+        assert!(Document::parse(FileType::Pdf, b"not a pdf", "").is_err());
+    }
+
+    #[test]
+    fn pdf_dates() {
+        assert_eq!(
+            parse_pdf_date("D:20220621193734Z"),
+            Some(DateTime::parse_from_rfc3339("2022-06-21T19:37:34+00:00").unwrap())
+        );
+        assert_eq!(
+            parse_pdf_date("D:20220621193734-05'30'"),
+            Some(DateTime::parse_from_rfc3339("2022-06-21T19:37:34-05:30").unwrap())
+        );
+        assert_eq!(
+            parse_pdf_date("20220621"),
+            Some(DateTime::parse_from_rfc3339("2022-06-21T00:00:00+00:00").unwrap())
+        );
+        assert_eq!(parse_pdf_date("D:2022"), None);
+        assert_eq!(parse_pdf_date("yesterday"), None);
+    }
+
+    #[test]
+    fn pdf_strings() {
+        assert_eq!(decode_pdf_string(b"plain"), "plain");
+        assert_eq!(
+            decode_pdf_string(&[0xfe, 0xff, 0x00, 0x68, 0x00, 0xe9]),
+            "h\u{e9}"
+        );
+        assert_eq!(decode_pdf_string(&[0x63, 0x61, 0x66, 0xe9]), "caf\u{e9}");
+    }
+}
@@ -0,0 +1,162 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::collections::HashSet;
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use whatlang::Lang;
+
+use super::{
+    just_text::{JustText, Paragraph},
+    readability::Readability,
+};
+
+/// Finds the main content of a page among its paragraphs, leaving out
+/// navigation, sidebars, footers and other boilerplate.
+pub trait ContentExtractor {
+    /// The paragraphs of the main content in document order.
+    fn main_content(&self, paragraphs: &[Paragraph], lang: &Lang) -> Vec<Paragraph>;
+
+    fn extract_from_paragraphs(&self, paragraphs: &[Paragraph], lang: &Lang) -> String {
+        self.main_content(paragraphs, lang)
+            .iter()
+            .flat_map(|paragraph| paragraph.text.split_whitespace())
+            .join(" ")
+    }
+
+    fn main_content_anchors(&self, paragraphs: &[Paragraph], lang: &Lang) -> HashSet<usize> {
+        self.main_content(paragraphs, lang)
+            .into_iter()
+            .flat_map(|paragraph| paragraph.anchors)
+            .collect()
+    }
+}
+
+/// The content extractor used for an index. Set with `content_extractor`
+/// in the indexer config.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Extractor {
+    #[default]
+    JustText,
+    Readability,
+}
+
+impl ContentExtractor for Extractor {
+    fn main_content(&self, paragraphs: &[Paragraph], lang: &Lang) -> Vec<Paragraph> {
+        match self {
+            Extractor::JustText => JustText::default().main_content(paragraphs, lang),
+            Extractor::Readability => Readability::default().main_content(paragraphs, lang),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::Path};
+
+    use super::*;
+    use crate::webpage::{Canonicalizer, Html};
+
+    #[derive(Deserialize)]
+    struct Labels {
+        content: Vec<String>,
+        boilerplate: Vec<String>,
+    }
+
+    /// The labelled snippets an extractor kept, and the ones it got wrong for the assertion messages.
+    #[derive(Default, Debug)]
+    struct Evaluation {
+        content_found: usize,
+        content_total: usize,
+        boilerplate_found: usize,
+        missed_content: Vec<String>,
+        kept_boilerplate: Vec<String>,
+    }
+
+    impl Evaluation {
+        fn recall(&self) -> f64 {
+            self.content_found as f64 / self.content_total as f64
+        }
+
+        fn precision(&self) -> f64 {
+            self.content_found as f64 / (self.content_found + self.boilerplate_found) as f64
+        }
+    }
+
+    fn evaluate(extractor: Extractor) -> Evaluation {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testcases/parsing");
+        let labels: HashMap<String, Labels> =
+            serde_json::from_str(&std::fs::read_to_string(dir.join("labels.json")).unwrap())
+                .unwrap();
+
+        let mut res = Evaluation::default();
+
+        for (page, labels) in labels {
+            let raw = std::fs::read_to_string(dir.join(&page)).unwrap();
+            let mut html = Html::parse_without_text(
+                &raw,
+                "https://www.example.com/",
+                &Canonicalizer::default(),
+            );
+            html.set_extractor(extractor);
+            html.parse_text();
+            let text = html.clean_text().unwrap_or_default();
+
+            for snippet in labels.content {
+                if text.contains(snippet.as_str()) {
+                    res.content_found += 1;
+                } else {
+                    res.missed_content.push(format!("{page}: {snippet}"));
+                }
+                res.content_total += 1;
+            }
+
+            for snippet in labels.boilerplate {
+                if text.contains(snippet.as_str()) {
+                    res.boilerplate_found += 1;
+                    res.kept_boilerplate.push(format!("{page}: {snippet}"));
+                }
+            }
+        }
+
+        res
+    }
+
+    #[test]
+    fn labelled_pages() {
+        let just_text = evaluate(Extractor::JustText);
+        let readability = evaluate(Extractor::Readability);
+
+        println!(
+            "just_text: precision {:.2} recall {:.2}",
+            just_text.precision(),
+            just_text.recall()
+        );
+        println!(
+            "readability: precision {:.2} recall {:.2}",
+            readability.precision(),
+            readability.recall()
+        );
+
+        // the pages are real crawled pages with little running text, so neither extractor is perfect
+        assert!(just_text.recall() >= 0.25, "{just_text:?}");
+        assert!(just_text.precision() >= 0.5, "{just_text:?}");
+
+        assert!(readability.recall() >= 0.5, "{readability:?}");
+        assert!(readability.precision() >= 0.5, "{readability:?}");
+    }
+}
@@ -19,7 +19,7 @@ use std::collections::{HashMap, HashSet};
 use kuchiki::{iter::NodeEdge, NodeRef};
 use whatlang::Lang;
 
-use super::Preprocessor;
+use super::{extractor::ContentExtractor, Preprocessor};
 
 // implementation of the JustText algorithm described in this thesis: https://is.muni.cz/th/45523/fi_d/phdthesis.pdf
 // reference implementation: https://github.com/miso-belica/jusText/blob/main/justext/core.py
@@ -147,9 +147,13 @@ impl Default for JustText {
 pub struct Paragraph {
     is_heading: bool,
     tags_count: usize,
-    chars_count_in_links: usize,
+    pub(super) chars_count_in_links: usize,
     pub text: String,
     last_was_whitespace: bool,
+    /// Document order index of the `<a>` elements that start inside the paragraph.
+    pub(super) anchors: Vec<usize>,
+    /// The innermost block element containing the paragraph.
+    pub(super) container: Option<NodeRef>,
 }
 
 impl Paragraph {
@@ -160,6 +164,8 @@ impl Paragraph {
             is_heading: false,
             text: String::new(),
             last_was_whitespace: false,
+            anchors: Vec::new(),
+            container: None,
         }
     }
 
@@ -183,7 +189,7 @@ impl Paragraph {
     }
 
     #[allow(clippy::cast_precision_loss)]
-    fn link_density(&self) -> f64 {
+    pub(super) fn link_density(&self) -> f64 {
         self.chars_count_in_links as f64 / self.text.len() as f64
     }
 }
@@ -207,8 +213,16 @@ impl JustText {
         let mut paragraph = Paragraph::new();
 
         let mut heading_count = 0;
+        let mut num_anchors = 0;
+        let mut open_blocks: Vec<NodeRef> = Vec::new();
 
         for edge in root.traverse() {
+            // anchors are counted before anything is removed, so the index matches `Html::links`
+            let anchor_idx = num_anchors;
+            if is_anchor_start(&edge) {
+                num_anchors += 1;
+            }
+
             preprocessor.update(&edge);
             if preprocessor.is_inside_removed() {
                 continue;
@@ -271,16 +285,22 @@ impl JustText {
                             paragraph.is_heading = heading_count > 0;
 
                             if paragraph.contains_text() {
+                                paragraph.container = open_blocks.last().cloned();
                                 res.push(paragraph);
                             }
 
                             paragraph = Paragraph::new();
+
+                            if name != "br" {
+                                open_blocks.push(node.clone());
+                            }
                         } else {
                             br = name == "br";
                             if br {
                                 paragraph.append_text(" ");
                             } else if name == "a" {
                                 link = true;
+                                paragraph.anchors.push(anchor_idx);
                             }
                             paragraph.tags_count += 1;
                         }
@@ -314,10 +334,16 @@ impl JustText {
                             | "tfoot" | "th" | "thead" | "tr" | "ul" | "li" | "h1" | "h2"
                             | "h3" | "h4" | "h5" | "h6" => {
                                 if paragraph.contains_text() {
+                                    paragraph.container = open_blocks.last().cloned();
                                     res.push(paragraph);
                                 }
 
                                 paragraph = Paragraph::new();
+
+                                // removed elements (e.g. forms) are never pushed
+                                if open_blocks.last() == Some(&node) {
+                                    open_blocks.pop();
+                                }
                             }
                             _ => {}
                         }
@@ -331,6 +357,7 @@ impl JustText {
         }
 
         if paragraph.contains_text() {
+            paragraph.container = open_blocks.last().cloned();
             res.push(paragraph);
         }
 
@@ -566,8 +593,22 @@ impl JustText {
         Classification::Intermediate(IntermediateClassification::Bad)
     }
 
-    fn extract_text(paragraphs: Vec<ClassifiedParagraph>) -> String {
-        paragraphs
+    fn classify(&self, paragraphs: &[Paragraph], lang: &Lang) -> Vec<ClassifiedParagraph> {
+        let mut classified = self
+            .initial_classification(paragraphs, lang)
+            .into_iter()
+            .filter(|par| par.paragraph.text.chars().any(|c| !c.is_whitespace()))
+            .collect();
+
+        self.contextual_classification(&mut classified);
+
+        classified
+    }
+}
+
+impl ContentExtractor for JustText {
+    fn main_content(&self, paragraphs: &[Paragraph], lang: &Lang) -> Vec<Paragraph> {
+        self.classify(paragraphs, lang)
             .into_iter()
             .filter(|paragraph| {
                 matches!(
@@ -575,26 +616,21 @@ impl JustText {
                     Classification::Final(FinalClassification::Good)
                 )
             })
-            .map(|paragraph| paragraph.paragraph.text)
-            .collect::<Vec<_>>()
-            .join(" ")
+            .map(|paragraph| paragraph.paragraph)
+            .collect()
     }
+}
 
-    pub fn extract_from_paragraphs(&self, paragraphs: &[Paragraph], lang: &Lang) -> String {
-        let mut classified = self
-            .initial_classification(paragraphs, lang)
-            .into_iter()
-            .filter(|par| par.paragraph.text.chars().any(|c| !c.is_whitespace()))
-            .collect();
-
-        self.contextual_classification(&mut classified);
-
-        Self::extract_text(classified)
-            .split_whitespace()
-            .collect::<Vec<_>>()
-            .join(" ")
+pub(crate) fn is_anchor_start(edge: &NodeEdge<NodeRef>) -> bool {
+    match edge {
+        NodeEdge::Start(node) => node
+            .as_element()
+            .map(|element| &element.name.local == "a")
+            .unwrap_or(false),
+        NodeEdge::End(_) => false,
     }
 }
+
 impl Classification {
     fn is_short(&self) -> bool {
         matches!(
@@ -617,6 +653,7 @@ mod tests {
                 chars_count_in_links: 0,
                 text: "0123456789".repeat(2),
                 last_was_whitespace: false,
+                ..Paragraph::new()
             },
             Paragraph {
                 is_heading: false,
@@ -624,6 +661,7 @@ mod tests {
                 chars_count_in_links: 20,
                 text: "0123456789".repeat(2),
                 last_was_whitespace: false,
+                ..Paragraph::new()
             },
             Paragraph {
                 is_heading: false,
@@ -631,6 +669,7 @@ mod tests {
                 chars_count_in_links: 40,
                 text: "0123456789".repeat(8),
                 last_was_whitespace: false,
+                ..Paragraph::new()
             },
             Paragraph {
                 is_heading: false,
@@ -638,6 +677,7 @@ mod tests {
                 chars_count_in_links: 39,
                 text: "0123456789".repeat(8),
                 last_was_whitespace: false,
+                ..Paragraph::new()
             },
             Paragraph {
                 is_heading: false,
@@ -645,6 +685,7 @@ mod tests {
                 chars_count_in_links: 41,
                 text: "0123456789".repeat(8),
                 last_was_whitespace: false,
+                ..Paragraph::new()
             },
         ];
 
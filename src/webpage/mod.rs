@@ -14,9 +14,11 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 use crate::{
+    feed::{FeedKind, FeedLink},
     prehashed::{hash, split_u128},
     schema::{FastField, TextField},
-    schema_org::SchemaOrg,
+    schema_org::{self, SchemaOrg},
+    simhash::simhash,
     tokenizer, Error, Result,
 };
 use chrono::{DateTime, FixedOffset, Utc};
@@ -33,15 +35,22 @@ use tantivy::tokenizer::{PreTokenizedString, Tokenizer};
 use uuid::Uuid;
 use whatlang::Lang;
 
+mod document;
+mod extractor;
 mod just_text;
+mod public_suffix;
+mod readability;
 pub mod region;
 mod url;
 
 use crate::schema::{Field, ALL_FIELDS, CENTRALITY_SCALING};
 
-pub use self::url::Url;
+pub use self::document::{Document, FileType};
+pub use self::extractor::Extractor;
+pub use self::url::{default_tracking_parameters, Canonicalizer, Url};
 use self::{
-    just_text::{JustText, Paragraph},
+    extractor::ContentExtractor,
+    just_text::{is_anchor_start, JustText, Paragraph},
     region::Region,
 };
 
@@ -125,6 +134,10 @@ pub struct Webpage {
     pub backlinks: Vec<Link>,
     pub host_centrality: f64,
     pub page_centrality: f64,
+    pub host_pagerank: f64,
+    pub page_pagerank: f64,
+    pub host_trustrank: f64,
+    pub host_antitrustrank: f64,
     pub fetch_time_ms: u64,
     pub pre_computed_score: f64,
     pub primary_image: Option<StoredPrimaryImage>,
@@ -140,14 +153,37 @@ impl Webpage {
             backlinks: Vec::new(),
             host_centrality: 0.0,
             page_centrality: 0.0,
+            host_pagerank: 0.0,
+            page_pagerank: 0.0,
+            host_trustrank: 0.0,
+            host_antitrustrank: 0.0,
             fetch_time_ms: 0,
             pre_computed_score: 0.0,
             primary_image: None,
         }
     }
 
+    /// Text of the endorsing backlinks. Links outside the main content (e.g. navigation that is
+    /// repeated on every page of the linking site) only count once per distinct text.
+    fn backlink_text(&self) -> String {
+        let mut seen_non_main = HashSet::new();
+
+        itertools::intersperse(
+            self.backlinks
+                .iter()
+                .filter(|link| link.rel.is_endorsement())
+                .filter(|link| {
+                    link.context == LinkContext::Main || seen_non_main.insert(link.text.as_str())
+                })
+                .map(|link| link.text.as_str()),
+            "\n",
+        )
+        .collect()
+    }
+
     pub fn into_tantivy(self, schema: &tantivy::schema::Schema) -> Result<tantivy::Document> {
         let region = Region::guess_from(&self);
+        let backlink_text = self.backlink_text();
 
         let mut doc = self.html.into_tantivy(schema)?;
 
@@ -167,11 +203,7 @@ impl Webpage {
             );
         }
 
-        let backlink_text: String = itertools::intersperse(
-            self.backlinks.into_iter().map(|link| link.text),
-            "\n".to_string(),
-        )
-        .collect();
+        let backlink_text = self.backlink_text();
 
         doc.add_text(
             schema
@@ -194,6 +226,34 @@ impl Webpage {
             (self.page_centrality * CENTRALITY_SCALING as f64) as u64,
         );
 
+        doc.add_u64(
+            schema
+                .get_field(Field::Fast(FastField::HostPageRank).name())
+                .expect("Failed to get host_pagerank field"),
+            (self.host_pagerank * CENTRALITY_SCALING as f64) as u64,
+        );
+
+        doc.add_u64(
+            schema
+                .get_field(Field::Fast(FastField::PagePageRank).name())
+                .expect("Failed to get page_pagerank field"),
+            (self.page_pagerank * CENTRALITY_SCALING as f64) as u64,
+        );
+
+        doc.add_u64(
+            schema
+                .get_field(Field::Fast(FastField::HostTrustRank).name())
+                .expect("Failed to get host_trustrank field"),
+            (self.host_trustrank * CENTRALITY_SCALING as f64) as u64,
+        );
+
+        doc.add_u64(
+            schema
+                .get_field(Field::Fast(FastField::HostAntiTrustRank).name())
+                .expect("Failed to get host_antitrustrank field"),
+            (self.host_antitrustrank * CENTRALITY_SCALING as f64) as u64,
+        );
+
         doc.add_u64(
             schema
                 .get_field(Field::Fast(FastField::FetchTimeMs).name())
@@ -258,21 +318,26 @@ pub struct Html {
     all_text: Option<String>,
     clean_text: Option<String>,
     lang: Option<Lang>,
+    filetype: FileType,
+    extractor: Extractor,
+    canonicalizer: Canonicalizer,
 }
 
 impl Html {
+    /// Parse the page with the default [`Canonicalizer`].
     pub fn parse(html: &str, url: &str) -> Self {
-        let mut html = Self::parse_without_text(html, url);
+        let mut html = Self::parse_without_text(html, url, &Canonicalizer::default());
 
         html.parse_text();
 
         html
     }
 
-    pub fn parse_without_text(html: &str, url: &str) -> Self {
+    /// The url of the page and the urls it points to as canonical are canonicalised
+    /// with `canonicalizer`.
+    pub fn parse_without_text(html: &str, url: &str, canonicalizer: &Canonicalizer) -> Self {
         let root = kuchiki::parse_html().one(html);
-        let mut url: Url = url.to_string().into();
-        url = url.full_without_id_tags().into();
+        let url = canonicalizer.canonicalize(&Url::from(url.to_string()));
 
         Self {
             root,
@@ -280,35 +345,79 @@ impl Html {
             clean_text: None,
             lang: None,
             url,
+            filetype: FileType::Html,
+            extractor: Extractor::default(),
+            canonicalizer: canonicalizer.clone(),
         }
     }
 
     pub fn parse_text(&mut self) {
         let paragraphs = JustText::paragraphs(self.root.clone());
-
-        self.lang = paragraphs
+        let longest_paragraph = paragraphs
             .iter()
-            .max_by_key(|paragraph| paragraph.text.len())
-            .and_then(|paragraph| {
-                whatlang::detect(&paragraph.text).and_then(|info| {
-                    if info.is_reliable() && info.confidence() > 0.95 {
-                        Some(info.lang())
-                    } else {
-                        None
-                    }
-                })
-            });
+            .max_by_key(|paragraph| paragraph.text.len());
+
+        self.lang = longest_paragraph.and_then(|paragraph| {
+            whatlang::detect(&paragraph.text).and_then(|info| {
+                if info.is_reliable() && info.confidence() > 0.95 {
+                    Some(info.lang())
+                } else {
+                    None
+                }
+            })
+        });
 
         self.all_text = Html::calculate_all_text(&paragraphs, &self.lang.unwrap_or(Lang::Eng));
-        self.clean_text = Html::calculate_clean_text(&paragraphs, &self.lang.unwrap_or(Lang::Eng));
+        self.clean_text = match self.filetype {
+            FileType::Html => Html::calculate_clean_text(
+                &self.extractor,
+                &paragraphs,
+                &self.lang.unwrap_or(Lang::Eng),
+            ),
+            // documents have no boilerplate (menus, footers etc.) to remove
+            _ => self.all_text.clone(),
+        };
+    }
+
+    pub fn filetype(&self) -> FileType {
+        self.filetype
+    }
+
+    /// Set the extractor used to find the main content. Must be called before `parse_text`.
+    pub fn set_extractor(&mut self, extractor: Extractor) {
+        self.extractor = extractor;
+    }
+
+    fn link_section(element: &kuchiki::ElementData) -> Option<LinkContext> {
+        let role = element.attributes.borrow().get("role").map(str::to_string);
+
+        match (&*element.name.local, role.as_deref()) {
+            ("footer", _) | (_, Some("contentinfo")) => Some(LinkContext::Footer),
+            ("nav" | "header", _) | (_, Some("navigation" | "banner")) => {
+                Some(LinkContext::Navigation)
+            }
+            _ => None,
+        }
     }
 
     pub fn links(&self) -> Vec<Link> {
+        let main_content_anchors = self.extractor.main_content_anchors(
+            &JustText::paragraphs(self.root.clone()),
+            &self.lang.unwrap_or(Lang::Eng),
+        );
+
         let mut links = Vec::new();
         let mut open_links = Vec::new();
+        let mut open_sections = Vec::new();
+        let mut num_anchors = 0;
         let mut preprocessor = Preprocessor::new(["script", "style", "head", "noscript"]);
 
         for edge in self.root.traverse() {
+            let anchor_idx = num_anchors;
+            if is_anchor_start(&edge) {
+                num_anchors += 1;
+            }
+
             preprocessor.update(&edge);
             if preprocessor.is_inside_removed() {
                 continue;
@@ -317,20 +426,44 @@ impl Html {
             match edge {
                 NodeEdge::Start(node) => {
                     if let Some(element) = node.as_element() {
+                        if let Some(section) = Html::link_section(element) {
+                            open_sections.push(section);
+                        }
+
                         if &element.name.local == "a" {
-                            open_links.push((String::new(), element.attributes.clone()));
+                            let context = if open_sections.contains(&LinkContext::Footer) {
+                                LinkContext::Footer
+                            } else if !open_sections.is_empty() {
+                                LinkContext::Navigation
+                            } else if main_content_anchors.contains(&anchor_idx) {
+                                LinkContext::Main
+                            } else {
+                                LinkContext::Other
+                            };
+
+                            open_links.push((String::new(), element.attributes.clone(), context));
                         }
                     }
                 }
                 NodeEdge::End(node) => {
                     if let Some(element) = node.as_element() {
+                        if Html::link_section(element).is_some() {
+                            open_sections.pop();
+                        }
+
                         if &element.name.local == "a" {
-                            if let Some((text, attributes)) = open_links.pop() {
+                            if let Some((text, attributes, context)) = open_links.pop() {
                                 if let Some(dest) = attributes.borrow().get("href") {
                                     links.push(Link {
                                         source: self.url.clone(),
                                         destination: dest.to_string().into(),
                                         text: text.trim().to_string(),
+                                        rel: attributes
+                                            .borrow()
+                                            .get("rel")
+                                            .map(LinkRel::parse)
+                                            .unwrap_or_default(),
+                                        context,
                                     });
                                 }
                             }
@@ -342,7 +475,7 @@ impl Html {
                         let text = raw_text.trim();
 
                         if !text.is_empty() {
-                            for (link_text, _) in &mut open_links {
+                            for (link_text, _, _) in &mut open_links {
                                 link_text.push('\n');
                                 link_text.push_str(text);
                             }
@@ -355,6 +488,125 @@ impl Html {
         links
     }
 
+    fn resolve(&self, link: &str) -> Url {
+        let mut url: Url = link.to_string().into();
+
+        if !url.is_full_path() {
+            url.prefix_with(&self.url);
+        }
+
+        url.full_without_id_tags().into()
+    }
+
+    /// The url from `<link rel="canonical">` if the page has one.
+    pub fn canonical_url(&self) -> Option<Url> {
+        self.root
+            .select("link")
+            .unwrap()
+            .filter(|node| {
+                node.attributes
+                    .borrow()
+                    .get("rel")
+                    .map(|rel| rel.eq_ignore_ascii_case("canonical"))
+                    .unwrap_or(false)
+            })
+            .find_map(|node| {
+                node.attributes
+                    .borrow()
+                    .get("href")
+                    .map(|href| href.trim().to_string())
+            })
+            .filter(|href| !href.is_empty())
+            .map(|href| self.canonicalizer.canonicalize(&self.resolve(&href)))
+    }
+
+    /// Directives from `<meta name="robots">`.
+    pub fn robots(&self) -> RobotsMeta {
+        self.root
+            .select("meta")
+            .unwrap()
+            .filter_map(|node| {
+                let attributes = node.attributes.borrow();
+                let name = attributes.get("name")?;
+
+                if name.eq_ignore_ascii_case("robots") || name.eq_ignore_ascii_case("cuely") {
+                    attributes.get("content").map(RobotsMeta::parse)
+                } else {
+                    None
+                }
+            })
+            .fold(RobotsMeta::default(), RobotsMeta::merge)
+    }
+
+    /// Language versions of the page from `<link rel="alternate" hreflang="..">`.
+    pub fn hreflang(&self) -> Vec<HrefLang> {
+        self.root
+            .select("link")
+            .unwrap()
+            .filter_map(|node| {
+                let attributes = node.attributes.borrow();
+
+                if !attributes
+                    .get("rel")
+                    .map(|rel| rel.eq_ignore_ascii_case("alternate"))
+                    .unwrap_or(false)
+                {
+                    return None;
+                }
+
+                let lang = attributes.get("hreflang")?.trim().to_ascii_lowercase();
+                let href = attributes.get("href")?.trim();
+
+                if lang.is_empty() || href.is_empty() {
+                    return None;
+                }
+
+                Some(HrefLang {
+                    lang,
+                    url: self.canonicalizer.canonicalize(&self.resolve(href)).full(),
+                })
+            })
+            .collect()
+    }
+
+    /// Feeds advertised by the page with `<link rel="alternate" type="application/rss+xml">`.
+    pub fn feeds(&self) -> Vec<FeedLink> {
+        self.root
+            .select("link")
+            .unwrap()
+            .filter_map(|node| {
+                let attributes = node.attributes.borrow();
+
+                if !attributes
+                    .get("rel")
+                    .map(|rel| {
+                        rel.split_ascii_whitespace()
+                            .any(|rel| rel.eq_ignore_ascii_case("alternate"))
+                    })
+                    .unwrap_or(false)
+                {
+                    return None;
+                }
+
+                let kind = FeedKind::from_mime(attributes.get("type")?)?;
+                let href = attributes.get("href")?.trim();
+
+                if href.is_empty() {
+                    return None;
+                }
+
+                Some(FeedLink {
+                    url: self.resolve(href),
+                    kind,
+                    title: attributes
+                        .get("title")
+                        .map(|title| title.trim().to_string())
+                        .filter(|title| !title.is_empty()),
+                })
+            })
+            .collect()
+    }
+
     pub fn favicon(&self) -> Option<FaviconLink> {
         for node in self.root.select("link").unwrap() {
             if !matches!(node.attributes.borrow().get("rel"), Some("icon")) {
@@ -394,8 +646,12 @@ impl Html {
         None
     }
 
-    fn calculate_clean_text(paragraphs: &[Paragraph], lang: &Lang) -> Option<String> {
-        let text = JustText::default().extract_from_paragraphs(paragraphs, lang);
+    fn calculate_clean_text(
+        extractor: &Extractor,
+        paragraphs: &[Paragraph],
+        lang: &Lang,
+    ) -> Option<String> {
+        let text = extractor.extract_from_paragraphs(paragraphs, lang);
 
         if text.is_empty() {
             None
@@ -447,6 +703,10 @@ impl Html {
         &self.url
     }
 
+    pub fn set_url(&mut self, url: Url) {
+        self.url = url;
+    }
+
     pub fn metadata(&self) -> Vec<Meta> {
         let mut metas = Vec::new();
 
@@ -531,6 +791,7 @@ impl Html {
         let clean_text = self.pretokenize_clean_text();
         let url = self.pretokenize_url();
         let description = self.pretokenize_description();
+        let schema_org = self.schema_org();
 
         for field in &ALL_FIELDS {
             let tantivy_field = schema
@@ -574,6 +835,9 @@ impl Html {
                 Field::Text(TextField::Url) => {
                     doc.add_pre_tokenized_text(tantivy_field, url.clone())
                 }
+                Field::Text(TextField::UrlNoTokenizer) => {
+                    doc.add_text(tantivy_field, self.url().full())
+                }
                 Field::Text(TextField::Site) => doc.add_text(tantivy_field, self.url().site()),
                 Field::Text(TextField::Domain) => doc.add_text(tantivy_field, self.url().domain()),
                 Field::Text(TextField::SiteNoTokenizer) => doc.add_pre_tokenized_text(
@@ -638,6 +902,54 @@ impl Html {
                 Field::Text(TextField::AllBody) => {
                     doc.add_pre_tokenized_text(tantivy_field, all_text.clone())
                 }
+                Field::Text(TextField::HrefLang) => {
+                    doc.add_bytes(tantivy_field, bincode::serialize(&self.hreflang()).unwrap())
+                }
+                Field::Text(TextField::SchemaOrgJson) => {
+                    doc.add_bytes(tantivy_field, serde_json::to_vec(&schema_org).unwrap())
+                }
+                Field::Text(TextField::SchemaOrgType) => {
+                    for schema_type in schema_org
+                        .iter()
+                        .map(|schema| schema.type_name().to_ascii_lowercase())
+                        .unique()
+                    {
+                        doc.add_text(tantivy_field, schema_type);
+                    }
+                }
+                Field::Text(TextField::FileType) => {
+                    doc.add_text(tantivy_field, self.filetype().as_str())
+                }
+                Field::Text(TextField::Headings) => doc.add_pre_tokenized_text(
+                    tantivy_field,
+                    self.pretokenize_string(
+                        self.headings()
+                            .into_iter()
+                            .map(|heading| heading.text)
+                            .join("\n"),
+                    ),
+                ),
+                Field::Text(TextField::MainHeadings) => doc.add_pre_tokenized_text(
+                    tantivy_field,
+                    self.pretokenize_string(
+                        self.headings()
+                            .into_iter()
+                            .filter(|heading| heading.level == 1)
+                            .map(|heading| heading.text)
+                            .join("\n"),
+                    ),
+                ),
+                Field::Text(TextField::Code) => {
+                    doc.add_text(tantivy_field, self.code_blocks().join("\n"))
+                }
+                Field::Text(TextField::Tables) => doc.add_pre_tokenized_text(
+                    tantivy_field,
+                    self.pretokenize_string(self.table_text().unwrap_or_default()),
+                ),
+                Field::Text(TextField::Diacritics) => doc.add_text(
+                    tantivy_field,
+                    format!("{}\n{}", title.text, clean_text.text),
+                ),
                 Field::Fast(FastField::IsHomepage) => {
                     doc.add_u64(tantivy_field, self.url().is_homepage().into());
                 }
@@ -650,16 +962,16 @@ impl Html {
                     doc.add_u64(tantivy_field, self.trackers().len() as u64)
                 }
                 Field::Fast(FastField::NumUrlTokens) => {
-                    doc.add_u64(tantivy_field, url.tokens.len() as u64)
+                    doc.add_u64(tantivy_field, num_positions(&url))
                 }
                 Field::Fast(FastField::NumTitleTokens) => {
-                    doc.add_u64(tantivy_field, title.tokens.len() as u64)
+                    doc.add_u64(tantivy_field, num_positions(&title))
                 }
                 Field::Fast(FastField::NumCleanBodyTokens) => {
-                    doc.add_u64(tantivy_field, clean_text.tokens.len() as u64)
+                    doc.add_u64(tantivy_field, num_positions(&clean_text))
                 }
                 Field::Fast(FastField::NumDescriptionTokens) => {
-                    doc.add_u64(tantivy_field, description.tokens.len() as u64)
+                    doc.add_u64(tantivy_field, num_positions(&description))
                 }
                 Field::Fast(FastField::SiteHash) => {
                     let hash = hash(self.url().site()).0;
@@ -691,9 +1003,17 @@ impl Html {
                     doc.add_u64(tantivy_field, u64s[0]);
                     doc.add_u64(tantivy_field, u64s[1]);
                 }
+                Field::Fast(FastField::SimHash) => doc.add_u64(
+                    tantivy_field,
+                    simhash(&self.clean_text().unwrap_or_default()),
+                ),
                 Field::Text(TextField::BacklinkText)
                 | Field::Fast(FastField::HostCentrality)
                 | Field::Fast(FastField::PageCentrality)
+                | Field::Fast(FastField::HostPageRank)
+                | Field::Fast(FastField::PagePageRank)
+                | Field::Fast(FastField::HostTrustRank)
+                | Field::Fast(FastField::HostAntiTrustRank)
                 | Field::Fast(FastField::FetchTimeMs)
                 | Field::Fast(FastField::PreComputedScore)
                 | Field::Fast(FastField::Region)
@@ -731,21 +1051,25 @@ impl Html {
         scripts
     }
 
+    /// Schema.org data from JSON-LD, microdata and RDFa.
     pub fn schema_org(&self) -> Vec<SchemaOrg> {
-        let mut schemas = Vec::new();
+        let mut items = Vec::new();
 
-        for schema in self.scripts().into_iter().filter(|script| {
+        for script in self.scripts().into_iter().filter(|script| {
             matches!(
                 script.attributes.get("type").map(String::as_str),
                 Some("application/ld+json")
             )
         }) {
-            if let Ok(schema) = serde_json::from_str(&schema.content) {
-                schemas.push(schema);
+            if let Ok(json) = serde_json::from_str(&script.content) {
+                items.extend(schema_org::Item::from_json_ld(&json));
             }
         }
 
-        schemas
+        items.extend(schema_org::parse_microdata(&self.root));
+        items.extend(schema_org::parse_rdfa(&self.root));
+
+        SchemaOrg::from_items(&items)
     }
 
     pub fn trackers(&self) -> Vec<Url> {
@@ -892,6 +1216,70 @@ impl Html {
             .and_then(|metadata| metadata.get("content").cloned())
     }
 
+    /// The `<h1>` to `<h3>` headings of the page in document order.
+    pub fn headings(&self) -> Vec<Heading> {
+        self.root
+            .select("h1, h2, h3")
+            .unwrap()
+            .filter_map(|node| {
+                let level = match &*node.name.local {
+                    "h1" => 1,
+                    "h2" => 2,
+                    _ => 3,
+                };
+                let text = node.text_contents().split_whitespace().join(" ");
+
+                if text.is_empty() {
+                    None
+                } else {
+                    Some(Heading { level, text })
+                }
+            })
+            .collect()
+    }
+
+    /// The text of `<pre>` blocks and of `<code>` elements that are not inside one.
+    /// Whitespace is kept as is, since it is often significant in code.
+    pub fn code_blocks(&self) -> Vec<String> {
+        self.root
+            .select("pre, code")
+            .unwrap()
+            .filter(|node| {
+                !node.as_node().ancestors().any(|ancestor| {
+                    matches!(ancestor.as_element(), Some(element) if &*element.name.local == "pre")
+                })
+            })
+            .map(|node| node.text_contents().trim().to_string())
+            .filter(|code| !code.is_empty())
+            .collect()
+    }
+
+    /// The text of all table cells, one row per line.
+    pub fn table_text(&self) -> Option<String> {
+        let text = self
+            .root
+            .select("tr")
+            .unwrap()
+            .map(|row| {
+                row.as_node()
+                    .children()
+                    .filter(|cell| {
+                        matches!(cell.as_element(), Some(element) if matches!(&*element.name.local, "td" | "th"))
+                    })
+                    .map(|cell| cell.text_contents().split_whitespace().join(" "))
+                    .filter(|cell| !cell.is_empty())
+                    .join(" ")
+            })
+            .filter(|row| !row.is_empty())
+            .join("\n");
+
+        if text.is_empty() {
+            None
+        } else {
+            Some(text)
+        }
+    }
+
     pub fn og_title(&self) -> Option<String> {
         self.metadata()
             .into_iter()
@@ -939,11 +1327,145 @@ fn stem_tokens(tokens: &mut [tantivy::tokenizer::Token], lang: Lang) {
     }
 }
 
+/// Number of positions in the pretokenized text. Chinese, Japanese, Korean and Thai characters
+/// share their position with the bigram they start, so there can be more tokens than positions.
+fn num_positions(text: &PreTokenizedString) -> u64 {
+    text.tokens
+        .last()
+        .map_or(0, |token| token.position as u64 + 1)
+}
+
+/// Directives from `<meta name="robots">` or the `X-Robots-Tag` header.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RobotsMeta {
+    pub noindex: bool,
+    pub nofollow: bool,
+}
+
+impl RobotsMeta {
+    /// Parse a comma separated list of directives. Directives for a specific
+    /// user agent (`googlebot: noindex`) are ignored unless they are meant for us.
+    pub fn parse(directives: &str) -> Self {
+        let mut res = Self::default();
+        let mut applies = true;
+
+        for directive in directives.split(',') {
+            let directive = directive.trim().to_ascii_lowercase();
+
+            let directive = match directive.split_once(':') {
+                Some((agent, rest))
+                    if !matches!(
+                        agent.trim(),
+                        "unavailable_after"
+                            | "max-snippet"
+                            | "max-image-preview"
+                            | "max-video-preview"
+                    ) =>
+                {
+                    applies = matches!(agent.trim(), "cuely" | "*");
+                    rest.trim()
+                }
+                _ => directive.as_str(),
+            };
+
+            if !applies {
+                continue;
+            }
+
+            match directive {
+                "noindex" => res.noindex = true,
+                "nofollow" => res.nofollow = true,
+                "none" => {
+                    res.noindex = true;
+                    res.nofollow = true;
+                }
+                _ => {}
+            }
+        }
+
+        res
+    }
+
+    pub fn merge(self, other: RobotsMeta) -> Self {
+        Self {
+            noindex: self.noindex || other.noindex,
+            nofollow: self.nofollow || other.nofollow,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading {
+    /// 1 for `<h1>`, 2 for `<h2>` and 3 for `<h3>`.
+    pub level: u8,
+    pub text: String,
+}
+
+/// An alternate language version of a page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HrefLang {
+    /// Lowercase language tag, e.g. `en`, `da-dk` or `x-default`.
+    pub lang: String,
+    pub url: String,
+}
+
+/// The `rel` values of a link that tell us the linking page does not vouch for the destination.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct LinkRel {
+    pub nofollow: bool,
+    pub sponsored: bool,
+    pub ugc: bool,
+}
+
+impl LinkRel {
+    pub fn parse(rel: &str) -> Self {
+        let mut res = Self::default();
+
+        for value in rel.split_ascii_whitespace() {
+            match value.to_ascii_lowercase().as_str() {
+                "nofollow" => res.nofollow = true,
+                "sponsored" => res.sponsored = true,
+                "ugc" => res.ugc = true,
+                _ => {}
+            }
+        }
+
+        res
+    }
+
+    /// Whether the link should count as a vote for the destination.
+    pub fn is_endorsement(&self) -> bool {
+        !(self.nofollow || self.sponsored || self.ugc)
+    }
+}
+
+/// Where on the page a link is placed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LinkContext {
+    /// Inside `<nav>` or `<header>`.
+    Navigation,
+    /// Inside `<footer>`.
+    Footer,
+    /// Part of a paragraph the content extractor classifies as main content.
+    Main,
+    #[default]
+    Other,
+}
+
+impl LinkContext {
+    /// Boilerplate links are repeated on every page of a site and say little about the destination.
+    pub fn is_boilerplate(&self) -> bool {
+        matches!(self, LinkContext::Navigation | LinkContext::Footer)
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct Link {
     pub source: Url,
     pub destination: Url,
     pub text: String,
+    pub rel: LinkRel,
+    pub context: LinkContext,
 }
 
 pub type Meta = HashMap<String, String>;
@@ -984,7 +1506,9 @@ mod tests {
             vec![Link {
                 source: "https://www.example.com/whatever".to_string().into(),
                 destination: "example.com".to_string().into(),
-                text: "Link to example".to_string()
+                text: "Link to example".to_string(),
+                rel: LinkRel::default(),
+                context: LinkContext::Other,
             }]
         );
         assert_eq!(webpage.clean_text(), Some(CONTENT.to_string()));
@@ -1002,6 +1526,195 @@ mod tests {
         assert_eq!(webpage.url().domain(), "example.com");
     }
 
+    #[test]
+    fn link_rel_and_context() {
+        let raw = format!(
+            r#"
+            <html>
+                <head>
+                    <title>Links</title>
+                </head>
+                <body>
+                    <nav><a href="https://www.example.com/about">About</a></nav>
+                    <p>{CONTENT} <a href="https://www.first.com">first</a></p>
+                    <p>Comment: <a href="https://www.spam.com" rel="nofollow UGC">cheap pills</a></p>
+                    <footer><a href="https://www.sponsor.com" rel="sponsored">Sponsor</a></footer>
+                </body>
+            </html>
+        "#
+        );
+
+        let webpage = Html::parse(&raw, "https://www.example.com/whatever");
+        let links: Vec<_> = webpage
+            .links()
+            .into_iter()
+            .map(|link| (link.destination.to_string(), link.rel, link.context))
+            .collect();
+
+        assert_eq!(
+            links,
+            vec![
+                (
+                    "https://www.example.com/about".to_string(),
+                    LinkRel::default(),
+                    LinkContext::Navigation
+                ),
+                (
+                    "https://www.first.com".to_string(),
+                    LinkRel::default(),
+                    LinkContext::Main
+                ),
+                (
+                    "https://www.spam.com".to_string(),
+                    LinkRel {
+                        nofollow: true,
+                        sponsored: false,
+                        ugc: true
+                    },
+                    LinkContext::Other
+                ),
+                (
+                    "https://www.sponsor.com".to_string(),
+                    LinkRel {
+                        nofollow: false,
+                        sponsored: true,
+                        ugc: false
+                    },
+                    LinkContext::Footer
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn non_main_backlinks_counted_once() {
+        let backlink = |text: &str, context| Link {
+            source: "https://www.a.com".to_string().into(),
+            destination: "https://www.example.com".to_string().into(),
+            text: text.to_string(),
+            rel: LinkRel::default(),
+            context,
+        };
+
+        let mut webpage = Webpage::new("<html></html>", "https://www.example.com");
+        webpage.backlinks = vec![
+            backlink("home", LinkContext::Navigation),
+            backlink("home", LinkContext::Navigation),
+            backlink("great article", LinkContext::Main),
+            backlink("great article", LinkContext::Main),
+            Link {
+                rel: LinkRel::parse("sponsored"),
+                ..backlink("buy now", LinkContext::Main)
+            },
+        ];
+
+        assert_eq!(
+            webpage.backlink_text(),
+            "home\ngreat article\ngreat article"
+        );
+    }
+
+    #[test]
+    fn feed_discovery() {
+        let raw = r#"
+            <html>
+                <head>
+                    <link rel="alternate" type="application/rss+xml" title="News" href="/feed.xml" />
+                    <link rel="alternate" type="application/atom+xml" href="https://feeds.example.org/atom" />
+                    <link rel="alternate" type="text/html" href="/other" />
+                    <link rel="stylesheet" type="application/rss+xml" href="/not-a-feed.xml" />
+                </head>
+                <body>
+                </body>
+            </html>
+        "#;
+
+        let webpage = Html::parse(raw, "https://www.example.com/blog/");
+
+        assert_eq!(
+            webpage.feeds(),
+            vec![
+                FeedLink {
+                    url: "https://www.example.com/feed.xml".to_string().into(),
+                    kind: FeedKind::Rss,
+                    title: Some("News".to_string()),
+                },
+                FeedLink {
+                    url: "https://feeds.example.org/atom".to_string().into(),
+                    kind: FeedKind::Atom,
+                    title: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn canonical_robots_and_hreflang() {
+        let raw = r#"
+            <html>
+                <head>
+                    <title>Alternates</title>
+                    <link rel="canonical" href="/article" />
+                    <meta name="ROBOTS" content="noindex, follow" />
+                    <link rel="alternate" hreflang="da-DK" href="https://www.example.com/da/article" />
+                    <link rel="alternate" hreflang="en" href="/article" />
+                </head>
+                <body>
+                </body>
+            </html>
+        "#;
+
+        let webpage = Html::parse(raw, "https://www.example.com/article?utm_source=feed");
+
+        assert_eq!(
+            webpage.canonical_url().map(|url| url.full()),
+            Some("https://www.example.com/article".to_string())
+        );
+        assert_eq!(
+            webpage.robots(),
+            RobotsMeta {
+                noindex: true,
+                nofollow: false
+            }
+        );
+        assert_eq!(
+            webpage.hreflang(),
+            vec![
+                HrefLang {
+                    lang: "da-dk".to_string(),
+                    url: "https://www.example.com/da/article".to_string()
+                },
+                HrefLang {
+                    lang: "en".to_string(),
+                    url: "https://www.example.com/article".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn robots_directives() {
+        assert_eq!(RobotsMeta::parse("all"), RobotsMeta::default());
+        assert_eq!(
+            RobotsMeta::parse("none"),
+            RobotsMeta {
+                noindex: true,
+                nofollow: true
+            }
+        );
+        assert_eq!(
+            RobotsMeta::parse("googlebot: noindex, nofollow"),
+            RobotsMeta::default()
+        );
+        assert_eq!(
+            RobotsMeta::parse("unavailable_after: 25 Jun 2010 15:00:00 PST, noindex"),
+            RobotsMeta {
+                noindex: true,
+                nofollow: false
+            }
+        );
+    }
+
     #[test]
     fn empty_title() {
         let raw = format!(
@@ -1254,8 +1967,18 @@ mod tests {
         full_link_favicon(
             "favicon.png",
             "https://www.example.com/test",
+            "https://www.example.com/favicon.png",
+        );
+        full_link_favicon(
+            "favicon.png",
+            "https://www.example.com/test/",
             "https://www.example.com/test/favicon.png",
         );
+        full_link_favicon(
+            "../favicon.png",
+            "https://www.example.com/test/page",
+            "https://www.example.com/favicon.png",
+        );
         full_link_favicon(
             "/favicon.png",
             "https://www.example.com/test",
@@ -1611,4 +2334,52 @@ mod tests {
         assert_eq!(html.title(), Some("Test site".to_string()));
         assert_eq!(html.all_text(), Some("test".to_string()));
     }
+
+    #[test]
+    fn headings_code_and_tables() {
+        let html = Html::parse(
+            r#"
+                    <html>
+                        <body>
+                            <h1>Understanding <em>ownership</em></h1>
+                            <p>Use <code>HashMap::new()</code> to create a map.</p>
+                            <h2>Example</h2>
+                            <pre><code>let mut map = HashMap::new();
+map.insert("a", 1);</code></pre>
+                            <h4>Not indexed as a heading</h4>
+                            <table>
+                                <tr><th>Method</th><th>Complexity</th></tr>
+                                <tr><td>insert</td><td>O(1)</td></tr>
+                            </table>
+                        </body>
+                    </html>
+                "#,
+            "https://www.example.com",
+        );
+
+        assert_eq!(
+            html.headings(),
+            vec![
+                Heading {
+                    level: 1,
+                    text: "Understanding ownership".to_string()
+                },
+                Heading {
+                    level: 2,
+                    text: "Example".to_string()
+                },
+            ]
+        );
+        assert_eq!(
+            html.code_blocks(),
+            vec![
+                "HashMap::new()".to_string(),
+                "let mut map = HashMap::new();\nmap.insert(\"a\", 1);".to_string(),
+            ]
+        );
+        assert_eq!(
+            html.table_text(),
+            Some("Method Complexity\ninsert O(1)".to_string())
+        );
+    }
 }
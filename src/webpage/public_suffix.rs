@@ -0,0 +1,158 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Lookups in the embedded Public Suffix List (https://publicsuffix.org).
+//! Both the ICANN and the private section of the list are used, so
+//! `user.github.io` is its own registrable domain.
+
+use std::collections::HashSet;
+
+use once_cell::sync::Lazy;
+
+static LIST: Lazy<PublicSuffixList> =
+    Lazy::new(|| PublicSuffixList::parse(include_str!("../../data/public_suffix_list.dat")));
+
+#[derive(Default)]
+struct PublicSuffixList {
+    rules: HashSet<String>,
+    /// `*.ck` is stored as `ck`
+    wildcards: HashSet<String>,
+    /// `!www.ck` is stored as `www.ck`
+    exceptions: HashSet<String>,
+}
+
+impl PublicSuffixList {
+    fn parse(list: &str) -> Self {
+        let mut res = Self::default();
+
+        for line in list.lines() {
+            let rule = match line.split_whitespace().next() {
+                Some(rule) if !rule.starts_with("//") => rule,
+                _ => continue,
+            };
+
+            if let Some(exception) = rule.strip_prefix('!') {
+                res.exceptions.insert(to_ascii(exception));
+            } else if let Some(wildcard) = rule.strip_prefix("*.") {
+                res.wildcards.insert(to_ascii(wildcard));
+            } else {
+                res.rules.insert(to_ascii(rule));
+            }
+        }
+
+        res
+    }
+
+    /// Byte offset in `host` where the public suffix begins.
+    fn suffix_start(&self, host: &str) -> usize {
+        let label_starts: Vec<usize> = std::iter::once(0)
+            .chain(host.match_indices('.').map(|(idx, _)| idx + 1))
+            .collect();
+
+        for (i, start) in label_starts.iter().enumerate() {
+            let candidate = &host[*start..];
+
+            if self.exceptions.contains(candidate) {
+                return label_starts.get(i + 1).copied().unwrap_or(host.len());
+            }
+
+            if self.rules.contains(candidate) {
+                return *start;
+            }
+
+            if let Some(parent) = label_starts.get(i + 1) {
+                if self.wildcards.contains(&host[*parent..]) {
+                    return *start;
+                }
+            }
+        }
+
+        // the implicit "*" rule
+        *label_starts.last().unwrap()
+    }
+}
+
+fn to_ascii(rule: &str) -> String {
+    if rule.is_ascii() {
+        rule.to_ascii_lowercase()
+    } else {
+        ::url::quirks::domain_to_ascii(rule)
+    }
+}
+
+/// The public suffix plus one label of an ascii lowercase host, e.g. `example.co.uk`
+/// for `www.example.co.uk`.
+/// Returns `None` if the host is itself a public suffix.
+pub fn registrable_domain(host: &str) -> Option<&str> {
+    let host = host.trim_end_matches('.');
+    let suffix_start = LIST.suffix_start(host);
+
+    if suffix_start == 0 {
+        return None;
+    }
+
+    let start = host[..suffix_start - 1]
+        .rfind('.')
+        .map(|idx| idx + 1)
+        .unwrap_or(0);
+
+    Some(&host[start..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn suffix(host: &str) -> &str {
+        &host[LIST.suffix_start(host)..]
+    }
+
+    #[test]
+    fn suffixes() {
+        assert_eq!(suffix("www.example.com"), "com");
+        assert_eq!(suffix("www.example.co.uk"), "co.uk");
+        assert_eq!(suffix("example.unknowntld"), "unknowntld");
+        assert_eq!(suffix("a.b.ck"), "b.ck");
+        assert_eq!(suffix("www.ck"), "ck");
+    }
+
+    #[test]
+    fn registrable_domains() {
+        assert_eq!(
+            registrable_domain("www.example.co.uk"),
+            Some("example.co.uk")
+        );
+        assert_eq!(registrable_domain("example.com."), Some("example.com"));
+        assert_eq!(registrable_domain("co.uk"), None);
+        assert_eq!(registrable_domain("user.github.io"), Some("user.github.io"));
+        assert_eq!(
+            registrable_domain("a.b.foo.kawasaki.jp"),
+            Some("b.foo.kawasaki.jp")
+        );
+        assert_eq!(
+            registrable_domain("www.city.kawasaki.jp"),
+            Some("city.kawasaki.jp")
+        );
+        assert_eq!(
+            registrable_domain("xn--bcher-kva.de"),
+            Some("xn--bcher-kva.de")
+        );
+        assert_eq!(
+            registrable_domain("shop.example.xn--55qx5d.cn"),
+            Some("example.xn--55qx5d.cn")
+        );
+    }
+}
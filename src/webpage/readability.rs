@@ -0,0 +1,373 @@
+// Cuely is an open source web search engine.
+// Copyright (C) 2022 Cuely ApS
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::{cmp::Ordering, collections::HashMap, rc::Rc};
+
+use kuchiki::{ElementData, NodeRef};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use whatlang::Lang;
+
+use super::{extractor::ContentExtractor, just_text::Paragraph};
+
+// DOM scoring in the style of Arc90's Readability (https://github.com/mozilla/readability).
+// Every paragraph adds to the score of the elements containing it, and the best scoring
+// element together with its similarly scored siblings is taken as the main content.
+// Unlike JustText it does not rely on stopwords, so it also works for code blocks, tables
+// and languages that are not written with whitespace between words.
+
+const MIN_PARAGRAPH_LEN_DEFAULT: usize = 25;
+const MAX_LINK_DENSITY_DEFAULT: f64 = 0.5;
+const SIBLING_SCORE_FRACTION_DEFAULT: f64 = 0.2;
+const MIN_SIBLING_SCORE_DEFAULT: f64 = 10.0;
+const NUM_SCORED_ANCESTORS: usize = 3;
+
+static POSITIVE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)article|body|content|entry|hentry|h-entry|main|page|post|text|blog|story")
+        .unwrap()
+});
+
+static NEGATIVE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)comment|meta|footer|footnote|sidebar|nav|menu|share|social|related|widget|banner|sponsor|advert|promo|popup|cookie|breadcrumb|masthead|combx|outbrain|shoutbox|pagination|pager|hidden",
+    )
+    .unwrap()
+});
+
+pub struct Readability {
+    /// Paragraphs with fewer characters than this do not add to the score of their ancestors.
+    pub min_paragraph_len: usize,
+    pub max_link_density: f64,
+    /// Siblings of the top candidate scoring at least this fraction of its score are included.
+    pub sibling_score_fraction: f64,
+    pub min_sibling_score: f64,
+}
+
+impl Default for Readability {
+    fn default() -> Self {
+        Self {
+            min_paragraph_len: MIN_PARAGRAPH_LEN_DEFAULT,
+            max_link_density: MAX_LINK_DENSITY_DEFAULT,
+            sibling_score_fraction: SIBLING_SCORE_FRACTION_DEFAULT,
+            min_sibling_score: MIN_SIBLING_SCORE_DEFAULT,
+        }
+    }
+}
+
+struct Candidate {
+    node: NodeRef,
+    score: f64,
+    text_len: usize,
+    link_len: usize,
+}
+
+impl Candidate {
+    fn new(node: NodeRef) -> Self {
+        Self {
+            score: initial_score(&node),
+            node,
+            text_len: 0,
+            link_len: 0,
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn link_density(&self) -> f64 {
+        if self.text_len == 0 {
+            0.0
+        } else {
+            self.link_len as f64 / self.text_len as f64
+        }
+    }
+
+    fn final_score(&self) -> f64 {
+        self.score * (1.0 - self.link_density())
+    }
+}
+
+fn key(node: &NodeRef) -> usize {
+    Rc::as_ptr(&node.0) as usize
+}
+
+fn class_weight(element: &ElementData) -> f64 {
+    let attributes = element.attributes.borrow();
+
+    ["class", "id"]
+        .into_iter()
+        .filter_map(|name| attributes.get(name))
+        .map(|value| {
+            let mut weight = 0.0;
+
+            if NEGATIVE.is_match(value) {
+                weight -= 25.0;
+            }
+
+            if POSITIVE.is_match(value) {
+                weight += 25.0;
+            }
+
+            weight
+        })
+        .sum()
+}
+
+fn initial_score(node: &NodeRef) -> f64 {
+    let element = match node.as_element() {
+        Some(element) => element,
+        None => return 0.0,
+    };
+
+    let tag = match &*element.name.local {
+        "div" | "article" | "main" => 5.0,
+        "pre" | "td" | "blockquote" => 3.0,
+        "address" | "ol" | "ul" | "dl" | "dd" | "dt" | "li" | "form" => -3.0,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => -5.0,
+        "nav" | "aside" | "footer" => -25.0,
+        _ => 0.0,
+    };
+
+    tag + class_weight(element)
+}
+
+fn is_boilerplate(node: &NodeRef) -> bool {
+    match node.as_element() {
+        Some(element) => {
+            matches!(&*element.name.local, "nav" | "aside" | "footer")
+                || class_weight(element) < 0.0
+        }
+        None => false,
+    }
+}
+
+/// Whether the container is inside one of the selected nodes without any
+/// boilerplate element in between.
+fn is_inside(container: &NodeRef, selected: &[NodeRef]) -> bool {
+    for node in container.inclusive_ancestors() {
+        if selected.contains(&node) {
+            return true;
+        }
+
+        if is_boilerplate(&node) {
+            return false;
+        }
+    }
+
+    false
+}
+
+fn num_commas(text: &str) -> usize {
+    text.chars()
+        .filter(|c| matches!(c, ',' | '，' | '、'))
+        .count()
+}
+
+impl Readability {
+    #[allow(clippy::cast_precision_loss)]
+    fn candidates(&self, paragraphs: &[Paragraph]) -> HashMap<usize, Candidate> {
+        let mut candidates: HashMap<usize, Candidate> = HashMap::new();
+
+        for paragraph in paragraphs {
+            let len = paragraph.text.trim().chars().count();
+            if len < self.min_paragraph_len {
+                continue;
+            }
+
+            let container = match &paragraph.container {
+                Some(container) => container,
+                None => continue,
+            };
+
+            let score = 1.0 + num_commas(&paragraph.text) as f64 + (len / 100).min(3) as f64;
+
+            for (level, ancestor) in container
+                .ancestors()
+                .filter(|node| node.as_element().is_some())
+                .take(NUM_SCORED_ANCESTORS)
+                .enumerate()
+            {
+                let divider = match level {
+                    0 => 1.0,
+                    1 => 2.0,
+                    _ => level as f64 * 3.0,
+                };
+
+                candidates
+                    .entry(key(&ancestor))
+                    .or_insert_with(|| Candidate::new(ancestor))
+                    .score += score / divider;
+            }
+        }
+
+        for paragraph in paragraphs {
+            if let Some(container) = &paragraph.container {
+                for node in container.inclusive_ancestors() {
+                    if let Some(candidate) = candidates.get_mut(&key(&node)) {
+                        candidate.text_len += paragraph.text.len();
+                        candidate.link_len += paragraph.chars_count_in_links;
+                    }
+                }
+            }
+        }
+
+        candidates
+    }
+
+    /// A `<p>` sibling of the top candidate with enough text and few links
+    /// is part of the content, even though it does not score on its own.
+    fn is_content_paragraph(node: &NodeRef, paragraphs: &[Paragraph]) -> bool {
+        if !matches!(node.as_element(), Some(element) if &*element.name.local == "p") {
+            return false;
+        }
+
+        let (text_len, link_len) = paragraphs
+            .iter()
+            .filter(|paragraph| paragraph.container.as_ref() == Some(node))
+            .fold((0, 0), |(text_len, link_len), paragraph| {
+                (
+                    text_len + paragraph.text.len(),
+                    link_len + paragraph.chars_count_in_links,
+                )
+            });
+
+        text_len > 80 && link_len * 4 < text_len
+    }
+}
+
+impl ContentExtractor for Readability {
+    fn main_content(&self, paragraphs: &[Paragraph], _lang: &Lang) -> Vec<Paragraph> {
+        let candidates = self.candidates(paragraphs);
+
+        let top = match candidates.values().max_by(|a, b| {
+            a.final_score()
+                .partial_cmp(&b.final_score())
+                .unwrap_or(Ordering::Equal)
+        }) {
+            Some(top) => top,
+            None => return Vec::new(),
+        };
+
+        let threshold =
+            (top.final_score() * self.sibling_score_fraction).max(self.min_sibling_score);
+        let mut selected = vec![top.node.clone()];
+
+        if let Some(parent) = top.node.parent() {
+            for sibling in parent
+                .children()
+                .filter(|node| node.as_element().is_some() && *node != top.node)
+            {
+                let is_content = match candidates.get(&key(&sibling)) {
+                    Some(candidate) if candidate.final_score() >= threshold => true,
+                    _ => Readability::is_content_paragraph(&sibling, paragraphs),
+                };
+
+                if is_content {
+                    selected.push(sibling);
+                }
+            }
+        }
+
+        paragraphs
+            .iter()
+            .filter(|paragraph| {
+                paragraph.text.chars().any(|c| !c.is_whitespace())
+                    && paragraph.link_density() <= self.max_link_density
+                    && matches!(&paragraph.container, Some(container) if is_inside(container, &selected))
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kuchiki::traits::TendrilSink;
+
+    use super::*;
+    use crate::webpage::just_text::JustText;
+
+    fn extract(html: &str) -> String {
+        let root = kuchiki::parse_html().one(html);
+        Readability::default().extract_from_paragraphs(&JustText::paragraphs(root), &Lang::Eng)
+    }
+
+    #[test]
+    fn picks_highest_scoring_container() {
+        let text = extract(
+            r#"
+            <html>
+                <body>
+                    <div class="sidebar">
+                        <p>This is a rather long paragraph in the sidebar, but it should not be part of the content.</p>
+                    </div>
+                    <div class="article">
+                        <p>This is the first paragraph of the article, and it has a couple of commas, as articles do.</p>
+                        <p>This is the second paragraph of the article, which is also part of the main content.</p>
+                    </div>
+                </body>
+            </html>
+            "#,
+        );
+
+        assert!(text.starts_with("This is the first paragraph of the article"));
+        assert!(text.contains("This is the second paragraph"));
+        assert!(!text.contains("sidebar"));
+    }
+
+    #[test]
+    fn includes_scoring_siblings() {
+        let text = extract(
+            r#"
+            <html>
+                <body>
+                    <div>
+                        <p>This is the first part of the story, with many words, commas, and clauses, which makes it score well.</p>
+                    </div>
+                    <div>
+                        <p>This is the second part of the story, which also has commas, clauses, words, and lots of other words in it.</p>
+                    </div>
+                </body>
+            </html>
+            "#,
+        );
+
+        assert!(text.contains("first part of the story"));
+        assert!(text.contains("second part of the story"));
+    }
+
+    #[test]
+    fn excludes_links() {
+        let text = extract(
+            r#"
+            <html>
+                <body>
+                    <div class="content">
+                        <p>This is the content of the page, and it is long enough to be scored as a paragraph.</p>
+                        <div><a href="/a">A link to another page</a> <a href="/b">and another one</a></div>
+                    </div>
+                </body>
+            </html>
+            "#,
+        );
+
+        assert!(text.contains("This is the content of the page"));
+        assert!(!text.contains("link"));
+    }
+
+    #[test]
+    fn no_paragraphs() {
+        assert_eq!(extract("<html><body></body></html>"), String::new());
+    }
+}
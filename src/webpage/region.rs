@@ -91,7 +91,46 @@ impl Region {
         }
     }
 
+    /// Region for a hreflang tag such as `da`, `en-us` or `es-ES`.
+    /// The country takes precedence over the language if both are given.
+    pub fn from_hreflang(tag: &str) -> Option<Self> {
+        let tag = tag.to_ascii_lowercase();
+        let mut parts = tag.split(|c| c == '-' || c == '_');
+        let lang = parts.next()?;
+
+        if let Some(country) = parts.next() {
+            match country {
+                "dk" => return Some(Region::Denmark),
+                "fr" => return Some(Region::France),
+                "de" => return Some(Region::Germany),
+                "es" => return Some(Region::Spain),
+                "us" => return Some(Region::US),
+                _ => {}
+            }
+        }
+
+        match lang {
+            "da" => Some(Region::Denmark),
+            "fr" => Some(Region::France),
+            "de" => Some(Region::Germany),
+            "es" => Some(Region::Spain),
+            "en" => Some(Region::US),
+            _ => None,
+        }
+    }
+
     pub fn guess_from(webpage: &Webpage) -> Result<Self> {
+        let url = webpage.html.url().full();
+        if let Some(region) = webpage
+            .html
+            .hreflang()
+            .into_iter()
+            .find(|alternate| alternate.url == url)
+            .and_then(|alternate| Region::from_hreflang(&alternate.lang))
+        {
+            return Ok(region);
+        }
+
         match webpage.html.lang {
             Some(lang) => match lang {
                 whatlang::Lang::Eng => Ok(Region::US),
@@ -239,4 +278,14 @@ mod tests {
         assert_eq!(a.score(&Region::Denmark), 0.4);
         assert_eq!(a.score(&Region::France), 0.0);
     }
+
+    #[test]
+    fn hreflang_region() {
+        assert_eq!(Region::from_hreflang("da"), Some(Region::Denmark));
+        assert_eq!(Region::from_hreflang("en-DK"), Some(Region::Denmark));
+        assert_eq!(Region::from_hreflang("es-ES"), Some(Region::Spain));
+        assert_eq!(Region::from_hreflang("en"), Some(Region::US));
+        assert_eq!(Region::from_hreflang("x-default"), None);
+        assert_eq!(Region::from_hreflang("ja"), None);
+    }
 }